@@ -0,0 +1,204 @@
+//! Self-signed statement of a device's public keys.
+//!
+//! `attestation.cbor` is a CBOR map `{"statement": bytes, "signature": bytes}`.
+//! The statement is itself CBOR, `{"firmware": text, "slots": {slot: pubkey}}`,
+//! and the signature is slot 0's Ed25519 signature over its BLAKE2b-256, the
+//! way the secure element signs a hash. A verifier holding slot 0's key can
+//! then trust that every listed key came from the same device.
+
+use crate::crypto::{hash_bytes, verify, CryptoError};
+use crate::spec::{HashAlgorithm, SignAlgorithm};
+use ciborium::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Slot whose key signs the attestation.
+pub const ATTESTING_SLOT: u8 = 0;
+
+#[derive(Debug, Error)]
+pub enum AttestationError {
+    #[error("attestation is not valid CBOR: {0}")]
+    Decode(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("attestation must be a {{statement, signature}} map over a {{firmware, slots}} map")]
+    Malformed,
+    #[error("attestation lists no key for slot {ATTESTING_SLOT}")]
+    NoAttestingKey,
+    #[error("attestation signature does not verify against slot {ATTESTING_SLOT}")]
+    BadSignature,
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// What the device attests to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attestation {
+    pub firmware: String,
+    /// Public key of every provisioned slot.
+    pub slots: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Attestation {
+    /// CBOR encoding of the statement that gets signed.
+    pub fn statement(&self) -> Vec<u8> {
+        let slots = self
+            .slots
+            .iter()
+            .map(|(slot, key)| (Value::Integer((*slot).into()), Value::Bytes(key.clone())))
+            .collect();
+        to_cbor(&Value::Map(vec![
+            (
+                Value::Text("firmware".into()),
+                Value::Text(self.firmware.clone()),
+            ),
+            (Value::Text("slots".into()), Value::Map(slots)),
+        ]))
+    }
+}
+
+/// Bytes the attesting slot signs for `statement`.
+pub fn attestation_digest(statement: &[u8]) -> Vec<u8> {
+    hash_bytes(HashAlgorithm::Blake2b256, statement)
+}
+
+/// Wrap a signed statement as `attestation.cbor`.
+pub fn encode_attestation(statement: &[u8], signature: &[u8]) -> Vec<u8> {
+    to_cbor(&Value::Map(vec![
+        (
+            Value::Text("statement".into()),
+            Value::Bytes(statement.to_vec()),
+        ),
+        (
+            Value::Text("signature".into()),
+            Value::Bytes(signature.to_vec()),
+        ),
+    ]))
+}
+
+/// Decode `attestation.cbor` and check its signature against the slot 0
+/// key it lists.
+///
+/// This only shows the keys came from one device; the verifier must
+/// already trust slot 0's key, e.g. from `pubkey.bin` exported at setup.
+pub fn verify_attestation(cbor: &[u8]) -> Result<Attestation, AttestationError> {
+    let mut outer = fields(ciborium::from_reader::<Value, _>(cbor)?)?;
+    let statement = take(&mut outer, "statement")?
+        .into_bytes()
+        .map_err(|_| AttestationError::Malformed)?;
+    let signature = take(&mut outer, "signature")?
+        .into_bytes()
+        .map_err(|_| AttestationError::Malformed)?;
+
+    let mut inner = fields(ciborium::from_reader::<Value, _>(statement.as_slice())?)?;
+    let firmware = take(&mut inner, "firmware")?
+        .into_text()
+        .map_err(|_| AttestationError::Malformed)?;
+    let mut slots = BTreeMap::new();
+    for (slot, key) in take(&mut inner, "slots")?
+        .into_map()
+        .map_err(|_| AttestationError::Malformed)?
+    {
+        let slot = slot
+            .as_integer()
+            .and_then(|i| u8::try_from(i).ok())
+            .ok_or(AttestationError::Malformed)?;
+        let key = key.into_bytes().map_err(|_| AttestationError::Malformed)?;
+        if slots.insert(slot, key).is_some() {
+            return Err(AttestationError::Malformed);
+        }
+    }
+
+    let key = slots
+        .get(&ATTESTING_SLOT)
+        .ok_or(AttestationError::NoAttestingKey)?;
+    if !verify(
+        SignAlgorithm::Ed25519,
+        key,
+        &attestation_digest(&statement),
+        &signature,
+    )? {
+        return Err(AttestationError::BadSignature);
+    }
+    Ok(Attestation { firmware, slots })
+}
+
+fn to_cbor(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(value, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// A text-keyed map's entries.
+fn fields(value: Value) -> Result<Vec<(String, Value)>, AttestationError> {
+    value
+        .into_map()
+        .map_err(|_| AttestationError::Malformed)?
+        .into_iter()
+        .map(|(key, value)| {
+            Ok((
+                key.into_text().map_err(|_| AttestationError::Malformed)?,
+                value,
+            ))
+        })
+        .collect()
+}
+
+fn take(fields: &mut Vec<(String, Value)>, name: &str) -> Result<Value, AttestationError> {
+    let at = fields
+        .iter()
+        .position(|(key, _)| key == name)
+        .ok_or(AttestationError::Malformed)?;
+    Ok(fields.swap_remove(at).1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SoftwareSigner;
+
+    fn signed(attestation: &Attestation, signer: &SoftwareSigner) -> Vec<u8> {
+        let statement = attestation.statement();
+        let signature = signer
+            .sign(SignAlgorithm::Ed25519, &attestation_digest(&statement))
+            .unwrap();
+        encode_attestation(&statement, &signature)
+    }
+
+    fn device() -> (SoftwareSigner, Attestation) {
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let attestation = Attestation {
+            firmware: "0.1.0".into(),
+            slots: BTreeMap::from([
+                (0, signer.public_key(SignAlgorithm::Ed25519).unwrap()),
+                (3, vec![3; 32]),
+            ]),
+        };
+        (signer, attestation)
+    }
+
+    #[test]
+    fn round_trips_and_verifies() {
+        let (signer, attestation) = device();
+        let cbor = signed(&attestation, &signer);
+        assert_eq!(verify_attestation(&cbor).unwrap(), attestation);
+    }
+
+    #[test]
+    fn foreign_signature_is_refused() {
+        let (_, attestation) = device();
+        let cbor = signed(&attestation, &SoftwareSigner::from_seed([8; 32]));
+        assert!(matches!(
+            verify_attestation(&cbor),
+            Err(AttestationError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn attestation_without_slot_zero_is_refused() {
+        let (signer, mut attestation) = device();
+        attestation.slots.remove(&0);
+        assert!(matches!(
+            verify_attestation(&signed(&attestation, &signer)),
+            Err(AttestationError::NoAttestingKey)
+        ));
+    }
+}
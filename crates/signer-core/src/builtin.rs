@@ -0,0 +1,49 @@
+//! Interpreters compiled into the device image.
+//!
+//! A spec that names a built-in interpreter does not need one on the USB
+//! stick, which removes the stick as a way to swap in a tampered interpreter.
+//! The WASM is embedded with `include_bytes!`, so the `builtin-interpreters`
+//! feature requires `just build-wasm` to have run first.
+
+#[cfg(feature = "builtin-interpreters")]
+static BUILTINS: &[(&str, &[u8])] = &[(
+    "echo-hex",
+    include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/echo_hex.wasm"
+    )),
+)];
+
+#[cfg(not(feature = "builtin-interpreters"))]
+static BUILTINS: &[(&str, &[u8])] = &[];
+
+/// WASM bytes of the built-in interpreter called `name`, if embedded.
+pub fn interpreter(name: &str) -> Option<&'static [u8]> {
+    BUILTINS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, wasm)| *wasm)
+}
+
+/// Names of all embedded interpreters.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(n, _)| *n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_name_is_none() {
+        assert!(interpreter("no-such-format").is_none());
+    }
+
+    #[cfg(feature = "builtin-interpreters")]
+    #[test]
+    fn echo_hex_is_embedded() {
+        let wasm = interpreter("echo-hex").unwrap();
+        assert_eq!(&wasm[..4], b"\0asm");
+        assert!(names().any(|n| n == "echo-hex"));
+    }
+}
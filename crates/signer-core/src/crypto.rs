@@ -1,9 +1,13 @@
-use crate::spec::{HashAlgorithm, Signable, SignableSource};
-use blake2::digest::consts::U32;
-use blake2::{Blake2b, Digest};
+use crate::spec::{HashAlgorithm, SignAlgorithm, Signable, SignableSource};
+use blake2::digest::consts::{U28, U32, U64};
+use blake2::digest::{KeyInit, Mac};
+use blake2::{Blake2b, Blake2bMac, Digest};
+use ed25519_dalek::{Signer, SigningKey, Verifier};
+use sha2::Sha512;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum CryptoError {
     #[error("range {offset}..{end} out of bounds (payload length {payload_len})")]
     RangeOutOfBounds {
@@ -11,17 +15,74 @@ pub enum CryptoError {
         end: usize,
         payload_len: usize,
     },
+    #[error("range of {length} bytes at {offset} overflows")]
+    RangeOverflow { offset: usize, length: usize },
+    #[error("signing algorithm {0:?} is not supported")]
+    UnsupportedAlgorithm(SignAlgorithm),
+    #[error("MAC key length {0} is invalid (must be 1-64 bytes)")]
+    InvalidMacKey(usize),
+    #[error("malformed leaf list at byte {offset}")]
+    MalformedLeafList { offset: usize },
+    #[error("leaf list is empty")]
+    EmptyLeafList,
+    #[error("the signable bytes are computed by the interpreter")]
+    InterpreterSignable,
+    #[error("public key of {len} bytes is not a valid {algorithm:?} key")]
+    InvalidPublicKey {
+        algorithm: SignAlgorithm,
+        len: usize,
+    },
+    #[error("{algorithm:?} signatures are {expected} bytes, got {len}")]
+    InvalidSignatureLength {
+        algorithm: SignAlgorithm,
+        expected: usize,
+        len: usize,
+    },
+    #[error("seed is not a valid {0:?} secret key")]
+    InvalidSeed(SignAlgorithm),
+    #[error("a {0}-byte digest is too short to sign with ECDSA")]
+    InvalidDigest(usize),
+    #[error("no recovery id yields the signing key")]
+    Unrecoverable,
 }
 
 /// Extract the bytes to sign from the payload according to the Signable spec.
 ///
-/// The result is the hash (or raw bytes) that gets sent to the secure element
-/// for signing. The Pi never handles private key material.
-pub fn extract_signable(payload: &[u8], signable: &Signable) -> Result<Vec<u8>, CryptoError> {
+/// Each result is a hash (or raw bytes) that gets sent to the secure element
+/// for signing: one per item of a [`Signable::Multi`], in order, and exactly
+/// one otherwise. The Pi never handles private key material.
+pub fn extract_signable(payload: &[u8], signable: &Signable) -> Result<Vec<Vec<u8>>, CryptoError> {
+    match signable {
+        Signable::Multi(items) => {
+            let mut messages = Vec::with_capacity(items.len());
+            for item in items {
+                messages.extend(extract_signable(payload, item)?);
+            }
+            Ok(messages)
+        }
+        Signable::Prefixed { prefix, inner } => match inner.as_ref() {
+            Signable::HashThenSign { hash, source } => {
+                let data = [prefix, source_bytes(payload, source)?].concat();
+                Ok(vec![hash_bytes(*hash, &data)])
+            }
+            Signable::KeyedHashThenSign { key, source } => {
+                let data = [prefix, source_bytes(payload, source)?].concat();
+                Ok(vec![keyed_blake2b256(key, &data)?])
+            }
+            other => Ok(extract_signable(payload, other)?
+                .into_iter()
+                .map(|message| [prefix.as_slice(), &message].concat())
+                .collect()),
+        },
+        single => Ok(vec![extract_one(payload, single)?]),
+    }
+}
+
+fn extract_one(payload: &[u8], signable: &Signable) -> Result<Vec<u8>, CryptoError> {
     match signable {
         Signable::Whole => Ok(payload.to_vec()),
         Signable::Range { offset, length } => {
-            let end = offset + length;
+            let end = range_end(*offset, *length)?;
             if end > payload.len() {
                 return Err(CryptoError::RangeOutOfBounds {
                     offset: *offset,
@@ -32,45 +93,522 @@ pub fn extract_signable(payload: &[u8], signable: &Signable) -> Result<Vec<u8>,
             Ok(payload[*offset..end].to_vec())
         }
         Signable::HashThenSign { hash, source } => {
-            let source_bytes = match source {
-                SignableSource::Whole => payload.to_vec(),
-                SignableSource::Range { offset, length } => {
-                    let end = offset + length;
-                    if end > payload.len() {
-                        return Err(CryptoError::RangeOutOfBounds {
-                            offset: *offset,
-                            end,
-                            payload_len: payload.len(),
-                        });
+            Ok(hash_bytes(*hash, source_bytes(payload, source)?))
+        }
+        Signable::KeyedHashThenSign { key, source } => {
+            keyed_blake2b256(key, source_bytes(payload, source)?)
+        }
+        Signable::MerkleRoot { hash } => Ok(merkle_root(*hash, &parse_leaves(payload)?)),
+        Signable::Interpreter => Err(CryptoError::InterpreterSignable),
+        Signable::Multi(_) | Signable::Prefixed { .. } => {
+            unreachable!("extract_signable handles these")
+        }
+    }
+}
+
+/// Split a payload of length-prefixed (4-byte LE) leaves.
+pub fn parse_leaves(payload: &[u8]) -> Result<Vec<&[u8]>, CryptoError> {
+    let mut leaves = Vec::new();
+    let mut offset = 0;
+    while offset < payload.len() {
+        let malformed = CryptoError::MalformedLeafList { offset };
+        let prefix: [u8; 4] = payload
+            .get(offset..offset + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(malformed)?;
+        let start = offset + 4;
+        let end = start
+            .checked_add(u32::from_le_bytes(prefix) as usize)
+            .filter(|&end| end <= payload.len())
+            .ok_or(CryptoError::MalformedLeafList { offset })?;
+        leaves.push(&payload[start..end]);
+        offset = end;
+    }
+    if leaves.is_empty() {
+        return Err(CryptoError::EmptyLeafList);
+    }
+    Ok(leaves)
+}
+
+/// RFC 6962 Merkle tree hash over `leaves`, using `algo` for every node.
+pub fn merkle_root(algo: HashAlgorithm, leaves: &[&[u8]]) -> Vec<u8> {
+    match leaves {
+        [] => hash_bytes(algo, &[]),
+        [leaf] => hash_bytes(algo, &[&[0x00], *leaf].concat()),
+        _ => {
+            // Largest power of two strictly less than the leaf count.
+            let split = leaves.len().next_power_of_two() / 2;
+            let left = merkle_root(algo, &leaves[..split]);
+            let right = merkle_root(algo, &leaves[split..]);
+            hash_bytes(algo, &[&[0x01], left.as_slice(), right.as_slice()].concat())
+        }
+    }
+}
+
+/// The bytes of the payload selected by `source`.
+fn source_bytes<'a>(payload: &'a [u8], source: &SignableSource) -> Result<&'a [u8], CryptoError> {
+    match source {
+        SignableSource::Whole => Ok(payload),
+        SignableSource::Range { offset, length } => {
+            let end = range_end(*offset, *length)?;
+            if end > payload.len() {
+                return Err(CryptoError::RangeOutOfBounds {
+                    offset: *offset,
+                    end,
+                    payload_len: payload.len(),
+                });
+            }
+            Ok(&payload[*offset..end])
+        }
+    }
+}
+
+/// End of the range of `length` bytes at `offset`.
+fn range_end(offset: usize, length: usize) -> Result<usize, CryptoError> {
+    offset
+        .checked_add(length)
+        .ok_or(CryptoError::RangeOverflow { offset, length })
+}
+
+/// Keyed Blake2b with 32-byte output (RFC 7693 MAC mode).
+pub fn keyed_blake2b256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if key.is_empty() {
+        return Err(CryptoError::InvalidMacKey(0));
+    }
+    let mut mac = <Blake2bMac<U32> as KeyInit>::new_from_slice(key)
+        .map_err(|_| CryptoError::InvalidMacKey(key.len()))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Incremental [`extract_signable`] for the hash-then-sign modes, so a
+/// payload can be hashed as it is streamed instead of held in memory.
+pub struct StreamHasher {
+    hasher: Hasher,
+    /// Byte range of the payload that is hashed; `None` hashes everything.
+    range: Option<(usize, usize)>,
+    /// Payload bytes seen so far.
+    seen: usize,
+}
+
+enum Hasher {
+    Digest(Box<dyn blake2::digest::DynDigest>),
+    /// SHA-256 whose digest is hashed again on finalize.
+    Double(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Keyed(Box<Blake2bMac<U32>>),
+}
+
+impl Hasher {
+    fn absorb(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Digest(digest) => digest.update(data),
+            Hasher::Double(sha) => sha.update(data),
+            Hasher::Blake3(blake3) => {
+                blake3.update(data);
+            }
+            Hasher::Keyed(mac) => mac.update(data),
+        }
+    }
+}
+
+impl StreamHasher {
+    /// Hasher producing what `extract_signable` returns for `signable`, or
+    /// `None` for modes that sign raw bytes or need the whole payload at once.
+    pub fn for_signable(signable: &Signable) -> Result<Option<Self>, CryptoError> {
+        let (hasher, source) = match signable {
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256d,
+                source,
+            } => (Hasher::Double(sha2::Sha256::new()), source),
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Blake3,
+                source,
+            } => (Hasher::Blake3(Box::new(blake3::Hasher::new())), source),
+            Signable::HashThenSign { hash, source } => {
+                let digest: Box<dyn blake2::digest::DynDigest> = match hash {
+                    HashAlgorithm::Blake2b224 => Box::new(Blake2b::<U28>::new()),
+                    HashAlgorithm::Blake2b256 => Box::new(Blake2b::<U32>::new()),
+                    HashAlgorithm::Blake2b512 => Box::new(Blake2b::<U64>::new()),
+                    HashAlgorithm::Sha256 => Box::new(sha2::Sha256::new()),
+                    HashAlgorithm::Sha256d | HashAlgorithm::Blake3 => {
+                        unreachable!("matched above")
                     }
-                    payload[*offset..end].to_vec()
+                    HashAlgorithm::Sha3_256 => Box::new(sha3::Sha3_256::new()),
+                    HashAlgorithm::Keccak256 => Box::new(sha3::Keccak256::new()),
+                };
+                (Hasher::Digest(digest), source)
+            }
+            Signable::KeyedHashThenSign { key, source } => {
+                if key.is_empty() {
+                    return Err(CryptoError::InvalidMacKey(0));
                 }
-            };
-            Ok(hash_bytes(*hash, &source_bytes))
+                let mac = <Blake2bMac<U32> as KeyInit>::new_from_slice(key)
+                    .map_err(|_| CryptoError::InvalidMacKey(key.len()))?;
+                (Hasher::Keyed(Box::new(mac)), source)
+            }
+            Signable::Whole
+            | Signable::Range { .. }
+            | Signable::MerkleRoot { .. }
+            | Signable::Interpreter
+            | Signable::Multi(_) => return Ok(None),
+            // The prefix goes into the hash ahead of the payload bytes.
+            Signable::Prefixed { prefix, inner }
+                if matches!(
+                    **inner,
+                    Signable::HashThenSign { .. } | Signable::KeyedHashThenSign { .. }
+                ) =>
+            {
+                let mut stream = Self::for_signable(inner)?.expect("hashing modes stream");
+                stream.hasher.absorb(prefix);
+                return Ok(Some(stream));
+            }
+            Signable::Prefixed { .. } => return Ok(None),
+        };
+        let range = match source {
+            SignableSource::Whole => None,
+            SignableSource::Range { offset, length } => {
+                Some((*offset, range_end(*offset, *length)?))
+            }
+        };
+        Ok(Some(Self {
+            hasher,
+            range,
+            seen: 0,
+        }))
+    }
+
+    /// Feed the next bytes of the payload.
+    pub fn update(&mut self, data: &[u8]) {
+        let start = self.seen;
+        self.seen += data.len();
+        let data = match self.range {
+            None => data,
+            Some((offset, end)) => {
+                let from = offset.clamp(start, self.seen) - start;
+                let to = end.clamp(start, self.seen) - start;
+                &data[from..to]
+            }
+        };
+        self.hasher.absorb(data);
+    }
+
+    /// Finish once the whole payload has been fed.
+    pub fn finalize(self) -> Result<Vec<u8>, CryptoError> {
+        if let Some((offset, end)) = self.range {
+            if end > self.seen {
+                return Err(CryptoError::RangeOutOfBounds {
+                    offset,
+                    end,
+                    payload_len: self.seen,
+                });
+            }
         }
+        Ok(match self.hasher {
+            Hasher::Digest(digest) => digest.finalize().to_vec(),
+            Hasher::Double(sha) => sha2::Sha256::digest(sha.finalize()).to_vec(),
+            Hasher::Blake3(blake3) => blake3.finalize().as_bytes().to_vec(),
+            Hasher::Keyed(mac) => mac.finalize().into_bytes().to_vec(),
+        })
     }
 }
 
+/// Fold a passphrase into a seed, BIP39 "25th word" style: one seed yields
+/// an unrelated key per passphrase.
+///
+/// The result is BLAKE2b-256 keyed with the seed over the passphrase. An empty
+/// passphrase returns the seed unchanged, so keys made without one are unaffected.
+pub fn derive_seed(seed: &[u8; 32], passphrase: &[u8]) -> [u8; 32] {
+    if passphrase.is_empty() {
+        return *seed;
+    }
+    keyed_blake2b256(seed, passphrase)
+        .expect("a 32-byte key is valid")
+        .try_into()
+        .expect("BLAKE2b-256 output is 32 bytes")
+}
+
 /// Hash bytes with the given algorithm.
-fn hash_bytes(algo: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+pub fn hash_bytes(algo: HashAlgorithm, data: &[u8]) -> Vec<u8> {
     match algo {
+        HashAlgorithm::Blake2b224 => Blake2b::<U28>::digest(data).to_vec(),
         HashAlgorithm::Blake2b256 => {
             let mut hasher = Blake2b::<U32>::new();
             hasher.update(data);
             hasher.finalize().to_vec()
         }
+        HashAlgorithm::Blake2b512 => Blake2b::<U64>::digest(data).to_vec(),
         HashAlgorithm::Sha256 => {
             use sha2::Sha256;
             let mut hasher = Sha256::new();
             hasher.update(data);
             hasher.finalize().to_vec()
         }
+        HashAlgorithm::Sha256d => {
+            use sha2::Sha256;
+            Sha256::digest(Sha256::digest(data)).to_vec()
+        }
         HashAlgorithm::Sha3_256 => {
             use sha3::Sha3_256;
             let mut hasher = Sha3_256::new();
             hasher.update(data);
             hasher.finalize().to_vec()
         }
+        HashAlgorithm::Keccak256 => sha3::Keccak256::digest(data).to_vec(),
+        HashAlgorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+    }
+}
+
+/// Outcome of [`seed_quality`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeedQuality {
+    Ok,
+    /// A known-bad pattern or low byte entropy; import only after the user confirms.
+    Weak,
+    /// All zeros: an erased or unwritten seed, never imported.
+    Refused,
+}
+
+/// Below this many bits of entropy per byte a seed counts as weak.
+///
+/// 32 uniformly random bytes estimate at about 4.8 bits/byte.
+const WEAK_SEED_ENTROPY: f64 = 3.5;
+
+/// Heuristic check of a seed about to be imported.
+///
+/// Flags constant bytes, short repeating patterns, arithmetic runs
+/// (`00 01 02 ...`) and a low Shannon entropy estimate over the byte
+/// histogram. Catches corrupted or placeholder seeds, not weak RNGs.
+pub fn seed_quality(seed: &[u8]) -> SeedQuality {
+    if seed.iter().all(|&b| b == 0) {
+        return SeedQuality::Refused;
+    }
+    let repeats = (1..=4).any(|period| {
+        seed.len() > period && seed.iter().skip(period).zip(seed).all(|(a, b)| a == b)
+    });
+    let arithmetic = seed.len() > 2
+        && seed
+            .windows(2)
+            .all(|w| w[1].wrapping_sub(w[0]) == seed[1].wrapping_sub(seed[0]));
+    if repeats || arithmetic || shannon_entropy(seed) < WEAK_SEED_ENTROPY {
+        return SeedQuality::Weak;
+    }
+    SeedQuality::Ok
+}
+
+/// Shannon entropy of the byte histogram, in bits per byte.
+fn shannon_entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Ed25519ph context string; RFC 8032 allows up to 255 bytes, none are used.
+const ED25519PH_CONTEXT: &[u8] = b"";
+
+/// Check `signature` over `message` (the output of `extract_signable`).
+///
+/// Keys are the 32-byte Ed25519 verifying key (also for Ed25519ph, which
+/// verifies against the SHA-512 of `message`), a SEC1-encoded secp256k1
+/// point (33 or 65 bytes) for ECDSA, and the 32-byte x-only key for BIP-340
+/// Schnorr. ECDSA treats `message` as the prehashed digest, as a secure
+/// element signs it; Schnorr signs it as-is.
+///
+/// A signature that does not verify is `Ok(false)`; only a key that cannot
+/// be decoded or a signature of the wrong length is an error.
+pub fn verify(
+    algorithm: SignAlgorithm,
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, CryptoError> {
+    let invalid_key = || CryptoError::InvalidPublicKey {
+        algorithm,
+        len: public_key.len(),
+    };
+    if signature.len() != algorithm.signature_len() {
+        return Err(CryptoError::InvalidSignatureLength {
+            algorithm,
+            expected: algorithm.signature_len(),
+            len: signature.len(),
+        });
+    }
+    Ok(match algorithm {
+        SignAlgorithm::Ed25519 => {
+            let key: [u8; 32] = public_key.try_into().map_err(|_| invalid_key())?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|_| invalid_key())?;
+            let signature =
+                ed25519_dalek::Signature::from_slice(signature).expect("length checked above");
+            key.verify(message, &signature).is_ok()
+        }
+        SignAlgorithm::Ed25519ph => {
+            let key: [u8; 32] = public_key.try_into().map_err(|_| invalid_key())?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|_| invalid_key())?;
+            let signature =
+                ed25519_dalek::Signature::from_slice(signature).expect("length checked above");
+            key.verify_prehashed(
+                Sha512::new_with_prefix(message),
+                Some(ED25519PH_CONTEXT),
+                &signature,
+            )
+            .is_ok()
+        }
+        SignAlgorithm::Secp256k1Ecdsa => {
+            use k256::ecdsa::signature::hazmat::PrehashVerifier;
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                .map_err(|_| invalid_key())?;
+            k256::ecdsa::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_prehash(message, &signature).is_ok())
+        }
+        SignAlgorithm::Secp256k1EcdsaRecoverable => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                .map_err(|_| invalid_key())?;
+            let (rs, v) = signature.split_at(64);
+            match (
+                k256::ecdsa::Signature::from_slice(rs),
+                k256::ecdsa::RecoveryId::from_byte(v[0]),
+            ) {
+                (Ok(signature), Some(recovery_id)) => {
+                    signature.normalize_s().is_none()
+                        && k256::ecdsa::VerifyingKey::recover_from_prehash(
+                            message,
+                            &signature,
+                            recovery_id,
+                        )
+                        .is_ok_and(|recovered| recovered == key)
+                }
+                _ => false,
+            }
+        }
+        SignAlgorithm::Secp256k1Schnorr => {
+            if public_key.len() != 32 {
+                return Err(invalid_key());
+            }
+            let key =
+                k256::schnorr::VerifyingKey::from_bytes(public_key).map_err(|_| invalid_key())?;
+            k256::schnorr::Signature::try_from(signature)
+                .is_ok_and(|signature| key.verify_raw(message, &signature).is_ok())
+        }
+    })
+}
+
+/// Turn a compact ECDSA `r || s` over `digest` into the 65-byte
+/// `r || s || v` of [`SignAlgorithm::Secp256k1EcdsaRecoverable`].
+///
+/// A secure element signs without reporting the recovery id, so it is found
+/// by trial recovery against the slot's SEC1 `public_key`. A high `s` is
+/// normalized first, as Ethereum rejects it.
+pub fn recoverable_signature(
+    public_key: &[u8],
+    digest: &[u8],
+    signature: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let algorithm = SignAlgorithm::Secp256k1EcdsaRecoverable;
+    let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key).map_err(|_| {
+        CryptoError::InvalidPublicKey {
+            algorithm,
+            len: public_key.len(),
+        }
+    })?;
+    let invalid_length = || CryptoError::InvalidSignatureLength {
+        algorithm: SignAlgorithm::Secp256k1Ecdsa,
+        expected: 64,
+        len: signature.len(),
+    };
+    if signature.len() != 64 {
+        return Err(invalid_length());
+    }
+    let signature = k256::ecdsa::Signature::from_slice(signature).map_err(|_| invalid_length())?;
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let recovery_id =
+        k256::ecdsa::RecoveryId::trial_recovery_from_prehash(&key, digest, &signature)
+            .map_err(|_| CryptoError::Unrecoverable)?;
+    let mut out = signature.to_bytes().to_vec();
+    out.push(recovery_id.to_byte());
+    Ok(out)
+}
+
+/// Signer holding a raw 32-byte seed in memory.
+///
+/// Used for test vectors and the simulator; on the device the seed never
+/// leaves the secure element.
+pub struct SoftwareSigner {
+    seed: [u8; 32],
+}
+
+impl SoftwareSigner {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self { seed }
+    }
+
+    /// The seed as a secp256k1 secret scalar.
+    fn ecdsa_key(&self, algorithm: SignAlgorithm) -> Result<k256::ecdsa::SigningKey, CryptoError> {
+        k256::ecdsa::SigningKey::from_bytes(&self.seed.into())
+            .map_err(|_| CryptoError::InvalidSeed(algorithm))
+    }
+
+    /// Public key for `algorithm` derived from the seed; compressed SEC1
+    /// for ECDSA.
+    pub fn public_key(&self, algorithm: SignAlgorithm) -> Result<Vec<u8>, CryptoError> {
+        match algorithm {
+            SignAlgorithm::Ed25519 | SignAlgorithm::Ed25519ph => {
+                Ok(SigningKey::from_bytes(&self.seed)
+                    .verifying_key()
+                    .to_bytes()
+                    .to_vec())
+            }
+            SignAlgorithm::Secp256k1Ecdsa | SignAlgorithm::Secp256k1EcdsaRecoverable => Ok(self
+                .ecdsa_key(algorithm)?
+                .verifying_key()
+                .to_sec1_bytes()
+                .to_vec()),
+            other => Err(CryptoError::UnsupportedAlgorithm(other)),
+        }
+    }
+
+    /// Sign `message` (the output of `extract_signable`) with `algorithm`.
+    /// ECDSA signs `message` as the prehashed digest.
+    pub fn sign(&self, algorithm: SignAlgorithm, message: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        use k256::ecdsa::signature::hazmat::PrehashSigner;
+        let prehash_err = |_| CryptoError::InvalidDigest(message.len());
+        match algorithm {
+            SignAlgorithm::Ed25519 => Ok(SigningKey::from_bytes(&self.seed)
+                .sign(message)
+                .to_bytes()
+                .to_vec()),
+            SignAlgorithm::Ed25519ph => Ok(SigningKey::from_bytes(&self.seed)
+                .sign_prehashed(Sha512::new_with_prefix(message), Some(ED25519PH_CONTEXT))
+                .expect("the empty context is within the 255-byte limit")
+                .to_bytes()
+                .to_vec()),
+            SignAlgorithm::Secp256k1Ecdsa => {
+                let signature: k256::ecdsa::Signature = self
+                    .ecdsa_key(algorithm)?
+                    .sign_prehash(message)
+                    .map_err(prehash_err)?;
+                Ok(signature.to_bytes().to_vec())
+            }
+            SignAlgorithm::Secp256k1EcdsaRecoverable => {
+                let (signature, recovery_id) = self
+                    .ecdsa_key(algorithm)?
+                    .sign_prehash_recoverable(message)
+                    .map_err(prehash_err)?;
+                let mut out = signature.to_bytes().to_vec();
+                out.push(recovery_id.to_byte());
+                Ok(out)
+            }
+            other => Err(CryptoError::UnsupportedAlgorithm(other)),
+        }
     }
 }
 
@@ -78,11 +616,146 @@ fn hash_bytes(algo: HashAlgorithm, data: &[u8]) -> Vec<u8> {
 mod tests {
     use super::*;
 
+    /// Flip one bit of the signature.
+    fn flipped(signature: &[u8]) -> Vec<u8> {
+        let mut signature = signature.to_vec();
+        signature[10] ^= 0x01;
+        signature
+    }
+
+    #[test]
+    fn ed25519ph_signs_the_sha512_prehash() {
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let key = signer.public_key(SignAlgorithm::Ed25519ph).unwrap();
+        let message = vec![0x5a; 100_000];
+        let sig = signer.sign(SignAlgorithm::Ed25519ph, &message).unwrap();
+        let dalek_key =
+            ed25519_dalek::VerifyingKey::from_bytes(&key.clone().try_into().unwrap()).unwrap();
+        let dalek_sig = ed25519_dalek::Signature::from_slice(&sig).unwrap();
+        dalek_key
+            .verify_prehashed(Sha512::new_with_prefix(&message), Some(b""), &dalek_sig)
+            .unwrap();
+        assert!(verify(SignAlgorithm::Ed25519ph, &key, &message, &sig).unwrap());
+        assert!(!verify(SignAlgorithm::Ed25519ph, &key, &message, &flipped(&sig)).unwrap());
+
+        // The two modes' signatures are not interchangeable.
+        let plain = signer.sign(SignAlgorithm::Ed25519, &message).unwrap();
+        assert!(!verify(SignAlgorithm::Ed25519ph, &key, &message, &plain).unwrap());
+        assert!(!verify(SignAlgorithm::Ed25519, &key, &message, &sig).unwrap());
+    }
+
+    #[test]
+    fn ed25519_signature_verifies_until_tampered() {
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let key = signer.public_key(SignAlgorithm::Ed25519).unwrap();
+        let sig = signer.sign(SignAlgorithm::Ed25519, b"digest").unwrap();
+        assert!(verify(SignAlgorithm::Ed25519, &key, b"digest", &sig).unwrap());
+        assert!(!verify(SignAlgorithm::Ed25519, &key, b"digest", &flipped(&sig)).unwrap());
+        assert!(!verify(SignAlgorithm::Ed25519, &key, b"other", &sig).unwrap());
+    }
+
+    #[test]
+    fn secp256k1_signatures_verify_until_tampered() {
+        use k256::ecdsa::signature::hazmat::PrehashSigner;
+        let digest = hash_bytes(HashAlgorithm::Sha256, b"tx");
+
+        let ecdsa = k256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        let key = ecdsa.verifying_key().to_sec1_bytes();
+        let sig: k256::ecdsa::Signature = ecdsa.sign_prehash(&digest).unwrap();
+        let sig = sig.to_bytes();
+        assert!(verify(SignAlgorithm::Secp256k1Ecdsa, &key, &digest, &sig).unwrap());
+        assert!(!verify(SignAlgorithm::Secp256k1Ecdsa, &key, &digest, &flipped(&sig)).unwrap());
+
+        let schnorr = k256::schnorr::SigningKey::from_bytes(&[7; 32]).unwrap();
+        let key = schnorr.verifying_key().to_bytes();
+        let sig = schnorr.sign_raw(&digest, &[0; 32]).unwrap().to_bytes();
+        assert!(verify(SignAlgorithm::Secp256k1Schnorr, &key, &digest, &sig).unwrap());
+        assert!(!verify(
+            SignAlgorithm::Secp256k1Schnorr,
+            &key,
+            &digest,
+            &flipped(&sig)
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn recoverable_signature_recovers_slot_key() {
+        let algorithm = SignAlgorithm::Secp256k1EcdsaRecoverable;
+        let digest = hash_bytes(HashAlgorithm::Sha3_256, b"tx");
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let key = signer.public_key(algorithm).unwrap();
+        let sig = signer.sign(algorithm, &digest).unwrap();
+        assert_eq!(sig.len(), 65);
+
+        let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+            &digest,
+            &k256::ecdsa::Signature::from_slice(&sig[..64]).unwrap(),
+            k256::ecdsa::RecoveryId::from_byte(sig[64]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(&recovered.to_sec1_bytes()[..], &key[..]);
+        assert!(verify(algorithm, &key, &digest, &sig).unwrap());
+
+        let mut wrong_v = sig.clone();
+        wrong_v[64] ^= 1;
+        assert!(!verify(algorithm, &key, &digest, &wrong_v).unwrap());
+    }
+
+    #[test]
+    fn recovery_id_is_found_for_secure_element_signatures() {
+        let digest = hash_bytes(HashAlgorithm::Sha3_256, b"tx");
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let key = signer.public_key(SignAlgorithm::Secp256k1Ecdsa).unwrap();
+        let expected = signer
+            .sign(SignAlgorithm::Secp256k1EcdsaRecoverable, &digest)
+            .unwrap();
+
+        let rs = signer.sign(SignAlgorithm::Secp256k1Ecdsa, &digest).unwrap();
+        assert_eq!(recoverable_signature(&key, &digest, &rs).unwrap(), expected);
+
+        // The same signature with high `s` is normalized before recovery.
+        let low = k256::ecdsa::Signature::from_slice(&rs).unwrap();
+        let (r, s) = low.split_scalars();
+        let high = k256::ecdsa::Signature::from_scalars(r, -*s).unwrap();
+        assert_eq!(
+            recoverable_signature(&key, &digest, &high.to_bytes()).unwrap(),
+            expected
+        );
+
+        let other = SoftwareSigner::from_seed([8; 32])
+            .public_key(SignAlgorithm::Secp256k1Ecdsa)
+            .unwrap();
+        assert!(matches!(
+            recoverable_signature(&other, &digest, &rs),
+            Err(CryptoError::Unrecoverable)
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_lengths() {
+        let signer = SoftwareSigner::from_seed([7; 32]);
+        let key = signer.public_key(SignAlgorithm::Ed25519).unwrap();
+        let sig = signer.sign(SignAlgorithm::Ed25519, b"m").unwrap();
+        assert!(matches!(
+            verify(SignAlgorithm::Ed25519, &key[..31], b"m", &sig),
+            Err(CryptoError::InvalidPublicKey { len: 31, .. })
+        ));
+        assert!(matches!(
+            verify(SignAlgorithm::Ed25519, &key, b"m", &sig[..63]),
+            Err(CryptoError::InvalidSignatureLength { len: 63, .. })
+        ));
+        assert!(matches!(
+            verify(SignAlgorithm::Secp256k1Schnorr, &[2; 33], b"m", &sig),
+            Err(CryptoError::InvalidPublicKey { len: 33, .. })
+        ));
+    }
+
     #[test]
     fn extract_whole() {
         let payload = b"test payload";
         let result = extract_signable(payload, &Signable::Whole).unwrap();
-        assert_eq!(result, payload);
+        assert_eq!(result, [payload]);
     }
 
     #[test]
@@ -96,7 +769,83 @@ mod tests {
             },
         )
         .unwrap();
-        assert_eq!(result, b"2345");
+        assert_eq!(result, [b"2345"]);
+    }
+
+    #[test]
+    fn extract_multi_in_order() {
+        let signable = Signable::Multi(vec![
+            Signable::Range {
+                offset: 4,
+                length: 2,
+            },
+            Signable::Whole,
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Whole,
+            },
+        ]);
+        let result = extract_signable(b"012345", &signable).unwrap();
+        assert_eq!(
+            result,
+            [
+                b"45".to_vec(),
+                b"012345".to_vec(),
+                hash_bytes(HashAlgorithm::Sha256, b"012345")
+            ]
+        );
+    }
+
+    #[test]
+    fn prefix_comes_before_the_signed_bytes() {
+        let prefixed = |inner| Signable::Prefixed {
+            prefix: b"tag:".to_vec(),
+            inner: Box::new(inner),
+        };
+        let range = Signable::Range {
+            offset: 1,
+            length: 2,
+        };
+        assert_eq!(
+            extract_signable(b"0123", &prefixed(range.clone())).unwrap(),
+            [b"tag:12"]
+        );
+        // Each item of a Multi gets the prefix.
+        let multi = prefixed(Signable::Multi(vec![range, Signable::Whole]));
+        assert_eq!(
+            extract_signable(b"0123", &multi).unwrap(),
+            [b"tag:12".to_vec(), b"tag:0123".to_vec()]
+        );
+    }
+
+    #[test]
+    fn prefix_is_hashed_with_the_payload() {
+        let hashed = Signable::Prefixed {
+            prefix: b"\x19Ethereum Signed Message:\n5".to_vec(),
+            inner: Box::new(Signable::HashThenSign {
+                hash: HashAlgorithm::Keccak256,
+                source: SignableSource::Whole,
+            }),
+        };
+        assert_eq!(
+            extract_signable(b"hello", &hashed).unwrap(),
+            [hash_bytes(
+                HashAlgorithm::Keccak256,
+                b"\x19Ethereum Signed Message:\n5hello"
+            )]
+        );
+        // The prefix is hashed, not prepended to the hash.
+        let keyed = Signable::Prefixed {
+            prefix: b"tag".to_vec(),
+            inner: Box::new(Signable::KeyedHashThenSign {
+                key: b"key".to_vec(),
+                source: SignableSource::Whole,
+            }),
+        };
+        assert_eq!(
+            extract_signable(b"hello", &keyed).unwrap(),
+            [keyed_blake2b256(b"key", b"taghello").unwrap()]
+        );
     }
 
     #[test]
@@ -123,6 +872,303 @@ mod tests {
             },
         )
         .unwrap();
-        assert_eq!(result.len(), 32);
+        assert_eq!(result[0].len(), 32);
+    }
+
+    #[test]
+    fn blake2b_output_lengths() {
+        let lens: Vec<_> = [
+            HashAlgorithm::Blake2b224,
+            HashAlgorithm::Blake2b256,
+            HashAlgorithm::Blake2b512,
+        ]
+        .into_iter()
+        .map(|algo| hash_bytes(algo, b"abc").len())
+        .collect();
+        assert_eq!(lens, [28, 32, 64]);
+    }
+
+    #[test]
+    fn blake2b512_known_answer() {
+        // RFC 7693 Appendix A.
+        assert_eq!(
+            hex::encode(hash_bytes(HashAlgorithm::Blake2b512, b"abc")),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+    }
+
+    #[test]
+    fn keccak256_known_answer() {
+        // Ethereum's empty-string hash (e.g. the code hash of an account
+        // without code); SHA3-256 of "" is a different digest.
+        assert_eq!(
+            hex::encode(hash_bytes(HashAlgorithm::Keccak256, b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_ne!(
+            hash_bytes(HashAlgorithm::Keccak256, b""),
+            hash_bytes(HashAlgorithm::Sha3_256, b"")
+        );
+    }
+
+    #[test]
+    fn sha256d_matches_bitcoin_txid() {
+        // The genesis block's coinbase transaction; a txid is its hash256,
+        // displayed byte-reversed.
+        let tx = hex::decode(
+            "01000000010000000000000000000000000000000000000000000000000000000000000000\
+             ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368\
+             616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f\
+             722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7\
+             105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba\
+             0b8d578a4c702b6bf11d5fac00000000",
+        )
+        .unwrap();
+        let mut txid = hash_bytes(HashAlgorithm::Sha256d, &tx);
+        txid.reverse();
+        assert_eq!(
+            hex::encode(txid),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+    }
+
+    #[test]
+    fn blake3_known_answer() {
+        // From the BLAKE3 reference implementation's test vectors.
+        assert_eq!(
+            hex::encode(hash_bytes(HashAlgorithm::Blake3, b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[test]
+    fn keyed_blake2b_known_answer() {
+        // Cross-checked with Python's hashlib.blake2b(key=..., digest_size=32).
+        let key: Vec<u8> = (0..32).collect();
+        let result = extract_signable(
+            b"hash me",
+            &Signable::KeyedHashThenSign {
+                key,
+                source: SignableSource::Whole,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&result[0]),
+            "b263d490ba5f2e3f4aa3708dc2e11d153c9f063cee29e3f1c9f5a63771dd738e"
+        );
+    }
+
+    #[test]
+    fn keyed_blake2b_rejects_bad_keys() {
+        assert!(matches!(
+            keyed_blake2b256(&[], b"x"),
+            Err(CryptoError::InvalidMacKey(0))
+        ));
+        assert!(matches!(
+            keyed_blake2b256(&[0; 65], b"x"),
+            Err(CryptoError::InvalidMacKey(65))
+        ));
+    }
+
+    fn leaf_list(leaves: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for leaf in leaves {
+            out.extend_from_slice(&(leaf.len() as u32).to_le_bytes());
+            out.extend_from_slice(leaf);
+        }
+        out
+    }
+
+    #[test]
+    fn merkle_root_rfc6962_vectors() {
+        // Leaves and roots from the certificate-transparency reference tests.
+        let leaves: [&[u8]; 8] = [
+            b"",
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+            b"\x50\x51\x52\x53\x54\x55\x56\x57",
+            b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+        ];
+        let signable = Signable::MerkleRoot {
+            hash: HashAlgorithm::Sha256,
+        };
+
+        let root = extract_signable(&leaf_list(&leaves), &signable).unwrap();
+        assert_eq!(
+            hex::encode(&root[0]),
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328"
+        );
+        let root = extract_signable(&leaf_list(&leaves[..3]), &signable).unwrap();
+        assert_eq!(
+            hex::encode(&root[0]),
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77"
+        );
+    }
+
+    #[test]
+    fn merkle_leaf_list_is_bounds_checked() {
+        // Length prefix cut short.
+        assert!(matches!(
+            parse_leaves(&[1, 0, 0, 0, 0xaa, 2, 0]),
+            Err(CryptoError::MalformedLeafList { offset: 5 })
+        ));
+        // Leaf runs past the end of the payload.
+        assert!(matches!(
+            parse_leaves(&[9, 0, 0, 0, 0xaa]),
+            Err(CryptoError::MalformedLeafList { offset: 0 })
+        ));
+        // Length that would overflow the offset arithmetic.
+        assert!(matches!(
+            parse_leaves(&[0xff, 0xff, 0xff, 0xff]),
+            Err(CryptoError::MalformedLeafList { offset: 0 })
+        ));
+        assert!(matches!(parse_leaves(&[]), Err(CryptoError::EmptyLeafList)));
+    }
+
+    #[test]
+    fn all_zero_seed_is_refused() {
+        assert_eq!(seed_quality(&[0u8; 32]), SeedQuality::Refused);
+    }
+
+    #[test]
+    fn low_entropy_seeds_are_weak() {
+        assert_eq!(seed_quality(&[0xFF; 32]), SeedQuality::Weak);
+        assert_eq!(seed_quality(&[0xDE, 0xAD].repeat(16)), SeedQuality::Weak);
+        let counting: Vec<u8> = (0..32).collect();
+        assert_eq!(seed_quality(&counting), SeedQuality::Weak);
+        let mut mostly_zero = [0u8; 32];
+        mostly_zero[..4].copy_from_slice(&[9, 1, 7, 3]);
+        assert_eq!(seed_quality(&mostly_zero), SeedQuality::Weak);
+    }
+
+    #[test]
+    fn random_seed_is_ok() {
+        for i in 0..64u8 {
+            let seed = hash_bytes(HashAlgorithm::Blake2b256, &[i]);
+            assert_eq!(
+                seed_quality(&seed),
+                SeedQuality::Ok,
+                "{}",
+                hex::encode(&seed)
+            );
+        }
+    }
+
+    #[test]
+    fn passphrase_changes_derived_seed() {
+        let seed = [7u8; 32];
+        assert_eq!(derive_seed(&seed, b""), seed);
+        let a = derive_seed(&seed, b"correct horse");
+        let b = derive_seed(&seed, b"battery staple");
+        assert_ne!(a, seed);
+        assert_ne!(a, b);
+        assert_eq!(a, derive_seed(&seed, b"correct horse"));
+    }
+
+    #[test]
+    fn stream_hasher_matches_extract_signable() {
+        let payload: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let range = SignableSource::Range {
+            offset: 100,
+            length: 500,
+        };
+        let modes = [
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Blake2b256,
+                source: SignableSource::Whole,
+            },
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Sha3_256,
+                source: range.clone(),
+            },
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256d,
+                source: range.clone(),
+            },
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Blake3,
+                source: SignableSource::Whole,
+            },
+            Signable::KeyedHashThenSign {
+                key: b"key".to_vec(),
+                source: range.clone(),
+            },
+            Signable::Prefixed {
+                prefix: b"\x19tag".to_vec(),
+                inner: Box::new(Signable::HashThenSign {
+                    hash: HashAlgorithm::Keccak256,
+                    source: range,
+                }),
+            },
+        ];
+        for signable in modes {
+            let mut hasher = StreamHasher::for_signable(&signable).unwrap().unwrap();
+            for chunk in payload.chunks(64) {
+                hasher.update(chunk);
+            }
+            assert_eq!(
+                hasher.finalize().unwrap(),
+                extract_signable(&payload, &signable).unwrap()[0],
+                "{signable:?}"
+            );
+        }
+        assert!(StreamHasher::for_signable(&Signable::Whole)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn stream_hasher_checks_range_against_payload_length() {
+        let signable = Signable::HashThenSign {
+            hash: HashAlgorithm::Sha256,
+            source: SignableSource::Range {
+                offset: 4,
+                length: 10,
+            },
+        };
+        let mut hasher = StreamHasher::for_signable(&signable).unwrap().unwrap();
+        hasher.update(b"short");
+        assert!(matches!(
+            hasher.finalize(),
+            Err(CryptoError::RangeOutOfBounds { payload_len: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn overflowing_range_is_an_error() {
+        let range = SignableSource::Range {
+            offset: usize::MAX,
+            length: 1,
+        };
+        let overflow = |r: Result<_, CryptoError>| {
+            matches!(
+                r,
+                Err(CryptoError::RangeOverflow {
+                    offset: usize::MAX,
+                    length: 1
+                })
+            )
+        };
+        assert!(overflow(
+            extract_signable(
+                b"payload",
+                &Signable::Range {
+                    offset: usize::MAX,
+                    length: 1
+                }
+            )
+            .map(drop)
+        ));
+        let hashed = Signable::HashThenSign {
+            hash: HashAlgorithm::Sha256,
+            source: range,
+        };
+        assert!(overflow(extract_signable(b"payload", &hashed).map(drop)));
+        assert!(overflow(StreamHasher::for_signable(&hashed).map(drop)));
     }
 }
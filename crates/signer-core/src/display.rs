@@ -1,22 +1,223 @@
+use serde::Deserialize;
 use serde_json::Value;
+use std::collections::HashMap;
 
 /// A line in the display layout.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct DisplayLine {
     pub indent: usize,
     pub key: Option<String>,
     pub value: String,
+    pub emphasis: LineEmphasis,
+}
+
+/// How strongly a line should stand out during review.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LineEmphasis {
+    #[default]
+    Normal,
+    /// Worth a second look, e.g. an unusually high fee.
+    Warning,
+    /// Likely to lose funds if wrong, e.g. change sent to a foreign address.
+    Danger,
+}
+
+impl DisplayLine {
+    /// This line with its value shortened by [`abbreviate`], for lists where
+    /// the full value is shown on request.
+    pub fn abbreviated(&self, head: usize, tail: usize) -> DisplayLine {
+        DisplayLine {
+            value: abbreviate(&self.value, head, tail),
+            ..self.clone()
+        }
+    }
+}
+
+/// Shorten `value` to its first `head` and last `tail` characters around an
+/// ellipsis, e.g. a hash as `abcd…wxyz`. Values no longer than
+/// `head + tail` characters are returned unchanged.
+pub fn abbreviate(value: &str, head: usize, tail: usize) -> String {
+    let len = value.chars().count();
+    if len <= head + tail {
+        return value.to_string();
+    }
+    let start: String = value.chars().take(head).collect();
+    let end: String = value.chars().skip(len - tail).collect();
+    format!("{start}\u{2026}{end}")
+}
+
+/// Prefix marking a warned line in text renderings.
+pub const WARNING_PREFIX: &str = "!! ";
+
+/// Render a review line as indented `key: value` text, prefixed with
+/// [`WARNING_PREFIX`] unless its emphasis is normal.
+pub fn format_line(line: &DisplayLine) -> String {
+    let mut pad = match line.emphasis {
+        LineEmphasis::Normal => String::new(),
+        LineEmphasis::Warning | LineEmphasis::Danger => WARNING_PREFIX.into(),
+    };
+    pad.push_str(&"  ".repeat(line.indent));
+    match &line.key {
+        Some(k) if line.value.is_empty() => format!("{pad}{k}:"),
+        Some(k) => format!("{pad}{k}: {}", line.value),
+        None => format!("{pad}{}", line.value),
+    }
+}
+
+/// Key an interpreter sets on list items (e.g. transaction outputs) to
+/// categorize them; categorized lists are grouped and summarized for review.
+pub const CATEGORY_KEY: &str = "_category";
+
+/// Key flagging a risky field. An object `{"_warn": true, "value": v}` is
+/// shown as `v` with [`LineEmphasis::Warning`]; `"_warn": "danger"` gives
+/// [`LineEmphasis::Danger`].
+pub const WARN_KEY: &str = "_warn";
+
+/// Display hints an interpreter can ship alongside its output.
+///
+/// Fields are addressed by dotted object-key paths from the root; array
+/// levels are skipped, so `outputs.amount` matches every output's amount.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct DisplaySchema {
+    #[serde(default)]
+    pub fields: HashMap<String, FieldSchema>,
+    /// Show integers without a field format in groups of three digits,
+    /// e.g. `1,000,000`. Off by default so numeric hashes and IDs stay
+    /// intact.
+    #[serde(default)]
+    pub group_digits: bool,
+}
+
+/// How to show one field.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FieldSchema {
+    /// Replaces the JSON key on screen.
+    pub label: Option<String>,
+    #[serde(default)]
+    pub format: FieldFormat,
+}
+
+/// Value formatting for a schema field.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldFormat {
+    /// Shown as-is.
+    #[default]
+    Text,
+    /// Integer shown as `0x`-prefixed hex.
+    Hex,
+    /// Integer count of base units shown with a decimal point, e.g.
+    /// 1500000 with 6 decimals as `1.500000 ADA`. At most
+    /// [`MAX_DECIMALS`] decimals.
+    Amount {
+        #[serde(deserialize_with = "bounded_decimals")]
+        decimals: u32,
+        unit: Option<String>,
+    },
+    /// String split into groups of four characters for easier comparison.
+    Address,
+}
+
+/// Most decimals an amount may have: a `u128` of base units has 39 digits,
+/// and a larger count would only pad the value with zeros.
+pub const MAX_DECIMALS: u32 = 38;
+
+fn bounded_decimals<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let decimals = u32::deserialize(deserializer)?;
+    if decimals > MAX_DECIMALS {
+        return Err(serde::de::Error::custom(format!(
+            "amount has {decimals} decimals, more than {MAX_DECIMALS}"
+        )));
+    }
+    Ok(decimals)
 }
 
 /// Convert a JSON value into a flat list of display lines
 /// suitable for rendering on a simple framebuffer.
 pub fn json_to_lines(value: &Value) -> Vec<DisplayLine> {
+    json_to_lines_with_schema(value, &DisplaySchema::default())
+}
+
+/// [`json_to_lines`] with every object's keys in sorted order, so equal
+/// values always give the same lines whatever order the interpreter emitted
+/// their fields in.
+pub fn json_to_lines_sorted(value: &Value) -> Vec<DisplayLine> {
+    json_to_lines(&sort_keys(value))
+}
+
+/// `value` with every object's keys inserted in sorted order.
+pub fn sort_keys(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(k, _)| *k);
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.clone(), sort_keys(v)))
+                    .collect(),
+            )
+        }
+        Value::Array(arr) => Value::Array(arr.iter().map(sort_keys).collect()),
+        other => other.clone(),
+    }
+}
+
+/// [`json_to_lines`], relabelling and formatting fields the schema names.
+pub fn json_to_lines_with_schema(value: &Value, schema: &DisplaySchema) -> Vec<DisplayLine> {
     let mut lines = Vec::new();
-    flatten(value, 0, None, &mut lines);
+    flatten(value, 0, None, "", schema, &mut lines);
     lines
 }
 
-fn flatten(value: &Value, indent: usize, key: Option<&str>, out: &mut Vec<DisplayLine>) {
+/// [`json_to_lines`] with values longer than `width` characters split over
+/// continuation lines, which have no key and the same indent.
+pub fn json_to_lines_wrapped(value: &Value, width: usize) -> Vec<DisplayLine> {
+    json_to_lines(value)
+        .into_iter()
+        .flat_map(|line| {
+            let indent = line.indent;
+            let emphasis = line.emphasis;
+            let mut key = line.key;
+            wrap_text(&line.value, width)
+                .into_iter()
+                .map(move |chunk| DisplayLine {
+                    indent,
+                    key: key.take(),
+                    value: chunk,
+                    emphasis,
+                })
+        })
+        .collect()
+}
+
+/// Length of the longest array anywhere in `value`, 0 if there is none.
+pub fn largest_array(value: &Value) -> usize {
+    match value {
+        Value::Array(arr) => arr.iter().map(largest_array).fold(arr.len(), usize::max),
+        Value::Object(map) => map.values().map(largest_array).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn flatten(
+    value: &Value,
+    indent: usize,
+    key: Option<&str>,
+    path: &str,
+    schema: &DisplaySchema,
+    out: &mut Vec<DisplayLine>,
+) {
+    if let Some((emphasis, inner)) = warned(value) {
+        let start = out.len();
+        flatten(inner, indent, key, path, schema, out);
+        for line in &mut out[start..] {
+            line.emphasis = line.emphasis.max(emphasis);
+        }
+        return;
+    }
+    let field = schema.fields.get(path);
+    let key = field.and_then(|f| f.label.as_deref()).or(key);
     match value {
         Value::Object(map) => {
             if let Some(k) = key {
@@ -24,22 +225,45 @@ fn flatten(value: &Value, indent: usize, key: Option<&str>, out: &mut Vec<Displa
                     indent,
                     key: Some(k.into()),
                     value: String::new(),
+                    ..Default::default()
                 });
             }
             for (k, v) in map {
-                flatten(v, indent + 1, Some(k), out);
+                let child = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{path}.{k}")
+                };
+                flatten(v, indent + 1, Some(k), &child, schema, out);
             }
         }
         Value::Array(arr) => {
+            let groups = group_by_category(arr);
             if let Some(k) = key {
                 out.push(DisplayLine {
                     indent,
                     key: Some(k.into()),
-                    value: format!("[{} items]", arr.len()),
+                    value: match &groups {
+                        Some(groups) => summarize_groups(groups),
+                        None => format!("[{} items]", arr.len()),
+                    },
+                    ..Default::default()
                 });
             }
-            for (i, v) in arr.iter().enumerate() {
-                flatten(v, indent + 1, Some(&format!("[{i}]")), out);
+            match groups {
+                Some(groups) => {
+                    for (category, items) in groups {
+                        for (i, item) in items {
+                            let key = format!("[{i}] {category}");
+                            flatten(&item, indent + 1, Some(&key), path, schema, out);
+                        }
+                    }
+                }
+                None => {
+                    for (i, v) in arr.iter().enumerate() {
+                        flatten(v, indent + 1, Some(&format!("[{i}]")), path, schema, out);
+                    }
+                }
             }
         }
         _ => {
@@ -50,29 +274,310 @@ fn flatten(value: &Value, indent: usize, key: Option<&str>, out: &mut Vec<Displa
                 Value::Null => "null".into(),
                 _ => unreachable!(),
             };
+            let text = match field {
+                Some(f) => format_value(&text, &f.format),
+                None if schema.group_digits && (value.is_i64() || value.is_u64()) => {
+                    group_thousands(&text)
+                }
+                None => text,
+            };
             out.push(DisplayLine {
                 indent,
                 key: key.map(Into::into),
                 value: text,
+                ..Default::default()
             });
         }
     }
 }
 
+/// The emphasis and wrapped value of a [`WARN_KEY`] object, or `None` for
+/// any other value.
+fn warned(value: &Value) -> Option<(LineEmphasis, &Value)> {
+    let map = value.as_object()?;
+    if map.len() != 2 {
+        return None;
+    }
+    let emphasis = match map.get(WARN_KEY)? {
+        Value::Bool(true) => LineEmphasis::Warning,
+        Value::String(s) if s == "danger" => LineEmphasis::Danger,
+        _ => return None,
+    };
+    Some((emphasis, map.get("value")?))
+}
+
+/// Items of one category, with their original indices and the category key removed.
+type CategoryGroup<'a> = (&'a str, Vec<(usize, Value)>);
+
+/// Group list items by their [`CATEGORY_KEY`], in order of first appearance.
+///
+/// Returns `None` unless every item is an object with a string category.
+fn group_by_category(arr: &[Value]) -> Option<Vec<CategoryGroup<'_>>> {
+    if arr.is_empty() {
+        return None;
+    }
+    let mut groups: Vec<CategoryGroup<'_>> = Vec::new();
+    for (i, item) in arr.iter().enumerate() {
+        let map = item.as_object()?;
+        let category = map.get(CATEGORY_KEY)?.as_str()?;
+        let mut rest = map.clone();
+        rest.remove(CATEGORY_KEY);
+        let item = (i, Value::Object(rest));
+        match groups.iter_mut().find(|(c, _)| *c == category) {
+            Some((_, items)) => items.push(item),
+            None => groups.push((category, vec![item])),
+        }
+    }
+    Some(groups)
+}
+
+/// "3 recipients, 1 change"
+fn summarize_groups(groups: &[CategoryGroup<'_>]) -> String {
+    groups
+        .iter()
+        .map(|(category, items)| match items.len() {
+            1 => format!("1 {category}"),
+            n => format!("{n} {category}s"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// An integer's digits in groups of three: `-1234567` as `-1,234,567`.
+fn group_thousands(text: &str) -> String {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let mut out = String::from(sign);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// Apply a schema format to a scalar's text; values that do not fit the
+/// format are shown unchanged.
+fn format_value(text: &str, format: &FieldFormat) -> String {
+    match format {
+        FieldFormat::Text => text.to_string(),
+        FieldFormat::Hex => match text.parse::<u128>() {
+            Ok(n) => format!("{n:#x}"),
+            Err(_) => text.to_string(),
+        },
+        FieldFormat::Amount { decimals, unit } => {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(rest) => ("-", rest),
+                None => ("", text),
+            };
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return text.to_string();
+            }
+            let decimals = *decimals as usize;
+            let padded = format!("{digits:0>width$}", width = decimals + 1);
+            let (whole, frac) = padded.split_at(padded.len() - decimals);
+            let mut out = format!("{sign}{whole}");
+            if decimals > 0 {
+                out.push('.');
+                out.push_str(frac);
+            }
+            if let Some(unit) = unit {
+                out.push(' ');
+                out.push_str(unit);
+            }
+            out
+        }
+        FieldFormat::Address => {
+            let chars: Vec<char> = text.chars().collect();
+            chars
+                .chunks(4)
+                .map(|c| c.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    }
+}
+
+/// Split `text` into chunks of at most `width` characters.
+///
+/// A character is never separated from the combining marks, variation
+/// selectors or zero-width-joined characters that follow it, so a chunk
+/// may run over `width` to keep such a cluster whole.
+/// Always returns at least one (possibly empty) chunk.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    if text.is_empty() || width == 0 {
+        return vec![text.to_string()];
+    }
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut clusters = 0;
+    let mut joined = false;
+    for c in text.chars() {
+        let extends = joined || extends_cluster(c);
+        joined = c == '\u{200D}';
+        if !extends {
+            if clusters == width {
+                chunks.push(std::mem::take(&mut chunk));
+                clusters = 0;
+            }
+            clusters += 1;
+        }
+        chunk.push(c);
+    }
+    chunks.push(chunk);
+    chunks
+}
+
+/// Whether `c` belongs to the character before it: a combining mark,
+/// variation selector, or zero width joiner.
+fn extends_cluster(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{200D}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FE20}'..='\u{FE2F}'
+            | '\u{1F3FB}'..='\u{1F3FF}'
+            | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+/// Hardened-derivation flag on a BIP32 path component.
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Format a derivation path as `m/44'/1815'/0'/0/5`.
+pub fn format_path(path: &[u32]) -> String {
+    let mut out = String::from("m");
+    for &c in path {
+        if c & HARDENED != 0 {
+            out.push_str(&format!("/{}'", c & !HARDENED));
+        } else {
+            out.push_str(&format!("/{c}"));
+        }
+    }
+    out
+}
+
+/// Format a derivation path for review: the whole path as [`format_path`]
+/// writes it, followed by the account branch it selects, e.g.
+/// `"m/44'/1815'/0'/0/5 (account 0, external, index 5)"`.
+///
+/// Absolute paths (`purpose'/coin'/account'/role/index`) and paths relative
+/// to an account (`role/index`) are recognised; anything else is the path
+/// alone.
+pub fn describe_path(path: &[u32]) -> String {
+    fn component(c: u32) -> String {
+        if c & HARDENED != 0 {
+            format!("{}'", c & !HARDENED)
+        } else {
+            c.to_string()
+        }
+    }
+    fn branch(role: u32, index: u32) -> String {
+        let role = match role {
+            0 => "external".to_string(),
+            1 => "change".to_string(),
+            2 => "staking".to_string(),
+            other => format!("role {}", component(other)),
+        };
+        format!("{role}, index {}", component(index))
+    }
+    let branch = match *path {
+        [_, _, account, role, index] if account & HARDENED != 0 => {
+            format!("account {}, {}", account & !HARDENED, branch(role, index))
+        }
+        [role, index] => branch(role, index),
+        _ => return format_path(path),
+    };
+    format!("{} ({branch})", format_path(path))
+}
+
 /// Render display lines to a plain-text string (for terminal / testing).
 pub fn render_text(lines: &[DisplayLine]) -> String {
     let mut out = String::new();
     for line in lines {
-        let pad = "  ".repeat(line.indent);
-        match &line.key {
-            Some(k) if line.value.is_empty() => out.push_str(&format!("{pad}{k}:\n")),
-            Some(k) => out.push_str(&format!("{pad}{k}: {}\n", line.value)),
-            None => out.push_str(&format!("{pad}{}\n", line.value)),
-        }
+        out.push_str(&format_line(line));
+        out.push('\n');
     }
     out
 }
 
+/// Text grid of a physical display, in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayGeometry {
+    pub cols: usize,
+    pub rows: usize,
+}
+
+/// The text rows a `geometry`-sized display shows for `lines` scrolled to
+/// `scroll_offset`: the lines from the offset on, each cut to `cols`
+/// characters, padded with empty rows to exactly `rows` strings.
+pub fn layout(
+    lines: &[DisplayLine],
+    geometry: DisplayGeometry,
+    scroll_offset: usize,
+) -> Vec<String> {
+    let mut rows: Vec<String> = lines
+        .iter()
+        .skip(scroll_offset)
+        .take(geometry.rows)
+        .map(|line| format_line(line).chars().take(geometry.cols).collect())
+        .collect();
+    rows.resize(geometry.rows, String::new());
+    rows
+}
+
+/// Where the visible window sits within a scrolled list of lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollIndicator {
+    /// 1-based index of the line at the scroll offset.
+    pub line: usize,
+    /// Total number of lines (at least 1).
+    pub total: usize,
+    /// First track cell covered by the scrollbar thumb.
+    pub thumb_start: usize,
+    /// Number of track cells covered by the thumb.
+    pub thumb_len: usize,
+}
+
+impl ScrollIndicator {
+    /// Short "line X of Y" text for headers.
+    pub fn label(&self) -> String {
+        format!("line {} of {}", self.line, self.total)
+    }
+}
+
+/// Compute the scroll indicator for `total` lines shown on a `track`-row viewport.
+///
+/// The thumb shrinks proportionally to the visible fraction and reaches the
+/// bottom of the track when `scroll_offset` is the last line. A track with
+/// no rows gets no thumb.
+pub fn scroll_indicator(scroll_offset: usize, total: usize, track: usize) -> ScrollIndicator {
+    let total = total.max(1);
+    let scroll_offset = scroll_offset.min(total - 1);
+    let thumb_len = if total <= track {
+        track
+    } else {
+        (track * track / total).max(1).min(track)
+    };
+    let max_scroll = total - 1;
+    let thumb_start = ((track - thumb_len) * scroll_offset)
+        .checked_div(max_scroll)
+        .unwrap_or(0);
+    ScrollIndicator {
+        line: scroll_offset + 1,
+        total,
+        thumb_start,
+        thumb_len,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,6 +591,18 @@ mod tests {
         assert!(lines.iter().any(|l| l.key.as_deref() == Some("amount")));
     }
 
+    #[test]
+    fn sorted_lines_ignore_field_order() {
+        let a: Value =
+            serde_json::from_str(r#"{"to": "addr1", "tx": {"fee": 2, "amount": 1}}"#).unwrap();
+        let b: Value =
+            serde_json::from_str(r#"{"tx": {"amount": 1, "fee": 2}, "to": "addr1"}"#).unwrap();
+        let lines = json_to_lines_sorted(&a);
+        assert_eq!(lines, json_to_lines_sorted(&b));
+        let keys: Vec<_> = lines.iter().filter_map(|l| l.key.as_deref()).collect();
+        assert_eq!(keys, ["to", "tx", "amount", "fee"]);
+    }
+
     #[test]
     fn nested_object() {
         let val = json!({"tx": {"to": "addr1", "value": "5 ADA"}});
@@ -95,6 +612,47 @@ mod tests {
         assert!(text.contains("  to: addr1"));
     }
 
+    #[test]
+    fn largest_array_looks_at_every_level() {
+        assert_eq!(largest_array(&json!({"fee": 1})), 0);
+        let val = json!({"inputs": [1, 2], "body": {"outputs": [[1, 2, 3, 4], 5, 6]}});
+        assert_eq!(largest_array(&val), 4);
+    }
+
+    #[test]
+    fn categorized_outputs_are_grouped_and_summarized() {
+        let val = json!({"outputs": [
+            {"_category": "recipient", "amount": 10},
+            {"_category": "change", "amount": 4},
+            {"_category": "recipient", "amount": 20},
+            {"_category": "fee", "amount": 1},
+        ]});
+        let lines: Vec<_> = json_to_lines(&val)
+            .into_iter()
+            .map(|l| (l.key.unwrap_or_default(), l.value))
+            .collect();
+        let expected = [
+            ("outputs", "2 recipients, 1 change, 1 fee"),
+            ("[0] recipient", ""),
+            ("amount", "10"),
+            ("[2] recipient", ""),
+            ("amount", "20"),
+            ("[1] change", ""),
+            ("amount", "4"),
+            ("[3] fee", ""),
+            ("amount", "1"),
+        ];
+        assert_eq!(lines, expected.map(|(k, v)| (k.to_string(), v.to_string())));
+    }
+
+    #[test]
+    fn partly_categorized_list_is_not_grouped() {
+        let val = json!({"outputs": [{"_category": "change"}, {"amount": 1}]});
+        let lines = json_to_lines(&val);
+        assert_eq!(lines[0].value, "[2 items]");
+        assert_eq!(lines[1].key.as_deref(), Some("[0]"));
+    }
+
     #[test]
     fn array_values() {
         let val = json!({"outputs": [{"addr": "a"}, {"addr": "b"}]});
@@ -102,4 +660,288 @@ mod tests {
         let text = render_text(&lines);
         assert!(text.contains("[2 items]"));
     }
+
+    #[test]
+    fn scroll_indicator_tracks_position() {
+        let top = scroll_indicator(0, 100, 10);
+        assert_eq!(top.label(), "line 1 of 100");
+        assert_eq!((top.thumb_start, top.thumb_len), (0, 1));
+
+        let middle = scroll_indicator(49, 100, 10);
+        assert_eq!(middle.line, 50);
+        assert!(middle.thumb_start > 0 && middle.thumb_start < 9);
+
+        let bottom = scroll_indicator(99, 100, 10);
+        assert_eq!(bottom.label(), "line 100 of 100");
+        assert_eq!(bottom.thumb_start + bottom.thumb_len, 10);
+    }
+
+    #[test]
+    fn scroll_indicator_fits_on_screen() {
+        let ind = scroll_indicator(0, 3, 10);
+        assert_eq!((ind.thumb_start, ind.thumb_len), (0, 10));
+        assert_eq!(scroll_indicator(0, 0, 10).label(), "line 1 of 1");
+    }
+
+    #[test]
+    fn scroll_indicator_without_a_track() {
+        let ind = scroll_indicator(5, 10, 0);
+        assert_eq!((ind.thumb_start, ind.thumb_len), (0, 0));
+        assert_eq!(ind.label(), "line 6 of 10");
+    }
+
+    #[test]
+    fn wrap_text_chunks() {
+        assert_eq!(wrap_text("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(wrap_text("", 3), vec![""]);
+        assert_eq!(wrap_text("héllo", 2), vec!["hé", "ll", "o"]);
+        // "e" + combining acute, and a ZWJ family emoji, stay whole.
+        assert_eq!(wrap_text("ae\u{301}b", 1), vec!["a", "e\u{301}", "b"]);
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(wrap_text(&format!("x{family}y"), 1), vec!["x", family, "y"]);
+    }
+
+    #[test]
+    fn abbreviate_keeps_head_and_tail() {
+        let hash = "0123456789abcdef".repeat(4);
+        assert_eq!(abbreviate(&hash, 4, 4), "0123\u{2026}cdef");
+        assert_eq!(abbreviate("short", 4, 4), "short");
+        assert_eq!(abbreviate("abcdwxyz", 4, 4), "abcdwxyz");
+        assert_eq!(abbreviate("abcdewxyz", 4, 4), "abcd\u{2026}wxyz");
+        assert_eq!(abbreviate("äöüßéèêë!", 2, 2), "äö\u{2026}ë!");
+
+        let line = DisplayLine {
+            indent: 1,
+            key: Some("hash".into()),
+            value: hash,
+            ..Default::default()
+        };
+        let short = line.abbreviated(4, 4);
+        assert_eq!(short.key, line.key);
+        assert_eq!(short.indent, 1);
+        assert_eq!(short.value, "0123\u{2026}cdef");
+    }
+
+    #[test]
+    fn layout_fits_geometry() {
+        let geometry = DisplayGeometry { cols: 16, rows: 4 };
+        let lines = json_to_lines(&json!({
+            "amount": 42,
+            "to": "addr1qxyzabcdefghijklmnop",
+            "fee": 1,
+            "memo": "hi",
+            "ttl": 99,
+        }));
+        assert!(lines.len() > geometry.rows);
+
+        let rows = layout(&lines, geometry, 0);
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|r| r.chars().count() <= 16));
+        assert_eq!(rows[0], format_line(&lines[0]));
+        assert_eq!(rows[3], "  to: addr1qxyza");
+
+        // Scrolled near the end, the rows past the last line are blank.
+        let rows = layout(&lines, geometry, 3);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[..2], ["  to: addr1qxyza", "  ttl: 99"]);
+        assert_eq!(rows[2..], ["", ""]);
+    }
+
+    #[test]
+    fn long_values_wrap_onto_continuation_lines() {
+        let address = "a".repeat(100);
+        assert_eq!(json_to_lines_wrapped(&json!({"to": address}), 20).len(), 5);
+        let lines = json_to_lines_wrapped(&json!({"tx": {"to": address}}), 20);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1].key.as_deref(), Some("to"));
+        assert!(lines[1..].iter().all(|l| l.indent == lines[1].indent));
+        assert!(lines[2..].iter().all(|l| l.key.is_none()));
+        let rejoined: String = lines[1..].iter().map(|l| l.value.as_str()).collect();
+        assert_eq!(rejoined, address);
+
+        let lines = json_to_lines_wrapped(&json!({"memo": "ünïcödé✓"}), 3);
+        let values: Vec<_> = lines.iter().map(|l| l.value.as_str()).collect();
+        assert_eq!(values, ["ünï", "cöd", "é✓"]);
+    }
+
+    #[test]
+    fn warn_convention_sets_emphasis() {
+        let val = json!({
+            "fee": {"_warn": true, "value": 900000},
+            "change": {"_warn": "danger", "value": {"address": "addr1x"}},
+            "to": "addr1y",
+        });
+        let lines = json_to_lines(&val);
+        let emphasis = |key: &str| {
+            lines
+                .iter()
+                .find(|l| l.key.as_deref() == Some(key))
+                .unwrap()
+                .emphasis
+        };
+        assert_eq!(emphasis("fee"), LineEmphasis::Warning);
+        assert_eq!(emphasis("change"), LineEmphasis::Danger);
+        assert_eq!(emphasis("address"), LineEmphasis::Danger);
+        assert_eq!(emphasis("to"), LineEmphasis::Normal);
+
+        let text = render_text(&lines);
+        assert!(text.contains("!!   fee: 900000\n"));
+        assert!(text.contains("\n  to: addr1y\n"));
+    }
+
+    #[test]
+    fn plain_json_stays_normal() {
+        // Objects merely containing the key, or with other fields, are not
+        // the convention.
+        let val = json!({
+            "a": {"_warn": false, "value": 1},
+            "b": {"_warn": true, "value": 2, "extra": 3},
+            "c": [1, {"d": "x"}],
+        });
+        let lines = json_to_lines(&val);
+        assert!(lines.iter().all(|l| l.emphasis == LineEmphasis::Normal));
+        assert!(!render_text(&lines).contains(WARNING_PREFIX));
+    }
+
+    const H: u32 = HARDENED;
+
+    #[test]
+    fn describe_bip44_paths() {
+        assert_eq!(
+            describe_path(&[44 | H, 1815 | H, H, 0, 5]),
+            "m/44'/1815'/0'/0/5 (account 0, external, index 5)"
+        );
+        assert_eq!(
+            describe_path(&[44 | H, H, 3 | H, 1, 7]),
+            "m/44'/0'/3'/1/7 (account 3, change, index 7)"
+        );
+        assert_eq!(
+            describe_path(&[1852 | H, 1815 | H, H, 2, 0]),
+            "m/1852'/1815'/0'/2/0 (account 0, staking, index 0)"
+        );
+        // Coin types differ even where the branch is the same.
+        assert_ne!(
+            describe_path(&[44 | H, 1815 | H, H, 0, 5]),
+            describe_path(&[44 | H, 501 | H, H, 0, 5])
+        );
+    }
+
+    #[test]
+    fn describe_relative_path() {
+        assert_eq!(describe_path(&[0, 5]), "m/0/5 (external, index 5)");
+        assert_eq!(describe_path(&[1, 2 | H]), "m/1/2' (change, index 2')");
+    }
+
+    #[test]
+    fn describe_unrecognised_path() {
+        assert_eq!(describe_path(&[44 | H, 1815 | H, H]), "m/44'/1815'/0'");
+        assert_eq!(format_path(&[]), "m");
+    }
+
+    fn schema(value: Value) -> DisplaySchema {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn schema_refuses_excessive_decimals() {
+        let amount = |decimals: u32| {
+            serde_json::from_value::<DisplaySchema>(json!({
+                "fields": { "fee": { "format": { "type": "amount", "decimals": decimals } } }
+            }))
+        };
+        assert!(amount(MAX_DECIMALS).is_ok());
+        assert!(amount(MAX_DECIMALS + 1).is_err());
+        assert!(amount(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn schema_formats_amount() {
+        let schema = schema(json!({
+            "fields": {
+                "fee": {
+                    "label": "Fee",
+                    "format": { "type": "amount", "decimals": 6, "unit": "ADA" }
+                }
+            }
+        }));
+        let lines = json_to_lines_with_schema(&json!({ "fee": 1500000 }), &schema);
+        assert_eq!(
+            lines,
+            [DisplayLine {
+                indent: 1,
+                key: Some("Fee".into()),
+                value: "1.500000 ADA".into(),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn grouped_digits() {
+        let grouped = schema(json!({ "group_digits": true }));
+        let values = |value: Value, schema: &DisplaySchema| -> Vec<String> {
+            json_to_lines_with_schema(&value, schema)
+                .into_iter()
+                .map(|l| l.value)
+                .collect()
+        };
+        let value = json!({ "a": 1000000000u64, "b": 1234.5, "c": -1234567, "d": 999 });
+        assert_eq!(
+            values(value.clone(), &grouped),
+            ["1,000,000,000", "1234.5", "-1,234,567", "999"]
+        );
+        assert_eq!(
+            values(json!({ "e": 1.5e300 }), &grouped),
+            values(json!({ "e": 1.5e300 }), &DisplaySchema::default())
+        );
+        assert_eq!(
+            values(value, &DisplaySchema::default()),
+            ["1000000000", "1234.5", "-1234567", "999"]
+        );
+    }
+
+    #[test]
+    fn schema_paths_skip_arrays() {
+        let schema = schema(json!({
+            "fields": {
+                "outputs.amount": { "format": { "type": "amount", "decimals": 2, "unit": null } },
+                "outputs.address": { "format": { "type": "address" } },
+                "nonce": { "format": { "type": "hex" } }
+            }
+        }));
+        let value = json!({
+            "nonce": 255,
+            "outputs": [{ "address": "addr1qxyz", "amount": 5 }]
+        });
+        let values: Vec<_> = json_to_lines_with_schema(&value, &schema)
+            .into_iter()
+            .map(|l| l.value)
+            .collect();
+        assert_eq!(values, ["0xff", "[1 items]", "", "addr 1qxy z", "0.05"]);
+    }
+
+    #[test]
+    fn schema_leaves_unfit_values_alone() {
+        assert_eq!(
+            format_value(
+                "n/a",
+                &FieldFormat::Amount {
+                    decimals: 6,
+                    unit: None
+                }
+            ),
+            "n/a"
+        );
+        assert_eq!(
+            format_value(
+                "-25",
+                &FieldFormat::Amount {
+                    decimals: 1,
+                    unit: None
+                }
+            ),
+            "-2.5"
+        );
+        assert_eq!(format_value("0x12", &FieldFormat::Hex), "0x12");
+    }
 }
@@ -0,0 +1,40 @@
+use crate::attestation::AttestationError;
+use crate::crypto::CryptoError;
+use crate::mnemonic::MnemonicError;
+use crate::output_manifest::OutputManifestError;
+use crate::psbt::PsbtError;
+use crate::qr::QrError;
+use crate::slip10::Slip10Error;
+use crate::spec::SpecError;
+use crate::stick::StickManifestError;
+use crate::wasm_sandbox::SandboxError;
+use thiserror::Error;
+
+/// Any error produced by signer-core, for embedders that want one type to
+/// match on. Each variant wraps the module's own error unchanged.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CoreError {
+    #[error(transparent)]
+    Attestation(#[from] AttestationError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Mnemonic(#[from] MnemonicError),
+    #[error(transparent)]
+    OutputManifest(#[from] OutputManifestError),
+    #[error(transparent)]
+    Psbt(#[from] PsbtError),
+    #[error(transparent)]
+    Qr(#[from] QrError),
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+    #[error(transparent)]
+    Slip10(#[from] Slip10Error),
+    #[error(transparent)]
+    Spec(#[from] SpecError),
+    #[error(transparent)]
+    StickManifest(#[from] StickManifestError),
+    #[error("invalid interpreter JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
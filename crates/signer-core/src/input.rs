@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Top-level key under which an interpreter lists the inputs it needs.
+pub const INPUTS_KEY: &str = "_inputs";
+
+/// A value the interpreter asks the user to enter on the device.
+///
+/// Declared in the `interpret` output as
+/// `"_inputs": [{"name": "limit", "prompt": "SPEND LIMIT", "kind": "number", "digits": 6}]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRequest {
+    /// Key under which the value is passed back to `assemble_with_inputs`.
+    pub name: String,
+    /// Text shown above the picker.
+    pub prompt: String,
+    #[serde(flatten)]
+    pub kind: InputKind,
+}
+
+/// What kind of value to collect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputKind {
+    /// Unsigned decimal number entered as a fixed number of digits, from 1
+    /// to [`MAX_DIGITS`].
+    Number { digits: usize },
+}
+
+/// Most digits a number input may ask for: every 19-digit number fits a
+/// `u64`, so the value is never clamped.
+pub const MAX_DIGITS: usize = 19;
+
+/// Remove the input requests from an interpreter's display JSON.
+///
+/// Returns an empty list when the interpreter declared none. The remaining
+/// value is what gets rendered for review. A number input outside
+/// `1..=MAX_DIGITS` digits is an error.
+pub fn take_input_requests(value: &mut Value) -> Result<Vec<InputRequest>, serde_json::Error> {
+    let requests: Vec<InputRequest> = match value.as_object_mut().and_then(|m| m.remove(INPUTS_KEY))
+    {
+        Some(requests) => serde_json::from_value(requests)?,
+        None => return Ok(Vec::new()),
+    };
+    for request in &requests {
+        let InputKind::Number { digits } = request.kind;
+        if !(1..=MAX_DIGITS).contains(&digits) {
+            return Err(serde::de::Error::custom(format!(
+                "input '{}' asks for {digits} digits, not 1 to {MAX_DIGITS}",
+                request.name
+            )));
+        }
+    }
+    Ok(requests)
+}
+
+/// Encode collected values as the JSON object handed to `assemble_with_inputs`.
+pub fn encode_inputs(values: &[(String, Value)]) -> Vec<u8> {
+    let map: Map<String, Value> = values.iter().cloned().collect();
+    Value::Object(map).to_string().into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn takes_requests_and_leaves_display() {
+        let mut val = json!({
+            "memo": "rent",
+            "_inputs": [{"name": "limit", "prompt": "LIMIT", "kind": "number", "digits": 4}]
+        });
+        let requests = take_input_requests(&mut val).unwrap();
+        assert_eq!(
+            requests,
+            vec![InputRequest {
+                name: "limit".into(),
+                prompt: "LIMIT".into(),
+                kind: InputKind::Number { digits: 4 },
+            }]
+        );
+        assert_eq!(val, json!({"memo": "rent"}));
+    }
+
+    #[test]
+    fn no_requests() {
+        let mut val = json!({"memo": "rent"});
+        assert!(take_input_requests(&mut val).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_digit_counts_out_of_range() {
+        for digits in [0, MAX_DIGITS + 1, usize::MAX] {
+            let mut val = json!({
+                "_inputs": [{"name": "limit", "prompt": "LIMIT", "kind": "number", "digits": digits}]
+            });
+            assert!(take_input_requests(&mut val).is_err(), "{digits} digits");
+        }
+    }
+
+    #[test]
+    fn encodes_values() {
+        let bytes = encode_inputs(&[("limit".into(), json!(42))]);
+        assert_eq!(bytes, br#"{"limit":42}"#);
+    }
+}
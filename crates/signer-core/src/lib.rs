@@ -1,4 +1,26 @@
+pub mod attestation;
+pub mod builtin;
 pub mod crypto;
 pub mod display;
+mod error;
+pub mod input;
+pub mod mnemonic;
+pub mod output_manifest;
+pub mod psbt;
+pub mod qr;
+pub mod review;
+pub mod slip10;
 pub mod spec;
+pub mod stick;
 pub mod wasm_sandbox;
+
+pub use attestation::AttestationError;
+pub use crypto::CryptoError;
+pub use error::CoreError;
+pub use mnemonic::MnemonicError;
+pub use output_manifest::OutputManifestError;
+pub use psbt::PsbtError;
+pub use qr::QrError;
+pub use slip10::Slip10Error;
+pub use stick::StickManifestError;
+pub use wasm_sandbox::SandboxError;
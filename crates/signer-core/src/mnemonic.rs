@@ -0,0 +1,164 @@
+//! BIP39 mnemonics, for recovering a key made by another wallet.
+//!
+//! A mnemonic's key is not its entropy: BIP39 stretches the phrase (and an
+//! optional passphrase) into a 64-byte seed, and SLIP-0010 turns that seed
+//! into an Ed25519 master key and chain code. [`seed_to_mnemonic`] only
+//! encodes entropy as words: the words of a `seed.bin` are a paper copy of
+//! those bytes, decoded with [`mnemonic_to_entropy`], not a wallet mnemonic.
+//!
+//! The words, checksum and seed stretching come from the `bip39` crate.
+
+use crate::slip10::ExtendedKey;
+use bip39::{Language, Mnemonic};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MnemonicError {
+    #[error("mnemonic has {0} words (expected 12, 15, 18, 21 or 24)")]
+    WordCount(usize),
+    #[error("{0:?} is not a BIP39 word")]
+    UnknownWord(String),
+    #[error("mnemonic checksum does not match")]
+    Checksum,
+    #[error("entropy is {0} bytes (expected 16, 20, 24, 28 or 32)")]
+    EntropyLength(usize),
+    #[error("passphrase is not UTF-8")]
+    Passphrase,
+}
+
+/// The words of `phrase`, lowercased and separated by single spaces, the
+/// form BIP39 stretches.
+fn normalize(phrase: &str) -> String {
+    phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse `phrase` as English words, checking the checksum word.
+fn parse(phrase: &str) -> Result<Mnemonic, MnemonicError> {
+    let normalized = normalize(phrase);
+    Mnemonic::parse_in_normalized(Language::English, &normalized).map_err(|e| match e {
+        bip39::Error::BadWordCount(n) => MnemonicError::WordCount(n),
+        bip39::Error::UnknownWord(i) => {
+            MnemonicError::UnknownWord(normalized.split(' ').nth(i).unwrap_or_default().to_string())
+        }
+        bip39::Error::BadEntropyBitCount(bits) => MnemonicError::EntropyLength(bits / 8),
+        bip39::Error::InvalidChecksum | bip39::Error::AmbiguousLanguages(_) => {
+            MnemonicError::Checksum
+        }
+    })
+}
+
+/// Encode `entropy` as words: 11 bits per word, the last word carrying a
+/// checksum from its SHA-256.
+pub fn seed_to_mnemonic(entropy: &[u8]) -> Result<String, MnemonicError> {
+    Mnemonic::from_entropy_in(Language::English, entropy)
+        .map(|mnemonic| mnemonic.to_string())
+        .map_err(|_| MnemonicError::EntropyLength(entropy.len()))
+}
+
+/// Decode a mnemonic back to its entropy, checking the checksum word.
+/// Words are separated by any whitespace and matched case-insensitively.
+pub fn mnemonic_to_entropy(phrase: &str) -> Result<Vec<u8>, MnemonicError> {
+    Ok(parse(phrase)?.to_entropy())
+}
+
+/// The 64-byte BIP39 seed of `phrase`, after checking its checksum. The
+/// passphrase is BIP39's, salting the seed; it must already be
+/// NFKD-normalized, which ASCII always is.
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &[u8]) -> Result<[u8; 64], MnemonicError> {
+    let passphrase = std::str::from_utf8(passphrase).map_err(|_| MnemonicError::Passphrase)?;
+    Ok(parse(phrase)?.to_seed_normalized(passphrase))
+}
+
+/// The SLIP-0010 Ed25519 master key and chain code a wallet derives from
+/// `phrase`, the root of its account keys.
+pub fn mnemonic_to_master_key(
+    phrase: &str,
+    passphrase: &[u8],
+) -> Result<ExtendedKey, MnemonicError> {
+    Ok(ExtendedKey::master(&mnemonic_to_seed(phrase, passphrase)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABANDON_ART: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon art";
+
+    #[test]
+    fn bip39_test_vectors() {
+        // From the reference implementation's vectors, passphrase "TREZOR".
+        let vectors = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                ABANDON_ART,
+                "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+            ),
+        ];
+        for (entropy, phrase, seed) in vectors {
+            let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+            let entropy = hex::decode(entropy).unwrap();
+            assert_eq!(seed_to_mnemonic(&entropy).unwrap(), phrase);
+            assert_eq!(mnemonic_to_entropy(&phrase).unwrap(), entropy);
+            assert_eq!(
+                hex::encode(mnemonic_to_seed(&phrase, b"TREZOR").unwrap()),
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn master_key_follows_bip39_then_slip10() {
+        let master = mnemonic_to_master_key(&ABANDON_ART.to_uppercase(), b"").unwrap();
+        assert_eq!(
+            master,
+            ExtendedKey::master(&mnemonic_to_seed(ABANDON_ART, b"").unwrap())
+        );
+        assert_ne!(
+            mnemonic_to_master_key(ABANDON_ART, b"extra").unwrap(),
+            master
+        );
+    }
+
+    #[test]
+    fn bad_mnemonics_are_refused() {
+        // "abandon" x24 fails the checksum that "art" satisfies.
+        let wrong_checksum = ABANDON_ART.replace(" art", " abandon");
+        assert_eq!(
+            mnemonic_to_seed(&wrong_checksum, b""),
+            Err(MnemonicError::Checksum)
+        );
+        assert_eq!(
+            mnemonic_to_entropy(&ABANDON_ART.replace(" art", " arts")),
+            Err(MnemonicError::UnknownWord("arts".into()))
+        );
+        assert_eq!(
+            mnemonic_to_entropy("abandon about"),
+            Err(MnemonicError::WordCount(2))
+        );
+        assert_eq!(
+            seed_to_mnemonic(&[0; 17]),
+            Err(MnemonicError::EntropyLength(17))
+        );
+        assert_eq!(
+            mnemonic_to_seed(ABANDON_ART, &[0xff]),
+            Err(MnemonicError::Passphrase)
+        );
+    }
+}
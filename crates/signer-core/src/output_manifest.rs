@@ -0,0 +1,224 @@
+//! Several named output files from one `assemble` call.
+//!
+//! An interpreter exporting [`MANIFEST_EXPORT`] returns, instead of the
+//! signed artifact itself, a CBOR array of `{"filename": text, "bytes": bytes}`
+//! maps. The device writes each entry to the stick under its own name.
+
+use ciborium::Value;
+use thiserror::Error;
+
+/// Export whose presence marks `assemble` output as a manifest.
+/// Only its presence matters; the device never calls it.
+pub const MANIFEST_EXPORT: &str = "assemble_manifest";
+
+/// Deepest nesting accepted when decoding a manifest.
+const MAX_MANIFEST_DEPTH: usize = 8;
+
+/// Stick files an interpreter must not overwrite: the request's own files
+/// and those the device reads or writes on setup and signing sticks.
+const RESERVED: &[&str] = &[
+    "payload.bin",
+    "interpreter.wasm",
+    "interpreter.cwasm",
+    "sign.cbor",
+    crate::stick::MANIFEST_FILE,
+    "signed.progress",
+    "blind-sign.log",
+    "device-backup.enc",
+    "attestation.cbor",
+    "pubkey.bin",
+    "seed.bin",
+    "mnemonic.txt",
+    "seed-words.txt",
+    "tools",
+];
+
+/// Whether `name` is a setup stick's per-slot public key,
+/// `pubkey-slot{n}.bin`.
+fn is_slot_pubkey(name: &str) -> bool {
+    name.strip_prefix("pubkey-slot")
+        .and_then(|rest| rest.strip_suffix(".bin"))
+        .is_some_and(|slot| !slot.is_empty() && slot.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[derive(Debug, Error)]
+pub enum OutputManifestError {
+    #[error("manifest is not valid CBOR: {0}")]
+    Decode(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("manifest must be a non-empty array of {{filename, bytes}} maps")]
+    Malformed,
+    #[error("manifest filename {0:?} is not allowed")]
+    BadFilename(String),
+    #[error("manifest lists {0:?} more than once")]
+    Duplicate(String),
+}
+
+/// One file written to the stick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFile {
+    pub filename: String,
+    pub bytes: Vec<u8>,
+}
+
+/// A plain file name: ASCII letters, digits, `.`, `-` and `_`, not starting
+/// with a dot, and not one of the files the device reads or writes on a stick.
+pub(crate) fn valid_filename(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_'))
+        && !RESERVED.contains(&name)
+        && !is_slot_pubkey(name)
+}
+
+fn entry(value: Value) -> Option<(Value, Value)> {
+    let mut filename = None;
+    let mut bytes = None;
+    for (key, value) in value.into_map().ok()? {
+        match key.as_text()? {
+            "filename" => filename = Some(value),
+            "bytes" => bytes = Some(value),
+            _ => return None,
+        }
+    }
+    Some((filename?, bytes?))
+}
+
+/// Decode and check an `assemble` manifest.
+pub fn parse_manifest(cbor: &[u8]) -> Result<Vec<OutputFile>, OutputManifestError> {
+    let entries =
+        ciborium::de::from_reader_with_recursion_limit::<Value, _>(cbor, MAX_MANIFEST_DEPTH)?
+            .into_array()
+            .map_err(|_| OutputManifestError::Malformed)?;
+    if entries.is_empty() {
+        return Err(OutputManifestError::Malformed);
+    }
+    let mut files: Vec<OutputFile> = Vec::with_capacity(entries.len());
+    for value in entries {
+        let (filename, bytes) = entry(value).ok_or(OutputManifestError::Malformed)?;
+        let filename = filename
+            .into_text()
+            .map_err(|_| OutputManifestError::Malformed)?;
+        let bytes = bytes
+            .into_bytes()
+            .map_err(|_| OutputManifestError::Malformed)?;
+        if !valid_filename(&filename) {
+            return Err(OutputManifestError::BadFilename(filename));
+        }
+        if files.iter().any(|f| f.filename == filename) {
+            return Err(OutputManifestError::Duplicate(filename));
+        }
+        files.push(OutputFile { filename, bytes });
+    }
+    Ok(files)
+}
+
+/// Encode `files` as an `assemble` manifest.
+pub fn encode_manifest(files: &[OutputFile]) -> Vec<u8> {
+    let value = Value::Array(
+        files
+            .iter()
+            .map(|f| {
+                Value::Map(vec![
+                    (
+                        Value::Text("filename".into()),
+                        Value::Text(f.filename.clone()),
+                    ),
+                    (Value::Text("bytes".into()), Value::Bytes(f.bytes.clone())),
+                ])
+            })
+            .collect(),
+    );
+    let mut buf = Vec::new();
+    ciborium::into_writer(&value, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, bytes: &[u8]) -> OutputFile {
+        OutputFile {
+            filename: name.into(),
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let files = vec![file("tx.signed", b"\x01\x02"), file("witness.bin", b"")];
+        assert_eq!(parse_manifest(&encode_manifest(&files)).unwrap(), files);
+    }
+
+    #[test]
+    fn rejects_unsafe_or_repeated_names() {
+        for name in [
+            "",
+            "../tx",
+            "dir/tx",
+            ".hidden",
+            "sign.cbor",
+            "signed.progress",
+            "blind-sign.log",
+            "device-backup.enc",
+            "attestation.cbor",
+            "pubkey.bin",
+            "pubkey-slot3.bin",
+            "seed.bin",
+        ] {
+            let cbor = encode_manifest(&[file(name, b"x")]);
+            assert!(
+                matches!(
+                    parse_manifest(&cbor),
+                    Err(OutputManifestError::BadFilename(_))
+                ),
+                "{name:?} accepted"
+            );
+        }
+        let cbor = encode_manifest(&[file("a", b"1"), file("a", b"2")]);
+        assert!(matches!(
+            parse_manifest(&cbor),
+            Err(OutputManifestError::Duplicate(_))
+        ));
+    }
+
+    #[test]
+    fn slot_pubkey_pattern_is_exact() {
+        assert!(is_slot_pubkey("pubkey-slot0.bin"));
+        assert!(is_slot_pubkey("pubkey-slot255.bin"));
+        for name in [
+            "pubkey-slot.bin",
+            "pubkey-slotx.bin",
+            "pubkey-slot1.bin.sig",
+        ] {
+            assert!(!is_slot_pubkey(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn deeply_nested_manifest_is_refused() {
+        // Deeper than MAX_MANIFEST_DEPTH, well within ciborium's default.
+        let mut cbor = vec![0x81; 20];
+        cbor.push(0x80);
+        assert!(matches!(
+            parse_manifest(&cbor),
+            Err(OutputManifestError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_or_malformed_manifest() {
+        assert!(matches!(
+            parse_manifest(&encode_manifest(&[])),
+            Err(OutputManifestError::Malformed)
+        ));
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&Value::Text("signed".into()), &mut cbor).unwrap();
+        assert!(matches!(
+            parse_manifest(&cbor),
+            Err(OutputManifestError::Malformed)
+        ));
+    }
+}
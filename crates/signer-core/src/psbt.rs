@@ -0,0 +1,357 @@
+//! Just enough BIP 174 to put a signature into a PSBT.
+//!
+//! A PSBT is a magic, a global key-value map and then one map per input and
+//! per output. The device does not interpret the transaction; it only needs
+//! to count the inputs and outputs to find the maps, add a partial signature
+//! to one input, and write every other entry back byte for byte.
+
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+/// Global key type of the unsigned transaction (PSBT version 0).
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+/// Global key types of the input and output counts (PSBT version 2).
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+
+/// Input key type of a partial signature, keyed by the signer's public key.
+const IN_PARTIAL_SIG: u8 = 0x02;
+/// Input key type of the sighash type the signature must commit to.
+const IN_SIGHASH_TYPE: u8 = 0x03;
+
+const SIGHASH_ALL: u8 = 0x01;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PsbtError {
+    #[error("payload is not a PSBT")]
+    Magic,
+    #[error("PSBT ends early")]
+    Truncated,
+    #[error("PSBT has {0} trailing bytes")]
+    Trailing(usize),
+    #[error("PSBT has neither an unsigned transaction nor input and output counts")]
+    NoCounts,
+    #[error("input {index} is out of range (PSBT has {inputs} inputs)")]
+    InputIndex { index: u32, inputs: usize },
+    #[error("signature is not a 64-byte secp256k1 ECDSA signature")]
+    Signature,
+}
+
+/// Key-value pairs of one map, in the order they were read.
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// A parsed PSBT whose entries are kept as raw bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psbt {
+    pub global: Map,
+    pub inputs: Vec<Map>,
+    pub outputs: Vec<Map>,
+}
+
+impl Psbt {
+    pub fn parse(bytes: &[u8]) -> Result<Self, PsbtError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()).map_err(|_| PsbtError::Magic)? != MAGIC {
+            return Err(PsbtError::Magic);
+        }
+        let global = reader.map()?;
+        let (inputs, outputs) = counts(&global)?;
+        // Every map takes at least its separator byte.
+        if inputs.saturating_add(outputs) > reader.0.len() {
+            return Err(PsbtError::Truncated);
+        }
+        let inputs = (0..inputs)
+            .map(|_| reader.map())
+            .collect::<Result<_, _>>()?;
+        let outputs = (0..outputs)
+            .map(|_| reader.map())
+            .collect::<Result<_, _>>()?;
+        if !reader.0.is_empty() {
+            return Err(PsbtError::Trailing(reader.0.len()));
+        }
+        Ok(Self {
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for map in std::iter::once(&self.global)
+            .chain(&self.inputs)
+            .chain(&self.outputs)
+        {
+            for (key, value) in map {
+                write_compact_size(&mut out, key.len() as u64);
+                out.extend_from_slice(key);
+                write_compact_size(&mut out, value.len() as u64);
+                out.extend_from_slice(value);
+            }
+            out.push(0);
+        }
+        out
+    }
+
+    /// Record `signature` (DER with the sighash byte) by `pubkey` on an
+    /// input, replacing an earlier signature by the same key.
+    pub fn add_partial_sig(
+        &mut self,
+        input_index: u32,
+        pubkey: &[u8],
+        signature: Vec<u8>,
+    ) -> Result<(), PsbtError> {
+        let input = self.input_mut(input_index)?;
+        let key = [&[IN_PARTIAL_SIG], pubkey].concat();
+        match input.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, value)) => *value = signature,
+            None => input.push((key, signature)),
+        }
+        Ok(())
+    }
+
+    /// The input's requested sighash type, `SIGHASH_ALL` when unset.
+    fn sighash_type(&mut self, input_index: u32) -> Result<u8, PsbtError> {
+        let input = self.input_mut(input_index)?;
+        Ok(input
+            .iter()
+            .find(|(k, _)| k[..] == [IN_SIGHASH_TYPE])
+            .and_then(|(_, v)| v.first().copied())
+            .unwrap_or(SIGHASH_ALL))
+    }
+
+    fn input_mut(&mut self, index: u32) -> Result<&mut Map, PsbtError> {
+        let inputs = self.inputs.len();
+        self.inputs
+            .get_mut(index as usize)
+            .ok_or(PsbtError::InputIndex { index, inputs })
+    }
+}
+
+/// Add a compact `r || s` ECDSA signature by `pubkey` to input
+/// `input_index` of the PSBT `psbt`, returning the re-serialized PSBT.
+/// The signature is stored DER-encoded with low `s` and the input's
+/// sighash type appended, as BIP 174 requires.
+pub fn insert_signature(
+    psbt: &[u8],
+    input_index: u32,
+    pubkey: &[u8],
+    signature: &[u8],
+) -> Result<Vec<u8>, PsbtError> {
+    let signature =
+        k256::ecdsa::Signature::from_slice(signature).map_err(|_| PsbtError::Signature)?;
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let mut psbt = Psbt::parse(psbt)?;
+    let mut der = der_signature(&signature.r().to_bytes(), &signature.s().to_bytes());
+    der.push(psbt.sighash_type(input_index)?);
+    psbt.add_partial_sig(input_index, pubkey, der)?;
+    Ok(psbt.serialize())
+}
+
+/// DER `SEQUENCE { INTEGER r, INTEGER s }` of big-endian scalars.
+fn der_signature(r: &[u8], s: &[u8]) -> Vec<u8> {
+    let integer = |n: &[u8]| {
+        let n = &n[n.iter().position(|&b| b != 0).unwrap_or(n.len() - 1)..];
+        let pad = n[0] & 0x80 != 0;
+        let mut out = vec![0x02, (n.len() + pad as usize) as u8];
+        if pad {
+            out.push(0);
+        }
+        out.extend_from_slice(n);
+        out
+    };
+    let body = [integer(r), integer(s)].concat();
+    [&[0x30, body.len() as u8][..], &body].concat()
+}
+
+/// Input and output counts, from the unsigned transaction (version 0) or
+/// the count fields (version 2).
+fn counts(global: &Map) -> Result<(usize, usize), PsbtError> {
+    let field = |kind: u8| global.iter().find(|(k, _)| k[..] == [kind]).map(|(_, v)| v);
+    if let Some(tx) = field(GLOBAL_UNSIGNED_TX) {
+        return transaction_counts(tx);
+    }
+    match (field(GLOBAL_INPUT_COUNT), field(GLOBAL_OUTPUT_COUNT)) {
+        (Some(inputs), Some(outputs)) => {
+            let count = |value: &[u8]| {
+                let n = Reader(value).compact_size()?;
+                usize::try_from(n).map_err(|_| PsbtError::Truncated)
+            };
+            Ok((count(inputs)?, count(outputs)?))
+        }
+        _ => Err(PsbtError::NoCounts),
+    }
+}
+
+/// Input and output counts of a transaction without witnesses.
+fn transaction_counts(tx: &[u8]) -> Result<(usize, usize), PsbtError> {
+    let mut reader = Reader(tx);
+    reader.take(4)?; // version
+    let inputs = reader.count()?;
+    for _ in 0..inputs {
+        reader.take(36)?; // previous txid and output index
+        let script = reader.count()?;
+        reader.take(script + 4)?; // script and sequence
+    }
+    let outputs = reader.count()?;
+    for _ in 0..outputs {
+        reader.take(8)?; // amount
+        let script = reader.count()?;
+        reader.take(script)?;
+    }
+    Ok((inputs, outputs))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PsbtError> {
+        if n > self.0.len() {
+            return Err(PsbtError::Truncated);
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    /// A Bitcoin CompactSize integer.
+    fn compact_size(&mut self) -> Result<u64, PsbtError> {
+        let width = match self.take(1)?[0] {
+            0xfd => 2,
+            0xfe => 4,
+            0xff => 8,
+            small => return Ok(small.into()),
+        };
+        let mut bytes = [0; 8];
+        bytes[..width].copy_from_slice(self.take(width)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// A CompactSize length or count, which cannot exceed what is left.
+    fn count(&mut self) -> Result<usize, PsbtError> {
+        usize::try_from(self.compact_size()?)
+            .ok()
+            .filter(|&n| n <= self.0.len())
+            .ok_or(PsbtError::Truncated)
+    }
+
+    /// Key-value pairs up to the `0x00` separator.
+    fn map(&mut self) -> Result<Map, PsbtError> {
+        let mut map = Map::new();
+        loop {
+            let key_len = self.count()?;
+            if key_len == 0 {
+                return Ok(map);
+            }
+            let key = self.take(key_len)?.to_vec();
+            let value_len = self.count()?;
+            map.push((key, self.take(value_len)?.to_vec()));
+        }
+    }
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SoftwareSigner;
+    use crate::spec::SignAlgorithm;
+
+    /// Version 0 PSBT spending one P2WPKH output into one: the unsigned
+    /// transaction, then an input map holding the witness UTXO, then an
+    /// empty output map.
+    const ONE_INPUT: &str = "70736274ff\
+        01005202000000\
+        01000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0000000000fdffffff\
+        01e803000000000000160014000102030405060708090a0b0c0d0e0f10111213\
+        00000000\
+        00\
+        01011f\
+        10270000000000001600140102030405060708090a0b0c0d0e0f1011121314\
+        00\
+        00";
+
+    #[test]
+    fn unchanged_psbt_round_trips() {
+        let bytes = hex::decode(ONE_INPUT).unwrap();
+        let psbt = Psbt::parse(&bytes).unwrap();
+        assert_eq!((psbt.inputs.len(), psbt.outputs.len()), (1, 1));
+        assert_eq!(psbt.inputs[0][0].0, [0x01]);
+        assert_eq!(psbt.serialize(), bytes);
+    }
+
+    #[test]
+    fn signature_lands_in_the_partial_sig_map() {
+        let signer = SoftwareSigner::from_seed([3; 32]);
+        let pubkey = signer.public_key(SignAlgorithm::Secp256k1Ecdsa).unwrap();
+        let sighash = [0x42; 32];
+        let sig = signer
+            .sign(SignAlgorithm::Secp256k1Ecdsa, &sighash)
+            .unwrap();
+
+        let psbt = hex::decode(ONE_INPUT).unwrap();
+        let signed = Psbt::parse(&insert_signature(&psbt, 0, &pubkey, &sig).unwrap()).unwrap();
+
+        let (key, value) = signed.inputs[0].last().unwrap();
+        assert_eq!(key[0], IN_PARTIAL_SIG);
+        assert_eq!(key[1..], pubkey[..]);
+        let (der, sighash_type) = value.split_at(value.len() - 1);
+        assert_eq!(sighash_type, [SIGHASH_ALL]);
+        let decoded = k256::ecdsa::Signature::from_der(der).unwrap();
+        assert_eq!(decoded.to_bytes()[..], sig[..]);
+        // Everything else is as it was.
+        let original = Psbt::parse(&psbt).unwrap();
+        assert_eq!(signed.global, original.global);
+        assert_eq!(signed.inputs[0][..1], original.inputs[0][..]);
+        assert_eq!(signed.outputs, original.outputs);
+    }
+
+    #[test]
+    fn bad_psbts_are_refused() {
+        let psbt = hex::decode(ONE_INPUT).unwrap();
+        let sig = [1; 64];
+        assert_eq!(
+            insert_signature(&psbt, 1, &[2; 33], &sig),
+            Err(PsbtError::InputIndex {
+                index: 1,
+                inputs: 1
+            })
+        );
+        assert_eq!(
+            insert_signature(&psbt[..psbt.len() - 1], 0, &[2; 33], &sig),
+            Err(PsbtError::Truncated)
+        );
+        assert_eq!(
+            insert_signature(b"not a psbt", 0, &[2; 33], &sig),
+            Err(PsbtError::Magic)
+        );
+    }
+
+    #[test]
+    fn der_pads_high_scalars_and_strips_zeros() {
+        let mut r = [0; 32];
+        r[31] = 0x80;
+        let s = [0x7f; 32];
+        let der = der_signature(&r, &s);
+        assert_eq!(der[..6], [0x30, 0x26, 0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(der[6..8], [0x02, 0x20]);
+    }
+}
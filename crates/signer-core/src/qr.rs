@@ -0,0 +1,288 @@
+//! QR codes for carrying signed output off the device by camera.
+//!
+//! [`output_to_qr`] base64-encodes the output and encodes the text in byte
+//! mode at error correction level M, in the smallest version (size) that
+//! fits. A [`Matrix`] is rows of modules, `true` for dark, without the
+//! four-module quiet zone a renderer must leave around it.
+//!
+//! Output too large for one code is split by [`output_to_qr_frames`] into
+//! frames shown one after another. Each frame's text is
+//! `{part}/{total}:{base64 chunk}`, with `part` counting from 1; a scanner
+//! collects all `total` parts, base64-decodes each chunk and concatenates
+//! them in part order. [`reassemble_frames`] does that for frames scanned
+//! by the device.
+//!
+//! The codes themselves come from the `qrcode` crate; this module only
+//! fixes the mode, level and version and frames the output.
+
+use base64ct::{Base64, Encoding};
+use qrcode::bits::Bits;
+use qrcode::{Color, EcLevel, QrCode, Version};
+use thiserror::Error;
+
+/// QR modules by row, then column; `true` is dark.
+pub type Matrix = Vec<Vec<bool>>;
+
+/// Largest version the standard defines (177×177 modules).
+const MAX_VERSION: usize = 40;
+
+/// Bytes a version 40 code at level M holds in byte mode.
+const MAX_TEXT: usize = 2331;
+
+/// Most frames [`reassemble_frames`] accepts for one file; with 1000-byte
+/// chunks that is about 4 MB.
+pub const MAX_FRAMES: usize = 4096;
+
+#[derive(Debug, Error)]
+pub enum QrError {
+    #[error("{len} bytes do not fit one QR code (at most {max}); use the animated multi-frame QR instead")]
+    TooLarge { len: usize, max: usize },
+    #[error("QR frame {0:?} is not part/total:base64")]
+    BadFrame(String),
+    #[error("QR frames disagree on part {0} or on the total")]
+    Conflict(usize),
+    #[error("QR part {part} of {total} is missing")]
+    MissingPart { part: usize, total: usize },
+    #[error("QR frames claim {total} parts (at most {max})")]
+    TooManyFrames { total: usize, max: usize },
+}
+
+/// Encode `data` as one QR code of its base64 text.
+pub fn output_to_qr(data: &[u8]) -> Result<Matrix, QrError> {
+    encode_text(Base64::encode_string(data).as_bytes()).map_err(|_| QrError::TooLarge {
+        len: data.len(),
+        // Every 3 bytes become 4 base64 characters.
+        max: MAX_TEXT / 4 * 3,
+    })
+}
+
+/// Split `data` into `chunk`-byte pieces, one QR code per piece, each
+/// headed `part/total`; see the module documentation for the frame format.
+pub fn output_to_qr_frames(data: &[u8], chunk: usize) -> Result<Vec<Matrix>, QrError> {
+    frame_texts(data, chunk)
+        .iter()
+        .map(|text| encode_text(text.as_bytes()))
+        .collect()
+}
+
+/// The text of each frame of `data` in `chunk`-byte pieces.
+pub fn frame_texts(data: &[u8], chunk: usize) -> Vec<String> {
+    let chunks: Vec<&[u8]> = data.chunks(chunk.max(1)).collect();
+    let total = chunks.len();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| format!("{}/{total}:{}", i + 1, Base64::encode_string(piece)))
+        .collect()
+}
+
+/// Join scanned frame texts back into the data [`frame_texts`] split.
+///
+/// Frames may come in any order and repeat, as a camera catches them;
+/// a repeated part must carry the same chunk.
+pub fn reassemble_frames<'a>(texts: impl IntoIterator<Item = &'a str>) -> Result<Vec<u8>, QrError> {
+    let mut parts: Vec<Option<Vec<u8>>> = Vec::new();
+    for text in texts {
+        let bad = || QrError::BadFrame(text.into());
+        let (header, chunk) = text.split_once(':').ok_or_else(bad)?;
+        let (part, total) = header.split_once('/').ok_or_else(bad)?;
+        let (part, total): (usize, usize) = (
+            part.parse().map_err(|_| bad())?,
+            total.parse().map_err(|_| bad())?,
+        );
+        if part == 0 || part > total {
+            return Err(bad());
+        }
+        if total > MAX_FRAMES {
+            return Err(QrError::TooManyFrames {
+                total,
+                max: MAX_FRAMES,
+            });
+        }
+        let chunk = Base64::decode_vec(chunk).map_err(|_| bad())?;
+        if parts.is_empty() {
+            parts.resize(total, None);
+        } else if parts.len() != total {
+            return Err(QrError::Conflict(part));
+        }
+        match &parts[part - 1] {
+            Some(seen) if *seen != chunk => return Err(QrError::Conflict(part)),
+            _ => parts[part - 1] = Some(chunk),
+        }
+    }
+    let total = parts.len();
+    if total == 0 {
+        return Err(QrError::MissingPart { part: 1, total: 1 });
+    }
+    let mut data = Vec::new();
+    for (i, chunk) in parts.into_iter().enumerate() {
+        data.extend(chunk.ok_or(QrError::MissingPart { part: i + 1, total })?);
+    }
+    Ok(data)
+}
+
+/// Encode `text` in byte mode in the smallest version that holds it.
+pub fn encode_text(text: &[u8]) -> Result<Matrix, QrError> {
+    let too_large = QrError::TooLarge {
+        len: text.len(),
+        max: MAX_TEXT,
+    };
+    if text.len() > MAX_TEXT {
+        return Err(too_large);
+    }
+    let code = (1..=MAX_VERSION)
+        .find_map(|version| {
+            let mut bits = Bits::new(Version::Normal(version as i16));
+            bits.push_byte_data(text).ok()?;
+            bits.push_terminator(EcLevel::M).ok()?;
+            QrCode::with_bits(bits, EcLevel::M).ok()
+        })
+        .ok_or(too_large)?;
+    let side = code.width();
+    let modules: Vec<bool> = code
+        .into_colors()
+        .into_iter()
+        .map(|color| color == Color::Dark)
+        .collect();
+    Ok(modules.chunks(side).map(<[bool]>::to_vec).collect())
+}
+
+/// Modules per side of a QR code of `version`.
+pub fn side(version: usize) -> usize {
+    version * 4 + 17
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scan `matrix` with an independent decoder: its version, level and
+    /// text.
+    fn scan(matrix: &Matrix) -> (usize, u16, String) {
+        let grid = rqrr::Grid::new(rqrr::SimpleGrid::from_func(matrix.len(), |x, y| {
+            matrix[y][x]
+        }));
+        let (meta, text) = grid.decode().expect("decodes");
+        (meta.version.0, meta.ecc_level, text)
+    }
+
+    #[test]
+    fn small_payload_fits_version_one() {
+        let payload = b"signature";
+        let matrix = output_to_qr(payload).unwrap();
+        assert_eq!(matrix.len(), side(1));
+        assert!(matrix.iter().all(|row| row.len() == side(1)));
+
+        // Finder patterns in three corners: dark ring, light ring, dark core.
+        for (x, y) in [(3, 3), (side(1) - 4, 3), (3, side(1) - 4)] {
+            assert!(matrix[y][x] && matrix[y - 3][x - 3] && !matrix[y - 2][x - 2]);
+        }
+
+        // rqrr numbers level M as 0, after the format information bits.
+        assert_eq!(scan(&matrix), (1, 0, Base64::encode_string(payload)));
+    }
+
+    #[test]
+    fn codes_scan_back_to_their_text() {
+        // Single- and multi-block versions, up to the largest.
+        for len in [1, 14, 15, 150, 400, 1000, 2331] {
+            let text: String = (0..len)
+                .map(|i| char::from(b'!' + (i % 90) as u8))
+                .collect();
+            let matrix = encode_text(text.as_bytes()).unwrap();
+            let (version, level, scanned) = scan(&matrix);
+            assert_eq!(matrix.len(), side(version), "{len}");
+            assert_eq!((level, scanned), (0, text), "{len}");
+        }
+    }
+
+    #[test]
+    fn version_grows_with_data() {
+        // 14 bytes is version 1-M's byte-mode capacity.
+        assert_eq!(encode_text(&[b'a'; 14]).unwrap().len(), side(1));
+        assert_eq!(encode_text(&[b'a'; 15]).unwrap().len(), side(2));
+        assert_eq!(encode_text(&[b'a'; 2331]).unwrap().len(), side(40));
+        assert!(matches!(
+            encode_text(&[b'a'; 2332]),
+            Err(QrError::TooLarge {
+                len: 2332,
+                max: 2331
+            })
+        ));
+    }
+
+    #[test]
+    fn oversized_output_suggests_frames() {
+        let err = output_to_qr(&[0; 2000]).unwrap_err();
+        assert!(matches!(
+            err,
+            QrError::TooLarge {
+                len: 2000,
+                max: 1746
+            }
+        ));
+        assert!(err.to_string().contains("multi-frame"));
+        assert!(output_to_qr(&[0; 1746]).is_ok());
+    }
+
+    #[test]
+    fn frames_reassemble_to_output() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let texts = frame_texts(&data, 1000);
+        assert_eq!(texts.len(), 5);
+        assert!(texts[0].starts_with("1/5:"));
+
+        // Out of order and with a frame caught twice, as a camera sees them.
+        let scanned = texts.iter().rev().chain(&texts[2..3]).map(String::as_str);
+        assert_eq!(reassemble_frames(scanned).unwrap(), data);
+
+        let frames = output_to_qr_frames(&data, 1000).unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0], encode_text(texts[0].as_bytes()).unwrap());
+        assert!(output_to_qr_frames(&data, 4000).is_err());
+    }
+
+    #[test]
+    fn incomplete_or_garbled_frames_are_refused() {
+        let texts = frame_texts(b"abcdefgh", 3);
+        let missing = [texts[0].as_str(), texts[2].as_str()];
+        assert!(matches!(
+            reassemble_frames(missing),
+            Err(QrError::MissingPart { part: 2, total: 3 })
+        ));
+        assert!(matches!(
+            reassemble_frames([texts[0].as_str(), "1/3:AAAA"]),
+            Err(QrError::Conflict(1))
+        ));
+        assert!(matches!(
+            reassemble_frames([texts[0].as_str(), "1/4:YWJj"]),
+            Err(QrError::Conflict(1))
+        ));
+        for bad in ["no header", "0/3:YWJj", "4/3:YWJj", "1/3:not base64!"] {
+            assert!(matches!(
+                reassemble_frames([bad]),
+                Err(QrError::BadFrame(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn frame_count_is_capped() {
+        assert!(matches!(
+            reassemble_frames(["1/4000000000:AA=="]),
+            Err(QrError::TooManyFrames {
+                total: 4_000_000_000,
+                max: MAX_FRAMES
+            })
+        ));
+        assert!(matches!(
+            reassemble_frames(["1/18446744073709551615:AA=="]),
+            Err(QrError::TooManyFrames { .. })
+        ));
+        let last = format!("{MAX_FRAMES}/{MAX_FRAMES}:AA==");
+        assert!(matches!(
+            reassemble_frames([last.as_str()]),
+            Err(QrError::MissingPart { part: 1, .. })
+        ));
+    }
+}
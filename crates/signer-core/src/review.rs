@@ -0,0 +1,62 @@
+//! Interpreter output → review lines, shared by the device and host tools.
+
+use crate::display::{
+    json_to_lines_with_schema, largest_array, sort_keys, DisplayLine, DisplaySchema,
+};
+use crate::input::{take_input_requests, InputRequest};
+use crate::wasm_sandbox::SandboxModule;
+use crate::CoreError;
+use serde::Deserialize;
+
+/// Interpreter output prepared for the review screen.
+#[derive(Debug)]
+pub struct Review {
+    pub lines: Vec<DisplayLine>,
+    pub input_requests: Vec<InputRequest>,
+    /// Length of the longest list in the output (e.g. a transaction's outputs).
+    pub max_items: usize,
+}
+
+/// What an interpreter's optional `metadata` export says about itself.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct InterpreterMetadata {
+    pub name: String,
+    pub version: String,
+}
+
+impl InterpreterMetadata {
+    /// The module's self-description, or `None` without a `metadata` export.
+    pub fn of(module: &SandboxModule<'_>) -> Result<Option<Self>, CoreError> {
+        match module.metadata()? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Review line naming the interpreter.
+    pub fn line(&self) -> DisplayLine {
+        DisplayLine {
+            key: Some("Interpreter".into()),
+            value: format!("{} {}", self.name, self.version),
+            indent: 0,
+            ..Default::default()
+        }
+    }
+}
+
+/// Render `interpret` output as review lines, applying the module's display
+/// schema, and split off any input requests. Object fields are shown in
+/// sorted order, so the same output always reviews the same way.
+pub fn review_lines(module: &SandboxModule<'_>, json: &str) -> Result<Review, CoreError> {
+    let mut value: serde_json::Value = serde_json::from_str(json)?;
+    let input_requests = take_input_requests(&mut value)?;
+    let schema = match module.schema()? {
+        Some(schema) => serde_json::from_str(&schema)?,
+        None => DisplaySchema::default(),
+    };
+    Ok(Review {
+        lines: json_to_lines_with_schema(&sort_keys(&value), &schema),
+        input_requests,
+        max_items: largest_array(&value),
+    })
+}
@@ -0,0 +1,169 @@
+//! SLIP-0010 hierarchical derivation of Ed25519 keys.
+//!
+//! A slot's seed is the root; each path component picks a child key, so
+//! one seed gives a separate key per account or chain. Ed25519 only has
+//! hardened children, so every component must have [`HARDENED`] set.
+
+use crate::display::HARDENED;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use std::fmt;
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Slip10Error {
+    #[error("Ed25519 derivation needs hardened path components, got {0}")]
+    NotHardened(u32),
+}
+
+/// A private key and the chain code its children are derived with.
+///
+/// Compared in constant time, and printed without either secret.
+#[derive(Clone)]
+pub struct ExtendedKey {
+    pub key: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+impl PartialEq for ExtendedKey {
+    fn eq(&self, other: &Self) -> bool {
+        (self.key.ct_eq(&other.key) & self.chain_code.ct_eq(&other.chain_code)).into()
+    }
+}
+
+impl Eq for ExtendedKey {}
+
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedKey").finish_non_exhaustive()
+    }
+}
+
+impl ExtendedKey {
+    /// The master key of `seed`.
+    pub fn master(seed: &[u8]) -> Self {
+        Self::from_hmac(b"ed25519 seed", &[seed])
+    }
+
+    /// The hardened child at `index`.
+    pub fn child(&self, index: u32) -> Result<Self, Slip10Error> {
+        if index & HARDENED == 0 {
+            return Err(Slip10Error::NotHardened(index));
+        }
+        Ok(Self::from_hmac(
+            &self.chain_code,
+            &[&[0], &self.key, &index.to_be_bytes()],
+        ))
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes any key");
+        for part in data {
+            mac.update(part);
+        }
+        let digest = mac.finalize().into_bytes();
+        let (key, chain_code) = digest.split_at(32);
+        Self {
+            key: key.try_into().expect("SHA-512 output is 64 bytes"),
+            chain_code: chain_code.try_into().expect("SHA-512 output is 64 bytes"),
+        }
+    }
+}
+
+/// The private key at `path` below `seed`'s master key.
+pub fn derive(seed: &[u8], path: &[u32]) -> Result<[u8; 32], Slip10Error> {
+    derive_from(&ExtendedKey::master(seed), path)
+}
+
+/// The private key at `path` below `parent`.
+pub fn derive_from(parent: &ExtendedKey, path: &[u32]) -> Result<[u8; 32], Slip10Error> {
+    let mut key = parent.clone();
+    for &index in path {
+        key = key.child(index)?;
+    }
+    Ok(key.key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    const H: u32 = HARDENED;
+
+    #[test]
+    fn test_vector_1() {
+        // SLIP-0010 Ed25519 test vector 1.
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed);
+        assert_eq!(
+            hex::encode(master.key),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+        let chain = [
+            (
+                &[H][..],
+                "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+            ),
+            (
+                &[H, 1 | H],
+                "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+            ),
+            (
+                &[H, 1 | H, 2 | H],
+                "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+            ),
+        ];
+        for (path, key) in chain {
+            assert_eq!(hex::encode(derive(&seed, path).unwrap()), key);
+        }
+    }
+
+    #[test]
+    fn cardano_account_path() {
+        // m/44'/1815'/0' below the seed of SLIP-0010 test vector 1, computed
+        // with Python's hmac and hashlib; the public key with `cryptography`.
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let path = [44 | H, 1815 | H, H];
+        let account = derive(&seed, &path).unwrap();
+        assert_eq!(
+            hex::encode(account),
+            "413c7397ccd8fd354e46eebad6d805c8c6094d9a10275ced3d3fba5371753c64"
+        );
+        let mut key = ExtendedKey::master(&seed);
+        for index in path {
+            key = key.child(index).unwrap();
+        }
+        assert_eq!(
+            hex::encode(key.chain_code),
+            "c5df315fb9f341d62455f9bbd55b91cd5c64c0ae1875fa76202e2170ad07b1c5"
+        );
+        assert_eq!(
+            hex::encode(SigningKey::from_bytes(&account).verifying_key().as_bytes()),
+            "d43aa3bc07ece9376bfae4876a27347ef865d8f200827410c895f6ffdd339fbf"
+        );
+        assert_ne!(account, derive(&seed, &[44 | H, 1815 | H, 1 | H]).unwrap());
+    }
+
+    #[test]
+    fn extended_key_hides_its_secrets() {
+        let key = ExtendedKey::master(&[7; 32]);
+        let printed = format!("{key:?}");
+        assert_eq!(printed, "ExtendedKey { .. }");
+        assert_eq!(key, key.clone());
+        assert_ne!(key, ExtendedKey::master(&[8; 32]));
+    }
+
+    #[test]
+    fn unhardened_component_is_refused() {
+        assert_eq!(
+            derive(&[0; 32], &[44 | H, 0]),
+            Err(Slip10Error::NotHardened(0))
+        );
+    }
+}
@@ -1,4 +1,44 @@
+use crate::display::HARDENED;
+use crate::output_manifest::valid_filename;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Largest `sign.cbor` accepted. Real specs are a few hundred bytes; the cap
+/// keeps a crafted stick from making the decoder chew through huge input.
+pub const MAX_SPEC_BYTES: usize = 16 * 1024;
+
+/// Deepest CBOR nesting accepted. The spec itself nests well under this.
+pub const MAX_SPEC_DEPTH: usize = 16;
+
+/// Spec schema version this build writes and understands. Specs written
+/// before the version field existed decode as version 1; version 3 added
+/// `nonce` and `not_after`, which a version 2 device would silently ignore.
+pub const SPEC_VERSION: u16 = 3;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SpecError {
+    #[error("signing spec is {0} bytes (limit {MAX_SPEC_BYTES})")]
+    TooLarge(usize),
+    #[error("invalid signing spec: {0}")]
+    Decode(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("signing spec version {0} is newer than this device supports ({SPEC_VERSION})")]
+    UnsupportedVersion(u16),
+    #[error("signing spec has an empty label")]
+    EmptyLabel,
+    #[error("range at {offset} of {length} bytes is outside the {payload_len}-byte payload")]
+    RangeOutOfBounds {
+        offset: usize,
+        length: usize,
+        payload_len: usize,
+    },
+    #[error("MAC key length {0} is invalid (must be 1-64 bytes)")]
+    InvalidMacKey(usize),
+    #[error("signing spec is inconsistent: {0}")]
+    Inconsistent(&'static str),
+    #[error("output file name {0:?} is not allowed")]
+    BadFileName(String),
+}
 
 /// What portion of the payload to sign.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -12,6 +52,32 @@ pub enum Signable {
         hash: HashAlgorithm,
         source: SignableSource,
     },
+    /// Keyed Blake2b-256 (MAC) over the source bytes, then sign the MAC.
+    /// The key must be 1–64 bytes.
+    KeyedHashThenSign {
+        key: Vec<u8>,
+        source: SignableSource,
+    },
+    /// Build a Merkle tree over the payload's leaves and sign the root.
+    ///
+    /// The payload is a list of leaves, each prefixed with its length as a
+    /// 4-byte little-endian integer. Hashing follows RFC 6962 (`0x00` leaf
+    /// prefix, `0x01` node prefix, left subtree a power of two).
+    MerkleRoot { hash: HashAlgorithm },
+    /// Sign the bytes returned by the interpreter's `signable` export, called
+    /// in the same instance that interpreted the payload.
+    Interpreter,
+    /// Several signables over the same payload, each signed in turn after
+    /// one review (e.g. the inputs of a PSBT).
+    Multi(Vec<Signable>),
+    /// Prepend a fixed domain-separation tag (Ethereum's `\x19Ethereum
+    /// Signed Message:\n…`, a Cosmos ADR-036 envelope) to what `inner`
+    /// selects: inside the hash for the hashing modes, before the signed
+    /// bytes otherwise.
+    Prefixed {
+        prefix: Vec<u8>,
+        inner: Box<Signable>,
+    },
 }
 
 /// Source selection for HashThenSign.
@@ -24,17 +90,49 @@ pub enum SignableSource {
 /// Supported hash algorithms.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum HashAlgorithm {
+    /// Blake2b with 28-byte output (e.g. Cardano key and script hashes).
+    Blake2b224,
     Blake2b256,
+    /// Blake2b with 64-byte output.
+    Blake2b512,
     Sha256,
+    /// SHA-256 applied twice, Bitcoin's "hash256".
+    Sha256d,
     Sha3_256,
+    /// Original Keccak padding, as Ethereum uses; not the same digest as SHA3-256.
+    Keccak256,
+    /// BLAKE3 with its default 32-byte output; faster than BLAKE2b on large payloads.
+    Blake3,
 }
 
 /// Supported signing algorithms.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SignAlgorithm {
     Ed25519,
+    /// Ed25519ph (RFC 8032): Ed25519 over the SHA-512 of the message, with
+    /// an empty context string.
+    Ed25519ph,
     Secp256k1Ecdsa,
     Secp256k1Schnorr,
+    /// ECDSA with the recovery id appended, as Ethereum's `ecrecover` expects.
+    Secp256k1EcdsaRecoverable,
+}
+
+impl SignAlgorithm {
+    /// Length in bytes of a signature the secure element must return.
+    ///
+    /// ECDSA signatures are compact `r || s`, not DER; Schnorr is BIP-340.
+    /// Recoverable ECDSA is `r || s || v` with low `s` and `v` the recovery
+    /// id (0 or 1).
+    pub fn signature_len(self) -> usize {
+        match self {
+            SignAlgorithm::Ed25519 => 64,
+            SignAlgorithm::Ed25519ph => 64,
+            SignAlgorithm::Secp256k1Ecdsa => 64,
+            SignAlgorithm::Secp256k1Schnorr => 64,
+            SignAlgorithm::Secp256k1EcdsaRecoverable => 65,
+        }
+    }
 }
 
 /// How to produce the final output.
@@ -46,22 +144,129 @@ pub enum OutputSpec {
     AppendToPayload,
     /// Call the WASM interpreter's `assemble(payload, sig)` function.
     WasmAssemble,
+    /// Add the signature to input `input_index` of the payload, a PSBT, as
+    /// a partial signature (see [`crate::psbt`]). The signable must produce
+    /// that input's sighash, usually through the interpreter.
+    PsbtInsert { input_index: u32 },
+    /// Write the raw signature to the stick as `name` instead of
+    /// `signed.bin`, for verifiers that expect a detached signature file.
+    DetachedFile { name: String },
+}
+
+/// Where the interpreter comes from.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum InterpreterSource {
+    /// `interpreter.wasm` on the USB stick.
+    #[default]
+    Usb,
+    /// An interpreter embedded in the device, by name (see [`crate::builtin`]).
+    Builtin(String),
 }
 
 /// Complete signing specification — deserialized from `sign.cbor` on the USB stick.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SigningSpec {
+    /// Schema version, [`SPEC_VERSION`] for specs built here. Serialized
+    /// first so a reader can tell the version before the rest of the map.
+    #[serde(default = "unversioned")]
+    pub version: u16,
     pub label: String,
     pub signable: Signable,
     pub algorithm: SignAlgorithm,
     pub key_slot: u8,
     pub output: OutputSpec,
+    /// Re-enter the PIN right before signing, even if the device is unlocked.
+    #[serde(default)]
+    pub require_pin_on_sign: bool,
+    /// Re-interpret the assembled output and refuse it if the review lines changed.
+    #[serde(default)]
+    pub verify_assembly: bool,
+    /// Declared payload format (e.g. `cardano-tx`), used to warn when a key
+    /// slot is reused across contexts.
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub interpreter: InterpreterSource,
+    /// Expected payload length in bytes; the device refuses any other size.
+    #[serde(default)]
+    pub payload_size: Option<u64>,
+    /// Refuse to sign until the secure element has made this many signatures.
+    /// The device has no wall clock, so scheduling counts signatures instead.
+    #[serde(default)]
+    pub not_before: Option<u64>,
+    /// Sign with the SLIP-0010 child of the slot key at this path (every
+    /// component hardened) instead of the slot key itself.
+    #[serde(default)]
+    pub derivation_path: Option<Vec<u32>>,
+    /// Single-use tag: the device remembers the nonces it has signed and
+    /// refuses a spec carrying one again.
+    #[serde(default)]
+    pub nonce: Option<[u8; 16]>,
+    /// Refuse to sign after this time (Unix seconds). Only checked on a
+    /// device with a trusted clock.
+    #[serde(default)]
+    pub not_after: Option<u64>,
 }
 
 impl SigningSpec {
-    /// Deserialize from CBOR bytes.
-    pub fn from_cbor(bytes: &[u8]) -> Result<Self, ciborium::de::Error<std::io::Error>> {
-        ciborium::from_reader(bytes)
+    /// Deserialize from CBOR bytes, refusing input over [`MAX_SPEC_BYTES`]
+    /// or nested deeper than [`MAX_SPEC_DEPTH`].
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, SpecError> {
+        if bytes.len() > MAX_SPEC_BYTES {
+            return Err(SpecError::TooLarge(bytes.len()));
+        }
+        let mut spec: Self = ciborium::de::from_reader_with_recursion_limit(bytes, MAX_SPEC_DEPTH)?;
+        if spec.version > SPEC_VERSION {
+            return Err(SpecError::UnsupportedVersion(spec.version));
+        }
+        // Older specs only lack fields, which decoding already defaulted.
+        spec.version = SPEC_VERSION;
+        Ok(spec)
+    }
+
+    /// Check the spec is consistent in itself and with a payload of
+    /// `payload_len` bytes, before the device commits to it.
+    pub fn validate(&self, payload_len: usize) -> Result<(), SpecError> {
+        if self.label.trim().is_empty() {
+            return Err(SpecError::EmptyLabel);
+        }
+        validate_signable(&self.signable, payload_len)?;
+        if self.verify_assembly && self.output != OutputSpec::WasmAssemble {
+            return Err(SpecError::Inconsistent(
+                "verify_assembly needs WasmAssemble output",
+            ));
+        }
+        if let Some(path) = &self.derivation_path {
+            if self.algorithm != SignAlgorithm::Ed25519 {
+                return Err(SpecError::Inconsistent(
+                    "derivation_path is only defined for Ed25519",
+                ));
+            }
+            // SLIP-0010 Ed25519 has hardened children only.
+            if path.iter().any(|&index| index & HARDENED == 0) {
+                return Err(SpecError::Inconsistent(
+                    "derivation_path components must all be hardened",
+                ));
+            }
+        }
+        if matches!(self.output, OutputSpec::PsbtInsert { .. }) {
+            if self.algorithm != SignAlgorithm::Secp256k1Ecdsa {
+                return Err(SpecError::Inconsistent(
+                    "PsbtInsert needs Secp256k1Ecdsa signatures",
+                ));
+            }
+            if matches!(self.signable, Signable::Multi(_)) {
+                return Err(SpecError::Inconsistent(
+                    "PsbtInsert takes a single signature",
+                ));
+            }
+        }
+        if let OutputSpec::DetachedFile { name } = &self.output {
+            if !valid_filename(name) {
+                return Err(SpecError::BadFileName(name.clone()));
+            }
+        }
+        Ok(())
     }
 
     /// Serialize to CBOR bytes.
@@ -72,18 +277,92 @@ impl SigningSpec {
     }
 }
 
+/// Check a signable's ranges and keys against a payload of `payload_len`
+/// bytes, and that it nests only where extraction can follow.
+fn validate_signable(signable: &Signable, payload_len: usize) -> Result<(), SpecError> {
+    match signable {
+        Signable::Multi(items) => {
+            if items.is_empty() {
+                return Err(SpecError::Inconsistent("Multi needs at least one signable"));
+            }
+            for item in items {
+                if matches!(item, Signable::Multi(_) | Signable::Interpreter) {
+                    return Err(SpecError::Inconsistent(
+                        "Multi items must be signed from the payload",
+                    ));
+                }
+                validate_signable(item, payload_len)?;
+            }
+        }
+        Signable::Prefixed { inner, .. } => {
+            if **inner == Signable::Interpreter {
+                return Err(SpecError::Inconsistent(
+                    "an interpreter signable cannot be prefixed",
+                ));
+            }
+            validate_signable(inner, payload_len)?;
+        }
+        _ => {}
+    }
+    let range = match signable {
+        Signable::Range { offset, length }
+        | Signable::HashThenSign {
+            source: SignableSource::Range { offset, length },
+            ..
+        }
+        | Signable::KeyedHashThenSign {
+            source: SignableSource::Range { offset, length },
+            ..
+        } => Some((*offset, *length)),
+        _ => None,
+    };
+    if let Some((offset, length)) = range {
+        if offset
+            .checked_add(length)
+            .is_none_or(|end| end > payload_len)
+        {
+            return Err(SpecError::RangeOutOfBounds {
+                offset,
+                length,
+                payload_len,
+            });
+        }
+    }
+    if let Signable::KeyedHashThenSign { key, .. } = signable {
+        if !(1..=64).contains(&key.len()) {
+            return Err(SpecError::InvalidMacKey(key.len()));
+        }
+    }
+    Ok(())
+}
+
+fn unversioned() -> u16 {
+    1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ciborium::Value;
 
     #[test]
     fn round_trip_whole_ed25519() {
         let spec = SigningSpec {
+            version: SPEC_VERSION,
             label: "Cardano Transaction".into(),
             signable: Signable::Whole,
             algorithm: SignAlgorithm::Ed25519,
             key_slot: 0,
             output: OutputSpec::SignatureOnly,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
         };
         let cbor = spec.to_cbor().unwrap();
         let decoded = SigningSpec::from_cbor(&cbor).unwrap();
@@ -93,6 +372,7 @@ mod tests {
     #[test]
     fn round_trip_hash_then_sign() {
         let spec = SigningSpec {
+            version: SPEC_VERSION,
             label: "Bitcoin PSBT".into(),
             signable: Signable::HashThenSign {
                 hash: HashAlgorithm::Sha256,
@@ -101,6 +381,15 @@ mod tests {
             algorithm: SignAlgorithm::Secp256k1Ecdsa,
             key_slot: 1,
             output: OutputSpec::WasmAssemble,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
         };
         let cbor = spec.to_cbor().unwrap();
         let decoded = SigningSpec::from_cbor(&cbor).unwrap();
@@ -110,6 +399,7 @@ mod tests {
     #[test]
     fn round_trip_range() {
         let spec = SigningSpec {
+            version: SPEC_VERSION,
             label: "Custom Format".into(),
             signable: Signable::Range {
                 offset: 4,
@@ -118,6 +408,39 @@ mod tests {
             algorithm: SignAlgorithm::Secp256k1Schnorr,
             key_slot: 2,
             output: OutputSpec::AppendToPayload,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
+        };
+        let cbor = spec.to_cbor().unwrap();
+        let decoded = SigningSpec::from_cbor(&cbor).unwrap();
+        assert_eq!(spec, decoded);
+    }
+
+    #[test]
+    fn round_trip_require_pin_on_sign() {
+        let spec = SigningSpec {
+            version: SPEC_VERSION,
+            label: "High Value".into(),
+            signable: Signable::Whole,
+            algorithm: SignAlgorithm::Ed25519,
+            key_slot: 0,
+            output: OutputSpec::SignatureOnly,
+            require_pin_on_sign: true,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
         };
         let cbor = spec.to_cbor().unwrap();
         let decoded = SigningSpec::from_cbor(&cbor).unwrap();
@@ -127,6 +450,7 @@ mod tests {
     #[test]
     fn round_trip_hash_then_sign_range() {
         let spec = SigningSpec {
+            version: SPEC_VERSION,
             label: "Partial Hash".into(),
             signable: Signable::HashThenSign {
                 hash: HashAlgorithm::Blake2b256,
@@ -138,9 +462,378 @@ mod tests {
             algorithm: SignAlgorithm::Ed25519,
             key_slot: 3,
             output: OutputSpec::SignatureOnly,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
         };
         let cbor = spec.to_cbor().unwrap();
         let decoded = SigningSpec::from_cbor(&cbor).unwrap();
         assert_eq!(spec, decoded);
     }
+
+    #[test]
+    fn round_trip_builtin_interpreter() {
+        let spec = SigningSpec {
+            version: SPEC_VERSION,
+            label: "Embedded".into(),
+            signable: Signable::Whole,
+            algorithm: SignAlgorithm::Ed25519,
+            key_slot: 0,
+            output: OutputSpec::SignatureOnly,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: Some("echo-hex".into()),
+            interpreter: InterpreterSource::Builtin("echo-hex".into()),
+            payload_size: Some(1024),
+            not_before: Some(42),
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
+        };
+        let cbor = spec.to_cbor().unwrap();
+        let decoded = SigningSpec::from_cbor(&cbor).unwrap();
+        assert_eq!(spec, decoded);
+    }
+
+    #[test]
+    fn round_trip_hash_algorithms() {
+        for hash in [
+            HashAlgorithm::Blake2b224,
+            HashAlgorithm::Blake2b256,
+            HashAlgorithm::Blake2b512,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha256d,
+            HashAlgorithm::Sha3_256,
+            HashAlgorithm::Keccak256,
+            HashAlgorithm::Blake3,
+        ] {
+            let signable = Signable::HashThenSign {
+                hash,
+                source: SignableSource::Whole,
+            };
+            let mut cbor = Vec::new();
+            ciborium::into_writer(&signable, &mut cbor).unwrap();
+            let decoded: Signable = ciborium::from_reader(cbor.as_slice()).unwrap();
+            assert_eq!(signable, decoded);
+        }
+    }
+
+    #[test]
+    fn hash_algorithms_encode_by_name() {
+        // Specs written before a variant was added still decode: unit
+        // variants are encoded as their names, not their positions.
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&Value::Text("Sha3_256".into()), &mut cbor).unwrap();
+        let decoded: HashAlgorithm = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(decoded, HashAlgorithm::Sha3_256);
+
+        let mut keccak = Vec::new();
+        ciborium::into_writer(&HashAlgorithm::Keccak256, &mut keccak).unwrap();
+        let value: Value = ciborium::from_reader(keccak.as_slice()).unwrap();
+        assert_eq!(value, Value::Text("Keccak256".into()));
+    }
+
+    #[test]
+    fn round_trip_keyed_hash() {
+        let signable = Signable::KeyedHashThenSign {
+            key: vec![0x42; 32],
+            source: SignableSource::Range {
+                offset: 0,
+                length: 16,
+            },
+        };
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&signable, &mut cbor).unwrap();
+        let decoded: Signable = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(signable, decoded);
+    }
+
+    #[test]
+    fn round_trip_merkle_root() {
+        let signable = Signable::MerkleRoot {
+            hash: HashAlgorithm::Sha256,
+        };
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&signable, &mut cbor).unwrap();
+        let decoded: Signable = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(signable, decoded);
+    }
+
+    fn keyed_spec(key: Vec<u8>) -> SigningSpec {
+        SigningSpec {
+            version: SPEC_VERSION,
+            label: "Keyed".into(),
+            signable: Signable::KeyedHashThenSign {
+                key,
+                source: SignableSource::Whole,
+            },
+            algorithm: SignAlgorithm::Ed25519,
+            key_slot: 0,
+            output: OutputSpec::SignatureOnly,
+            require_pin_on_sign: false,
+            verify_assembly: false,
+            format: None,
+            interpreter: InterpreterSource::Usb,
+            payload_size: None,
+            not_before: None,
+            derivation_path: None,
+            nonce: None,
+            not_after: None,
+        }
+    }
+
+    #[test]
+    fn huge_declared_array_length_is_an_error() {
+        let cbor = keyed_spec(vec![7; 3]).to_cbor().unwrap();
+        // The key is encoded as a 3-element array; claim 2^64 - 1 elements instead.
+        let at = cbor.windows(4).position(|w| w == [0x83, 7, 7, 7]).unwrap();
+        let mut crafted = cbor[..at].to_vec();
+        crafted.push(0x9b);
+        crafted.extend_from_slice(&[0xff; 8]);
+        crafted.extend_from_slice(&cbor[at + 1..]);
+
+        assert!(matches!(
+            SigningSpec::from_cbor(&crafted),
+            Err(SpecError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn huge_declared_string_length_is_an_error() {
+        // Map with one entry whose key claims to be a 2^64 - 1 byte string.
+        let mut crafted = vec![0xa1, 0x7b];
+        crafted.extend_from_slice(&[0xff; 8]);
+        crafted.extend_from_slice(b"label");
+        assert!(matches!(
+            SigningSpec::from_cbor(&crafted),
+            Err(SpecError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // An unknown field holding 1000 nested one-element arrays.
+        let mut crafted = vec![0xa1, 0x61, b'x'];
+        crafted.extend_from_slice(&[0x81; 1000]);
+        crafted.push(0x00);
+        assert!(matches!(
+            SigningSpec::from_cbor(&crafted),
+            Err(SpecError::Decode(
+                ciborium::de::Error::RecursionLimitExceeded
+            ))
+        ));
+    }
+
+    #[test]
+    fn oversized_input_is_refused_before_decoding() {
+        let crafted = vec![0; MAX_SPEC_BYTES + 1];
+        assert!(matches!(
+            SigningSpec::from_cbor(&crafted),
+            Err(SpecError::TooLarge(len)) if len == MAX_SPEC_BYTES + 1
+        ));
+    }
+
+    /// `spec` encoded as a CBOR map, with `version` replaced (or dropped).
+    fn with_version(spec: &SigningSpec, version: Option<u16>) -> Vec<u8> {
+        let cbor = spec.to_cbor().unwrap();
+        let Value::Map(mut entries) = ciborium::from_reader(cbor.as_slice()).unwrap() else {
+            panic!("spec is not a map");
+        };
+        assert_eq!(entries[0].0, Value::Text("version".into()));
+        match version {
+            Some(version) => entries[0].1 = Value::Integer(version.into()),
+            None => {
+                entries.remove(0);
+            }
+        }
+        let mut out = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn unversioned_spec_is_upgraded() {
+        // A v1 spec, written before `version` and `derivation_path` existed.
+        let spec = keyed_spec(vec![1, 2, 3]);
+        let cbor = with_version(&spec, None);
+        let Value::Map(mut entries) = ciborium::from_reader(cbor.as_slice()).unwrap() else {
+            unreachable!()
+        };
+        entries.retain(|(k, _)| *k != Value::Text("derivation_path".into()));
+        let mut v1 = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut v1).unwrap();
+
+        let decoded = SigningSpec::from_cbor(&v1).unwrap();
+        assert_eq!(decoded.version, SPEC_VERSION);
+        assert_eq!(decoded, spec);
+        let explicit = SigningSpec::from_cbor(&with_version(&spec, Some(1))).unwrap();
+        assert_eq!(explicit, spec);
+    }
+
+    #[test]
+    fn version_2_spec_is_upgraded() {
+        // Written before `nonce` and `not_after` existed.
+        let spec = keyed_spec(vec![1, 2, 3]);
+        let cbor = with_version(&spec, Some(2));
+        let Value::Map(mut entries) = ciborium::from_reader(cbor.as_slice()).unwrap() else {
+            unreachable!()
+        };
+        entries.retain(|(k, _)| {
+            *k != Value::Text("nonce".into()) && *k != Value::Text("not_after".into())
+        });
+        let mut v2 = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut v2).unwrap();
+
+        let decoded = SigningSpec::from_cbor(&v2).unwrap();
+        assert_eq!(decoded.version, 3);
+        assert_eq!(decoded, spec);
+    }
+
+    #[test]
+    fn newer_spec_version_is_refused() {
+        let cbor = with_version(&keyed_spec(vec![1]), Some(999));
+        assert!(matches!(
+            SigningSpec::from_cbor(&cbor),
+            Err(SpecError::UnsupportedVersion(999))
+        ));
+    }
+
+    #[test]
+    fn range_must_fit_the_payload() {
+        let ranged = |offset, length| SigningSpec {
+            signable: Signable::Range { offset, length },
+            ..keyed_spec(vec![1])
+        };
+        ranged(2, 3).validate(5).unwrap();
+        assert!(matches!(
+            ranged(2, 4).validate(5),
+            Err(SpecError::RangeOutOfBounds {
+                offset: 2,
+                length: 4,
+                payload_len: 5
+            })
+        ));
+        // offset + length overflows rather than exceeding the payload.
+        assert!(matches!(
+            ranged(usize::MAX, 1).validate(5),
+            Err(SpecError::RangeOutOfBounds { .. })
+        ));
+        let hashed = SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Range {
+                    offset: 0,
+                    length: 6,
+                },
+            },
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(
+            hashed.validate(5),
+            Err(SpecError::RangeOutOfBounds { .. })
+        ));
+        let multi = SigningSpec {
+            signable: Signable::Multi(vec![Signable::Whole, ranged(5, 1).signable]),
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(
+            multi.validate(5),
+            Err(SpecError::RangeOutOfBounds { offset: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn empty_label_is_refused() {
+        let spec = SigningSpec {
+            label: " ".into(),
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(spec.validate(0), Err(SpecError::EmptyLabel)));
+    }
+
+    #[test]
+    fn inconsistent_specs_are_refused() {
+        assert!(matches!(
+            keyed_spec(vec![]).validate(0),
+            Err(SpecError::InvalidMacKey(0))
+        ));
+        let verified = SigningSpec {
+            verify_assembly: true,
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(
+            verified.validate(0),
+            Err(SpecError::Inconsistent(_))
+        ));
+        let prefixed = SigningSpec {
+            signable: Signable::Prefixed {
+                prefix: b"tag".to_vec(),
+                inner: Box::new(Signable::Interpreter),
+            },
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(
+            prefixed.validate(0),
+            Err(SpecError::Inconsistent(_))
+        ));
+        for items in [vec![], vec![Signable::Interpreter]] {
+            let multi = SigningSpec {
+                signable: Signable::Multi(items),
+                ..keyed_spec(vec![1])
+            };
+            assert!(matches!(multi.validate(0), Err(SpecError::Inconsistent(_))));
+        }
+        let derived = SigningSpec {
+            algorithm: SignAlgorithm::Secp256k1Ecdsa,
+            derivation_path: Some(vec![0x8000_0000]),
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(
+            derived.validate(0),
+            Err(SpecError::Inconsistent(_))
+        ));
+        let soft = SigningSpec {
+            derivation_path: Some(vec![0x8000_002c, 0]),
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(soft.validate(0), Err(SpecError::Inconsistent(_))));
+        let hardened = SigningSpec {
+            derivation_path: Some(vec![0x8000_002c, 0x8000_0000]),
+            ..keyed_spec(vec![1])
+        };
+        hardened.validate(0).unwrap();
+        let psbt = SigningSpec {
+            output: OutputSpec::PsbtInsert { input_index: 0 },
+            ..keyed_spec(vec![1])
+        };
+        assert!(matches!(psbt.validate(0), Err(SpecError::Inconsistent(_))));
+        let psbt = SigningSpec {
+            algorithm: SignAlgorithm::Secp256k1Ecdsa,
+            ..psbt
+        };
+        psbt.validate(0).unwrap();
+        for name in ["../x", "dir/x", "dir\\x", "", "sign.cbor"] {
+            let detached = SigningSpec {
+                output: OutputSpec::DetachedFile { name: name.into() },
+                ..keyed_spec(vec![1])
+            };
+            assert!(matches!(
+                detached.validate(0),
+                Err(SpecError::BadFileName(n)) if n == name
+            ));
+        }
+    }
+
+    #[test]
+    fn largest_valid_spec_fits_the_limits() {
+        let spec = keyed_spec(vec![0xff; 64]);
+        let cbor = spec.to_cbor().unwrap();
+        assert_eq!(SigningSpec::from_cbor(&cbor).unwrap(), spec);
+    }
 }
@@ -0,0 +1,197 @@
+//! Integrity record binding the files on a prepared stick together.
+//!
+//! `usb-pack` writes [`MANIFEST_FILE`] next to the files it packs: the
+//! BLAKE2b-256 of `payload.bin`, `interpreter.wasm` (if any) and
+//! `sign.cbor`, and the version of the tool that packed them. A file
+//! swapped or corrupted after packing then no longer matches. A stick
+//! packed with `--precompile` also records `interpreter.cwasm`, which a
+//! device only loads if it matches.
+
+use crate::crypto::{hash_bytes, StreamHasher};
+use crate::spec::{HashAlgorithm, Signable, SignableSource};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Name of the manifest on the stick.
+pub const MANIFEST_FILE: &str = "manifest.cbor";
+
+/// Deepest nesting accepted when decoding a manifest.
+const MAX_MANIFEST_DEPTH: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum StickManifestError {
+    #[error("invalid {MANIFEST_FILE}: {0}")]
+    Decode(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("{0} does not match {MANIFEST_FILE}")]
+    Mismatch(&'static str),
+}
+
+/// BLAKE2b-256 digests of a stick's files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the tool that packed the stick, for reading only.
+    pub tool_version: String,
+    pub payload: Vec<u8>,
+    /// `None` when the stick carries no interpreter.
+    pub interpreter: Option<Vec<u8>>,
+    pub spec: Vec<u8>,
+    /// `None` when the stick carries no precompiled interpreter.
+    #[serde(default)]
+    pub precompiled: Option<Vec<u8>>,
+}
+
+impl Manifest {
+    /// Record the digests of a stick's files.
+    pub fn new(
+        tool_version: &str,
+        payload: &[u8],
+        interpreter: Option<&[u8]>,
+        spec_cbor: &[u8],
+    ) -> Self {
+        Self {
+            tool_version: tool_version.into(),
+            payload: digest(payload),
+            interpreter: interpreter.map(digest),
+            spec: digest(spec_cbor),
+            precompiled: None,
+        }
+    }
+
+    /// Also record `interpreter.cwasm`, compiled from the interpreter.
+    pub fn with_precompiled(mut self, cwasm: &[u8]) -> Self {
+        self.precompiled = Some(digest(cwasm));
+        self
+    }
+
+    /// Check `interpreter.cwasm` against the recorded digest. A manifest
+    /// that records none matches no artifact.
+    pub fn verify_precompiled(&self, cwasm: &[u8]) -> Result<(), StickManifestError> {
+        if self.precompiled.as_deref() != Some(&digest(cwasm)[..]) {
+            return Err(StickManifestError::Mismatch("interpreter.cwasm"));
+        }
+        Ok(())
+    }
+
+    /// Check a stick's files against the recorded digests, naming the
+    /// first file that differs.
+    pub fn verify(
+        &self,
+        payload: &[u8],
+        interpreter: Option<&[u8]>,
+        spec_cbor: &[u8],
+    ) -> Result<(), StickManifestError> {
+        self.verify_payload_digest(&digest(payload))?;
+        self.verify_files(interpreter, spec_cbor)
+    }
+
+    /// Check the BLAKE2b-256 of `payload.bin`, for a payload that was
+    /// hashed while streaming it (see [`payload_hasher`]).
+    pub fn verify_payload_digest(&self, payload_digest: &[u8]) -> Result<(), StickManifestError> {
+        if payload_digest != self.payload {
+            return Err(StickManifestError::Mismatch("payload.bin"));
+        }
+        Ok(())
+    }
+
+    /// Check every file but the payload against the recorded digests.
+    pub fn verify_files(
+        &self,
+        interpreter: Option<&[u8]>,
+        spec_cbor: &[u8],
+    ) -> Result<(), StickManifestError> {
+        if interpreter.map(digest) != self.interpreter {
+            return Err(StickManifestError::Mismatch("interpreter.wasm"));
+        }
+        if digest(spec_cbor) != self.spec {
+            return Err(StickManifestError::Mismatch("sign.cbor"));
+        }
+        Ok(())
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, StickManifestError> {
+        Ok(ciborium::de::from_reader_with_recursion_limit(
+            bytes,
+            MAX_MANIFEST_DEPTH,
+        )?)
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(self, &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+}
+
+fn digest(data: &[u8]) -> Vec<u8> {
+    hash_bytes(HashAlgorithm::Blake2b256, data)
+}
+
+/// Hasher computing the manifest's payload digest from a payload fed to
+/// it in pieces.
+pub fn payload_hasher() -> StreamHasher {
+    let whole = Signable::HashThenSign {
+        hash: HashAlgorithm::Blake2b256,
+        source: SignableSource::Whole,
+    };
+    StreamHasher::for_signable(&whole)
+        .expect("BLAKE2b-256 takes no key")
+        .expect("hashing modes stream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_files_verify() {
+        let manifest = Manifest::new("0.1.0", b"tx", Some(b"\0asm"), b"spec");
+        let decoded = Manifest::from_cbor(&manifest.to_cbor()).unwrap();
+        assert_eq!(decoded, manifest);
+        decoded.verify(b"tx", Some(b"\0asm"), b"spec").unwrap();
+
+        let blind = Manifest::new("0.1.0", b"tx", None, b"spec");
+        blind.verify(b"tx", None, b"spec").unwrap();
+    }
+
+    #[test]
+    fn tampered_files_are_named() {
+        let manifest = Manifest::new("0.1.0", b"tx", Some(b"\0asm"), b"spec");
+        let mismatch = |payload: &[u8], interpreter: Option<&[u8]>, spec: &[u8]| match manifest
+            .verify(payload, interpreter, spec)
+        {
+            Err(StickManifestError::Mismatch(file)) => file,
+            other => panic!("expected a mismatch, got {other:?}"),
+        };
+        assert_eq!(mismatch(b"tX", Some(b"\0asm"), b"spec"), "payload.bin");
+        assert_eq!(mismatch(b"tx", Some(b"\0asn"), b"spec"), "interpreter.wasm");
+        assert_eq!(mismatch(b"tx", None, b"spec"), "interpreter.wasm");
+        assert_eq!(mismatch(b"tx", Some(b"\0asm"), b"spex"), "sign.cbor");
+    }
+
+    #[test]
+    fn precompiled_interpreter_must_be_recorded() {
+        let manifest = Manifest::new("0.1.0", b"tx", Some(b"\0asm"), b"spec");
+        assert!(matches!(
+            manifest.verify_precompiled(b"native"),
+            Err(StickManifestError::Mismatch("interpreter.cwasm"))
+        ));
+        let manifest = manifest.with_precompiled(b"native");
+        let decoded = Manifest::from_cbor(&manifest.to_cbor()).unwrap();
+        decoded.verify_precompiled(b"native").unwrap();
+        assert!(matches!(
+            decoded.verify_precompiled(b"nativE"),
+            Err(StickManifestError::Mismatch("interpreter.cwasm"))
+        ));
+    }
+
+    #[test]
+    fn streamed_payload_digest_matches() {
+        let manifest = Manifest::new("0.1.0", b"payload", None, b"spec");
+        let mut hasher = payload_hasher();
+        hasher.update(b"pay");
+        hasher.update(b"load");
+        manifest
+            .verify_payload_digest(&hasher.finalize().unwrap())
+            .unwrap();
+    }
+}
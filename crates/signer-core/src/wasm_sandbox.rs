@@ -1,24 +1,96 @@
+use std::io::Read;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use thiserror::Error;
-use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime::{
+    Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
 
-/// Fuel budget: 10 million operations.
-const FUEL_LIMIT: u64 = 10_000_000;
+/// Default fuel budget: 10 million operations.
+pub const FUEL_LIMIT: u64 = 10_000_000;
 
-/// Memory cap: 16 MB.
-const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+/// Default memory cap: 16 MB.
+pub const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Default call stack limit: 512 KiB.
+pub const MAX_STACK_BYTES: usize = 512 * 1024;
+
+/// Default wall-clock limit per instance: 10 seconds.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// In debug builds, pages the guest grows are filled with this byte instead
+/// of zero, so output pointing at memory the interpreter never wrote shows up
+/// as a run of it. Interpreters must write (or zero) every output byte.
+pub const SENTINEL: u8 = 0xCD;
+
+/// Sentinel bytes in a row that mark output as unwritten.
+const SENTINEL_RUN: usize = 16;
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum SandboxError {
     #[error("WASM engine error: {0}")]
-    Engine(#[from] wasmtime::Error),
+    Engine(wasmtime::Error),
+    #[error("WASM execution exceeded its time limit")]
+    Timeout,
+    /// The guest trapped; `reason` is the trap's description, e.g. for
+    /// `unreachable`, an out-of-bounds access or fuel exhaustion.
+    #[error("WASM module trapped: {reason}")]
+    Trap { reason: String },
     #[error("module has no '{0}' export")]
     MissingExport(String),
-    #[error("interpret returned null pointer")]
-    NullPointer,
+    #[error("module is missing exports: {}", .0.join(", "))]
+    MissingExports(Vec<String>),
+    #[error("'{0}' returned null pointer")]
+    NullPointer(String),
     #[error("output length {0} exceeds sandbox memory")]
     OutputOverflow(usize),
     #[error("invalid UTF-8 in WASM output")]
     InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("interpret_chunk rejected the chunk at byte {offset} (status {status})")]
+    ChunkRejected { offset: usize, status: i32 },
+    #[error("failed to read payload: {0}")]
+    PayloadRead(#[from] std::io::Error),
+    /// Only detected in debug builds; see [`SENTINEL`].
+    #[error("WASM output at byte {0} was never written by the interpreter")]
+    UninitializedOutput(usize),
+}
+
+impl From<wasmtime::Error> for SandboxError {
+    fn from(e: wasmtime::Error) -> Self {
+        match e.downcast_ref::<Trap>() {
+            Some(Trap::Interrupt) => SandboxError::Timeout,
+            Some(trap) => SandboxError::Trap {
+                reason: trap.to_string(),
+            },
+            _ => SandboxError::Engine(e),
+        }
+    }
+}
+
+/// Resource limits applied to every instance of a [`Sandbox`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SandboxConfig {
+    /// Fuel budget per instance, in operations.
+    pub fuel: u64,
+    /// Linear memory cap per instance.
+    pub max_memory_bytes: usize,
+    /// WASM call stack limit.
+    pub max_stack: usize,
+    /// Wall-clock limit per instance, which fuel alone does not bound.
+    pub timeout: Duration,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            fuel: FUEL_LIMIT,
+            max_memory_bytes: MAX_MEMORY_BYTES,
+            max_stack: MAX_STACK_BYTES,
+            timeout: TIMEOUT,
+        }
+    }
 }
 
 /// Sandboxed WASM interpreter engine.
@@ -27,15 +99,27 @@ pub enum SandboxError {
 /// Fuel-metered and memory-capped.
 pub struct Sandbox {
     engine: Engine,
+    config: SandboxConfig,
 }
 
 impl Sandbox {
+    /// A sandbox with the default [`SandboxConfig`].
     pub fn new() -> Result<Self, SandboxError> {
+        Self::with_config(SandboxConfig::default())
+    }
+
+    /// A sandbox with raised or lowered limits, e.g. for large transactions
+    /// or a memory-starved device.
+    pub fn with_config(limits: SandboxConfig) -> Result<Self, SandboxError> {
         let mut config = Config::new();
         config.consume_fuel(true);
-        config.max_wasm_stack(512 * 1024); // 512 KiB call stack
+        config.max_wasm_stack(limits.max_stack);
+        config.epoch_interruption(true);
+        #[cfg(debug_assertions)]
+        sentinel::install(&mut config, limits.max_memory_bytes);
         Ok(Self {
             engine: Engine::new(&config)?,
+            config: limits,
         })
     }
 
@@ -44,28 +128,325 @@ impl Sandbox {
         let module = Module::new(&self.engine, wasm_bytes)?;
         Ok(SandboxModule {
             engine: &self.engine,
+            config: &self.config,
+            module,
+        })
+    }
+
+    /// Compile `wasm_bytes` ahead of time to a native artifact that
+    /// [`load_precompiled`](Self::load_precompiled) loads without compiling.
+    ///
+    /// The artifact only loads into a sandbox with the same configuration on
+    /// the same architecture.
+    pub fn precompile(&self, wasm_bytes: &[u8]) -> Result<Vec<u8>, SandboxError> {
+        Ok(self.engine.precompile_module(wasm_bytes)?)
+    }
+
+    /// Load a module from an artifact made by [`precompile`](Self::precompile).
+    ///
+    /// Fails when the artifact was made for another architecture or sandbox
+    /// configuration; callers can then compile the source module instead.
+    ///
+    /// # Safety
+    ///
+    /// The artifact is native code that runs unchecked: only bytes produced by
+    /// [`precompile`](Self::precompile) on a trusted host may be loaded.
+    pub unsafe fn load_precompiled(&self, bytes: &[u8]) -> Result<SandboxModule<'_>, SandboxError> {
+        let module = Module::deserialize(&self.engine, bytes)?;
+        Ok(SandboxModule {
+            engine: &self.engine,
+            config: &self.config,
             module,
         })
     }
 }
 
-fn new_store(engine: &Engine) -> Result<Store<StoreLimits>, SandboxError> {
+/// Bumps the engine epoch once its timeout passes, unless dropped first.
+///
+/// Stores run with an epoch deadline of one tick, so the bump interrupts
+/// whatever the store is executing with [`Trap::Interrupt`]. The epoch is
+/// shared by every store of the engine, so a watchdog must be dropped as
+/// soon as its store's call returns, or it interrupts another store's.
+struct Watchdog {
+    _cancel: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    fn start(engine: &Engine, timeout: Duration) -> Self {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let engine = engine.clone();
+        thread::spawn(move || {
+            if cancelled.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                engine.increment_epoch();
+            }
+        });
+        Self { _cancel: cancel }
+    }
+}
+
+/// Per-instance state: the memory limiter and the watchdog timing it,
+/// present only while the store executes.
+struct StoreState {
+    limits: StoreLimits,
+    timeout: Duration,
+    watchdog: Option<Watchdog>,
+}
+
+fn new_store(engine: &Engine, config: &SandboxConfig) -> Result<Store<StoreState>, SandboxError> {
     let limits = StoreLimitsBuilder::new()
-        .memory_size(MAX_MEMORY_BYTES)
+        .memory_size(config.max_memory_bytes)
         .build();
-    let mut store = Store::new(engine, limits);
-    store.limiter(|s| s);
-    store.set_fuel(FUEL_LIMIT)?;
+    let mut store = Store::new(
+        engine,
+        StoreState {
+            limits,
+            timeout: config.timeout,
+            watchdog: Some(Watchdog::start(engine, config.timeout)),
+        },
+    );
+    store.limiter(|s| &mut s.limits);
+    store.set_fuel(config.fuel)?;
+    store.set_epoch_deadline(1);
     Ok(store)
 }
 
+/// Read until `buf` is full or the reader is exhausted; returns bytes read.
+fn fill(reader: &mut dyn Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Read a length-prefixed (4 bytes LE) result from guest memory.
+fn read_result_bytes(
+    memory: &Memory,
+    store: &Store<StoreState>,
+    result_ptr: i32,
+) -> Result<Vec<u8>, SandboxError> {
+    let mem_data = memory.data(store);
+    let result_offset = result_ptr as u32 as usize;
+    if result_offset + 4 > mem_data.len() {
+        return Err(SandboxError::OutputOverflow(result_offset + 4));
+    }
+    let len = u32::from_le_bytes(
+        mem_data[result_offset..result_offset + 4]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    if result_offset + 4 + len > mem_data.len() {
+        return Err(SandboxError::OutputOverflow(len));
+    }
+    Ok(mem_data[result_offset + 4..result_offset + 4 + len].to_vec())
+}
+
+/// Read a length-prefixed (4 bytes LE) UTF-8 JSON result from guest memory.
+fn read_result_json(
+    memory: &Memory,
+    store: &Store<StoreState>,
+    result_ptr: i32,
+) -> Result<String, SandboxError> {
+    let json_bytes = read_result_bytes(memory, store, result_ptr)?;
+    if cfg!(debug_assertions) {
+        if let Some(at) = json_bytes
+            .windows(SENTINEL_RUN)
+            .position(|run| run.iter().all(|&b| b == SENTINEL))
+        {
+            return Err(SandboxError::UninitializedOutput(at));
+        }
+    }
+    Ok(String::from_utf8(json_bytes)?)
+}
+
+/// Host-allocated linear memory that fills grown pages with [`SENTINEL`].
+#[cfg(debug_assertions)]
+mod sentinel {
+    use super::SENTINEL;
+    use std::alloc::{self, Layout};
+    use std::sync::Arc;
+    use wasmtime::{Config, LinearMemory, MemoryCreator, MemoryType};
+
+    /// Back guest memories with [`SentinelMemory`]. Without a reservation or
+    /// guard region, generated code bounds-checks every access. Host memories
+    /// cannot be initialized copy-on-write, so data segments are copied in.
+    pub fn install(config: &mut Config, max_memory_bytes: usize) {
+        config
+            .with_host_memory(Arc::new(SentinelCreator { max_memory_bytes }))
+            .memory_init_cow(false)
+            .memory_reservation(0)
+            .memory_reservation_for_growth(0)
+            .memory_guard_size(0);
+    }
+
+    struct SentinelCreator {
+        max_memory_bytes: usize,
+    }
+
+    unsafe impl MemoryCreator for SentinelCreator {
+        fn new_memory(
+            &self,
+            _ty: MemoryType,
+            minimum: usize,
+            maximum: Option<usize>,
+            reserved_size_in_bytes: Option<usize>,
+            guard_size_in_bytes: usize,
+        ) -> Result<Box<dyn LinearMemory>, String> {
+            if reserved_size_in_bytes.unwrap_or(0) != 0 || guard_size_in_bytes != 0 {
+                return Err("sentinel memory needs a memory without reservation or guard".into());
+            }
+            // The store caps memory anyway, so allocate the cap up front and
+            // never move. Zeroed, as the initial pages must be.
+            let capacity = maximum
+                .unwrap_or(self.max_memory_bytes)
+                .min(self.max_memory_bytes);
+            if minimum > capacity {
+                return Err(format!("memory of {minimum} bytes exceeds the sandbox cap"));
+            }
+            let layout =
+                Layout::from_size_align(capacity.max(1), WASM_PAGE).map_err(|e| e.to_string())?;
+            // SAFETY: the layout has a non-zero size.
+            let ptr = unsafe { alloc::alloc_zeroed(layout) };
+            if ptr.is_null() {
+                return Err("out of memory".into());
+            }
+            Ok(Box::new(SentinelMemory {
+                ptr,
+                layout,
+                size: minimum,
+            }))
+        }
+    }
+
+    const WASM_PAGE: usize = 64 * 1024;
+
+    struct SentinelMemory {
+        ptr: *mut u8,
+        layout: Layout,
+        size: usize,
+    }
+
+    // SAFETY: the allocation is owned exclusively by this memory.
+    unsafe impl Send for SentinelMemory {}
+    unsafe impl Sync for SentinelMemory {}
+
+    impl Drop for SentinelMemory {
+        fn drop(&mut self) {
+            // SAFETY: allocated in `new_memory` with this layout.
+            unsafe { alloc::dealloc(self.ptr, self.layout) }
+        }
+    }
+
+    unsafe impl LinearMemory for SentinelMemory {
+        fn byte_size(&self) -> usize {
+            self.size
+        }
+
+        fn byte_capacity(&self) -> usize {
+            self.layout.size()
+        }
+
+        fn grow_to(&mut self, new_size: usize) -> wasmtime::Result<()> {
+            if new_size > self.layout.size() {
+                return Err(wasmtime::Error::msg(format!(
+                    "memory of {new_size} bytes exceeds the sandbox cap"
+                )));
+            }
+            if new_size > self.size {
+                // SAFETY: `size..new_size` lies within the allocation.
+                unsafe {
+                    self.ptr
+                        .add(self.size)
+                        .write_bytes(SENTINEL, new_size - self.size)
+                };
+            }
+            self.size = new_size;
+            Ok(())
+        }
+
+        fn as_ptr(&self) -> *mut u8 {
+            self.ptr
+        }
+    }
+}
+
 /// A loaded WASM module ready to execute.
 pub struct SandboxModule<'a> {
     engine: &'a Engine,
+    config: &'a SandboxConfig,
     module: Module,
 }
 
 impl SandboxModule<'_> {
+    /// Whether the module exports an item with this name.
+    pub fn has_export(&self, name: &str) -> bool {
+        self.module.get_export(name).is_some()
+    }
+
+    /// Instantiate the module once and check it has every export the
+    /// signing cycle will call, with the right signature, reporting all
+    /// missing ones together.
+    ///
+    /// Always required: `memory`, `alloc`, and `interpret` (or the
+    /// `interpret_chunk`/`interpret_finish` pair). With `needs_assemble`,
+    /// also `assemble`, satisfied by `assemble_with_inputs` or `finalize`.
+    pub fn validate_exports(&self, needs_assemble: bool) -> Result<(), SandboxError> {
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let mut missing = Vec::new();
+        if instance.get_memory(&mut store, "memory").is_none() {
+            missing.push("memory");
+        }
+        if instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .is_err()
+        {
+            missing.push("alloc");
+        }
+        let interpret = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "interpret")
+            .is_ok();
+        let chunked = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "interpret_chunk")
+            .is_ok()
+            && instance
+                .get_typed_func::<(), i32>(&mut store, "interpret_finish")
+                .is_ok();
+        if !interpret && !chunked {
+            missing.push("interpret");
+        }
+        if needs_assemble {
+            let assemble = instance
+                .get_typed_func::<(i32, i32, i32, i32), i32>(&mut store, "assemble")
+                .is_ok()
+                || instance
+                    .get_typed_func::<(i32, i32, i32, i32, i32, i32), i32>(
+                        &mut store,
+                        "assemble_with_inputs",
+                    )
+                    .is_ok()
+                || instance
+                    .get_typed_func::<(i32, i32), i32>(&mut store, "finalize")
+                    .is_ok();
+            if !assemble {
+                missing.push("assemble");
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(SandboxError::MissingExports(
+                missing.into_iter().map(String::from).collect(),
+            ))
+        }
+    }
+
     /// Call `interpret(ptr, len) -> ptr` on the WASM module.
     ///
     /// The module must export:
@@ -74,8 +455,16 @@ impl SandboxModule<'_> {
     /// - `interpret(ptr, len) -> ptr`: interpret payload, return pointer to
     ///   length-prefixed (4 bytes LE) UTF-8 JSON string
     pub fn interpret(&self, payload: &[u8]) -> Result<String, SandboxError> {
-        let linker: Linker<StoreLimits> = Linker::new(self.engine);
-        let mut store = new_store(self.engine)?;
+        self.interpret_metered(payload).map(|(json, _)| json)
+    }
+
+    /// Like [`interpret`](Self::interpret), also returning the fuel the
+    /// instance consumed (out of [`SandboxConfig::fuel`]), for judging how
+    /// close an interpreter comes to the cap.
+    pub fn interpret_metered(&self, payload: &[u8]) -> Result<(String, u64), SandboxError> {
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let fuel_before = store.get_fuel()?;
 
         let instance = linker.instantiate(&mut store, &self.module)?;
 
@@ -83,18 +472,8 @@ impl SandboxModule<'_> {
             .get_memory(&mut store, "memory")
             .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
 
-        // Allocate space in WASM memory for the payload
-        let alloc = instance
-            .get_typed_func::<i32, i32>(&mut store, "alloc")
-            .map_err(|_| SandboxError::MissingExport("alloc".into()))?;
-        let payload_ptr = alloc.call(&mut store, payload.len() as i32)?;
-        if payload_ptr == 0 {
-            return Err(SandboxError::NullPointer);
-        }
-
         // Copy payload into WASM memory
-        memory.data_mut(&mut store)[payload_ptr as usize..payload_ptr as usize + payload.len()]
-            .copy_from_slice(payload);
+        let payload_ptr = alloc(&instance, &mut store, &memory, payload)?;
 
         // Call interpret
         let interpret = instance
@@ -102,33 +481,188 @@ impl SandboxModule<'_> {
             .map_err(|_| SandboxError::MissingExport("interpret".into()))?;
         let result_ptr = interpret.call(&mut store, (payload_ptr, payload.len() as i32))?;
         if result_ptr == 0 {
-            return Err(SandboxError::NullPointer);
+            return Err(SandboxError::NullPointer("interpret".into()));
+        }
+        let json = read_result_json(&memory, &store, result_ptr)?;
+        dealloc(&instance, &mut store, payload_ptr, payload.len())?;
+        let consumed = fuel_before - store.get_fuel()?;
+
+        Ok((json, consumed))
+    }
+
+    /// Call the optional `schema() -> ptr` export, returning its
+    /// length-prefixed JSON [`crate::display::DisplaySchema`], or `None` if
+    /// the module does not export one.
+    pub fn schema(&self) -> Result<Option<String>, SandboxError> {
+        if !self.has_export("schema") {
+            return Ok(None);
         }
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
+        let schema = instance
+            .get_typed_func::<(), i32>(&mut store, "schema")
+            .map_err(|_| SandboxError::MissingExport("schema".into()))?;
+        let result_ptr = schema.call(&mut store, ())?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("schema".into()));
+        }
+        read_result_json(&memory, &store, result_ptr).map(Some)
+    }
+
+    /// Call the optional `validate(ptr, len) -> status` export on `payload`,
+    /// returning its status (0 accepts, anything else rejects), or `None` if
+    /// the module does not export one.
+    pub fn validate(&self, payload: &[u8]) -> Result<Option<i32>, SandboxError> {
+        if !self.has_export("validate") {
+            return Ok(None);
+        }
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
+        let payload_ptr = alloc(&instance, &mut store, &memory, payload)?;
+        let validate = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "validate")
+            .map_err(|_| SandboxError::MissingExport("validate".into()))?;
+        let status = validate.call(&mut store, (payload_ptr, payload.len() as i32))?;
+        Ok(Some(status))
+    }
 
-        // Read length-prefixed result: 4 bytes LE length, then UTF-8 JSON
-        let mem_data = memory.data(&store);
-        let result_offset = result_ptr as usize;
-        if result_offset + 4 > mem_data.len() {
-            return Err(SandboxError::OutputOverflow(result_offset + 4));
+    /// Call the optional `metadata() -> ptr` export, returning its
+    /// length-prefixed JSON `{"name": ..., "version": ...}`, or `None` if
+    /// the module does not export one.
+    pub fn metadata(&self) -> Result<Option<String>, SandboxError> {
+        if !self.has_export("metadata") {
+            return Ok(None);
         }
-        let len = u32::from_le_bytes(
-            mem_data[result_offset..result_offset + 4]
-                .try_into()
-                .unwrap(),
-        ) as usize;
-        if result_offset + 4 + len > mem_data.len() {
-            return Err(SandboxError::OutputOverflow(len));
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
+        let metadata = instance
+            .get_typed_func::<(), i32>(&mut store, "metadata")
+            .map_err(|_| SandboxError::MissingExport("metadata".into()))?;
+        let result_ptr = metadata.call(&mut store, ())?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("metadata".into()));
+        }
+        read_result_json(&memory, &store, result_ptr).map(Some)
+    }
+
+    /// Interpret `payload` in `chunk_size` pieces, calling `on_progress(done, total)`
+    /// after each one so the host can show progress on large payloads.
+    ///
+    /// Uses the chunked ABI when the module exports `interpret_chunk` and
+    /// `interpret_finish`; otherwise falls back to a single `interpret` call
+    /// and reports progress once at the end.
+    pub fn interpret_chunked(
+        &self,
+        payload: &[u8],
+        chunk_size: usize,
+        on_progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<String, SandboxError> {
+        let mut reader = payload;
+        self.interpret_reader(&mut reader, payload.len(), chunk_size, on_progress)
+    }
+
+    /// [`interpret_chunked`](Self::interpret_chunked) reading the `len`-byte
+    /// payload from `reader`, so a module with the chunked ABI never needs
+    /// the whole payload in host memory. Other modules get it read in full.
+    pub fn interpret_reader(
+        &self,
+        reader: &mut dyn Read,
+        len: usize,
+        chunk_size: usize,
+        on_progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<String, SandboxError> {
+        if !self.has_export("interpret_chunk") || !self.has_export("interpret_finish") {
+            let mut payload = Vec::with_capacity(len);
+            reader.read_to_end(&mut payload)?;
+            let json = self.interpret(&payload)?;
+            on_progress(payload.len(), payload.len());
+            return Ok(json);
+        }
+
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
+        let interpret_chunk = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "interpret_chunk")
+            .map_err(|_| SandboxError::MissingExport("interpret_chunk".into()))?;
+        let interpret_finish = instance
+            .get_typed_func::<(), i32>(&mut store, "interpret_finish")
+            .map_err(|_| SandboxError::MissingExport("interpret_finish".into()))?;
+
+        let mut buf = vec![0u8; chunk_size.max(1)];
+        let mut done = 0;
+        loop {
+            let filled = fill(reader, &mut buf)?;
+            if filled == 0 {
+                break;
+            }
+            let chunk = &buf[..filled];
+            let ptr = alloc(&instance, &mut store, &memory, chunk)?;
+            let status = interpret_chunk.call(&mut store, (ptr, chunk.len() as i32))?;
+            if status != 0 {
+                return Err(SandboxError::ChunkRejected {
+                    offset: done,
+                    status,
+                });
+            }
+            done += chunk.len();
+            on_progress(done, len);
+        }
+
+        let result_ptr = interpret_finish.call(&mut store, ())?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("interpret_finish".into()));
         }
-        let json_bytes = mem_data[result_offset + 4..result_offset + 4 + len].to_vec();
-        Ok(String::from_utf8(json_bytes)?)
+        read_result_json(&memory, &store, result_ptr)
     }
 
     /// Call `assemble(payload_ptr, payload_len, sig_ptr, sig_len) -> ptr` on the WASM module.
     ///
     /// Returns length-prefixed output bytes (same convention as `interpret`).
     pub fn assemble(&self, payload: &[u8], signature: &[u8]) -> Result<Vec<u8>, SandboxError> {
-        let linker: Linker<StoreLimits> = Linker::new(self.engine);
-        let mut store = new_store(self.engine)?;
+        self.call_assemble(payload, signature, None)
+    }
+
+    /// Call `assemble_with_inputs(payload_ptr, payload_len, sig_ptr, sig_len, inputs_ptr, inputs_len) -> ptr`.
+    ///
+    /// `inputs` is the JSON object of values the user entered on the device
+    /// in response to the interpreter's input requests.
+    pub fn assemble_with_inputs(
+        &self,
+        payload: &[u8],
+        signature: &[u8],
+        inputs: &[u8],
+    ) -> Result<Vec<u8>, SandboxError> {
+        self.call_assemble(payload, signature, Some(inputs))
+    }
+
+    fn call_assemble(
+        &self,
+        payload: &[u8],
+        signature: &[u8],
+        inputs: Option<&[u8]>,
+    ) -> Result<Vec<u8>, SandboxError> {
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
 
         let instance = linker.instantiate(&mut store, &self.module)?;
 
@@ -136,43 +670,190 @@ impl SandboxModule<'_> {
             .get_memory(&mut store, "memory")
             .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
 
-        let alloc = instance
-            .get_typed_func::<i32, i32>(&mut store, "alloc")
-            .map_err(|_| SandboxError::MissingExport("alloc".into()))?;
-
-        // Allocate and copy payload
-        let payload_ptr = alloc.call(&mut store, payload.len() as i32)?;
-        memory.data_mut(&mut store)[payload_ptr as usize..payload_ptr as usize + payload.len()]
-            .copy_from_slice(payload);
-
-        // Allocate and copy signature
-        let sig_ptr = alloc.call(&mut store, signature.len() as i32)?;
-        memory.data_mut(&mut store)[sig_ptr as usize..sig_ptr as usize + signature.len()]
-            .copy_from_slice(signature);
-
-        let assemble = instance
-            .get_typed_func::<(i32, i32, i32, i32), i32>(&mut store, "assemble")
-            .map_err(|_| SandboxError::MissingExport("assemble".into()))?;
-        let result_ptr = assemble.call(
-            &mut store,
-            (
-                payload_ptr,
-                payload.len() as i32,
-                sig_ptr,
-                signature.len() as i32,
-            ),
-        )?;
+        // Allocate and copy payload and signature
+        let payload_ptr = alloc(&instance, &mut store, &memory, payload)?;
+        let sig_ptr = alloc(&instance, &mut store, &memory, signature)?;
+
+        let args = (
+            payload_ptr,
+            payload.len() as i32,
+            sig_ptr,
+            signature.len() as i32,
+        );
+        let export = match inputs {
+            None => "assemble",
+            Some(_) => "assemble_with_inputs",
+        };
+        let result_ptr = match inputs {
+            None => {
+                let assemble = instance
+                    .get_typed_func::<(i32, i32, i32, i32), i32>(&mut store, export)
+                    .map_err(|_| SandboxError::MissingExport(export.into()))?;
+                assemble.call(&mut store, args)?
+            }
+            Some(inputs) => {
+                let inputs_ptr = alloc(&instance, &mut store, &memory, inputs)?;
+                let assemble = instance
+                    .get_typed_func::<(i32, i32, i32, i32, i32, i32), i32>(&mut store, export)
+                    .map_err(|_| SandboxError::MissingExport(export.into()))?;
+                assemble.call(
+                    &mut store,
+                    (
+                        args.0,
+                        args.1,
+                        args.2,
+                        args.3,
+                        inputs_ptr,
+                        inputs.len() as i32,
+                    ),
+                )?
+            }
+        };
         if result_ptr == 0 {
-            return Err(SandboxError::NullPointer);
+            return Err(SandboxError::NullPointer(export.into()));
         }
+        read_result_bytes(&memory, &store, result_ptr)
+    }
+
+    /// Instantiate the module with `payload` copied in once, for the
+    /// single-instance ABI (`interpret`, then `signable` and `finalize`).
+    pub fn instantiate(&self, payload: &[u8]) -> Result<PayloadInstance, SandboxError> {
+        let linker: Linker<StoreState> = Linker::new(self.engine);
+        let mut store = new_store(self.engine, self.config)?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| SandboxError::MissingExport("memory".into()))?;
+        let payload_ptr = alloc(&instance, &mut store, &memory, payload)?;
+        store.data_mut().watchdog = None;
+        Ok(PayloadInstance {
+            store,
+            instance,
+            memory,
+            payload: (payload_ptr, payload.len() as i32),
+        })
+    }
+}
+
+/// Call `alloc` and copy `data` into the allocation.
+fn alloc(
+    instance: &Instance,
+    store: &mut Store<StoreState>,
+    memory: &Memory,
+    data: &[u8],
+) -> Result<i32, SandboxError> {
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut *store, "alloc")
+        .map_err(|_| SandboxError::MissingExport("alloc".into()))?;
+    let ptr = alloc.call(&mut *store, data.len() as i32)?;
+    if ptr == 0 {
+        return Err(SandboxError::NullPointer("alloc".into()));
+    }
+    // Pointers are unsigned in wasm32.
+    let start = ptr as u32 as usize;
+    let end = start + data.len();
+    memory
+        .data_mut(&mut *store)
+        .get_mut(start..end)
+        .ok_or(SandboxError::OutputOverflow(end))?
+        .copy_from_slice(data);
+    Ok(ptr)
+}
+
+/// Hand `len` bytes at `ptr` back to the module's optional
+/// `dealloc(ptr, size)` export; without one the bytes stay allocated.
+fn dealloc(
+    instance: &Instance,
+    store: &mut Store<StoreState>,
+    ptr: i32,
+    len: usize,
+) -> Result<(), SandboxError> {
+    if let Ok(dealloc) = instance.get_typed_func::<(i32, i32), ()>(&mut *store, "dealloc") {
+        dealloc.call(&mut *store, (ptr, len as i32))?;
+    }
+    Ok(())
+}
 
-        let mem_data = memory.data(&store);
-        let result_offset = result_ptr as usize;
-        let len = u32::from_le_bytes(
-            mem_data[result_offset..result_offset + 4]
-                .try_into()
-                .unwrap(),
-        ) as usize;
-        Ok(mem_data[result_offset + 4..result_offset + 4 + len].to_vec())
+/// A module instance holding one payload for a whole signing cycle.
+///
+/// The module interprets the payload, optionally computes the bytes to sign
+/// (`signable() -> ptr`), and builds the output from the signature
+/// (`finalize(sig_ptr, sig_len) -> ptr`), all without the payload being
+/// copied in again. Results use the length-prefixed convention of
+/// `interpret`. One fuel budget covers every call.
+pub struct PayloadInstance {
+    store: Store<StoreState>,
+    instance: Instance,
+    memory: Memory,
+    /// Pointer and length of the payload in guest memory.
+    payload: (i32, i32),
+}
+
+impl PayloadInstance {
+    /// Run `call` under a fresh time limit, cancelled when it returns: the
+    /// instance outlives its calls while the user reviews, and only its
+    /// own execution should count against it.
+    fn timed<T>(
+        &mut self,
+        call: impl FnOnce(&mut Self) -> Result<T, SandboxError>,
+    ) -> Result<T, SandboxError> {
+        let watchdog = Watchdog::start(self.store.engine(), self.store.data().timeout);
+        self.store.data_mut().watchdog = Some(watchdog);
+        self.store.set_epoch_deadline(1);
+        let result = call(self);
+        self.store.data_mut().watchdog = None;
+        result
+    }
+
+    /// Call `interpret(ptr, len) -> ptr` on the held payload.
+    pub fn interpret(&mut self) -> Result<String, SandboxError> {
+        self.timed(Self::call_interpret)
+    }
+
+    /// Call `signable() -> ptr` for the bytes to sign.
+    pub fn signable(&mut self) -> Result<Vec<u8>, SandboxError> {
+        self.timed(Self::call_signable)
+    }
+
+    /// Call `finalize(sig_ptr, sig_len) -> ptr` for the signed output.
+    pub fn finalize(&mut self, signature: &[u8]) -> Result<Vec<u8>, SandboxError> {
+        self.timed(|this| this.call_finalize(signature))
+    }
+
+    fn call_interpret(&mut self) -> Result<String, SandboxError> {
+        let interpret = self
+            .instance
+            .get_typed_func::<(i32, i32), i32>(&mut self.store, "interpret")
+            .map_err(|_| SandboxError::MissingExport("interpret".into()))?;
+        let result_ptr = interpret.call(&mut self.store, self.payload)?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("interpret".into()));
+        }
+        read_result_json(&self.memory, &self.store, result_ptr)
+    }
+
+    fn call_signable(&mut self) -> Result<Vec<u8>, SandboxError> {
+        let signable = self
+            .instance
+            .get_typed_func::<(), i32>(&mut self.store, "signable")
+            .map_err(|_| SandboxError::MissingExport("signable".into()))?;
+        let result_ptr = signable.call(&mut self.store, ())?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("signable".into()));
+        }
+        read_result_bytes(&self.memory, &self.store, result_ptr)
+    }
+
+    fn call_finalize(&mut self, signature: &[u8]) -> Result<Vec<u8>, SandboxError> {
+        let finalize = self
+            .instance
+            .get_typed_func::<(i32, i32), i32>(&mut self.store, "finalize")
+            .map_err(|_| SandboxError::MissingExport("finalize".into()))?;
+        let sig_ptr = alloc(&self.instance, &mut self.store, &self.memory, signature)?;
+        let result_ptr = finalize.call(&mut self.store, (sig_ptr, signature.len() as i32))?;
+        if result_ptr == 0 {
+            return Err(SandboxError::NullPointer("finalize".into()));
+        }
+        read_result_bytes(&self.memory, &self.store, result_ptr)
     }
 }
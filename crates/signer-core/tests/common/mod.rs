@@ -0,0 +1,38 @@
+//! Shared helpers for signer-core integration tests.
+
+use signer_core::wasm_sandbox::{SandboxError, FUEL_LIMIT};
+use wasmtime::Trap;
+
+/// Render a sandbox error with the export that was being called and, for
+/// traps, what the guest ran into.
+pub fn explain(export: &str, err: &SandboxError) -> String {
+    let detail = match err {
+        SandboxError::Trap { reason } if *reason == Trap::OutOfFuel.to_string() => {
+            format!("ran out of fuel (limit {FUEL_LIMIT} units)")
+        }
+        SandboxError::Trap { reason } => format!("trapped: {reason}"),
+        SandboxError::Engine(e) => format!("engine error: {e:#}"),
+        SandboxError::MissingExport(name) => {
+            format!("module does not export '{name}' (or it has the wrong signature)")
+        }
+        SandboxError::NullPointer(name) => format!("'{name}' returned a null pointer"),
+        SandboxError::OutputOverflow(len) => {
+            format!("result length {len} runs past the end of linear memory")
+        }
+        SandboxError::InvalidUtf8(e) => format!("result is not UTF-8: {e}"),
+        other => other.to_string(),
+    };
+    format!("`{export}` failed: {detail}")
+}
+
+/// `unwrap` for sandbox results that panics with [`explain`] instead of `Debug`.
+pub trait ExpectSandbox<T> {
+    fn expect_sandbox(self, export: &str) -> T;
+}
+
+impl<T> ExpectSandbox<T> for Result<T, SandboxError> {
+    #[track_caller]
+    fn expect_sandbox(self, export: &str) -> T {
+        self.unwrap_or_else(|e| panic!("{}", explain(export, &e)))
+    }
+}
@@ -0,0 +1,74 @@
+//! Downstream crates can match on every `CoreError` kind without parsing messages.
+
+use signer_core::spec::{SignAlgorithm, Signable, SigningSpec};
+use signer_core::wasm_sandbox::Sandbox;
+use signer_core::{crypto, CoreError, CryptoError, SandboxError};
+
+fn kind(err: &CoreError) -> &'static str {
+    match err {
+        CoreError::Crypto(CryptoError::RangeOutOfBounds { .. }) => "range",
+        CoreError::Crypto(CryptoError::UnsupportedAlgorithm(SignAlgorithm::Secp256k1Schnorr)) => {
+            "unsupported-schnorr"
+        }
+        CoreError::Crypto(_) => "crypto",
+        CoreError::Sandbox(SandboxError::MissingExport(name)) if name == "interpret" => {
+            "missing-interpret"
+        }
+        CoreError::Sandbox(_) => "sandbox",
+        CoreError::Spec(_) => "spec",
+        CoreError::Json(_) => "json",
+        _ => "other",
+    }
+}
+
+#[test]
+fn each_variant_is_matchable() {
+    let range = crypto::extract_signable(
+        b"ab",
+        &Signable::Range {
+            offset: 1,
+            length: 5,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(kind(&range.into()), "range");
+
+    let schnorr = crypto::SoftwareSigner::from_seed([1; 32])
+        .sign(SignAlgorithm::Secp256k1Schnorr, b"m")
+        .unwrap_err();
+    assert_eq!(kind(&schnorr.into()), "unsupported-schnorr");
+
+    let sandbox = Sandbox::new().unwrap();
+    let empty = wat::parse_str("(module (memory (export \"memory\") 1))").unwrap();
+    let missing = sandbox
+        .load_module(&empty)
+        .unwrap()
+        .interpret(b"")
+        .unwrap_err();
+    assert_eq!(kind(&missing.into()), "sandbox");
+
+    let spec = SigningSpec::from_cbor(b"\xff").unwrap_err();
+    assert_eq!(kind(&spec.into()), "spec");
+
+    let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    assert_eq!(kind(&json.into()), "json");
+}
+
+#[test]
+fn sandbox_errors_name_the_export() {
+    let sandbox = Sandbox::new().unwrap();
+    let no_interpret = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (func (export "alloc") (param i32) (result i32) (i32.const 16)))"#,
+    )
+    .unwrap();
+    let err: CoreError = sandbox
+        .load_module(&no_interpret)
+        .unwrap()
+        .interpret(b"tx")
+        .unwrap_err()
+        .into();
+    assert_eq!(kind(&err), "missing-interpret");
+    assert_eq!(err.to_string(), "module has no 'interpret' export");
+}
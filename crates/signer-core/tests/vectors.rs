@@ -0,0 +1,85 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use signer_core::crypto::{extract_signable, SoftwareSigner};
+use signer_core::spec::{SignAlgorithm, SigningSpec};
+use std::path::PathBuf;
+
+/// One `tests/vectors/*.json` fixture. Byte fields are hex-encoded.
+#[derive(serde::Deserialize)]
+struct Vector {
+    spec: SigningSpec,
+    seed: String,
+    payload: String,
+    pubkey: String,
+    signature: String,
+}
+
+fn load_vectors() -> Vec<(String, Vector)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("tests/vectors directory")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == "json"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|p| {
+            let name = p.file_stem().unwrap().to_string_lossy().into_owned();
+            let json = std::fs::read_to_string(&p).unwrap();
+            let vector = serde_json::from_str(&json).unwrap_or_else(|e| panic!("{name}: {e}"));
+            (name, vector)
+        })
+        .collect()
+}
+
+fn verify(algorithm: SignAlgorithm, pubkey: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    match algorithm {
+        SignAlgorithm::Ed25519 => {
+            let key = VerifyingKey::from_bytes(pubkey.try_into().unwrap()).unwrap();
+            let sig = Signature::from_slice(signature).unwrap();
+            key.verify(message, &sig).is_ok()
+        }
+        SignAlgorithm::Secp256k1Ecdsa => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey).unwrap();
+            let sig = k256::ecdsa::Signature::from_slice(signature).unwrap();
+            key.verify_prehash(message, &sig).is_ok()
+        }
+        SignAlgorithm::Secp256k1EcdsaRecoverable => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey).unwrap();
+            let sig = k256::ecdsa::Signature::from_slice(&signature[..64]).unwrap();
+            let recovery_id = k256::ecdsa::RecoveryId::from_byte(signature[64]).unwrap();
+            k256::ecdsa::VerifyingKey::recover_from_prehash(message, &sig, recovery_id)
+                .is_ok_and(|recovered| recovered == key)
+        }
+        other => panic!("no verifier for {other:?}"),
+    }
+}
+
+#[test]
+fn signing_vectors() {
+    let vectors = load_vectors();
+    assert!(!vectors.is_empty(), "no vectors found");
+
+    for (name, v) in vectors {
+        let seed: [u8; 32] = hex::decode(&v.seed).unwrap().try_into().unwrap();
+        let payload = hex::decode(&v.payload).unwrap();
+        let pubkey = hex::decode(&v.pubkey).unwrap();
+        let expected = hex::decode(&v.signature).unwrap();
+
+        let signer = SoftwareSigner::from_seed(seed);
+        let message = &extract_signable(&payload, &v.spec.signable).unwrap()[0];
+        let signature = signer.sign(v.spec.algorithm, message).unwrap();
+
+        assert_eq!(
+            signer.public_key(v.spec.algorithm).unwrap(),
+            pubkey,
+            "{name}: pubkey"
+        );
+        assert_eq!(signature, expected, "{name}: signature");
+        assert!(
+            verify(v.spec.algorithm, &pubkey, message, &signature),
+            "{name}: verify"
+        );
+    }
+}
@@ -1,4 +1,9 @@
-use signer_core::wasm_sandbox::Sandbox;
+mod common;
+
+use common::{explain, ExpectSandbox};
+use signer_core::review::InterpreterMetadata;
+use signer_core::wasm_sandbox::{Sandbox, SandboxConfig, SandboxError, FUEL_LIMIT};
+use std::time::{Duration, Instant};
 
 fn echo_hex_wasm() -> Vec<u8> {
     let path = concat!(
@@ -14,19 +19,227 @@ fn interpret_echo_hex() {
     let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
 
     let payload = b"\xde\xad\xbe\xef";
-    let json_str = module.interpret(payload).unwrap();
+    let json_str = module.interpret(payload).expect_sandbox("interpret");
 
     let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
     assert_eq!(parsed["hex"], "deadbeef");
     assert_eq!(parsed["length"], 4);
 }
 
+#[test]
+fn assemble_echo_hex_appends_signature() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+    module.validate_exports(true).expect_sandbox("validate");
+
+    let assembled = module
+        .assemble(b"\xde\xad\xbe\xef", &[0x5a; 64])
+        .expect_sandbox("assemble");
+    let mut expected = b"\xde\xad\xbe\xef".to_vec();
+    expected.extend([0x5a; 64]);
+    assert_eq!(assembled, expected);
+
+    assert_eq!(
+        module.assemble(b"", b"sig").expect_sandbox("assemble"),
+        b"sig"
+    );
+}
+
+fn base58_wasm() -> Vec<u8> {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/base58.wasm"
+    );
+    std::fs::read(path).expect("base58.wasm not found — run `just build-wasm` first")
+}
+
+#[test]
+fn interpret_base58() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&base58_wasm()).unwrap();
+
+    let json_str = module
+        .interpret(b"Hello World!")
+        .expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["base58"], "2NEpo7TZRRrLZSi2U");
+    assert_eq!(parsed["length"], 12);
+
+    // Leading zero bytes are leading '1's.
+    let payload = b"\x00\x00\x28\x7f\xb4\xcd";
+    let json_str = module.interpret(payload).expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["base58"], "11233QC4");
+
+    let json_str = module.interpret(b"").expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["base58"], "");
+    assert_eq!(parsed["length"], 0);
+}
+
+#[test]
+fn base58_accepts_only_what_fits_the_fuel_budget() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&base58_wasm()).unwrap();
+
+    let largest = [0xff; 256];
+    assert_eq!(
+        module.validate(&largest).expect_sandbox("validate"),
+        Some(0)
+    );
+    let (json_str, fuel) = module
+        .interpret_metered(&largest)
+        .expect_sandbox("interpret_metered");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["length"], 256);
+    assert!(fuel < FUEL_LIMIT / 2, "{fuel}");
+    assert_eq!(
+        module.validate(&[0xff; 257]).expect_sandbox("validate"),
+        Some(1)
+    );
+}
+
+fn cardano_tx_wasm() -> Vec<u8> {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/cardano_tx.wasm"
+    );
+    std::fs::read(path).expect("cardano_tx.wasm not found — run `just build-wasm` first")
+}
+
+/// A testnet transaction paying the CIP-19 test-vector key hashes: 1.5 ADA
+/// to an enterprise address (legacy output) and 2 ADA plus a native token
+/// to a base address (map output), with fee and TTL.
+const CARDANO_TESTNET_TX: &str = "84a40081825820000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f01018282581d609493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e1a0016e360a2005839009493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c4725101821a001e8480a1581cababababababababababababababababababababababababababababa143746f6b05021a0002a909031a00bc614ea0f5f6";
+
+#[test]
+fn interpret_cardano_tx() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&cardano_tx_wasm()).unwrap();
+    let tx = hex::decode(CARDANO_TESTNET_TX).unwrap();
+
+    assert_eq!(module.validate(&tx).expect_sandbox("validate"), Some(0));
+    let (json_str, fuel) = module.interpret_metered(&tx).expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(
+        parsed,
+        serde_json::json!({
+            "fee": 174345,
+            "ttl": 12345678,
+            "outputs": [
+                {
+                    "address": "addr_test1vz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzerspjrlsz",
+                    "amount": 1500000
+                },
+                {
+                    "address": "addr_test1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs68faae",
+                    "amount": 2000000,
+                    "assets": [{
+                        "policy": "abababababababababababababababababababababababababababab",
+                        "name": "746f6b",
+                        "quantity": 5
+                    }]
+                }
+            ]
+        })
+    );
+    assert!(fuel < FUEL_LIMIT / 100, "{fuel}");
+
+    // The bare body reads the same as the whole transaction.
+    let body = &tx[1..tx.len() - 3];
+    assert_eq!(module.interpret(body).expect_sandbox("interpret"), json_str);
+
+    let schema = module.schema().expect_sandbox("schema").unwrap();
+    assert!(schema.contains(r#""unit":"ADA""#), "{schema}");
+}
+
+#[test]
+fn cardano_tx_names_fields_it_does_not_show() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&cardano_tx_wasm()).unwrap();
+    let tx = hex::decode(CARDANO_TESTNET_TX).unwrap();
+    // The body with two more entries: empty withdrawals (5) and mint (9),
+    // and an unknown key 30.
+    let mut body = tx[1..tx.len() - 3].to_vec();
+    body[0] = 0xa7;
+    body.extend([0x05, 0xa0, 0x09, 0xa0, 0x18, 0x1e, 0x00]);
+
+    let parsed: serde_json::Value =
+        serde_json::from_str(&module.interpret(&body).expect_sandbox("interpret")).unwrap();
+    assert_eq!(
+        parsed["unshown"],
+        serde_json::json!({"_warn": true, "value": ["withdrawals", "mint", "key 30"]})
+    );
+
+    // The plain transaction shows everything it carries.
+    let parsed: serde_json::Value =
+        serde_json::from_str(&module.interpret(&tx).expect_sandbox("interpret")).unwrap();
+    assert!(parsed.get("unshown").is_none(), "{parsed}");
+}
+
+#[test]
+fn cardano_tx_rejects_other_payloads() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&cardano_tx_wasm()).unwrap();
+    let tx = hex::decode(CARDANO_TESTNET_TX).unwrap();
+
+    assert_eq!(module.validate(b"tx").expect_sandbox("validate"), Some(1));
+    assert_eq!(
+        module
+            .validate(&tx[..tx.len() / 2])
+            .expect_sandbox("validate"),
+        Some(1)
+    );
+    assert!(matches!(
+        module.interpret(b"tx"),
+        Err(SandboxError::NullPointer(_))
+    ));
+}
+
+fn utf8_text_wasm() -> Vec<u8> {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/utf8_text.wasm"
+    );
+    std::fs::read(path).expect("utf8_text.wasm not found — run `just build-wasm` first")
+}
+
+#[test]
+fn interpret_utf8_text() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&utf8_text_wasm()).unwrap();
+
+    let message = "Sign in to \"example.com\"\nNonce: ünï✓\u{7}\\";
+    let json_str = module
+        .interpret(message.as_bytes())
+        .expect_sandbox("interpret");
+    assert!(
+        !json_str.contains('\n') && !json_str.contains('\u{7}'),
+        "{json_str}"
+    );
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["text"], message);
+    assert_eq!(parsed["valid_utf8"], true);
+}
+
+#[test]
+fn invalid_utf8_falls_back_to_hex() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&utf8_text_wasm()).unwrap();
+
+    let json_str = module.interpret(b"ok\xc3\x28").expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["valid_utf8"], false);
+    assert_eq!(parsed["hex"], "6f6bc328");
+    assert!(parsed.get("text").is_none());
+}
+
 #[test]
 fn interpret_empty_payload() {
     let sandbox = Sandbox::new().unwrap();
     let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
 
-    let json_str = module.interpret(b"").unwrap();
+    let json_str = module.interpret(b"").expect_sandbox("interpret");
     let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
     assert_eq!(parsed["hex"], "");
     assert_eq!(parsed["length"], 0);
@@ -38,7 +251,7 @@ fn interpret_larger_payload() {
     let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
 
     let payload: Vec<u8> = (0..=255).collect();
-    let json_str = module.interpret(&payload).unwrap();
+    let json_str = module.interpret(&payload).expect_sandbox("interpret");
     let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
     assert_eq!(parsed["length"], 256);
 
@@ -47,3 +260,503 @@ fn interpret_larger_payload() {
     assert!(hex.starts_with("000102"));
     assert!(hex.ends_with("fdfeff"));
 }
+
+#[test]
+fn interpret_reports_fuel_consumed() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+
+    let payload: Vec<u8> = (0..=255).collect();
+    let (json_str, fuel) = module
+        .interpret_metered(&payload)
+        .expect_sandbox("interpret_metered");
+
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["length"], 256);
+    // Hex-encoding 256 bytes takes thousands of operations, far from the cap.
+    assert!(fuel > 256, "{fuel}");
+    assert!(fuel < FUEL_LIMIT / 100, "{fuel}");
+}
+
+#[test]
+fn fuel_budget_is_configurable() {
+    let payload: Vec<u8> = (0..=255).collect();
+    let (_, needed) = Sandbox::new()
+        .unwrap()
+        .load_module(&echo_hex_wasm())
+        .unwrap()
+        .interpret_metered(&payload)
+        .expect_sandbox("interpret_metered");
+
+    let tight = Sandbox::with_config(SandboxConfig {
+        fuel: needed / 2,
+        ..SandboxConfig::default()
+    })
+    .unwrap();
+    let err = tight
+        .load_module(&echo_hex_wasm())
+        .unwrap()
+        .interpret(&payload)
+        .unwrap_err();
+    assert!(
+        explain("interpret", &err).contains("ran out of fuel"),
+        "{err}"
+    );
+
+    let raised = Sandbox::with_config(SandboxConfig {
+        fuel: needed,
+        ..SandboxConfig::default()
+    })
+    .unwrap();
+    let json_str = raised
+        .load_module(&echo_hex_wasm())
+        .unwrap()
+        .interpret(&payload)
+        .expect_sandbox("interpret");
+    let parsed: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(parsed["length"], 256);
+}
+
+#[test]
+fn memory_cap_is_configurable() {
+    // Grows memory by 32 pages (2 MiB) before answering.
+    let growing = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "\02\00\00\00{}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (if (i32.eq (memory.grow (i32.const 32)) (i32.const -1))
+              (then (return (i32.const 0))))
+            (i32.const 16)))
+        "#,
+    )
+    .unwrap();
+
+    let small = Sandbox::with_config(SandboxConfig {
+        max_memory_bytes: 1024 * 1024,
+        ..SandboxConfig::default()
+    })
+    .unwrap();
+    assert!(matches!(
+        small.load_module(&growing).unwrap().interpret(b"tx"),
+        Err(SandboxError::NullPointer(_))
+    ));
+
+    let default = Sandbox::new().unwrap();
+    assert_eq!(
+        default
+            .load_module(&growing)
+            .unwrap()
+            .interpret(b"tx")
+            .expect_sandbox("interpret"),
+        "{}"
+    );
+}
+
+#[test]
+fn wall_clock_timeout_interrupts_unmetered_loop() {
+    let spinning = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (loop $forever (br $forever))
+            (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+    let sandbox = Sandbox::with_config(SandboxConfig {
+        fuel: u64::MAX,
+        timeout: Duration::from_millis(100),
+        ..SandboxConfig::default()
+    })
+    .unwrap();
+
+    let started = Instant::now();
+    let err = sandbox
+        .load_module(&spinning)
+        .unwrap()
+        .interpret(b"tx")
+        .unwrap_err();
+    assert!(matches!(err, SandboxError::Timeout), "{err}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn idle_instance_does_not_time_out_other_calls() {
+    let spinning = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (loop $forever (br $forever))
+            (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+    let timeout = Duration::from_millis(300);
+    let sandbox = Sandbox::with_config(SandboxConfig {
+        fuel: u64::MAX,
+        timeout,
+        ..SandboxConfig::default()
+    })
+    .unwrap();
+
+    // An instance held for review, as the flow does between its calls.
+    let mut held = sandbox
+        .load_module(&echo_hex_wasm())
+        .unwrap()
+        .instantiate(b"tx")
+        .unwrap();
+    assert!(held
+        .interpret()
+        .expect_sandbox("interpret")
+        .contains("7478"));
+    std::thread::sleep(timeout / 2);
+
+    // Had the held instance's watchdog kept running, it would interrupt
+    // this call half way through its own time limit.
+    let started = Instant::now();
+    let err = sandbox
+        .load_module(&spinning)
+        .unwrap()
+        .interpret(b"tx")
+        .unwrap_err();
+    assert!(matches!(err, SandboxError::Timeout), "{err}");
+    assert!(started.elapsed() >= timeout, "{:?}", started.elapsed());
+}
+
+#[test]
+fn echo_hex_metadata_parses() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+    let metadata = InterpreterMetadata::of(&module).unwrap().unwrap();
+    assert_eq!(metadata.name, "echo-hex");
+    assert_eq!(metadata.version, "0.1.0");
+
+    let anonymous = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "interpret") (param i32 i32) (result i32) (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&anonymous).unwrap();
+    assert_eq!(module.metadata().expect_sandbox("metadata"), None);
+    assert_eq!(module.validate(b"tx").expect_sandbox("validate"), None);
+}
+
+#[test]
+fn echo_hex_validate_rejects_oversized_payload() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+    assert_eq!(module.validate(b"tx").expect_sandbox("validate"), Some(0));
+    let oversized = vec![0u8; (1 << 20) + 1];
+    assert_eq!(
+        module.validate(&oversized).expect_sandbox("validate"),
+        Some(1)
+    );
+}
+
+#[test]
+fn validate_exports_lists_everything_missing() {
+    let sandbox = Sandbox::new().unwrap();
+    let no_alloc = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "interpret") (param i32 i32) (result i32) (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&no_alloc).unwrap();
+    assert!(matches!(
+        module.validate_exports(false),
+        Err(SandboxError::MissingExports(missing)) if missing == ["alloc"]
+    ));
+
+    let no_interpret = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&no_interpret).unwrap();
+    let err = module.validate_exports(true).unwrap_err();
+    assert!(
+        matches!(&err, SandboxError::MissingExports(missing) if missing == &["interpret", "assemble"]),
+        "{err}"
+    );
+    assert_eq!(
+        err.to_string(),
+        "module is missing exports: interpret, assemble"
+    );
+
+    let complete = sandbox.load_module(&echo_hex_wasm()).unwrap();
+    complete.validate_exports(false).expect_sandbox("validate");
+}
+
+#[test]
+fn trap_reason_is_reported() {
+    let trapping = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (unreachable)))
+        "#,
+    )
+    .unwrap();
+    let err = Sandbox::new()
+        .unwrap()
+        .load_module(&trapping)
+        .unwrap()
+        .interpret(b"tx")
+        .unwrap_err();
+    match &err {
+        SandboxError::Trap { reason } => assert!(reason.contains("unreachable"), "{reason}"),
+        other => panic!("expected a trap, got {other}"),
+    }
+    assert!(err.to_string().contains("unreachable"), "{err}");
+}
+
+#[test]
+fn precompiled_module_interprets_like_source() {
+    let sandbox = Sandbox::new().unwrap();
+    let wasm = echo_hex_wasm();
+    let cwasm = sandbox.precompile(&wasm).expect_sandbox("precompile");
+
+    let source = sandbox.load_module(&wasm).unwrap();
+    // SAFETY: produced by `precompile` on this sandbox just above.
+    let precompiled = unsafe { sandbox.load_precompiled(&cwasm) }.unwrap();
+    for payload in [&b""[..], b"tx", &[0xab; 300]] {
+        assert_eq!(
+            precompiled.interpret(payload).expect_sandbox("interpret"),
+            source.interpret(payload).expect_sandbox("interpret")
+        );
+    }
+
+    // Not an artifact: rejected rather than run.
+    assert!(unsafe { sandbox.load_precompiled(&wasm) }.is_err());
+}
+
+#[test]
+fn echo_hex_dealloc_reclaims_top_allocation() {
+    let engine = wasmtime::Engine::default();
+    let module = wasmtime::Module::new(&engine, echo_hex_wasm()).unwrap();
+    let mut store = wasmtime::Store::new(&engine, ());
+    let instance = wasmtime::Instance::new(&mut store, &module, &[]).unwrap();
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")
+        .unwrap();
+    let dealloc = instance
+        .get_typed_func::<(i32, i32), ()>(&mut store, "dealloc")
+        .unwrap();
+
+    // Without freeing, ten 4 MiB buffers would need 40 MiB.
+    let large = 4 * 1024 * 1024;
+    for _ in 0..10 {
+        let big = alloc.call(&mut store, large).unwrap();
+        assert_ne!(big, 0);
+        dealloc.call(&mut store, (big, large)).unwrap();
+        let small = alloc.call(&mut store, 16).unwrap();
+        assert_eq!(small, big, "freed space is reused");
+        dealloc.call(&mut store, (small, 16)).unwrap();
+    }
+    assert!(memory.data_size(&store) < 2 * large as usize);
+}
+
+#[test]
+fn broken_module_errors_are_explained() {
+    let sandbox = Sandbox::new().unwrap();
+    let spinning = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (loop $forever (br $forever))
+            (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&spinning).unwrap();
+
+    let err = module.interpret(b"tx").unwrap_err();
+    assert_eq!(
+        explain("interpret", &err),
+        "`interpret` failed: ran out of fuel (limit 10000000 units)"
+    );
+
+    let err = module.assemble(b"tx", b"sig").unwrap_err();
+    assert_eq!(
+        explain("assemble", &err),
+        "`assemble` failed: module does not export 'assemble' (or it has the wrong signature)"
+    );
+}
+
+#[test]
+fn chunked_interpret_reports_progress() {
+    let sandbox = Sandbox::new().unwrap();
+    let chunked = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 2)
+          (global $seen (mut i32) (i32.const 0))
+          (data (i32.const 16) "\10\00\00\00{\"chunked\":true}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "interpret_chunk") (param i32 i32) (result i32)
+            (global.set $seen (i32.add (global.get $seen) (local.get 1)))
+            (i32.const 0))
+          (func (export "interpret_finish") (result i32)
+            (i32.const 16)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&chunked).unwrap();
+
+    let mut progress = Vec::new();
+    let json = module
+        .interpret_chunked(&[0u8; 10_000], 4096, &mut |done, total| {
+            progress.push((done, total))
+        })
+        .expect_sandbox("interpret_chunk");
+
+    assert_eq!(json, r#"{"chunked":true}"#);
+    assert_eq!(progress, [(4096, 10_000), (8192, 10_000), (10_000, 10_000)]);
+}
+
+#[test]
+fn chunk_allocated_out_of_memory_is_refused() {
+    let sandbox = Sandbox::new().unwrap();
+    // One page of memory, but chunks are "allocated" near the end of it
+    // and far beyond it.
+    let chunked = |ptr: i32| {
+        wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const {ptr}))
+              (func (export "interpret_chunk") (param i32 i32) (result i32) (i32.const 0))
+              (func (export "interpret_finish") (result i32) (i32.const 0)))
+            "#
+        ))
+        .unwrap()
+    };
+
+    for ptr in [65_000, -256] {
+        let module = sandbox.load_module(&chunked(ptr)).unwrap();
+        let err = module
+            .interpret_chunked(&[0u8; 4096], 4096, &mut |_, _| {})
+            .unwrap_err();
+        assert!(matches!(err, SandboxError::OutputOverflow(_)), "{err:?}");
+    }
+}
+
+#[test]
+fn unchunked_interpreter_reports_completion_once() {
+    let sandbox = Sandbox::new().unwrap();
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+
+    let mut progress = Vec::new();
+    module
+        .interpret_chunked(b"abc", 1, &mut |done, total| progress.push((done, total)))
+        .expect_sandbox("interpret");
+
+    assert_eq!(progress, [(3, 3)]);
+}
+
+/// Reader handing out at most seven bytes per call, like a slow stream.
+struct Trickle<'a>(&'a [u8]);
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(7).min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn streamed_payload_interprets_identically() {
+    let sandbox = Sandbox::new().unwrap();
+    // Reports the byte sum of the payload modulo 10.
+    let summing = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 2)
+          (global $sum (mut i32) (i32.const 0))
+          (data (i32.const 16) "\09\00\00\00{\"sum\":0}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "interpret_chunk") (param $p i32) (param $len i32) (result i32)
+            (local $i i32)
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                (global.set $sum
+                  (i32.add (global.get $sum)
+                    (i32.load8_u (i32.add (local.get $p) (local.get $i)))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (i32.const 0))
+          (func (export "interpret_finish") (result i32)
+            (i32.store8 (i32.const 27)
+              (i32.add (i32.const 48) (i32.rem_u (global.get $sum) (i32.const 10))))
+            (i32.const 16)))
+        "#,
+    )
+    .unwrap();
+    let payload: Vec<u8> = (0..10_000u32).map(|i| (i * 31 % 251) as u8).collect();
+    let sum: u32 = payload.iter().map(|&b| b as u32).sum();
+
+    let mut outputs = Vec::new();
+    for wasm in [summing, echo_hex_wasm()] {
+        let module = sandbox.load_module(&wasm).unwrap();
+        let whole = module
+            .interpret_chunked(&payload, 4096, &mut |_, _| {})
+            .expect_sandbox("interpret_chunked");
+        let streamed = module
+            .interpret_reader(&mut Trickle(&payload), payload.len(), 4096, &mut |_, _| {})
+            .expect_sandbox("interpret_reader");
+        assert_eq!(streamed, whole);
+        outputs.push(streamed);
+    }
+    assert_eq!(outputs[0], format!(r#"{{"sum":{}}}"#, sum % 10));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn output_in_unwritten_memory_is_caught() {
+    let sandbox = Sandbox::new().unwrap();
+    // Grows a page and returns a 32-byte result in it without writing the bytes.
+    let lazy = wat::parse_str(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (drop (memory.grow (i32.const 1)))
+            (i32.store (i32.const 65536) (i32.const 32))
+            (i32.const 65536)))
+        "#,
+    )
+    .unwrap();
+    let module = sandbox.load_module(&lazy).unwrap();
+
+    let err = module.interpret(b"tx").unwrap_err();
+    assert!(matches!(err, SandboxError::UninitializedOutput(0)), "{err}");
+    // Interpreters that write their output are unaffected.
+    let module = sandbox.load_module(&echo_hex_wasm()).unwrap();
+    module.interpret(&[0xCD; 64]).expect_sandbox("interpret");
+}
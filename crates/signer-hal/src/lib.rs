@@ -1,6 +1,11 @@
 use signer_core::display::DisplayLine;
+use signer_core::slip10::ExtendedKey;
+use signer_core::spec::SignAlgorithm;
+use std::io::Read;
 use thiserror::Error;
 
+pub mod serial;
+
 #[derive(Debug, Error)]
 pub enum HalError {
     #[error("display error: {0}")]
@@ -9,8 +14,20 @@ pub enum HalError {
     Button(String),
     #[error("USB error: {0}")]
     Usb(String),
+    #[error("serial link error: {0}")]
+    Serial(String),
     #[error("storage error: {0}")]
     Storage(String),
+    #[error("entropy error: {0}")]
+    Entropy(String),
+    /// The secure element cannot sign with this algorithm.
+    #[error("{0} not supported by the secure element")]
+    Unsupported(String),
+    #[error("wrong PIN, {remaining} attempts left")]
+    WrongPin { remaining: u32 },
+    /// Too many wrong PINs; the secure element refuses every further one.
+    #[error("PIN locked after too many wrong attempts")]
+    PinLocked,
 }
 
 /// User button action.
@@ -20,20 +37,67 @@ pub enum ButtonEvent {
     Reject,
     Up,
     Down,
+    /// Open the selected review line in full.
+    Select,
+}
+
+/// Which physical action button means Confirm.
+///
+/// Enclosures differ in where the two action buttons sit, so the mapping is
+/// calibrated on first boot and persisted in [`Settings`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ButtonMap {
+    /// The physical Reject button confirms and the physical Confirm button rejects.
+    pub swapped: bool,
+}
+
+impl ButtonMap {
+    /// Translate a physical button event into its logical meaning.
+    pub fn apply(&self, raw: ButtonEvent) -> ButtonEvent {
+        match (self.swapped, raw) {
+            (true, ButtonEvent::Confirm) => ButtonEvent::Reject,
+            (true, ButtonEvent::Reject) => ButtonEvent::Confirm,
+            (_, ev) => ev,
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![u8::from(self.swapped)]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HalError> {
+        match bytes {
+            [0] => Ok(Self { swapped: false }),
+            [1] => Ok(Self { swapped: true }),
+            _ => Err(HalError::Storage("invalid button map".into())),
+        }
+    }
 }
 
 /// USB stick contents.
 pub struct UsbContents {
     pub payload: Vec<u8>,
-    pub interpreter_wasm: Vec<u8>,
+    /// `None` when the stick carries no interpreter (blind signing).
+    pub interpreter_wasm: Option<Vec<u8>>,
     pub signing_spec_cbor: Vec<u8>,
 }
 
+/// Payload opened for reading in pieces rather than whole.
+pub struct PayloadStream<'a> {
+    pub len: u64,
+    pub reader: Box<dyn Read + 'a>,
+}
+
 /// Display output.
 pub trait Display {
     fn clear(&mut self) -> Result<(), HalError>;
     fn show_message(&mut self, text: &str) -> Result<(), HalError>;
     fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError>;
+    /// Show a QR code (rows of modules, `true` for dark) with a caption.
+    /// Displays that cannot draw one show only the caption.
+    fn show_qr(&mut self, caption: &str, _modules: &[Vec<bool>]) -> Result<(), HalError> {
+        self.show_message(caption)
+    }
 }
 
 /// Button input.
@@ -41,17 +105,114 @@ pub trait Buttons {
     fn wait_event(&mut self) -> Result<ButtonEvent, HalError>;
 }
 
-/// USB mass storage mount/unmount.
-pub trait UsbMount {
-    fn wait_insert(&mut self) -> Result<(), HalError>;
-    fn mount_readonly(&mut self) -> Result<(), HalError>;
+/// Persistent storage for non-secret device settings.
+pub trait Settings {
+    /// Read a setting. Returns `None` if it was never stored.
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, HalError>;
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<(), HalError>;
+}
+
+/// Where a signing request is read from and its result written to: a USB
+/// stick, or QR codes scanned by a camera.
+pub trait PayloadSource {
     fn read_contents(&self) -> Result<UsbContents, HalError>;
     fn write_output(&mut self, data: &[u8]) -> Result<(), HalError>;
-    /// Read a named file from USB storage. Returns `None` if the file doesn't exist.
+    /// Write `data` into the output file starting at byte `offset`,
+    /// leaving earlier bytes in place.
+    fn write_output_at(&mut self, offset: u64, data: &[u8]) -> Result<(), HalError>;
+    /// Read a named file from the source. Returns `None` if the file doesn't exist.
     fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, HalError>;
-    /// Write a named file to USB storage.
+    /// Write a named file to the source.
     fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), HalError>;
+    /// Release the source once the request is handled.
     fn unmount(&mut self) -> Result<(), HalError>;
+
+    /// Open the payload for streaming, so it need not be held in memory.
+    ///
+    /// The default reads it whole through [`read_contents`](Self::read_contents);
+    /// file-backed implementations should stream from the file instead.
+    fn open_payload(&self) -> Result<PayloadStream<'_>, HalError> {
+        let payload = self.read_contents()?.payload;
+        Ok(PayloadStream {
+            len: payload.len() as u64,
+            reader: Box::new(std::io::Cursor::new(payload)),
+        })
+    }
+}
+
+/// USB mass storage mount/unmount.
+pub trait UsbMount: PayloadSource {
+    fn wait_insert(&mut self) -> Result<(), HalError>;
+    fn mount_readonly(&mut self) -> Result<(), HalError>;
+}
+
+/// Wall-clock time, for specs that expire.
+///
+/// The Pi keeps no time across power-off without an RTC module, and an
+/// air-gapped device cannot fetch it. A board without a trusted clock keeps
+/// the default, and time-based checks are skipped.
+pub trait Clock {
+    /// Seconds since the Unix epoch, or `None` without a trusted clock.
+    fn now(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Colors of the status LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedColor {
+    Off,
+    Green,
+    Amber,
+    Red,
+}
+
+/// A status LED, telling the signing cycle's state from across the room:
+/// amber while a request is reviewed, green once it is signed, red when it
+/// is rejected or fails.
+///
+/// A board without an LED keeps the default, which does nothing.
+pub trait StatusLed {
+    fn set(&mut self, _color: LedColor) {}
+}
+
+/// Source of random bytes for key generation, PIN salts and backups.
+///
+/// On the device this is a hardware RNG (the SoC's TRNG or the secure
+/// element's), which is sound even early in boot when the kernel's entropy
+/// pool may not be.
+pub trait Entropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), HalError>;
+}
+
+/// Secure element operations that reveal nothing secret and need no PIN.
+///
+/// Split out so that view-only mode can be handed just these.
+pub trait PublicKeys {
+    /// Check whether the device has been provisioned (PIN set, key generated).
+    fn is_provisioned(&self) -> bool;
+
+    /// Read the public key from a slot.
+    fn public_key(&self, slot: u8) -> Result<Vec<u8>, HalError>;
+
+    /// Public key of the SLIP-0010 child of a slot's key at `path`.
+    fn derive_public_key(&self, slot: u8, path: &[u32]) -> Result<Vec<u8>, HalError>;
+
+    /// Number of signatures made so far. Monotonic across reboots.
+    fn sign_count(&self) -> u64;
+
+    /// Public key of a slot for signing with `algorithm`: 32 bytes for
+    /// Ed25519 and Ed25519ph, compressed SEC1 for secp256k1 ECDSA.
+    ///
+    /// Fails with [`HalError::Unsupported`], whether or not the slot holds
+    /// a key, for an algorithm the element cannot sign with. The default
+    /// supports Ed25519 only.
+    fn public_key_for(&self, algorithm: SignAlgorithm, slot: u8) -> Result<Vec<u8>, HalError> {
+        match algorithm {
+            SignAlgorithm::Ed25519 => self.public_key(slot),
+            other => Err(HalError::Unsupported(format!("{other:?}"))),
+        }
+    }
 }
 
 /// Hardware secure element (SE050 or similar).
@@ -59,30 +220,71 @@ pub trait UsbMount {
 /// Private keys are generated and stored inside the chip.
 /// The Pi never sees raw key material. PIN retry limits
 /// are enforced in hardware.
-pub trait SecureElement {
+pub trait SecureElement: PublicKeys {
     /// Set the initial PIN during first-time setup.
     fn set_pin(&mut self, pin: &[u8]) -> Result<(), HalError>;
 
     /// Verify the user PIN. Returns remaining attempts on failure.
     fn verify_pin(&mut self, pin: &[u8]) -> Result<(), HalError>;
 
-    /// Check whether the device has been provisioned (PIN set, key generated).
-    fn is_provisioned(&self) -> bool;
-
     /// Generate a keypair in the given slot. Returns the public key.
-    fn generate_key(&mut self, slot: u8) -> Result<Vec<u8>, HalError>;
+    /// A non-empty `passphrase` is folded in as for [`import_key`](Self::import_key).
+    fn generate_key(&mut self, slot: u8, passphrase: &[u8]) -> Result<Vec<u8>, HalError>;
 
     /// Sign a hash using the key in the given slot.
     /// Requires prior PIN verification in the same session.
     fn sign(&mut self, slot: u8, hash: &[u8]) -> Result<Vec<u8>, HalError>;
 
-    /// Read the public key from a slot.
-    fn public_key(&self, slot: u8) -> Result<Vec<u8>, HalError>;
+    /// Sign `message` with the slot's key under `algorithm`: Ed25519 signs
+    /// it as-is, Ed25519ph signs its SHA-512, and secp256k1 ECDSA takes it
+    /// as the prehashed digest and returns compact `r || s`. Requires the
+    /// PIN like [`sign`](Self::sign), and fails like
+    /// [`public_key_for`](PublicKeys::public_key_for) for an unsupported
+    /// algorithm. The default supports Ed25519 only.
+    fn sign_with(
+        &mut self,
+        algorithm: SignAlgorithm,
+        slot: u8,
+        message: &[u8],
+    ) -> Result<Vec<u8>, HalError> {
+        match algorithm {
+            SignAlgorithm::Ed25519 => self.sign(slot, message),
+            other => Err(HalError::Unsupported(format!("{other:?}"))),
+        }
+    }
+
+    /// Sign with the SLIP-0010 child of a slot's key at `path`. Every path
+    /// component must be hardened. Requires the PIN like [`sign`](Self::sign).
+    fn sign_derived(&mut self, slot: u8, path: &[u32], hash: &[u8]) -> Result<Vec<u8>, HalError>;
 
     /// Import an existing seed into a slot (recovery from backup).
     /// Returns the public key.
-    fn import_key(&mut self, slot: u8, seed: &[u8]) -> Result<Vec<u8>, HalError>;
+    ///
+    /// The slot key is derived from the seed and `passphrase` (empty for
+    /// none), so one seed gives a different key per passphrase. The
+    /// passphrase is never stored.
+    fn import_key(&mut self, slot: u8, seed: &[u8], passphrase: &[u8])
+        -> Result<Vec<u8>, HalError>;
 
-    /// Export the seed for backup during provisioning.
+    /// Import a SLIP-0010 master key with its chain code (recovery from a
+    /// BIP39 mnemonic). Returns the public key.
+    ///
+    /// The slot key is `master.key`, and derived keys are children of
+    /// `master` itself, as the wallet that made the mnemonic derives them.
+    fn import_master_key(&mut self, slot: u8, master: &ExtendedKey) -> Result<Vec<u8>, HalError>;
+
+    /// Export the seed for backup during provisioning: the seed as
+    /// generated, before any passphrase is applied.
     fn export_seed(&self, slot: u8) -> Result<Vec<u8>, HalError>;
+
+    /// Export the whole element state (PIN and every key slot) as a blob
+    /// encrypted under `pin`, for migrating to another device.
+    /// Requires prior PIN verification, and `pin` must be the current PIN.
+    /// The encryption salt and nonce come from the element's RNG.
+    fn export_backup(&mut self, pin: &[u8]) -> Result<Vec<u8>, HalError>;
+
+    /// Replace the element state with a blob from [`export_backup`](Self::export_backup),
+    /// decrypting it with `pin`. Refused while any slot holds a key.
+    /// Leaves the PIN verified on success.
+    fn restore_backup(&mut self, pin: &[u8], blob: &[u8]) -> Result<(), HalError>;
 }
@@ -0,0 +1,513 @@
+//! A [`PayloadSource`] over a serial link, for boards without USB mass
+//! storage.
+//!
+//! Both directions carry records, each COBS-encoded and ended by a zero
+//! byte, so a receiver that lost sync skips to the next zero. Inside the
+//! encoding a record is a kind byte and length-prefixed fields (lengths are
+//! little-endian `u32`, offsets `u64`):
+//!
+//! | Kind | Fields | Direction |
+//! |------|--------|-----------|
+//! | `0x00` end of request | none | host to device |
+//! | `0x01` file | name, data | both |
+//! | `0x02` output | data | device to host |
+//! | `0x03` output at | offset, data | device to host |
+//!
+//! The host sends the stick's files (`payload.bin`, `sign.cbor`, optionally
+//! `interpreter.wasm` and others) and an end record; the device answers
+//! with its output and any files it writes.
+
+use crate::{HalError, PayloadSource, UsbContents};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Largest decoded record accepted, so a runaway sender cannot exhaust memory.
+pub const MAX_RECORD_BYTES: usize = 256 << 20;
+
+/// Most file records one request may carry.
+pub const MAX_REQUEST_FILES: usize = 64;
+
+/// Most bytes of names and data one request may carry: the largest record
+/// and room for the files beside it.
+pub const MAX_REQUEST_BYTES: usize = MAX_RECORD_BYTES + (32 << 20);
+
+const END: u8 = 0x00;
+const FILE: u8 = 0x01;
+const OUTPUT: u8 = 0x02;
+const OUTPUT_AT: u8 = 0x03;
+
+/// One message on the link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    End,
+    File { name: String, data: Vec<u8> },
+    Output(Vec<u8>),
+    OutputAt { offset: u64, data: Vec<u8> },
+}
+
+impl Record {
+    fn to_bytes(&self) -> Vec<u8> {
+        let field = |out: &mut Vec<u8>, bytes: &[u8]| {
+            out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(bytes);
+        };
+        match self {
+            Record::End => vec![END],
+            Record::File { name, data } => {
+                let mut out = vec![FILE];
+                field(&mut out, name.as_bytes());
+                field(&mut out, data);
+                out
+            }
+            Record::Output(data) => {
+                let mut out = vec![OUTPUT];
+                field(&mut out, data);
+                out
+            }
+            Record::OutputAt { offset, data } => {
+                let mut out = vec![OUTPUT_AT];
+                out.extend_from_slice(&offset.to_le_bytes());
+                field(&mut out, data);
+                out
+            }
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, HalError> {
+        let malformed = || HalError::Serial("malformed record".into());
+        let (&kind, mut rest) = bytes.split_first().ok_or_else(malformed)?;
+        let rest = &mut rest;
+        let record = match kind {
+            END => Record::End,
+            FILE => {
+                let name = String::from_utf8(take_field(rest)?).map_err(|_| malformed())?;
+                Record::File {
+                    name,
+                    data: take_field(rest)?,
+                }
+            }
+            OUTPUT => Record::Output(take_field(rest)?),
+            OUTPUT_AT => {
+                let offset = take(rest, 8)?.try_into().expect("8 bytes");
+                Record::OutputAt {
+                    offset: u64::from_le_bytes(offset),
+                    data: take_field(rest)?,
+                }
+            }
+            other => return Err(HalError::Serial(format!("unknown record kind {other}"))),
+        };
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+        Ok(record)
+    }
+}
+
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Result<&'a [u8], HalError> {
+    let (head, tail) = rest
+        .split_at_checked(n)
+        .ok_or_else(|| HalError::Serial("truncated record".into()))?;
+    *rest = tail;
+    Ok(head)
+}
+
+fn take_field(rest: &mut &[u8]) -> Result<Vec<u8>, HalError> {
+    let len = take(rest, 4)?.try_into().expect("4 bytes");
+    Ok(take(rest, u32::from_le_bytes(len) as usize)?.to_vec())
+}
+
+/// COBS-encode `data`: the result has no zero bytes.
+pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_at = 0;
+    out.push(0);
+    for &byte in data {
+        if byte != 0 {
+            out.push(byte);
+        }
+        let run = out.len() - code_at;
+        if byte == 0 || run == 0xff {
+            out[code_at] = run as u8;
+            code_at = out.len();
+            out.push(0);
+        }
+    }
+    out[code_at] = (out.len() - code_at) as u8;
+    out
+}
+
+/// Undo [`cobs_encode`]; `None` if `encoded` is not a valid encoding.
+pub fn cobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len());
+    let mut rest = encoded;
+    while let Some((&code, tail)) = rest.split_first() {
+        let run = usize::from(code).checked_sub(1)?;
+        let (bytes, tail) = tail.split_at_checked(run)?;
+        if bytes.contains(&0) {
+            return None;
+        }
+        out.extend_from_slice(bytes);
+        rest = tail;
+        if code != 0xff && !rest.is_empty() {
+            out.push(0);
+        }
+    }
+    Some(out)
+}
+
+/// Send `record` as one zero-terminated COBS frame.
+pub fn write_record(link: &mut impl Write, record: &Record) -> Result<(), HalError> {
+    let mut frame = cobs_encode(&record.to_bytes());
+    frame.push(0);
+    link.write_all(&frame)
+        .and_then(|()| link.flush())
+        .map_err(|e| HalError::Serial(e.to_string()))
+}
+
+/// Receive the next record, reading up to its zero terminator.
+pub fn read_record(link: &mut impl Read) -> Result<Record, HalError> {
+    read_record_within(link, MAX_RECORD_BYTES)
+}
+
+fn read_record_within(link: &mut impl Read, max_bytes: usize) -> Result<Record, HalError> {
+    let mut frame = Vec::new();
+    let mut byte = [0];
+    loop {
+        link.read_exact(&mut byte)
+            .map_err(|e| HalError::Serial(e.to_string()))?;
+        if byte[0] == 0 {
+            break;
+        }
+        // COBS adds a byte per 254, plus one.
+        if frame.len() > max_bytes + max_bytes / 254 {
+            return Err(HalError::Serial("record too large".into()));
+        }
+        frame.push(byte[0]);
+    }
+    let bytes = cobs_decode(&frame).ok_or_else(|| HalError::Serial("invalid COBS frame".into()))?;
+    Record::from_bytes(&bytes)
+}
+
+/// Host side: send a stick's files, then the end of the request.
+pub fn send_request(
+    link: &mut impl Write,
+    contents: &UsbContents,
+    extra: &[(&str, &[u8])],
+) -> Result<(), HalError> {
+    let mut files = vec![
+        ("payload.bin", &contents.payload[..]),
+        ("sign.cbor", &contents.signing_spec_cbor[..]),
+    ];
+    if let Some(wasm) = &contents.interpreter_wasm {
+        files.push(("interpreter.wasm", wasm));
+    }
+    for (name, data) in files.into_iter().chain(extra.iter().copied()) {
+        let record = Record::File {
+            name: name.into(),
+            data: data.to_vec(),
+        };
+        write_record(link, &record)?;
+    }
+    write_record(link, &Record::End)
+}
+
+/// Device side of the link: the request's files, once received, and a
+/// channel back to the host for the output.
+pub struct SerialTransport<L: Read + Write> {
+    link: L,
+    files: BTreeMap<String, Vec<u8>>,
+    max_files: usize,
+    max_bytes: usize,
+}
+
+impl<L: Read + Write> SerialTransport<L> {
+    pub fn new(link: L) -> Self {
+        Self {
+            link,
+            files: BTreeMap::new(),
+            max_files: MAX_REQUEST_FILES,
+            max_bytes: MAX_REQUEST_BYTES,
+        }
+    }
+
+    /// Wait for the host's next request, replacing any earlier one. A
+    /// request over [`MAX_REQUEST_FILES`] file records or
+    /// [`MAX_REQUEST_BYTES`] is refused, and leaves no files behind.
+    pub fn receive(&mut self) -> Result<(), HalError> {
+        self.files.clear();
+        let mut records = 0;
+        let mut bytes = 0usize;
+        loop {
+            match read_record(&mut self.link)? {
+                Record::End => return Ok(()),
+                Record::File { name, data } => {
+                    records += 1;
+                    bytes = bytes.saturating_add(name.len() + data.len());
+                    if records > self.max_files {
+                        self.files.clear();
+                        return Err(HalError::Serial(format!(
+                            "request has more than {} files",
+                            self.max_files
+                        )));
+                    }
+                    if bytes > self.max_bytes {
+                        self.files.clear();
+                        return Err(HalError::Serial(format!(
+                            "request is over {} bytes",
+                            self.max_bytes
+                        )));
+                    }
+                    self.files.insert(name, data);
+                }
+                other => {
+                    return Err(HalError::Serial(format!(
+                        "unexpected record from host: {other:?}"
+                    )))
+                }
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.link
+    }
+
+    fn file(&self, name: &str) -> Result<Vec<u8>, HalError> {
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| HalError::Serial(format!("{name} not received")))
+    }
+}
+
+impl<L: Read + Write> PayloadSource for SerialTransport<L> {
+    fn read_contents(&self) -> Result<UsbContents, HalError> {
+        Ok(UsbContents {
+            payload: self.file("payload.bin")?,
+            interpreter_wasm: self.files.get("interpreter.wasm").cloned(),
+            signing_spec_cbor: self.file("sign.cbor")?,
+        })
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> Result<(), HalError> {
+        write_record(&mut self.link, &Record::Output(data.to_vec()))
+    }
+
+    fn write_output_at(&mut self, offset: u64, data: &[u8]) -> Result<(), HalError> {
+        let record = Record::OutputAt {
+            offset,
+            data: data.to_vec(),
+        };
+        write_record(&mut self.link, &record)
+    }
+
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, HalError> {
+        Ok(self.files.get(name).cloned())
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), HalError> {
+        let record = Record::File {
+            name: name.into(),
+            data: data.to_vec(),
+        };
+        write_record(&mut self.link, &record)?;
+        // A later read in the same request sees what was written.
+        self.files.insert(name.into(), data.to_vec());
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Host-to-device bytes in, device-to-host bytes out.
+    #[derive(Default)]
+    struct Link {
+        inbound: VecDeque<u8>,
+        outbound: Vec<u8>,
+    }
+
+    impl Read for Link {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inbound.read(buf)
+        }
+    }
+
+    impl Write for Link {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.outbound.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(record: &Record) -> Vec<u8> {
+        let mut link = Vec::new();
+        write_record(&mut link, record).unwrap();
+        link
+    }
+
+    fn serial_error(result: Result<Record, HalError>) -> String {
+        match result {
+            Err(HalError::Serial(message)) => message,
+            other => panic!("expected a serial error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn cobs_round_trips() {
+        let long: Vec<u8> = (0..600u32).map(|i| (i % 255) as u8 + 1).collect();
+        for data in [
+            &[][..],
+            &[0],
+            &[0, 0],
+            &[1, 0, 2],
+            &[7; 253],
+            &[7; 254],
+            &[7; 255],
+            &long,
+        ] {
+            let encoded = cobs_encode(data);
+            assert!(!encoded.contains(&0), "{data:?}");
+            assert_eq!(cobs_decode(&encoded).as_deref(), Some(data));
+        }
+    }
+
+    #[test]
+    fn malformed_cobs_is_refused() {
+        // A code byte promising more bytes than follow, and a stray zero.
+        assert_eq!(cobs_decode(&[3, 1]), None);
+        assert_eq!(cobs_decode(&[0]), None);
+        assert_eq!(cobs_decode(&[3, 1, 0]), None);
+        let mut link = &[5, 1, 1, 0][..];
+        assert_eq!(serial_error(read_record(&mut link)), "invalid COBS frame");
+    }
+
+    #[test]
+    fn records_round_trip() {
+        for record in [
+            Record::End,
+            Record::File {
+                name: "sign.cbor".into(),
+                data: vec![0, 1, 0],
+            },
+            Record::Output(vec![0; 300]),
+            Record::OutputAt {
+                offset: 1 << 40,
+                data: b"tail".to_vec(),
+            },
+        ] {
+            let mut link = &frame(&record)[..];
+            assert_eq!(read_record(&mut link).unwrap(), record);
+            assert!(link.is_empty());
+        }
+    }
+
+    #[test]
+    fn truncated_frames_are_refused() {
+        // The link closes before the terminator.
+        let whole = frame(&Record::Output(b"signature".to_vec()));
+        let mut link = &whole[..whole.len() - 1];
+        assert!(read_record(&mut link).is_err());
+
+        // A field length past the end of the record.
+        let mut bytes = vec![OUTPUT];
+        bytes.extend_from_slice(&10u32.to_le_bytes());
+        bytes.extend_from_slice(b"short");
+        let mut framed = cobs_encode(&bytes);
+        framed.push(0);
+        assert_eq!(
+            serial_error(read_record(&mut &framed[..])),
+            "truncated record"
+        );
+
+        // Bytes left over after the last field.
+        let mut framed = cobs_encode(&[END, 1]);
+        framed.push(0);
+        assert_eq!(
+            serial_error(read_record(&mut &framed[..])),
+            "malformed record"
+        );
+        let mut framed = cobs_encode(&[9]);
+        framed.push(0);
+        assert_eq!(
+            serial_error(read_record(&mut &framed[..])),
+            "unknown record kind 9"
+        );
+    }
+
+    #[test]
+    fn oversize_frame_is_refused_before_its_end() {
+        let record = Record::Output(vec![1; 100]);
+        let fits = frame(&record);
+        assert_eq!(read_record_within(&mut &fits[..], 105).unwrap(), record);
+        // A frame with no terminator in sight stops at the limit.
+        let mut endless = std::io::repeat(1);
+        assert_eq!(
+            serial_error(read_record_within(&mut endless, 100)),
+            "record too large"
+        );
+    }
+
+    #[test]
+    fn request_files_arrive_exactly() {
+        let contents = UsbContents {
+            payload: (0..600u32).map(|i| (i % 7) as u8).collect(),
+            interpreter_wasm: Some(vec![0; 300]),
+            signing_spec_cbor: b"spec".to_vec(),
+        };
+        let mut link = Link::default();
+        send_request(&mut link.inbound, &contents, &[("seed.bin", b"\0seed")]).unwrap();
+        let mut serial = SerialTransport::new(link);
+        serial.receive().unwrap();
+
+        let received = serial.read_contents().unwrap();
+        assert_eq!(received.payload, contents.payload);
+        assert_eq!(received.interpreter_wasm, contents.interpreter_wasm);
+        assert_eq!(received.signing_spec_cbor, contents.signing_spec_cbor);
+        assert_eq!(
+            serial.read_file("seed.bin").unwrap().as_deref(),
+            Some(&b"\0seed"[..])
+        );
+    }
+
+    #[test]
+    fn request_is_bounded_in_files_and_bytes() {
+        let request = |files: &[(&str, &[u8])]| {
+            let mut link = Link::default();
+            for &(name, data) in files {
+                let record = Record::File {
+                    name: name.into(),
+                    data: data.to_vec(),
+                };
+                write_record(&mut link.inbound, &record).unwrap();
+            }
+            write_record(&mut link.inbound, &Record::End).unwrap();
+            let mut serial = SerialTransport::new(link);
+            serial.max_files = 3;
+            serial.max_bytes = 20;
+            let result = serial.receive().map_err(|e| e.to_string());
+            (result, serial.files.len())
+        };
+
+        assert_eq!(
+            request(&[("a", b"1"), ("b", b"2"), ("c", b"3")]),
+            (Ok(()), 3)
+        );
+        // Repeating a name still counts against the limit.
+        let (result, kept) = request(&[("a", &b"1"[..]); 4]);
+        assert!(result.unwrap_err().contains("more than 3 files"));
+        assert_eq!(kept, 0);
+        let (result, kept) = request(&[("a", &[0; 10]), ("b", &[0; 10])]);
+        assert!(result.unwrap_err().contains("over 20 bytes"));
+        assert_eq!(kept, 0);
+    }
+}
@@ -27,6 +27,9 @@ pub fn poll_event(window: &mut Window) -> Result<Option<ButtonEvent>, HalError>
     if window.is_key_pressed(Key::Down, minifb::KeyRepeat::Yes) {
         return Ok(Some(ButtonEvent::Down));
     }
+    if window.is_key_pressed(Key::Space, minifb::KeyRepeat::No) {
+        return Ok(Some(ButtonEvent::Select));
+    }
 
     Ok(None)
 }
@@ -0,0 +1,236 @@
+//! Loopback control channel for scripting the simulator (testing only).
+//!
+//! One client at a time connects over TCP and exchanges newline-delimited JSON:
+//!
+//! - `{"cmd":"screen"}` → `{"screen":{"kind":"message","text":"INSERT USB - SIGNED: 0"}}`
+//! - `{"cmd":"press","button":"confirm"}` → `{"ok":true}`
+//!
+//! Requests are only served while the flow is waiting for a button, so a
+//! `screen` reply always shows what the device is prompting for.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use signer_core::display::{format_line, DisplayLine};
+use signer_hal::{ButtonEvent, Buttons, Clock, Display, HalError, StatusLed};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+/// What the simulated display is currently showing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Screen {
+    Blank,
+    Message {
+        text: String,
+    },
+    Lines {
+        lines: Vec<String>,
+        scroll_offset: usize,
+    },
+}
+
+/// A button as named on the wire (and in session recordings).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Confirm,
+    Reject,
+    Up,
+    Down,
+    Select,
+}
+
+impl From<Button> for ButtonEvent {
+    fn from(b: Button) -> Self {
+        match b {
+            Button::Confirm => ButtonEvent::Confirm,
+            Button::Reject => ButtonEvent::Reject,
+            Button::Up => ButtonEvent::Up,
+            Button::Down => ButtonEvent::Down,
+            Button::Select => ButtonEvent::Select,
+        }
+    }
+}
+
+impl From<ButtonEvent> for Button {
+    fn from(ev: ButtonEvent) -> Self {
+        match ev {
+            ButtonEvent::Confirm => Button::Confirm,
+            ButtonEvent::Reject => Button::Reject,
+            ButtonEvent::Up => Button::Up,
+            ButtonEvent::Down => Button::Down,
+            ButtonEvent::Select => Button::Select,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Screen,
+    Press { button: Button },
+}
+
+/// `Display`/`Buttons` shim driven over the control socket.
+pub struct ControlHal {
+    listener: TcpListener,
+    conn: Option<BufReader<TcpStream>>,
+    screen: Screen,
+}
+
+impl ControlHal {
+    /// Listen on `addr`, which must be a loopback address.
+    pub fn bind(addr: SocketAddr) -> Result<Self, HalError> {
+        if !addr.ip().is_loopback() {
+            return Err(HalError::Button(format!(
+                "control socket must be loopback, got {addr}"
+            )));
+        }
+        let listener = TcpListener::bind(addr).map_err(|e| HalError::Button(e.to_string()))?;
+        Ok(Self {
+            listener,
+            conn: None,
+            screen: Screen::Blank,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, HalError> {
+        self.listener
+            .local_addr()
+            .map_err(|e| HalError::Button(e.to_string()))
+    }
+
+    fn reply(&mut self, value: serde_json::Value) -> Result<(), HalError> {
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(());
+        };
+        let mut line = value.to_string();
+        line.push('\n');
+        if conn.get_mut().write_all(line.as_bytes()).is_err() {
+            self.conn = None;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ControlHal {
+    fn clear(&mut self) -> Result<(), HalError> {
+        self.screen = Screen::Blank;
+        Ok(())
+    }
+
+    fn show_message(&mut self, text: &str) -> Result<(), HalError> {
+        self.screen = Screen::Message { text: text.into() };
+        Ok(())
+    }
+
+    fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
+        self.screen = Screen::Lines {
+            lines: lines.iter().map(format_line).collect(),
+            scroll_offset,
+        };
+        Ok(())
+    }
+}
+
+/// No clock, so scripted runs do not depend on the time.
+impl Clock for ControlHal {}
+
+impl StatusLed for ControlHal {}
+
+impl Buttons for ControlHal {
+    fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
+        loop {
+            let conn = match self.conn.as_mut() {
+                Some(conn) => conn,
+                None => {
+                    let (stream, _) = self
+                        .listener
+                        .accept()
+                        .map_err(|e| HalError::Button(e.to_string()))?;
+                    self.conn.insert(BufReader::new(stream))
+                }
+            };
+            let mut line = String::new();
+            match conn.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // Client went away; wait for the next one.
+                    self.conn = None;
+                    continue;
+                }
+                Ok(_) => {}
+            }
+            match serde_json::from_str::<Request>(&line) {
+                Ok(Request::Screen) => {
+                    let screen = json!({ "screen": self.screen });
+                    self.reply(screen)?;
+                }
+                Ok(Request::Press { button }) => {
+                    self.reply(json!({ "ok": true }))?;
+                    return Ok(button.into());
+                }
+                Err(e) => self.reply(json!({ "error": e.to_string() }))?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::{run_once, FlowConfig, Session};
+    use crate::mock::{echo_hex_wasm, spec, MockSe, MockSettings, MockUsb};
+    use signer_core::spec::SigningSpec;
+    use std::thread;
+
+    fn request(conn: &mut BufReader<TcpStream>, line: &str) -> serde_json::Value {
+        conn.get_mut()
+            .write_all(format!("{line}\n").as_bytes())
+            .unwrap();
+        let mut reply = String::new();
+        conn.read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[test]
+    fn rejects_non_loopback_address() {
+        assert!(ControlHal::bind("0.0.0.0:0".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn scripted_client_drives_flow() {
+        let spec = SigningSpec {
+            label: "Control test".into(),
+            ..spec()
+        };
+        let mut hal = ControlHal::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = hal.local_addr().unwrap();
+
+        let device = thread::spawn(move || {
+            let mut usb = MockUsb::signing(&spec, b"hi", echo_hex_wasm());
+            let mut se = MockSe::unlocked();
+            run_once(
+                &mut hal,
+                &mut usb,
+                &mut se,
+                &mut MockSettings::default(),
+                &FlowConfig::default(),
+                &mut Session::default(),
+            )
+            .unwrap()
+        });
+
+        let mut conn = BufReader::new(TcpStream::connect(addr).unwrap());
+        let screen = request(&mut conn, r#"{"cmd":"screen"}"#);
+        assert_eq!(screen["screen"]["kind"], "lines");
+        assert!(!screen["screen"]["lines"].as_array().unwrap().is_empty());
+
+        let bad = request(&mut conn, r#"{"cmd":"press","button":"sideways"}"#);
+        assert!(bad["error"].is_string());
+
+        let ok = request(&mut conn, r#"{"cmd":"press","button":"confirm"}"#);
+        assert_eq!(ok["ok"], true);
+
+        assert!(device.join().unwrap());
+    }
+}
@@ -1,5 +1,5 @@
 use minifb::Window;
-use signer_core::display::DisplayLine;
+use signer_core::display::{layout, scroll_indicator, DisplayGeometry, DisplayLine};
 use signer_hal::HalError;
 
 const WIDTH: usize = 640;
@@ -8,6 +8,7 @@ const CHAR_W: usize = 8;
 const LINE_HEIGHT: usize = 10; // 8px glyph + 2px gap
 const COLS: usize = WIDTH / CHAR_W; // 80
 const VISIBLE_LINES: usize = HEIGHT / LINE_HEIGHT; // 48
+const SCROLLBAR_W: usize = 4;
 
 /// Minimal 8x8 bitmap font covering ASCII 32..127.
 /// Each character is 8 bytes, one byte per row, MSB-left.
@@ -209,6 +210,11 @@ static FONT_8X8: [u8; 96 * 8] = [
 
 const FG: u32 = 0x00FF_FFFF; // white
 const BG: u32 = 0x0010_1010; // near-black
+const TRACK: u32 = 0x0040_4040; // dark grey
+const QR_DARK: u32 = 0x0000_0000; // black
+
+/// Light modules around a QR code so scanners can find its edges.
+const QR_QUIET: usize = 4;
 
 pub struct SimDisplay {
     window: Window,
@@ -268,6 +274,22 @@ impl SimDisplay {
         }
     }
 
+    /// Draw a vertical scrollbar on the right edge spanning `rows` text rows from `row`.
+    fn draw_scrollbar(&mut self, row: usize, rows: usize, thumb_start: usize, thumb_len: usize) {
+        let x0 = WIDTH - SCROLLBAR_W;
+        for r in 0..rows {
+            let color = if (thumb_start..thumb_start + thumb_len).contains(&r) {
+                FG
+            } else {
+                TRACK
+            };
+            let y0 = (row + r) * LINE_HEIGHT;
+            for y in y0..(y0 + LINE_HEIGHT).min(HEIGHT) {
+                self.buf[y * WIDTH + x0..(y + 1) * WIDTH].fill(color);
+            }
+        }
+    }
+
     fn clear_buf(&mut self) {
         self.buf.fill(BG);
     }
@@ -300,34 +322,53 @@ impl signer_hal::Display for SimDisplay {
 
     fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
         self.clear_buf();
+        let content_start = 2;
+        let content_lines = VISIBLE_LINES.saturating_sub(content_start);
+        let indicator = scroll_indicator(scroll_offset, lines.len(), content_lines);
+
         // Header
         let header = format!(
-            " [{}/{}]  Enter=OK  Esc=Cancel  \u{2191}/\u{2193}=Scroll",
-            scroll_offset + 1,
-            lines.len().max(1),
+            " [{}]  Enter=OK  Esc=Cancel  \u{2191}/\u{2193}=Scroll",
+            indicator.label(),
         );
         self.draw_text(&header, 0, 0);
         // Separator line
         self.draw_text(&"\u{2500}".repeat(COLS), 0, 1);
+        self.draw_scrollbar(
+            content_start,
+            content_lines,
+            indicator.thumb_start,
+            indicator.thumb_len,
+        );
 
-        let content_start = 2;
-        let content_lines = VISIBLE_LINES.saturating_sub(content_start);
-
-        for (i, line) in lines
-            .iter()
-            .skip(scroll_offset)
-            .take(content_lines)
-            .enumerate()
-        {
-            let pad = "  ".repeat(line.indent);
-            let text = match &line.key {
-                Some(k) if line.value.is_empty() => format!("{pad}{k}:"),
-                Some(k) => format!("{pad}{k}: {}", line.value),
-                None => format!("{pad}{}", line.value),
-            };
-            self.draw_text(&text, 0, content_start + i);
+        let geometry = DisplayGeometry {
+            cols: COLS,
+            rows: content_lines,
+        };
+        for (i, row) in layout(lines, geometry, scroll_offset).iter().enumerate() {
+            self.draw_text(row, 0, content_start + i);
         }
 
         self.update()
     }
+
+    fn show_qr(&mut self, caption: &str, modules: &[Vec<bool>]) -> Result<(), HalError> {
+        self.clear_buf();
+        // Scale to the space above the caption, keeping a 4-module quiet zone.
+        let side = modules.len() + 2 * QR_QUIET;
+        let scale = ((HEIGHT - 2 * LINE_HEIGHT) / side).min(WIDTH / side).max(1);
+        let x0 = WIDTH.saturating_sub(side * scale) / 2;
+        for y in 0..(side * scale).min(HEIGHT) {
+            for x in 0..(side * scale).min(WIDTH - x0) {
+                let (my, mx) = (y / scale, x / scale);
+                let dark = (QR_QUIET..QR_QUIET + modules.len()).contains(&my)
+                    && (QR_QUIET..QR_QUIET + modules.len()).contains(&mx)
+                    && modules[my - QR_QUIET][mx - QR_QUIET];
+                self.buf[y * WIDTH + x0 + x] = if dark { QR_DARK } else { FG };
+            }
+        }
+        let col = COLS.saturating_sub(caption.len()) / 2;
+        self.draw_text(caption, col, VISIBLE_LINES - 1);
+        self.update()
+    }
 }
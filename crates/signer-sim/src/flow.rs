@@ -1,18 +1,213 @@
-use signer_core::crypto::extract_signable;
-use signer_core::display::{json_to_lines, DisplayLine};
-use signer_core::spec::{OutputSpec, SigningSpec};
-use signer_core::wasm_sandbox::Sandbox;
-use signer_hal::{ButtonEvent, Buttons, Display, HalError, SecureElement, UsbMount};
+use signer_core::attestation::{
+    attestation_digest, encode_attestation, Attestation, ATTESTING_SLOT,
+};
+use signer_core::builtin;
+use signer_core::crypto::{
+    extract_signable, hash_bytes, recoverable_signature, seed_quality, verify, SeedQuality,
+    StreamHasher,
+};
+use signer_core::display::{describe_path, wrap_text, DisplayLine};
+use signer_core::input::{encode_inputs, InputKind, InputRequest};
+use signer_core::mnemonic::{mnemonic_to_entropy, mnemonic_to_master_key, seed_to_mnemonic};
+use signer_core::output_manifest::{parse_manifest, MANIFEST_EXPORT};
+use signer_core::psbt;
+use signer_core::qr::{encode_text, frame_texts, output_to_qr};
+use signer_core::review::{review_lines, InterpreterMetadata, Review};
+use signer_core::spec::{
+    HashAlgorithm, InterpreterSource, OutputSpec, SignAlgorithm, Signable, SigningSpec,
+};
+use signer_core::stick::{payload_hasher, Manifest, MANIFEST_FILE};
+use signer_core::wasm_sandbox::{PayloadInstance, Sandbox, SandboxError, SandboxModule};
+use signer_hal::{
+    ButtonEvent, ButtonMap, Buttons, Clock, Display, HalError, LedColor, PayloadSource,
+    PayloadStream, PublicKeys, SecureElement, Settings, StatusLed, UsbMount,
+};
+use std::io::{self, Read};
 
 const PIN_LEN: usize = 4;
 
-/// Digit-by-digit PIN entry using 4 buttons.
+/// Payload bytes passed per `interpret_chunk` call.
+const INTERPRET_CHUNK: usize = 64 * 1024;
+
+/// Bytes written per `write_output_at` call.
+const WRITE_CHUNK: usize = 64 * 1024;
+
+/// USB file recording how much of the output has been written.
+const WRITE_PROGRESS: &str = "signed.progress";
+
+/// Settings key holding the calibrated [`ButtonMap`].
+const BUTTON_MAP_KEY: &str = "button_map";
+
+/// USB file recording every blind signature.
+const BLIND_SIGN_LOG: &str = "blind-sign.log";
+
+/// USB file holding an encrypted [`SecureElement::export_backup`] blob.
+const DEVICE_BACKUP: &str = "device-backup.enc";
+
+/// Private-stick file holding a BIP39 mnemonic to recover from.
+const MNEMONIC_FILE: &str = "mnemonic.txt";
+
+/// Private-stick file holding `seed.bin` as 24 words, a copy to write down.
+const SEED_WORDS_FILE: &str = "seed-words.txt";
+
+/// Stick files that hold a key or can recover one, never to leave the
+/// device's sticks in a recording.
+pub const SECRET_FILES: [&str; 4] = ["seed.bin", MNEMONIC_FILE, SEED_WORDS_FILE, DEVICE_BACKUP];
+
+/// USB file holding the signed statement of the device's public keys.
+const ATTESTATION: &str = "attestation.cbor";
+
+/// Output bytes per frame when the output needs an animated QR code.
+const QR_FRAME_CHUNK: usize = 512;
+
+/// Most frames an animated QR code may take (8 KiB of output); a larger
+/// output is only written to the stick.
+const MAX_QR_FRAMES: usize = 16;
+
+/// USB file holding `interpreter.wasm` compiled ahead of time.
+const PRECOMPILED_INTERPRETER: &str = "interpreter.cwasm";
+
+/// Firmware version named in attestations.
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Number of secure element key slots.
+const KEY_SLOTS: u8 = 16;
+
+/// Settings key prefix for the last format each key slot signed.
+const LAST_FORMAT_KEY: &str = "last_format";
+
+/// Settings key holding the nonces of signed specs, oldest first.
+const SEEN_NONCES_KEY: &str = "seen_nonces";
+
+/// Nonces remembered; past this many the oldest is forgotten.
+const MAX_SEEN_NONCES: usize = 256;
+
+/// What happens when scrolling past the first or last review line.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScrollMode {
+    /// Stop at the first and last line.
+    #[default]
+    Clamp,
+    /// Down on the last line goes to the top, Up on the first goes to the bottom.
+    Wrap,
+}
+
+/// Device behaviour knobs chosen at boot.
+#[derive(Debug, Clone)]
+pub struct FlowConfig {
+    pub scroll: ScrollMode,
+    /// Hardware override: sign sticks that carry no interpreter, showing only
+    /// the raw bytes to be signed. Off by default.
+    pub allow_blind_sign: bool,
+    /// Characters per line when a review line is opened in full.
+    pub detail_width: usize,
+    /// Longest list (e.g. transaction outputs) reviewed without an explicit
+    /// override; past it, confirming by habit is too likely.
+    pub max_review_items: usize,
+    /// On boots that reuse a stored button mapping, require a Reject press
+    /// before anything else, so an inverted mapping is noticed. On by default.
+    pub button_interlock: bool,
+    /// Hardware override: load the stick's `interpreter.cwasm` (made by
+    /// `usb-pack --precompile`) instead of compiling `interpreter.wasm`.
+    /// The artifact is native code outside the sandbox, so this trusts
+    /// whoever packed the stick; it must match the stick's manifest. Off
+    /// by default.
+    pub allow_precompiled: bool,
+    /// Show long review values as their first and last characters around
+    /// an ellipsis, e.g. `(4, 4)` for `abcd…wxyz`; Select still opens the
+    /// full value. Off by default.
+    pub abbreviate: Option<(usize, usize)>,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            scroll: ScrollMode::default(),
+            allow_blind_sign: false,
+            detail_width: 80,
+            max_review_items: 50,
+            button_interlock: true,
+            allow_precompiled: false,
+            abbreviate: None,
+        }
+    }
+}
+
+/// State kept across signing cycles until the device powers off.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Digest of the last signed stick (spec + payload) and its signature.
+    last_signed: Option<(Vec<u8>, Vec<u8>)>,
+    /// BLAKE2b-256 of the last signed spec, whose nonce a re-inserted stick
+    /// repeats.
+    last_spec: Option<Vec<u8>>,
+}
+
+impl Session {
+    /// Digest identifying a stick's signing request: its spec plus the
+    /// payload, or for a streamed payload the signable bytes, which alone
+    /// determine a signature-only output.
+    fn request_digest(spec_cbor: &[u8], signed_over: &[u8]) -> Vec<u8> {
+        let mut data = (spec_cbor.len() as u64).to_le_bytes().to_vec();
+        data.extend_from_slice(spec_cbor);
+        data.extend_from_slice(signed_over);
+        hash_bytes(HashAlgorithm::Blake2b256, &data)
+    }
+}
+
+/// Compute the next scroll offset for an Up/Down press.
+fn scroll_step(scroll: usize, max_scroll: usize, event: ButtonEvent, mode: ScrollMode) -> usize {
+    match (event, mode) {
+        (ButtonEvent::Up, ScrollMode::Clamp) => scroll.saturating_sub(1),
+        (ButtonEvent::Up, ScrollMode::Wrap) if scroll == 0 => max_scroll,
+        (ButtonEvent::Up, ScrollMode::Wrap) => scroll - 1,
+        (ButtonEvent::Down, ScrollMode::Clamp) => max_scroll.min(scroll + 1),
+        (ButtonEvent::Down, ScrollMode::Wrap) if scroll >= max_scroll => 0,
+        (ButtonEvent::Down, ScrollMode::Wrap) => scroll + 1,
+        _ => scroll,
+    }
+}
+
+/// PIN entry: four masked digits.
+fn enter_pin<H: Display + Buttons>(hal: &mut H, prompt: &str) -> Result<Option<Vec<u8>>, HalError> {
+    enter_digits(hal, prompt, PIN_LEN, true)
+}
+
+/// Digit-by-digit entry of `len` digits using 4 buttons.
 ///
 /// Up/Down cycles current digit 0–9, Confirm advances to next digit,
-/// Reject goes back (or cancels if at first position).
-/// Returns `None` if the user cancelled.
-fn enter_pin<H: Display + Buttons>(hal: &mut H, prompt: &str) -> Result<Option<Vec<u8>>, HalError> {
-    let mut digits = [0u8; PIN_LEN];
+/// Reject goes back (or cancels if at first position). When `masked`,
+/// digits already entered are shown as '*'.
+/// Returns the ASCII digits, or `None` if the user cancelled.
+fn enter_digits<H: Display + Buttons>(
+    hal: &mut H,
+    prompt: &str,
+    len: usize,
+    masked: bool,
+) -> Result<Option<Vec<u8>>, HalError> {
+    match read_digits(hal, prompt, len, masked, false)? {
+        DigitEntry::Entered(digits) => Ok(Some(digits)),
+        DigitEntry::Cancelled | DigitEntry::ViewOnly => Ok(None),
+    }
+}
+
+/// How a digit entry screen was left.
+enum DigitEntry {
+    Entered(Vec<u8>),
+    Cancelled,
+    /// Select was pressed on a screen that offers view-only mode.
+    ViewOnly,
+}
+
+/// [`enter_digits`], optionally letting Select leave for view-only mode.
+fn read_digits<H: Display + Buttons>(
+    hal: &mut H,
+    prompt: &str,
+    len: usize,
+    masked: bool,
+    offer_view_only: bool,
+) -> Result<DigitEntry, HalError> {
+    let mut digits = vec![0u8; len];
     let mut pos: usize = 0;
 
     loop {
@@ -22,9 +217,9 @@ fn enter_pin<H: Display + Buttons>(hal: &mut H, prompt: &str) -> Result<Option<V
             if i > 0 {
                 display.push(' ');
             }
-            if i < pos {
+            if i < pos && masked {
                 display.push('*');
-            } else if i == pos {
+            } else if i <= pos {
                 display.push((b'0' + d) as char);
             } else {
                 display.push('_');
@@ -36,26 +231,35 @@ fn enter_pin<H: Display + Buttons>(hal: &mut H, prompt: &str) -> Result<Option<V
                 key: None,
                 value: prompt.to_string(),
                 indent: 0,
+                ..Default::default()
             },
             DisplayLine {
                 key: None,
                 value: String::new(),
                 indent: 0,
+                ..Default::default()
             },
             DisplayLine {
                 key: None,
                 value: format!("  [ {display} ]"),
                 indent: 0,
+                ..Default::default()
             },
             DisplayLine {
                 key: None,
                 value: String::new(),
                 indent: 0,
+                ..Default::default()
             },
             DisplayLine {
                 key: None,
-                value: "Up/Down=digit  Enter=next  Esc=back".to_string(),
+                value: if offer_view_only {
+                    "Up/Down=digit  Enter=next  Esc=back  Space=view only".to_string()
+                } else {
+                    "Up/Down=digit  Enter=next  Esc=back".to_string()
+                },
                 indent: 0,
+                ..Default::default()
             },
         ];
         hal.show_lines(&lines, 0)?;
@@ -69,23 +273,129 @@ fn enter_pin<H: Display + Buttons>(hal: &mut H, prompt: &str) -> Result<Option<V
             }
             ButtonEvent::Confirm => {
                 pos += 1;
-                if pos >= PIN_LEN {
+                if pos >= len {
                     // Convert digits to ASCII bytes
-                    let pin: Vec<u8> = digits.iter().map(|d| b'0' + d).collect();
-                    return Ok(Some(pin));
+                    let entered: Vec<u8> = digits.iter().map(|d| b'0' + d).collect();
+                    return Ok(DigitEntry::Entered(entered));
                 }
             }
             ButtonEvent::Reject => {
                 if pos == 0 {
-                    return Ok(None);
+                    return Ok(DigitEntry::Cancelled);
                 }
                 pos -= 1;
             }
+            ButtonEvent::Select if offer_view_only => return Ok(DigitEntry::ViewOnly),
+            ButtonEvent::Select => {}
+        }
+    }
+}
+
+/// Characters offered when entering a passphrase: printable ASCII,
+/// lowercase first.
+const PASSPHRASE_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz\
+    ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// Free-length masked text entry using 4 buttons.
+///
+/// Up/Down cycles the next character, Confirm appends it, Reject deletes the
+/// last one (or cancels when empty), Select finishes.
+/// Returns `None` if the user cancelled.
+fn enter_text<H: Display + Buttons>(
+    hal: &mut H,
+    prompt: &str,
+) -> Result<Option<Vec<u8>>, HalError> {
+    let mut text = Vec::new();
+    let mut current = 0;
+    loop {
+        let lines = [
+            prompt.to_string(),
+            String::new(),
+            format!(
+                "  [ {}{} ]",
+                "*".repeat(text.len()),
+                PASSPHRASE_CHARS[current] as char
+            ),
+            String::new(),
+            "Up/Down=char  Enter=add  Esc=delete  Space=done".to_string(),
+        ]
+        .map(|value| DisplayLine {
+            key: None,
+            value,
+            indent: 0,
+            ..Default::default()
+        });
+        hal.show_lines(&lines, 0)?;
+
+        match hal.wait_event()? {
+            ButtonEvent::Up => current = (current + 1) % PASSPHRASE_CHARS.len(),
+            ButtonEvent::Down => {
+                current = (current + PASSPHRASE_CHARS.len() - 1) % PASSPHRASE_CHARS.len()
+            }
+            ButtonEvent::Confirm => text.push(PASSPHRASE_CHARS[current]),
+            ButtonEvent::Reject => {
+                if text.pop().is_none() {
+                    return Ok(None);
+                }
+            }
+            ButtonEvent::Select => return Ok(Some(text)),
+        }
+    }
+}
+
+/// Offer an optional passphrase for key derivation, entered twice.
+/// Returns an empty passphrase when declined.
+fn ask_passphrase<H: Display + Buttons>(hal: &mut H) -> Result<Vec<u8>, HalError> {
+    loop {
+        hal.show_message("ADD PASSPHRASE? CONFIRM=YES REJECT=NO")?;
+        if wait_action(hal)? == ButtonEvent::Reject {
+            return Ok(Vec::new());
+        }
+        let Some(passphrase) = enter_text(hal, "ENTER PASSPHRASE")? else {
+            continue;
+        };
+        let Some(confirm) = enter_text(hal, "CONFIRM PASSPHRASE")? else {
+            continue;
+        };
+        if passphrase == confirm {
+            return Ok(passphrase);
+        }
+        hal.show_message("PASSPHRASE MISMATCH")?;
+        hal.wait_event()?;
+    }
+}
+
+/// Prompt for each value the interpreter requested.
+///
+/// Returns `None` if the user cancelled any of the prompts.
+fn collect_inputs<H: Display + Buttons>(
+    hal: &mut H,
+    requests: &[InputRequest],
+) -> Result<Option<Vec<(String, serde_json::Value)>>, HalError> {
+    let mut values = Vec::new();
+    for request in requests {
+        match request.kind {
+            InputKind::Number { digits } => {
+                let Some(entered) = enter_digits(hal, &request.prompt, digits, false)? else {
+                    return Ok(None);
+                };
+                let n = entered.iter().fold(0u64, |acc, d| {
+                    acc.saturating_mul(10).saturating_add(u64::from(d - b'0'))
+                });
+                values.push((request.name.clone(), n.into()));
+            }
         }
     }
+    Ok(Some(values))
 }
 
-/// First-time setup: set PIN, provision key (generate or recover from USB), export to USBs.
+/// First-time setup: set PIN, provision key (generate, recover from a seed,
+/// its words or a BIP39 mnemonic, or restore a device backup from USB),
+/// export to USBs. A generated seed is written both raw and as words.
+///
+/// A device backup is decrypted with the PIN just set, so it must be the PIN
+/// of the device that exported it. A mnemonic's passphrase is BIP39's own,
+/// so the key matches what other wallets derive from the same words.
 fn run_setup<H: Display + Buttons>(
     hal: &mut H,
     usb: &mut dyn UsbMount,
@@ -122,16 +432,61 @@ fn run_setup<H: Display + Buttons>(
         hal.show_message("INSERT PRIVATE USB")?;
         hal.wait_event()?;
 
-        let pubkey = match usb.read_file("seed.bin")? {
-            Some(seed) => {
+        // The words of a seed stand in for `seed.bin`.
+        let seed = match (usb.read_file("seed.bin")?, usb.read_file(SEED_WORDS_FILE)?) {
+            (Some(seed), _) => Some(seed),
+            (None, Some(words)) => match mnemonic_to_entropy(&String::from_utf8_lossy(&words)) {
+                Ok(seed) => Some(seed),
+                Err(e) => {
+                    hal.show_message("INVALID SEED WORDS")?;
+                    hal.wait_event()?;
+                    return Err(HalError::Storage(e.to_string()));
+                }
+            },
+            (None, None) => None,
+        };
+        let pubkey = match (
+            usb.read_file(DEVICE_BACKUP)?,
+            seed,
+            usb.read_file(MNEMONIC_FILE)?,
+        ) {
+            (Some(blob), _, _) => {
+                hal.show_message("RESTORING DEVICE BACKUP...")?;
+                if let Err(e) = se.restore_backup(&pin, &blob) {
+                    hal.show_message("BACKUP DOES NOT MATCH PIN")?;
+                    hal.wait_event()?;
+                    return Err(e);
+                }
+                se.public_key(0)?
+            }
+            (None, Some(seed), _) => {
+                accept_seed(hal, &seed)?;
+                let passphrase = ask_passphrase(hal)?;
                 hal.show_message("RECOVERING FROM SEED...")?;
-                se.import_key(0, &seed)?
+                se.import_key(0, &seed, &passphrase)?
             }
-            None => {
+            (None, None, Some(text)) => {
+                let phrase = String::from_utf8_lossy(&text);
+                if let Err(e) = mnemonic_to_entropy(&phrase) {
+                    hal.show_message("INVALID MNEMONIC")?;
+                    hal.wait_event()?;
+                    return Err(HalError::Storage(e.to_string()));
+                }
+                let passphrase = ask_passphrase(hal)?;
+                hal.show_message("RECOVERING FROM MNEMONIC...")?;
+                let master = mnemonic_to_master_key(&phrase, &passphrase)
+                    .map_err(|e| HalError::Storage(e.to_string()))?;
+                se.import_master_key(0, &master)?
+            }
+            (None, None, None) => {
+                let passphrase = ask_passphrase(hal)?;
                 hal.show_message("GENERATING NEW KEY...")?;
-                let pubkey = se.generate_key(0)?;
+                let pubkey = se.generate_key(0, &passphrase)?;
                 let seed = se.export_seed(0)?;
+                let words =
+                    seed_to_mnemonic(&seed).map_err(|e| HalError::Storage(e.to_string()))?;
                 usb.write_file("seed.bin", &seed)?;
+                usb.write_file(SEED_WORDS_FILE, words.as_bytes())?;
                 hal.show_message("SEED SAVED TO USB")?;
                 hal.wait_event()?;
                 pubkey
@@ -156,27 +511,168 @@ fn run_setup<H: Display + Buttons>(
     }
 }
 
-/// Boot flow: run setup if needed, verify PIN, then enter signing loop.
-pub fn run<H: Display + Buttons>(
+/// Vet a recovery seed: all-zero is refused, a weak one needs confirmation.
+fn accept_seed<H: Display + Buttons>(hal: &mut H, seed: &[u8]) -> Result<(), HalError> {
+    match seed_quality(seed) {
+        SeedQuality::Ok => Ok(()),
+        SeedQuality::Refused => {
+            hal.show_message("ALL-ZERO SEED REFUSED")?;
+            hal.wait_event()?;
+            Err(HalError::Storage("all-zero seed".into()))
+        }
+        SeedQuality::Weak => {
+            hal.show_message("WEAK SEED \u{2014} CONFIRM TO PROCEED")?;
+            if wait_action(hal)? == ButtonEvent::Confirm {
+                return Ok(());
+            }
+            hal.show_message("SETUP CANCELLED")?;
+            hal.wait_event()?;
+            Err(HalError::Storage("setup cancelled".into()))
+        }
+    }
+}
+
+/// Wraps a HAL so that every button event goes through a [`ButtonMap`].
+struct Mapped<'a, H> {
+    hal: &'a mut H,
+    map: ButtonMap,
+}
+
+impl<H: Display> Display for Mapped<'_, H> {
+    fn clear(&mut self) -> Result<(), HalError> {
+        self.hal.clear()
+    }
+
+    fn show_message(&mut self, text: &str) -> Result<(), HalError> {
+        self.hal.show_message(text)
+    }
+
+    fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
+        self.hal.show_lines(lines, scroll_offset)
+    }
+
+    fn show_qr(&mut self, caption: &str, modules: &[Vec<bool>]) -> Result<(), HalError> {
+        self.hal.show_qr(caption, modules)
+    }
+}
+
+impl<H: Buttons> Buttons for Mapped<'_, H> {
+    fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
+        Ok(self.map.apply(self.hal.wait_event()?))
+    }
+}
+
+impl<H: Clock> Clock for Mapped<'_, H> {
+    fn now(&self) -> Option<u64> {
+        self.hal.now()
+    }
+}
+
+impl<H: StatusLed> StatusLed for Mapped<'_, H> {
+    fn set(&mut self, color: LedColor) {
+        self.hal.set(color)
+    }
+}
+
+/// Wait for one of the two physical action buttons, ignoring the others.
+fn wait_action<H: Buttons>(hal: &mut H) -> Result<ButtonEvent, HalError> {
+    loop {
+        let ev = hal.wait_event()?;
+        if matches!(ev, ButtonEvent::Confirm | ButtonEvent::Reject) {
+            return Ok(ev);
+        }
+    }
+}
+
+/// First-boot calibration: ask the user to press Confirm, then Reject.
+fn calibrate_buttons<H: Display + Buttons>(hal: &mut H) -> Result<ButtonMap, HalError> {
+    loop {
+        hal.show_message("PRESS THE CONFIRM BUTTON")?;
+        let confirm = wait_action(hal)?;
+        hal.show_message("PRESS THE REJECT BUTTON")?;
+        let reject = wait_action(hal)?;
+        if confirm != reject {
+            return Ok(ButtonMap {
+                swapped: confirm == ButtonEvent::Reject,
+            });
+        }
+        hal.show_message("SAME BUTTON TWICE - TRY AGAIN")?;
+        hal.wait_event()?;
+    }
+}
+
+/// Boot interlock: require the Reject button specifically, warning when
+/// Confirm is pressed instead since the mapping may then be inverted.
+fn button_interlock<H: Display + Buttons>(hal: &mut H) -> Result<(), HalError> {
+    loop {
+        hal.show_message("PRESS REJECT")?;
+        if wait_action(hal)? == ButtonEvent::Reject {
+            return Ok(());
+        }
+        hal.show_message("CONFIRM PRESSED - BUTTONS MAY BE INVERTED")?;
+        hal.wait_event()?;
+    }
+}
+
+/// Boot flow: calibrate buttons on first boot, then run the device with the
+/// stored button mapping applied.
+///
+/// Later boots start with the button interlock unless `config` disables it.
+pub fn run<H: Display + Buttons + Clock + StatusLed>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+    settings: &mut dyn Settings,
+    config: &FlowConfig,
+) -> Result<(), HalError> {
+    let (map, calibrated) = match settings.load(BUTTON_MAP_KEY)? {
+        Some(bytes) => (ButtonMap::from_bytes(&bytes)?, false),
+        None => {
+            let map = calibrate_buttons(hal)?;
+            settings.store(BUTTON_MAP_KEY, &map.to_bytes())?;
+            (map, true)
+        }
+    };
+    let mut hal = Mapped { hal, map };
+    if config.button_interlock && !calibrated {
+        button_interlock(&mut hal)?;
+    }
+    run_unlocked(&mut hal, usb, se, settings, config)
+}
+
+/// Run setup if needed, verify PIN, then enter signing loop.
+fn run_unlocked<H: Display + Buttons + Clock + StatusLed>(
     hal: &mut H,
     usb: &mut dyn UsbMount,
     se: &mut dyn SecureElement,
+    settings: &mut dyn Settings,
+    config: &FlowConfig,
 ) -> Result<(), HalError> {
     if !se.is_provisioned() {
         run_setup(hal, usb, se)?;
     } else {
-        // PIN verification on every boot
+        // PIN verification on every boot; Select instead opens view-only mode
         loop {
-            let pin = match enter_pin(hal, "ENTER PIN")? {
-                Some(p) => p,
-                None => {
+            let pin = match read_digits(hal, "ENTER PIN", PIN_LEN, true, true)? {
+                DigitEntry::Entered(p) => p,
+                DigitEntry::Cancelled => {
                     hal.show_message("GOODBYE")?;
                     hal.wait_event()?;
                     return Ok(());
                 }
+                DigitEntry::ViewOnly => return run_view_only(hal, usb, &*se),
             };
             match se.verify_pin(&pin) {
                 Ok(()) => break,
+                Err(HalError::PinLocked) => {
+                    hal.show_message("PIN LOCKED")?;
+                    hal.wait_event()?;
+                    return Ok(());
+                }
+                Err(HalError::WrongPin { remaining }) => {
+                    hal.show_message(&format!("WRONG PIN - {remaining} LEFT"))?;
+                    hal.wait_event()?;
+                }
                 Err(_) => {
                     hal.show_message("WRONG PIN")?;
                     hal.wait_event()?;
@@ -185,98 +681,3355 @@ pub fn run<H: Display + Buttons>(
         }
     }
 
-    run_loop(hal, usb, se)
+    run_loop(hal, usb, se, settings, config)
 }
 
-/// Run one signing cycle: read USB, interpret, display, sign, write output.
+/// View-only mode: show the public keys without unlocking, and refuse to sign.
 ///
-/// Returns `Ok(true)` on successful signing, `Ok(false)` on rejection.
-pub fn run_once<H: Display + Buttons>(
+/// Only [`PublicKeys`] operations are reachable from here; any stick inserted
+/// for signing is turned away with "LOCKED".
+fn run_view_only<H: Display + Buttons>(
     hal: &mut H,
     usb: &mut dyn UsbMount,
-    se: &mut dyn SecureElement,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    usb.mount_readonly()?;
-    let contents = usb.read_contents()?;
+    keys: &dyn PublicKeys,
+) -> Result<(), HalError> {
+    let mut lines = vec![DisplayLine {
+        key: None,
+        value: "VIEW ONLY - SIGNING LOCKED".into(),
+        indent: 0,
+        ..Default::default()
+    }];
+    for slot in 0..KEY_SLOTS {
+        if let Ok(pubkey) = keys.public_key(slot) {
+            lines.push(DisplayLine {
+                key: Some(format!("Slot {slot} public key")),
+                value: hex::encode(pubkey),
+                indent: 0,
+                ..Default::default()
+            });
+        }
+    }
+    hal.show_lines(&lines, 0)?;
+    hal.wait_event()?;
 
-    let spec = SigningSpec::from_cbor(&contents.signing_spec_cbor)?;
-    hal.show_message(&spec.label)?;
+    loop {
+        hal.show_message("VIEW ONLY - INSERT USB")?;
+        usb.wait_insert()?;
+        hal.show_message("LOCKED")?;
+        let _ = usb.unmount();
+        hal.wait_event()?;
+    }
+}
 
-    // Run WASM interpreter to produce display JSON
-    let sandbox = Sandbox::new()?;
-    let wasm_module = sandbox.load_module(&contents.interpreter_wasm)?;
-    let json_str = wasm_module.interpret(&contents.payload)?;
-    let json_val: serde_json::Value = serde_json::from_str(&json_str)?;
-    let lines = json_to_lines(&json_val);
+/// Run the interpreter and turn its output into review lines plus input requests.
+///
+/// Interpreters exporting the chunked ABI are fed the payload in pieces, with
+/// a progress message after each.
+///
+/// Also checks that the module can assemble when the spec asks for it, so a
+/// broken interpreter is reported before review rather than after signing.
+fn interpret_for_review<H: Display + Buttons>(
+    hal: &mut H,
+    module: &SandboxModule<'_>,
+    spec: &SigningSpec,
+    payload: &mut dyn Read,
+    len: usize,
+) -> Result<Review, Box<dyn std::error::Error>> {
+    check_can_assemble(module, spec)?;
+    let mut shown = None;
+    let json_str = module.interpret_reader(payload, len, INTERPRET_CHUNK, &mut |done, total| {
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        if shown != Some(percent) {
+            shown = Some(percent);
+            let _ = hal.show_message(&format!("INTERPRETING {percent}%"));
+        }
+    })?;
+    let review = review_lines(module, &json_str)?;
+    check_review(module, spec, &review)?;
+    Ok(review)
+}
 
-    // Scrollable review
-    let mut scroll: usize = 0;
-    let max_scroll = lines.len().saturating_sub(1);
-    hal.show_lines(&lines, scroll)?;
+/// Whether the payload is held in one live instance for the whole cycle:
+/// the interpreter computes the signable, or finalizes the output itself.
+fn uses_instance(module: &SandboxModule<'_>, spec: &SigningSpec) -> bool {
+    spec.signable == Signable::Interpreter
+        || (spec.output == OutputSpec::WasmAssemble && module.has_export("finalize"))
+}
 
-    let confirmed = loop {
-        match hal.wait_event()? {
-            ButtonEvent::Up => {
-                scroll = scroll.saturating_sub(1);
-                hal.show_lines(&lines, scroll)?;
-            }
-            ButtonEvent::Down => {
-                scroll = max_scroll.min(scroll + 1);
-                hal.show_lines(&lines, scroll)?;
-            }
-            ButtonEvent::Confirm => break true,
-            ButtonEvent::Reject => break false,
+/// Fail before review rather than after the user has confirmed and the SE has signed.
+fn check_can_assemble(module: &SandboxModule<'_>, spec: &SigningSpec) -> Result<(), SandboxError> {
+    if spec.output == OutputSpec::WasmAssemble
+        && !module.has_export("assemble")
+        && !module.has_export("assemble_with_inputs")
+        && !module.has_export("finalize")
+    {
+        return Err(SandboxError::MissingExport("assemble".into()));
+    }
+    if spec.signable == Signable::Interpreter && !module.has_export("signable") {
+        return Err(SandboxError::MissingExport("signable".into()));
+    }
+    Ok(())
+}
+
+/// Check that the export producing the output can take the requested inputs.
+fn check_review(
+    module: &SandboxModule<'_>,
+    spec: &SigningSpec,
+    review: &Review,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if spec.output != OutputSpec::WasmAssemble {
+        if !review.input_requests.is_empty() {
+            return Err(
+                "interpreter requested inputs but the output mode does not assemble".into(),
+            );
+        }
+        return Ok(());
+    }
+    if module.has_export("finalize") {
+        if !review.input_requests.is_empty() {
+            return Err("interpreter requested inputs but finalize takes none".into());
         }
+        return Ok(());
+    }
+    let assemble_export = if review.input_requests.is_empty() {
+        "assemble"
+    } else {
+        "assemble_with_inputs"
     };
+    if !module.has_export(assemble_export) {
+        return Err(SandboxError::MissingExport(assemble_export.into()).into());
+    }
+    Ok(())
+}
 
-    if !confirmed {
-        hal.show_message("REJECTED")?;
-        usb.unmount()?;
-        return Ok(false);
+/// Where one signing cycle reads the payload from.
+enum Payload<'a> {
+    /// Read whole, for signables and output modes that need all of it.
+    Whole(Vec<u8>),
+    /// Streamed through the hasher producing the signable bytes.
+    Streamed(PayloadStream<'a>, StreamHasher),
+}
+
+/// Reader feeding everything read through it to a [`StreamHasher`], and
+/// to the hasher of the stick manifest's payload digest.
+struct HashingReader<R> {
+    inner: R,
+    hasher: StreamHasher,
+    payload_digest: StreamHasher,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.payload_digest.update(&buf[..n]);
+        Ok(n)
     }
+}
 
-    // Extract signable bytes and sign via secure element
-    let message = extract_signable(&contents.payload, &spec.signable)?;
-    let sig = se.sign(spec.key_slot, &message)?;
+fn blind_review(messages: &[Vec<u8>]) -> Review {
+    Review {
+        lines: blind_lines(messages),
+        input_requests: Vec::new(),
+        max_items: 0,
+    }
+}
 
-    // Produce output
-    let output = match &spec.output {
-        OutputSpec::SignatureOnly => sig,
-        OutputSpec::AppendToPayload => {
-            let mut buf = contents.payload.clone();
-            buf.extend_from_slice(&sig);
-            buf
+/// Payload after review: what gets signed and what the user was shown.
+struct Reviewed {
+    /// The payload, when it was read whole.
+    payload: Option<Vec<u8>>,
+    /// BLAKE2b-256 of a streamed payload, for the stick manifest.
+    payload_digest: Option<Vec<u8>>,
+    /// Signable bytes passed to the secure element, one entry per signature.
+    messages: Vec<Vec<u8>>,
+    review: Review,
+    /// The instance holding the payload, for interpreters that compute the
+    /// signable or finalize the output.
+    instance: Option<PayloadInstance>,
+}
+
+/// Extract the signable bytes and review the payload, reading it whole or
+/// streaming it through the hasher.
+fn read_for_review<H: Display + Buttons>(
+    hal: &mut H,
+    wasm_module: Option<&SandboxModule<'_>>,
+    spec: &SigningSpec,
+    payload: Payload<'_>,
+) -> Result<Reviewed, Box<dyn std::error::Error>> {
+    Ok(match payload {
+        Payload::Whole(payload) => match wasm_module {
+            Some(module) if uses_instance(module, spec) => {
+                check_can_assemble(module, spec)?;
+                let mut instance = module.instantiate(&payload)?;
+                let interpreted = review_lines(module, &instance.interpret()?)?;
+                check_review(module, spec, &interpreted)?;
+                let messages = match spec.signable {
+                    Signable::Interpreter => vec![instance.signable()?],
+                    _ => extract_signable(&payload, &spec.signable)?,
+                };
+                Reviewed {
+                    payload: Some(payload),
+                    payload_digest: None,
+                    messages,
+                    review: interpreted,
+                    instance: Some(instance),
+                }
+            }
+            _ => {
+                let messages = extract_signable(&payload, &spec.signable)?;
+                let interpreted = match wasm_module {
+                    Some(module) => {
+                        interpret_for_review(hal, module, spec, &mut &payload[..], payload.len())?
+                    }
+                    None => blind_review(&messages),
+                };
+                Reviewed {
+                    payload: Some(payload),
+                    payload_digest: None,
+                    messages,
+                    review: interpreted,
+                    instance: None,
+                }
+            }
+        },
+        Payload::Streamed(stream, hasher) => {
+            let mut reader = HashingReader {
+                inner: stream.reader,
+                hasher,
+                payload_digest: payload_hasher(),
+            };
+            let len = stream.len as usize;
+            let interpreted = match wasm_module {
+                Some(module) if module.has_export("validate") => {
+                    // `validate` needs the payload whole. An interpreter
+                    // without the chunked pair is given it whole anyway; one
+                    // with it cannot also ask for `validate`.
+                    if module.has_export("interpret_chunk") {
+                        return Err(
+                            "interpreter exports validate, which a streamed payload cannot run"
+                                .into(),
+                        );
+                    }
+                    let mut bytes = Vec::with_capacity(len);
+                    reader.read_to_end(&mut bytes)?;
+                    if module.validate(&bytes)?.is_some_and(|status| status != 0) {
+                        return Err("PAYLOAD REJECTED BY INTERPRETER".into());
+                    }
+                    Some(interpret_for_review(
+                        hal,
+                        module,
+                        spec,
+                        &mut &bytes[..],
+                        len,
+                    )?)
+                }
+                Some(module) => Some(interpret_for_review(hal, module, spec, &mut reader, len)?),
+                None => None,
+            };
+            // Hash whatever the interpreter did not consume.
+            io::copy(&mut reader, &mut io::sink())?;
+            let messages = vec![reader.hasher.finalize()?];
+            let review = interpreted.unwrap_or_else(|| blind_review(&messages));
+            Reviewed {
+                payload: None,
+                payload_digest: Some(reader.payload_digest.finalize()?),
+                messages,
+                review,
+                instance: None,
+            }
+        }
+    })
+}
+
+/// Write `output` in chunks, resuming an earlier interrupted write of the same output.
+///
+/// After each chunk, `signed.progress` records the output's BLAKE2b-256 and the
+/// number of bytes written. On reinsertion, writing continues from that offset
+/// if the digest matches; a different output starts over. A finished write
+/// empties the record, so writing the same output again starts over too.
+fn write_output_resumable(source: &mut dyn PayloadSource, output: &[u8]) -> Result<(), HalError> {
+    let digest = hex::encode(hash_bytes(HashAlgorithm::Blake2b256, output));
+    let resume_from = source.read_file(WRITE_PROGRESS)?.and_then(|progress| {
+        let progress = String::from_utf8(progress).ok()?;
+        let (recorded, offset) = progress.split_once(' ')?;
+        let offset: usize = offset.trim().parse().ok()?;
+        (recorded == digest && offset < output.len()).then_some(offset)
+    });
+    let mut offset = match resume_from {
+        Some(offset) => offset,
+        None => {
+            source.write_output(&[])?;
+            0
         }
-        OutputSpec::WasmAssemble => wasm_module.assemble(&contents.payload, &sig)?,
     };
+    while offset < output.len() {
+        let end = (offset + WRITE_CHUNK).min(output.len());
+        source.write_output_at(offset as u64, &output[offset..end])?;
+        offset = end;
+        source.write_file(WRITE_PROGRESS, format!("{digest} {offset}").as_bytes())?;
+    }
+    source.write_file(WRITE_PROGRESS, b"")
+}
+
+/// Check that the assembled output still renders every line the user reviewed.
+///
+/// Assembly may add lines (e.g. witnesses) but must not change or drop any.
+fn verify_assembly(
+    module: &SandboxModule<'_>,
+    reviewed: &[DisplayLine],
+    assembled: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lines = review_lines(module, &module.interpret(assembled)?)?.lines;
+    if reviewed.iter().all(|l| lines.contains(l)) {
+        Ok(())
+    } else {
+        Err("ASSEMBLY CHANGED TRANSACTION".into())
+    }
+}
 
-    usb.write_output(&output)?;
-    usb.unmount()?;
-    hal.show_message("DONE \u{2014} REMOVE USB")?;
+/// Review lines for a blind signature: only the raw bytes that will be signed.
+fn blind_lines(messages: &[Vec<u8>]) -> Vec<DisplayLine> {
+    let mut lines = vec![DisplayLine {
+        indent: 0,
+        key: None,
+        value: "BLIND SIGN - NO INTERPRETER".into(),
+        ..Default::default()
+    }];
+    lines.extend(messages.iter().map(|message| DisplayLine {
+        indent: 0,
+        key: Some("sign".into()),
+        value: hex::encode(message),
+        ..Default::default()
+    }));
+    lines
+}
 
-    Ok(true)
+/// Append a record of a blind signature to the log on the USB stick,
+/// stamped with the time in Unix seconds when the board has a clock.
+fn log_blind_sign(
+    source: &mut dyn PayloadSource,
+    spec: &SigningSpec,
+    messages: &[Vec<u8>],
+    now: Option<u64>,
+) -> Result<(), HalError> {
+    let mut log = source.read_file(BLIND_SIGN_LOG)?.unwrap_or_default();
+    let time = now.map(|secs| format!("time={secs} ")).unwrap_or_default();
+    for message in messages {
+        let entry = format!(
+            "{time}slot={} label={:?} message={}\n",
+            spec.key_slot,
+            spec.label,
+            hex::encode(message)
+        );
+        log.extend_from_slice(entry.as_bytes());
+    }
+    source.write_file(BLIND_SIGN_LOG, &log)
 }
 
-/// Main signing loop: idle -> insert -> sign -> repeat.
-pub fn run_loop<H: Display + Buttons>(
-    hal: &mut H,
-    usb: &mut dyn UsbMount,
+/// Sign one message with the spec's key, as the spec's algorithm expects it.
+fn sign_message(
     se: &mut dyn SecureElement,
-) -> Result<(), HalError> {
-    loop {
-        hal.show_message("INSERT USB")?;
-        usb.wait_insert()?;
+    spec: &SigningSpec,
+    message: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut sig = match &spec.derivation_path {
+        Some(path) => se.sign_derived(spec.key_slot, path, message)?,
+        None => se.sign_with(element_algorithm(spec.algorithm), spec.key_slot, message)?,
+    };
+    let pubkey = signing_public_key(se, spec)?;
+    // The secure element returns `r || s`; the recovery id is found here.
+    if spec.algorithm == SignAlgorithm::Secp256k1EcdsaRecoverable {
+        sig = recoverable_signature(&pubkey, message, &sig)?;
+    }
+    if sig.len() != spec.algorithm.signature_len() {
+        return Err("UNEXPECTED SIGNATURE LENGTH".into());
+    }
+    // Nothing leaves the device that would not verify against the key.
+    if !verify(spec.algorithm, &pubkey, message, &sig)? {
+        return Err("SIGNATURE DOES NOT VERIFY".into());
+    }
+    Ok(sig)
+}
 
-        match run_once(hal, usb, se) {
-            Ok(_) => {}
-            Err(e) => {
-                let msg = format!("ERROR: {e}");
-                let _ = hal.show_message(&msg);
-                let _ = usb.unmount();
-            }
-        }
+/// What the secure element is asked to sign with for `algorithm`:
+/// recoverable ECDSA is plain ECDSA there, its recovery id added after.
+fn element_algorithm(algorithm: SignAlgorithm) -> SignAlgorithm {
+    match algorithm {
+        SignAlgorithm::Secp256k1EcdsaRecoverable => SignAlgorithm::Secp256k1Ecdsa,
+        other => other,
+    }
+}
 
-        // Wait for acknowledgment before returning to idle
-        let _ = hal.wait_event();
+/// The key `spec` signs with, encoded for its algorithm: the derived
+/// Ed25519 key for a derivation path, else the slot's key as the secure
+/// element reports it for the algorithm.
+fn signing_public_key(se: &dyn SecureElement, spec: &SigningSpec) -> Result<Vec<u8>, HalError> {
+    match &spec.derivation_path {
+        Some(path) => se.derive_public_key(spec.key_slot, path),
+        None => se.public_key_for(element_algorithm(spec.algorithm), spec.key_slot),
+    }
+}
+
+/// The signatures of a `Multi` signable, each prefixed with its length as a
+/// 4-byte little-endian integer, in signable order.
+fn length_prefixed(sigs: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for sig in sigs {
+        out.extend_from_slice(&(sig.len() as u32).to_le_bytes());
+        out.extend_from_slice(sig);
+    }
+    out
+}
+
+/// Full-screen view of one review line, with its value wrapped to `width`.
+fn detail_lines(line: &DisplayLine, width: usize) -> Vec<DisplayLine> {
+    let mut out = vec![DisplayLine {
+        indent: 0,
+        key: Some(line.key.clone().unwrap_or_default()),
+        value: String::new(),
+        ..Default::default()
+    }];
+    out.extend(
+        wrap_text(&line.value, width)
+            .into_iter()
+            .map(|chunk| DisplayLine {
+                indent: 0,
+                key: None,
+                value: chunk,
+                ..Default::default()
+            }),
+    );
+    out
+}
+
+fn last_format_key(slot: u8) -> String {
+    format!("{LAST_FORMAT_KEY}.{slot}")
+}
+
+/// Warn when the spec's declared format differs from the one this key slot
+/// last signed. Returns `false` if the user rejects; soft advisory only, so
+/// undeclared formats and first use of a slot pass silently.
+fn acknowledge_format_change<H: Display + Buttons>(
+    hal: &mut H,
+    settings: &dyn Settings,
+    spec: &SigningSpec,
+) -> Result<bool, HalError> {
+    let Some(format) = &spec.format else {
+        return Ok(true);
+    };
+    let Some(previous) = settings.load(&last_format_key(spec.key_slot))? else {
+        return Ok(true);
+    };
+    let previous = String::from_utf8_lossy(&previous);
+    if previous == *format {
+        return Ok(true);
+    }
+    hal.show_message(&format!(
+        "SLOT {} LAST SIGNED {previous}, NOW {format} - CONFIRM TO CONTINUE",
+        spec.key_slot
+    ))?;
+    Ok(hal.wait_event()? == ButtonEvent::Confirm)
+}
+
+fn nonce_seen(settings: &dyn Settings, nonce: &[u8; 16]) -> Result<bool, HalError> {
+    let seen = settings.load(SEEN_NONCES_KEY)?.unwrap_or_default();
+    Ok(seen.chunks_exact(nonce.len()).any(|n| n == nonce))
+}
+
+fn record_nonce(settings: &mut dyn Settings, nonce: &[u8; 16]) -> Result<(), HalError> {
+    let mut seen = settings.load(SEEN_NONCES_KEY)?.unwrap_or_default();
+    seen.extend_from_slice(nonce);
+    let excess = seen.len().saturating_sub(MAX_SEEN_NONCES * nonce.len());
+    seen.drain(..excess);
+    settings.store(SEEN_NONCES_KEY, &seen)
+}
+
+/// USB file receiving the public key of a slot provisioned while signing.
+fn slot_pubkey_file(slot: u8) -> String {
+    format!("pubkey-slot{slot}.bin")
+}
+
+/// Offer to generate a key for an empty slot the spec asks to sign with.
+///
+/// Returns `true` once the key exists and its public key is on the stick,
+/// `false` if the user declined.
+fn provision_slot<H: Display + Buttons>(
+    hal: &mut H,
+    source: &mut dyn PayloadSource,
+    se: &mut dyn SecureElement,
+    slot: u8,
+) -> Result<bool, HalError> {
+    hal.show_message(&format!("NO KEY IN SLOT {slot} - CONFIRM TO CREATE"))?;
+    if wait_action(hal)? != ButtonEvent::Confirm {
+        return Ok(false);
+    }
+    let Some(pin) = enter_pin(hal, "ENTER PIN TO CREATE KEY")? else {
+        return Ok(false);
+    };
+    se.verify_pin(&pin)?;
+    hal.show_message("GENERATING NEW KEY...")?;
+    let pubkey = se.generate_key(slot, b"")?;
+    source.write_file(&slot_pubkey_file(slot), &pubkey)?;
+    Ok(true)
+}
+
+/// Safety gate for outputs too long to review: only Select proceeds, so a
+/// habitual Confirm cannot wave it through. Returns `false` on Reject.
+fn override_item_cap<H: Display + Buttons>(hal: &mut H, items: usize) -> Result<bool, HalError> {
+    hal.show_message(&format!(
+        "TRANSACTION HAS {items} OUTPUTS \u{2014} TOO MANY TO REVIEW SAFELY"
+    ))?;
+    loop {
+        match hal.wait_event()? {
+            ButtonEvent::Select => return Ok(true),
+            ButtonEvent::Reject => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Scrollable review. Returns `true` if the user confirmed.
+///
+/// The line at the scroll offset is the selected one; Select opens it in
+/// full (wrapped) until any button is pressed.
+fn review<H: Display + Buttons>(
+    hal: &mut H,
+    lines: &[DisplayLine],
+    config: &FlowConfig,
+) -> Result<bool, HalError> {
+    let shown: Vec<DisplayLine> = match config.abbreviate {
+        Some((head, tail)) => lines.iter().map(|l| l.abbreviated(head, tail)).collect(),
+        None => lines.to_vec(),
+    };
+    let mut cursor: usize = 0;
+    let max_cursor = lines.len().saturating_sub(1);
+    hal.show_lines(&shown, cursor)?;
+
+    loop {
+        match hal.wait_event()? {
+            ev @ (ButtonEvent::Up | ButtonEvent::Down) => {
+                cursor = scroll_step(cursor, max_cursor, ev, config.scroll);
+                hal.show_lines(&shown, cursor)?;
+            }
+            ButtonEvent::Select => {
+                if let Some(line) = lines.get(cursor) {
+                    hal.show_lines(&detail_lines(line, config.detail_width), 0)?;
+                    hal.wait_event()?;
+                }
+                hal.show_lines(&shown, cursor)?;
+            }
+            ButtonEvent::Confirm => return Ok(true),
+            ButtonEvent::Reject => return Ok(false),
+        }
+    }
+}
+
+/// The payload stream and its hasher when the spec's output and signable
+/// allow signing without holding the payload. The spec is checked against
+/// the payload length before its signable is turned into a hasher.
+fn open_streamed<'a>(
+    source: &'a dyn PayloadSource,
+    spec: &SigningSpec,
+) -> Result<Option<(PayloadStream<'a>, StreamHasher)>, Box<dyn std::error::Error>> {
+    if !matches!(
+        spec.output,
+        OutputSpec::SignatureOnly | OutputSpec::DetachedFile { .. }
+    ) {
+        return Ok(None);
+    }
+    let stream = source.open_payload()?;
+    spec.validate(usize::try_from(stream.len).unwrap_or(usize::MAX))?;
+    Ok(StreamHasher::for_signable(&spec.signable)?.map(|hasher| (stream, hasher)))
+}
+
+/// Run one signing cycle: read the request from `source` (a mounted USB
+/// stick or scanned QR codes), interpret, display, sign, write output.
+///
+/// The interpreter is the device's built-in one when the spec names it,
+/// otherwise the stick's. A stick without an interpreter is only signed blind
+/// when `config.allow_blind_sign` is set; every blind signature is logged.
+///
+/// Re-inserting the stick that was just signed (e.g. after a failed write)
+/// reuses the session's cached signature instead of signing a second time.
+///
+/// A spec naming an empty key slot gets an offer to create the key there
+/// and then.
+///
+/// A signature-only spec that hashes the payload before signing gets the
+/// payload streamed through the interpreter and hasher, never held whole.
+///
+/// An interpreter exporting `validate` can turn a whole payload away before
+/// review.
+///
+/// An assembling interpreter that exports `assemble_manifest` returns a
+/// manifest of named files, each written to the stick in place of `signed.bin`.
+///
+/// When the spec declares a `format` that differs from the one the key slot
+/// last signed, the user must acknowledge an advisory before signing.
+///
+/// Returns `Ok(true)` on successful signing, `Ok(false)` on rejection.
+pub fn run_once<H: Display + Buttons + Clock + StatusLed>(
+    hal: &mut H,
+    source: &mut dyn PayloadSource,
+    se: &mut dyn SecureElement,
+    settings: &mut dyn Settings,
+    config: &FlowConfig,
+    session: &mut Session,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let spec_cbor = source
+        .read_file("sign.cbor")?
+        .ok_or_else(|| HalError::Usb("sign.cbor missing".into()))?;
+    let spec = SigningSpec::from_cbor(&spec_cbor)?;
+    let (payload, stick_interpreter) = match open_streamed(source, &spec)? {
+        Some((stream, hasher)) => (
+            Payload::Streamed(stream, hasher),
+            source.read_file("interpreter.wasm")?,
+        ),
+        None => {
+            let contents = source.read_contents()?;
+            (Payload::Whole(contents.payload), contents.interpreter_wasm)
+        }
+    };
+    // A stick packed with a manifest must still match it. A streamed
+    // payload is checked once it has been read through, before review.
+    let stick_manifest = source
+        .read_file(MANIFEST_FILE)?
+        .map(|bytes| Manifest::from_cbor(&bytes))
+        .transpose()?;
+    if let Some(manifest) = &stick_manifest {
+        manifest.verify_files(stick_interpreter.as_deref(), &spec_cbor)?;
+        if let Payload::Whole(bytes) = &payload {
+            manifest.verify_payload_digest(&hash_bytes(HashAlgorithm::Blake2b256, bytes))?;
+        }
+    }
+    let payload_len = match &payload {
+        Payload::Whole(bytes) => bytes.len() as u64,
+        Payload::Streamed(stream, _) => stream.len,
+    };
+    spec.validate(usize::try_from(payload_len).unwrap_or(usize::MAX))?;
+
+    if let Some(size) = spec.payload_size {
+        if size != payload_len {
+            return Err("PAYLOAD SIZE MISMATCH".into());
+        }
+    }
+    if let Some(not_before) = spec.not_before {
+        let count = se.sign_count();
+        if count < not_before {
+            return Err(
+                format!("TOO EARLY - SIGNS FROM COUNT {not_before}, DEVICE AT {count}").into(),
+            );
+        }
+    }
+    // Without a trusted clock the expiry cannot be checked, so it is not.
+    if let (Some(not_after), Some(now)) = (spec.not_after, hal.now()) {
+        if now > not_after {
+            return Err("SPEC EXPIRED".into());
+        }
+    }
+    // Refuse an algorithm the secure element cannot sign with before the
+    // user reviews anything.
+    if let Err(HalError::Unsupported(algorithm)) = signing_public_key(se, &spec) {
+        let algorithm = algorithm.to_uppercase();
+        return Err(format!("{algorithm} NOT SUPPORTED BY SECURE ELEMENT").into());
+    }
+    // The stick just signed repeats its nonce when re-inserted for the
+    // cached signature; whether it is that stick is known after review.
+    let resigned_spec = session.last_spec.as_deref()
+        == Some(&hash_bytes(HashAlgorithm::Blake2b256, &spec_cbor)[..]);
+    if let Some(nonce) = &spec.nonce {
+        if !resigned_spec && nonce_seen(settings, nonce)? {
+            return Err("SPEC ALREADY USED".into());
+        }
+    }
+    let sandbox = Sandbox::new()?;
+    let interpreter_wasm = match &spec.interpreter {
+        // A built-in interpreter wins over anything on the stick.
+        InterpreterSource::Builtin(name) => Some(
+            builtin::interpreter(name)
+                .ok_or_else(|| format!("unknown built-in interpreter '{name}'"))?,
+        ),
+        InterpreterSource::Usb => stick_interpreter.as_deref(),
+    };
+    let precompiled = match (&spec.interpreter, config.allow_precompiled) {
+        (InterpreterSource::Usb, true) => source.read_file(PRECOMPILED_INTERPRETER)?,
+        _ => None,
+    };
+    // Native code is only run when the stick's manifest records it.
+    if let Some(cwasm) = &precompiled {
+        stick_manifest
+            .as_ref()
+            .ok_or_else(|| format!("{PRECOMPILED_INTERPRETER} needs {MANIFEST_FILE}"))?
+            .verify_precompiled(cwasm)?;
+    }
+    let wasm_module = match interpreter_wasm {
+        Some(wasm) => match precompiled {
+            // SAFETY: `allow_precompiled` trusts the stick's native code,
+            // and the manifest ties it to this stick.
+            Some(cwasm) => Some(
+                unsafe { sandbox.load_precompiled(&cwasm) }
+                    .map_err(|e| format!("{PRECOMPILED_INTERPRETER} does not load: {e}"))?,
+            ),
+            None => Some(sandbox.load_module(wasm)?),
+        },
+        None if !config.allow_blind_sign => {
+            return Err("no interpreter on USB and blind signing is disabled".into())
+        }
+        None if spec.output == OutputSpec::WasmAssemble => {
+            return Err("blind signing cannot assemble output".into())
+        }
+        None => None,
+    };
+    // A broken interpreter fails here, before the user sees the label.
+    if let Some(module) = &wasm_module {
+        module.validate_exports(spec.output == OutputSpec::WasmAssemble)?;
+    }
+    hal.set(LedColor::Amber);
+    hal.show_message(&spec.label)?;
+
+    // A payload streamed for hashing is checked as it is read, in
+    // `read_for_review`.
+    let rejected = match (&wasm_module, &payload) {
+        (Some(module), Payload::Whole(bytes)) => {
+            module.validate(bytes)?.is_some_and(|status| status != 0)
+        }
+        _ => false,
+    };
+    if rejected {
+        drop(payload);
+        hal.set(LedColor::Red);
+        hal.show_message("PAYLOAD REJECTED BY INTERPRETER")?;
+        source.unmount()?;
+        return Ok(false);
+    }
+
+    // Extract signable bytes and run the WASM interpreter to produce display JSON
+    let Reviewed {
+        payload,
+        payload_digest,
+        messages,
+        review:
+            Review {
+                lines: interpreted,
+                input_requests,
+                max_items,
+            },
+        mut instance,
+    } = read_for_review(hal, wasm_module.as_ref(), &spec, payload)?;
+    if let (Some(manifest), Some(digest)) = (&stick_manifest, &payload_digest) {
+        manifest.verify_payload_digest(digest)?;
+    }
+    if max_items > config.max_review_items && !override_item_cap(hal, max_items)? {
+        hal.set(LedColor::Red);
+        hal.show_message("REJECTED")?;
+        source.unmount()?;
+        return Ok(false);
+    }
+    // The screen shows the interpreter's lines with the flow's own around
+    // them; an assembly is checked against the interpreter's lines alone.
+    let mut lines = interpreted.clone();
+    // The root is computed here, not by the interpreter, so show it too;
+    // an interpreter-computed signable is shown so the user sees what is signed.
+    let signable_key = match spec.signable {
+        Signable::MerkleRoot { .. } => Some("Merkle root"),
+        Signable::Interpreter => Some("Signable"),
+        _ => None,
+    };
+    if let Some(key) = signable_key {
+        lines.insert(
+            0,
+            DisplayLine {
+                key: Some(key.into()),
+                value: hex::encode(&messages[0]),
+                indent: 0,
+                ..Default::default()
+            },
+        );
+    }
+    if messages.len() > 1 {
+        lines.insert(
+            0,
+            DisplayLine {
+                key: Some("Signatures".into()),
+                value: messages.len().to_string(),
+                ..Default::default()
+            },
+        );
+    }
+    // Name the interpreter first, so the user knows what rendered the rest.
+    if let Some(module) = &wasm_module {
+        if let Some(metadata) = InterpreterMetadata::of(module)? {
+            lines.insert(0, metadata.line());
+        }
+    }
+    if let Some(path) = &spec.derivation_path {
+        lines.push(DisplayLine {
+            key: Some("Key path".into()),
+            value: describe_path(path),
+            ..Default::default()
+        });
+    }
+
+    let inputs = if review(hal, &lines, config)? {
+        collect_inputs(hal, &input_requests)?
+    } else {
+        None
+    };
+    let Some(inputs) = inputs else {
+        hal.set(LedColor::Red);
+        hal.show_message("REJECTED")?;
+        source.unmount()?;
+        return Ok(false);
+    };
+
+    // Sign via secure element, unless this exact request was already signed
+    let digest =
+        Session::request_digest(&spec_cbor, payload.as_deref().unwrap_or(&messages.concat()));
+    let cached = match &session.last_signed {
+        Some((d, sig)) if *d == digest => Some(sig.clone()),
+        _ => None,
+    };
+    let sig = match cached {
+        Some(sig) => {
+            hal.show_message("ALREADY SIGNED - REUSING SIGNATURE")?;
+            sig
+        }
+        None => {
+            if let Some(nonce) = &spec.nonce {
+                if nonce_seen(settings, nonce)? {
+                    return Err("SPEC ALREADY USED".into());
+                }
+            }
+            if !acknowledge_format_change(hal, settings, &spec)? {
+                hal.set(LedColor::Red);
+                hal.show_message("REJECTED")?;
+                source.unmount()?;
+                return Ok(false);
+            }
+            if se.public_key(spec.key_slot).is_err()
+                && !provision_slot(hal, source, se, spec.key_slot)?
+            {
+                hal.set(LedColor::Red);
+                hal.show_message("REJECTED")?;
+                source.unmount()?;
+                return Ok(false);
+            }
+            if spec.require_pin_on_sign {
+                match enter_pin(hal, "ENTER PIN TO SIGN")? {
+                    Some(pin) => se.verify_pin(&pin)?,
+                    None => {
+                        hal.set(LedColor::Red);
+                        hal.show_message("REJECTED")?;
+                        source.unmount()?;
+                        return Ok(false);
+                    }
+                }
+            }
+            let mut sigs = messages
+                .iter()
+                .map(|message| sign_message(se, &spec, message))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sig = match spec.signable {
+                Signable::Multi(_) => length_prefixed(&sigs),
+                _ => sigs.remove(0),
+            };
+            if wasm_module.is_none() {
+                log_blind_sign(source, &spec, &messages, hal.now())?;
+            }
+            if let Some(format) = &spec.format {
+                settings.store(&last_format_key(spec.key_slot), format.as_bytes())?;
+            }
+            if let Some(nonce) = &spec.nonce {
+                record_nonce(settings, nonce)?;
+            }
+            session.last_signed = Some((digest, sig.clone()));
+            session.last_spec = Some(hash_bytes(HashAlgorithm::Blake2b256, &spec_cbor));
+            sig
+        }
+    };
+
+    // Produce output
+    let output = match (&spec.output, &wasm_module, payload) {
+        (OutputSpec::SignatureOnly | OutputSpec::DetachedFile { .. }, _, _) => sig,
+        (_, _, None) => unreachable!("only raw signature output is streamed"),
+        (OutputSpec::AppendToPayload, _, Some(mut buf)) => {
+            buf.extend_from_slice(&sig);
+            buf
+        }
+        (OutputSpec::WasmAssemble, Some(module), Some(_)) if module.has_export("finalize") => {
+            instance
+                .as_mut()
+                .expect("finalizing interpreters are instantiated for review")
+                .finalize(&sig)?
+        }
+        (OutputSpec::WasmAssemble, Some(module), Some(payload)) if !inputs.is_empty() => {
+            module.assemble_with_inputs(&payload, &sig, &encode_inputs(&inputs))?
+        }
+        (OutputSpec::WasmAssemble, Some(module), Some(payload)) => {
+            module.assemble(&payload, &sig)?
+        }
+        (OutputSpec::WasmAssemble, None, _) => unreachable!("rejected before review"),
+        (OutputSpec::PsbtInsert { input_index }, _, Some(payload)) => {
+            let pubkey = signing_public_key(se, &spec)?;
+            psbt::insert_signature(&payload, *input_index, &pubkey, &sig)?
+        }
+    };
+
+    // Interpreters exporting the manifest marker return several named files.
+    let files = match &wasm_module {
+        Some(module)
+            if spec.output == OutputSpec::WasmAssemble && module.has_export(MANIFEST_EXPORT) =>
+        {
+            Some(parse_manifest(&output)?)
+        }
+        _ => None,
+    };
+
+    if spec.verify_assembly && spec.output == OutputSpec::WasmAssemble {
+        if let Some(module) = &wasm_module {
+            // The first file of a manifest is the signed artifact itself.
+            let assembled = files.as_ref().map_or(&output, |files| &files[0].bytes);
+            verify_assembly(module, &interpreted, assembled)?;
+        }
+    }
+
+    // A single output is also shown as QR codes, for workflows that carry
+    // it off by phone rather than on the stick.
+    let qr_output = match files {
+        Some(files) => {
+            for file in files {
+                source.write_file(&file.filename, &file.bytes)?;
+            }
+            None
+        }
+        None => {
+            match &spec.output {
+                OutputSpec::DetachedFile { name } => source.write_file(name, &output)?,
+                _ => write_output_resumable(source, &output)?,
+            }
+            Some(output)
+        }
+    };
+    source.unmount()?;
+    hal.set(LedColor::Green);
+    // The output is written: a failure on the done screen must not report
+    // the signing as failed.
+    let _ = show_done(hal, qr_output.as_deref());
+
+    Ok(true)
+}
+
+/// Final screen of a signing cycle, with `output` as a QR code when there
+/// is a single output.
+///
+/// An output split over several frames is shown one frame at a time, each
+/// encoded only when shown: Up and Down step through them, Confirm or Reject
+/// finishes. An output over [`MAX_QR_FRAMES`] frames is not shown at all.
+fn show_done<H: Display + Buttons>(hal: &mut H, output: Option<&[u8]>) -> Result<(), HalError> {
+    let done = "DONE \u{2014} REMOVE USB";
+    let Some(output) = output else {
+        return hal.show_message(done);
+    };
+    if output.len().div_ceil(QR_FRAME_CHUNK) > MAX_QR_FRAMES {
+        return hal.show_message(&format!("{done} \u{2014} TOO LARGE FOR QR"));
+    }
+    if let Ok(modules) = output_to_qr(output) {
+        return hal.show_qr(done, &modules);
+    }
+    let frames = frame_texts(output, QR_FRAME_CHUNK);
+    let mut frame = 0;
+    loop {
+        let Ok(modules) = encode_text(frames[frame].as_bytes()) else {
+            return hal.show_message(done);
+        };
+        let caption = format!(
+            "{done} \u{2014} QR {}/{} UP/DOWN, CONFIRM TO FINISH",
+            frame + 1,
+            frames.len()
+        );
+        hal.show_qr(&caption, &modules)?;
+        match hal.wait_event()? {
+            ButtonEvent::Down => frame = (frame + 1) % frames.len(),
+            ButtonEvent::Up => frame = (frame + frames.len() - 1) % frames.len(),
+            ButtonEvent::Confirm | ButtonEvent::Reject => return Ok(()),
+            ButtonEvent::Select => {}
+        }
+    }
+}
+
+/// Device actions offered when a stick with nothing to sign is inserted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    Backup,
+    PublicKey,
+    Attestation,
+}
+
+impl Tool {
+    const ALL: &'static [Tool] = &[Tool::Backup, Tool::PublicKey, Tool::Attestation];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Backup => "EXPORT DEVICE BACKUP",
+            Tool::PublicKey => "EXPORT PUBLIC KEY",
+            Tool::Attestation => "EXPORT KEY ATTESTATION",
+        }
+    }
+}
+
+/// Tools menu: Up/Down to choose, Confirm to run, Reject to leave.
+fn run_tools<H: Display + Buttons>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+) -> Result<(), HalError> {
+    let mut selected = 0;
+    loop {
+        let mut lines = vec![DisplayLine {
+            key: None,
+            value: "TOOLS".into(),
+            indent: 0,
+            ..Default::default()
+        }];
+        lines.extend(Tool::ALL.iter().enumerate().map(|(i, tool)| DisplayLine {
+            key: None,
+            value: format!("{} {}", if i == selected { ">" } else { " " }, tool.label()),
+            indent: 0,
+            ..Default::default()
+        }));
+        hal.show_lines(&lines, 0)?;
+
+        match hal.wait_event()? {
+            ButtonEvent::Up => selected = selected.saturating_sub(1),
+            ButtonEvent::Down => selected = (selected + 1).min(Tool::ALL.len() - 1),
+            ButtonEvent::Confirm => {
+                match Tool::ALL[selected] {
+                    Tool::Backup => export_backup(hal, usb, se)?,
+                    Tool::PublicKey => export_public_key(hal, usb, se)?,
+                    Tool::Attestation => export_attestation(hal, usb, se)?,
+                }
+                return usb.unmount();
+            }
+            ButtonEvent::Reject => return usb.unmount(),
+            ButtonEvent::Select => {}
+        }
+    }
+}
+
+/// Write the encrypted device state to the stick for migration.
+fn export_backup<H: Display + Buttons>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+) -> Result<(), HalError> {
+    let Some(pin) = enter_pin(hal, "ENTER PIN TO EXPORT")? else {
+        return hal.show_message("CANCELLED");
+    };
+    let blob = se.export_backup(&pin)?;
+    usb.write_file(DEVICE_BACKUP, &blob)?;
+    hal.show_message("BACKUP SAVED TO USB")
+}
+
+/// Write slot 0's public key to the stick again, as setup does, e.g. after
+/// the public stick was lost.
+fn export_public_key<H: Display + Buttons>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+) -> Result<(), HalError> {
+    let Some(pin) = enter_pin(hal, "ENTER PIN TO EXPORT")? else {
+        return hal.show_message("CANCELLED");
+    };
+    se.verify_pin(&pin)?;
+    usb.write_file("pubkey.bin", &se.public_key(0)?)?;
+    hal.show_message("PUBKEY SAVED TO USB")
+}
+
+/// Write every provisioned slot's public key and the firmware version,
+/// signed by slot 0, so a verifier can confirm the keys share a device.
+fn export_attestation<H: Display + Buttons>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+) -> Result<(), HalError> {
+    let Some(pin) = enter_pin(hal, "ENTER PIN TO EXPORT")? else {
+        return hal.show_message("CANCELLED");
+    };
+    se.verify_pin(&pin)?;
+    let attestation = Attestation {
+        firmware: FIRMWARE_VERSION.into(),
+        slots: (0..KEY_SLOTS)
+            .filter_map(|slot| Some((slot, se.public_key(slot).ok()?)))
+            .collect(),
+    };
+    let statement = attestation.statement();
+    let signature = se.sign(ATTESTING_SLOT, &attestation_digest(&statement))?;
+    usb.write_file(ATTESTATION, &encode_attestation(&statement, &signature))?;
+    hal.show_message("ATTESTATION SAVED TO USB")
+}
+
+/// Main signing loop: idle -> insert -> sign -> repeat.
+///
+/// A stick without `sign.cbor` opens the tools menu instead.
+pub fn run_loop<H: Display + Buttons + Clock + StatusLed>(
+    hal: &mut H,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+    settings: &mut dyn Settings,
+    config: &FlowConfig,
+) -> Result<(), HalError> {
+    let mut session = Session::default();
+    loop {
+        hal.set(LedColor::Off);
+        // The count lets the user notice signatures made behind their back.
+        hal.show_message(&format!("INSERT USB - SIGNED: {}", se.sign_count()))?;
+        usb.wait_insert()?;
+        usb.mount_readonly()?;
+
+        let result = match usb.read_file("sign.cbor") {
+            Ok(None) => run_tools(hal, usb, se).map(|()| false).map_err(Into::into),
+            _ => run_once(hal, usb, se, settings, config, &mut session),
+        };
+        match result {
+            Ok(_) => {}
+            Err(e) => {
+                hal.set(LedColor::Red);
+                let msg = format!("ERROR: {e}");
+                let _ = hal.show_message(&msg);
+                let _ = usb.unmount();
+            }
+        }
+
+        // Wait for acknowledgment before returning to idle
+        let _ = hal.wait_event();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        echo_hex_wasm, run_stick, run_with, spec, MockHal, MockSe, MockSettings, MockUsb,
+    };
+    use signer_core::attestation::verify_attestation;
+    use signer_core::output_manifest::{encode_manifest, OutputFile};
+    use signer_core::qr::output_to_qr_frames;
+    use signer_core::spec::SignableSource;
+
+    fn prompted(hal: &MockHal, prompt: &str) -> bool {
+        hal.screens
+            .iter()
+            .any(|s| s.first().is_some_and(|l| l.value == prompt))
+    }
+
+    #[test]
+    fn wrap_down_at_bottom_goes_to_top() {
+        assert_eq!(scroll_step(5, 5, ButtonEvent::Down, ScrollMode::Wrap), 0);
+        assert_eq!(scroll_step(0, 5, ButtonEvent::Up, ScrollMode::Wrap), 5);
+    }
+
+    #[test]
+    fn clamp_down_at_bottom_stays() {
+        assert_eq!(scroll_step(5, 5, ButtonEvent::Down, ScrollMode::Clamp), 5);
+        assert_eq!(scroll_step(0, 5, ButtonEvent::Up, ScrollMode::Clamp), 0);
+    }
+
+    #[test]
+    fn no_pin_prompt_on_sign_by_default() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert!(!prompted(&hal, "ENTER PIN TO SIGN"));
+        assert_eq!(se.verify_calls, 0);
+    }
+
+    #[test]
+    fn pin_prompt_on_sign_when_required() {
+        let spec = SigningSpec {
+            require_pin_on_sign: true,
+            ..spec()
+        };
+        // Confirm review, then accept PIN 0000 digit by digit.
+        let mut hal = MockHal::new([ButtonEvent::Confirm; 5]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert!(prompted(&hal, "ENTER PIN TO SIGN"));
+        assert_eq!(se.verify_calls, 1);
+        assert_eq!(se.sign_calls, 1);
+    }
+
+    /// Interpreter asking for a two-digit `limit` and echoing the inputs from assemble.
+    const INPUT_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $heap (mut i32) (i32.const 1024))
+          (data (i32.const 16) "\58\00\00\00{\"memo\":\"rent\",\"_inputs\":[{\"name\":\"limit\",\"prompt\":\"LIMIT\",\"kind\":\"number\",\"digits\":2}]}")
+          (func $alloc (export "alloc") (param $size i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $heap))
+            (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+            (local.get $ptr))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (i32.const 16))
+          (func (export "assemble_with_inputs")
+            (param i32 i32 i32 i32) (param $in i32) (param $in_len i32) (result i32)
+            (local $out i32)
+            (local.set $out (call $alloc (i32.add (local.get $in_len) (i32.const 4))))
+            (i32.store (local.get $out) (local.get $in_len))
+            (memory.copy
+              (i32.add (local.get $out) (i32.const 4))
+              (local.get $in)
+              (local.get $in_len))
+            (local.get $out)))
+    "#;
+
+    /// Interpreter whose `assemble` returns `manifest` as a file manifest.
+    fn manifest_wasm(manifest: &[u8]) -> Vec<u8> {
+        let mut data = (manifest.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(manifest);
+        let data: String = data.iter().map(|b| format!("\\{b:02x}")).collect();
+        wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 16) "\0c\00\00\00{{\"memo\":\"a\"}}")
+              (data (i32.const 256) "{data}")
+              (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "interpret") (param i32 i32) (result i32) (i32.const 16))
+              (func (export "assemble") (param i32 i32 i32 i32) (result i32) (i32.const 256))
+              (func (export "assemble_manifest")))
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn echo_hex_assembles_payload_and_signature() {
+        let spec = SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut se,
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        let mut expected = b"tx".to_vec();
+        expected.extend(se.sign(0, b"tx").unwrap());
+        assert_eq!(usb.output, Some(expected));
+    }
+
+    #[test]
+    fn manifest_outputs_are_written_as_named_files() {
+        let spec = SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let files = [
+            OutputFile {
+                filename: "tx.signed".into(),
+                bytes: b"signed tx".to_vec(),
+            },
+            OutputFile {
+                filename: "tx.witness".into(),
+                bytes: b"witness".to_vec(),
+            },
+        ];
+        let usb = MockUsb::signing(&spec, b"tx", manifest_wasm(&encode_manifest(&files)));
+
+        let (signed, usb) = run_stick([ButtonEvent::Confirm], usb, &FlowConfig::default());
+
+        assert!(signed);
+        assert_eq!(usb.files["tx.signed"], b"signed tx");
+        assert_eq!(usb.files["tx.witness"], b"witness");
+        assert_eq!(usb.output, None);
+    }
+
+    #[test]
+    fn manifest_may_not_overwrite_stick_inputs() {
+        let spec = SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let files = [OutputFile {
+            filename: "payload.bin".into(),
+            bytes: b"evil".to_vec(),
+        }];
+        let mut usb = MockUsb::signing(&spec, b"tx", manifest_wasm(&encode_manifest(&files)));
+
+        let result = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(usb.files["payload.bin"], b"tx");
+    }
+
+    #[test]
+    fn requested_input_reaches_assemble() {
+        let spec = SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let wasm = wat::parse_str(INPUT_WAT).unwrap();
+        // Confirm review, then enter 1, 2.
+        let mut hal = MockHal::new([
+            ButtonEvent::Confirm,
+            ButtonEvent::Up,
+            ButtonEvent::Confirm,
+            ButtonEvent::Up,
+            ButtonEvent::Up,
+            ButtonEvent::Confirm,
+        ]);
+        let mut usb = MockUsb::signing(&spec, b"tx", wasm);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert!(prompted(&hal, "LIMIT"));
+        assert_eq!(usb.output.as_deref(), Some(&br#"{"limit":12}"#[..]));
+    }
+
+    /// Interpreter with the required exports only: always renders `{}`.
+    const NO_ASSEMBLE_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "\02\00\00\00{}")
+          (func (export "alloc") (param i32) (result i32)
+            (i32.const 1024))
+          (func (export "interpret") (param i32 i32) (result i32)
+            (i32.const 16)))
+    "#;
+
+    #[test]
+    fn missing_assemble_fails_before_review() {
+        let spec = SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let wasm = wat::parse_str(NO_ASSEMBLE_WAT).unwrap();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", wasm);
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert!(
+            err.to_string().contains("missing exports: assemble"),
+            "{err}"
+        );
+        assert!(hal.messages.is_empty(), "label must not be shown");
+        assert!(hal.screens.is_empty(), "review must not be shown");
+        assert_eq!(se.sign_calls, 0);
+        assert!(usb.output.is_none());
+    }
+
+    fn blind_usb() -> MockUsb {
+        let mut usb = MockUsb::signing(&spec(), b"raw hash", Vec::new());
+        usb.files.remove("interpreter.wasm");
+        usb
+    }
+
+    #[test]
+    fn blind_sign_refused_without_override() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = blind_usb();
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert!(
+            err.to_string().contains("blind signing is disabled"),
+            "{err}"
+        );
+        assert_eq!(se.sign_calls, 0);
+        assert!(!usb.files.contains_key(BLIND_SIGN_LOG));
+    }
+
+    #[test]
+    fn blind_sign_permitted_and_logged_with_override() {
+        let config = FlowConfig {
+            allow_blind_sign: true,
+            ..FlowConfig::default()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = blind_usb();
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &config).unwrap();
+
+        assert!(signed);
+        assert!(prompted(&hal, "BLIND SIGN - NO INTERPRETER"));
+        assert_eq!(usb.output.as_ref().map(Vec::len), Some(64));
+        let log = String::from_utf8(usb.files[BLIND_SIGN_LOG].clone()).unwrap();
+        assert!(log.contains(&hex::encode(b"raw hash")), "{log}");
+        assert!(log.starts_with("slot=0 "), "{log}");
+    }
+
+    #[test]
+    fn blind_sign_log_is_timestamped_when_the_time_is_known() {
+        let config = FlowConfig {
+            allow_blind_sign: true,
+            ..FlowConfig::default()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        hal.now = Some(1_700_000_000);
+        let mut usb = blind_usb();
+
+        run_with(&mut hal, &mut usb, &mut MockSe::unlocked(), &config).unwrap();
+
+        let log = String::from_utf8(usb.files[BLIND_SIGN_LOG].clone()).unwrap();
+        assert!(log.starts_with("time=1700000000 slot=0 "), "{log}");
+    }
+
+    #[test]
+    fn abbreviated_review_still_opens_full_value() {
+        let payload = [0xabu8; 100];
+        let mut hal = MockHal::new([
+            ButtonEvent::Down, // past the interpreter line
+            ButtonEvent::Select,
+            ButtonEvent::Reject,
+            ButtonEvent::Confirm,
+        ]);
+        let mut usb = MockUsb::signing(&spec(), &payload, echo_hex_wasm());
+        let config = FlowConfig {
+            abbreviate: Some((4, 4)),
+            ..FlowConfig::default()
+        };
+
+        let signed = run_with(&mut hal, &mut usb, &mut MockSe::unlocked(), &config).unwrap();
+
+        assert!(signed);
+        let hex_line = hal.screens[0]
+            .iter()
+            .find(|l| l.key.as_deref() == Some("hex"))
+            .unwrap();
+        assert_eq!(hex_line.value, "abab\u{2026}abab");
+        let full: String = hal.screens[2][1..]
+            .iter()
+            .map(|l| l.value.as_str())
+            .collect();
+        assert_eq!(full, hex::encode(payload));
+    }
+
+    #[test]
+    fn select_opens_full_value() {
+        // 100 payload bytes render as a 200-char hex line, wider than the screen.
+        let payload = [0xabu8; 100];
+        let mut hal = MockHal::new([
+            ButtonEvent::Down, // past the interpreter line
+            ButtonEvent::Select,
+            ButtonEvent::Reject, // close the detail view
+            ButtonEvent::Confirm,
+        ]);
+        let mut usb = MockUsb::signing(&spec(), &payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed, "Reject in the detail view only closes it");
+        let detail = &hal.screens[2];
+        assert_eq!(detail[0].key.as_deref(), Some("hex"));
+        assert!(detail[1..].iter().all(|l| l.value.chars().count() <= 80));
+        let full: String = detail[1..].iter().map(|l| l.value.as_str()).collect();
+        assert_eq!(full, hex::encode(payload));
+    }
+
+    #[test]
+    fn calibration_records_swapped_mapping() {
+        let mut settings = MockSettings::default();
+        // The enclosure's Reject-labelled button is pressed for CONFIRM and vice
+        // versa; the PIN 0000 is then accepted with four presses of that button.
+        let mut hal = MockHal::new([
+            ButtonEvent::Reject,
+            ButtonEvent::Confirm,
+            ButtonEvent::Reject,
+            ButtonEvent::Reject,
+            ButtonEvent::Reject,
+            ButtonEvent::Reject,
+        ]);
+        let mut usb = MockUsb::default();
+        let mut se = MockSe::unlocked();
+
+        let result = run(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err(), "ends when no stick is inserted");
+        assert_eq!(
+            settings.values[BUTTON_MAP_KEY],
+            ButtonMap { swapped: true }.to_bytes()
+        );
+        assert_eq!(se.verify_calls, 1);
+        assert!(!hal.messages.contains(&"WRONG PIN".to_string()));
+    }
+
+    #[test]
+    fn stored_mapping_skips_calibration() {
+        let mut settings = MockSettings::default();
+        settings.values.insert(
+            BUTTON_MAP_KEY.into(),
+            ButtonMap { swapped: true }.to_bytes(),
+        );
+        // Raw Confirm reads as Reject through the swap and passes the interlock.
+        let mut events = vec![ButtonEvent::Confirm];
+        events.extend([ButtonEvent::Reject; 4]);
+        let mut hal = MockHal::new(events);
+        let mut usb = MockUsb::default();
+        let mut se = MockSe::unlocked();
+
+        let _ = run(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(!hal
+            .messages
+            .contains(&"PRESS THE CONFIRM BUTTON".to_string()));
+        assert_eq!(se.verify_calls, 1);
+        assert!(!hal.messages.contains(&"WRONG PIN".to_string()));
+    }
+
+    #[test]
+    fn interlock_warns_when_confirm_is_pressed() {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        // Wrong button, dismiss the warning, then the right one.
+        let mut hal = MockHal::new([
+            ButtonEvent::Confirm,
+            ButtonEvent::Confirm,
+            ButtonEvent::Reject,
+        ]);
+        let mut se = MockSe::unlocked();
+
+        // Ends at the PIN prompt when the events run out.
+        let result = run(
+            &mut hal,
+            &mut MockUsb::default(),
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err());
+        let messages: Vec<_> = hal.messages.iter().map(String::as_str).collect();
+        assert_eq!(
+            messages[..3],
+            [
+                "PRESS REJECT",
+                "CONFIRM PRESSED - BUTTONS MAY BE INVERTED",
+                "PRESS REJECT"
+            ]
+        );
+        assert_eq!(se.verify_calls, 0);
+    }
+
+    /// Interpreter showing the first payload byte as `amount`; `assemble`
+    /// appends the signature and then runs `$tamper` on the output. It
+    /// names itself through `metadata`, as the bundled interpreters do, and
+    /// offers its rendered JSON as `signable`.
+    fn amount_wat(tamper: &str) -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (global $heap (mut i32) (i32.const 1024))
+              (data (i32.const 16) "\0e\00\00\00{{\"amount\":\"?\"}}")
+              (data (i32.const 64) "\1f\00\00\00{{\"name\":\"amount\",\"version\":\"1\"}}")
+              (func (export "metadata") (result i32) (i32.const 64))
+              (func (export "signable") (result i32) (i32.const 16))
+              (func $alloc (export "alloc") (param $size i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $heap))
+                (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+                (local.get $ptr))
+              (func (export "interpret") (param $p i32) (param $len i32) (result i32)
+                (i32.store8 (i32.const 31) (i32.load8_u (local.get $p)))
+                (i32.const 16))
+              (func (export "assemble")
+                (param $p i32) (param $pl i32) (param $s i32) (param $sl i32) (result i32)
+                (local $out i32)
+                (local.set $out
+                  (call $alloc (i32.add (i32.add (local.get $pl) (local.get $sl)) (i32.const 4))))
+                (i32.store (local.get $out) (i32.add (local.get $pl) (local.get $sl)))
+                (memory.copy (i32.add (local.get $out) (i32.const 4)) (local.get $p) (local.get $pl))
+                (memory.copy
+                  (i32.add (i32.add (local.get $out) (i32.const 4)) (local.get $pl))
+                  (local.get $s)
+                  (local.get $sl))
+                {tamper}
+                (local.get $out)))
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn verified_assemble_spec() -> SigningSpec {
+        SigningSpec {
+            output: OutputSpec::WasmAssemble,
+            verify_assembly: true,
+            ..spec()
+        }
+    }
+
+    #[test]
+    fn faithful_assembly_passes_verification() {
+        let usb = MockUsb::signing(&verified_assemble_spec(), b"5", amount_wat(""));
+
+        let (signed, usb) = run_stick([ButtonEvent::Confirm], usb, &FlowConfig::default());
+
+        assert!(signed);
+        assert_eq!(usb.output.as_ref().map(Vec::len), Some(1 + 64));
+    }
+
+    #[test]
+    fn key_path_line_is_not_verified_against_the_assembly() {
+        use signer_core::display::HARDENED;
+
+        let spec = SigningSpec {
+            derivation_path: Some(vec![44 | HARDENED, 1815 | HARDENED, HARDENED]),
+            ..verified_assemble_spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"5", amount_wat(""));
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        let last = hal.screens[0].last().unwrap();
+        assert_eq!(last.key.as_deref(), Some("Key path"));
+    }
+
+    #[test]
+    fn signature_count_line_is_not_verified_against_the_assembly() {
+        let spec = SigningSpec {
+            signable: Signable::Multi(vec![
+                Signable::Range {
+                    offset: 0,
+                    length: 1,
+                },
+                Signable::Range {
+                    offset: 1,
+                    length: 1,
+                },
+            ]),
+            ..verified_assemble_spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"56", amount_wat(""));
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert_eq!(se.sign_calls, 2);
+        assert_eq!(hal.screens[0][1].key.as_deref(), Some("Signatures"));
+    }
+
+    #[test]
+    fn flow_computed_signable_line_is_not_verified_against_the_assembly() {
+        // One Merkle leaf of 0x35 bytes: the first payload byte is '5'.
+        let merkle = [&[0x35, 0, 0, 0][..], &[b'x'; 0x35]].concat();
+        for (signable, payload, key) in [
+            (
+                Signable::MerkleRoot {
+                    hash: HashAlgorithm::Sha256,
+                },
+                merkle,
+                "Merkle root",
+            ),
+            (Signable::Interpreter, b"5".to_vec(), "Signable"),
+        ] {
+            let spec = SigningSpec {
+                signable,
+                ..verified_assemble_spec()
+            };
+            let mut hal = MockHal::new([ButtonEvent::Confirm]);
+            let mut usb = MockUsb::signing(&spec, &payload, amount_wat(""));
+
+            let signed = run_with(
+                &mut hal,
+                &mut usb,
+                &mut MockSe::unlocked(),
+                &FlowConfig::default(),
+            )
+            .unwrap();
+
+            assert!(signed, "{key}");
+            assert_eq!(hal.screens[0][1].key.as_deref(), Some(key));
+        }
+    }
+
+    #[test]
+    fn interpreter_metadata_line_is_not_verified_against_the_assembly() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&verified_assemble_spec(), b"5", amount_wat(""));
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert_eq!(hal.screens[0][0].value, "amount 1");
+    }
+
+    #[test]
+    fn assembly_changing_amount_is_caught() {
+        let tamper = "(i32.store8 (i32.add (local.get $out) (i32.const 4)) (i32.const 57))";
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&verified_assemble_spec(), b"5", amount_wat(tamper));
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert_eq!(err.to_string(), "ASSEMBLY CHANGED TRANSACTION");
+        assert!(usb.output.is_none());
+    }
+
+    #[test]
+    fn interrupted_write_resumes() {
+        let output: Vec<u8> = (0..WRITE_CHUNK * 2 + 100).map(|i| i as u8).collect();
+        let mut usb = MockUsb {
+            write_budget: Some(WRITE_CHUNK),
+            ..MockUsb::default()
+        };
+
+        assert!(write_output_resumable(&mut usb, &output).is_err());
+        assert_eq!(usb.written, WRITE_CHUNK);
+
+        // Reinserted: the first chunk is not written again.
+        usb.write_budget = None;
+        write_output_resumable(&mut usb, &output).unwrap();
+
+        assert_eq!(usb.output.as_deref(), Some(&output[..]));
+        assert_eq!(usb.written, output.len());
+    }
+
+    #[test]
+    fn finished_write_is_not_resumed() {
+        let output = vec![3u8; WRITE_CHUNK + 1];
+        let mut usb = MockUsb::default();
+        write_output_resumable(&mut usb, &output).unwrap();
+        assert_eq!(usb.files[WRITE_PROGRESS], b"");
+
+        // The output was deleted from the stick; writing it again must not
+        // skip any of it.
+        usb.output = None;
+        write_output_resumable(&mut usb, &output).unwrap();
+
+        assert_eq!(usb.output.as_deref(), Some(&output[..]));
+        assert_eq!(usb.written, output.len() * 2);
+    }
+
+    #[test]
+    fn different_output_restarts_write() {
+        let mut usb = MockUsb {
+            write_budget: Some(WRITE_CHUNK),
+            ..MockUsb::default()
+        };
+        let first = vec![1u8; WRITE_CHUNK + 1];
+        assert!(write_output_resumable(&mut usb, &first).is_err());
+
+        usb.write_budget = None;
+        let second = vec![2u8; 10];
+        write_output_resumable(&mut usb, &second).unwrap();
+
+        assert_eq!(usb.output.as_deref(), Some(&second[..]));
+    }
+
+    #[test]
+    fn reinserting_signed_stick_does_not_sign_again() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm, ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        let mut session = Session::default();
+        let config = FlowConfig::default();
+
+        run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut MockSettings::default(),
+            &config,
+            &mut session,
+        )
+        .unwrap();
+        let first = usb.output.clone();
+        run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut MockSettings::default(),
+            &config,
+            &mut session,
+        )
+        .unwrap();
+
+        assert_eq!(se.sign_calls, 1);
+        assert_eq!(usb.output, first);
+        assert!(hal
+            .messages
+            .contains(&"ALREADY SIGNED - REUSING SIGNATURE".to_string()));
+    }
+
+    #[test]
+    fn different_payload_is_signed() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm, ButtonEvent::Confirm]);
+        let mut se = MockSe::unlocked();
+        let mut session = Session::default();
+        let config = FlowConfig::default();
+
+        let mut usb = MockUsb::signing(&spec(), b"tx1", echo_hex_wasm());
+        run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut MockSettings::default(),
+            &config,
+            &mut session,
+        )
+        .unwrap();
+        let mut usb = MockUsb::signing(&spec(), b"tx2", echo_hex_wasm());
+        run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut MockSettings::default(),
+            &config,
+            &mut session,
+        )
+        .unwrap();
+
+        assert_eq!(se.sign_calls, 2);
+    }
+
+    #[test]
+    fn format_change_on_slot_needs_acknowledgement() {
+        let spec = SigningSpec {
+            format: Some("message".into()),
+            ..spec()
+        };
+        let mut settings = MockSettings::default();
+        settings.store("last_format.0", b"cardano-tx").unwrap();
+        // Confirm review, then reject the advisory.
+        let mut hal = MockHal::new([ButtonEvent::Confirm, ButtonEvent::Reject]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+            &mut Session::default(),
+        )
+        .unwrap();
+
+        assert!(!signed);
+        assert!(hal
+            .messages
+            .iter()
+            .any(|m| m.starts_with("SLOT 0 LAST SIGNED cardano-tx, NOW message")));
+        assert_eq!(se.sign_calls, 0);
+        assert_eq!(settings.values["last_format.0"], b"cardano-tx");
+    }
+
+    #[test]
+    fn format_is_recorded_per_slot() {
+        let spec = SigningSpec {
+            format: Some("cardano-tx".into()),
+            ..spec()
+        };
+        let mut settings = MockSettings::default();
+        let mut se = MockSe::unlocked();
+        let config = FlowConfig::default();
+
+        for payload in [b"tx1", b"tx2"] {
+            let mut hal = MockHal::new([ButtonEvent::Confirm]);
+            let mut usb = MockUsb::signing(&spec, payload, echo_hex_wasm());
+            let signed = run_once(
+                &mut hal,
+                &mut usb,
+                &mut se,
+                &mut settings,
+                &config,
+                &mut Session::default(),
+            )
+            .unwrap();
+            assert!(signed);
+            assert!(!hal.messages.iter().any(|m| m.contains("LAST SIGNED")));
+        }
+        assert_eq!(settings.values["last_format.0"], b"cardano-tx");
+    }
+
+    #[test]
+    fn builtin_interpreter_replaces_stick_interpreter() {
+        let spec = SigningSpec {
+            interpreter: InterpreterSource::Builtin("echo-hex".into()),
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        // Whatever is on the stick is ignored, even if it is not WASM at all.
+        let mut usb = MockUsb::signing(&spec, b"\xca\xfe", b"not wasm".to_vec());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert!(hal.screens[0].iter().any(|l| l.value.contains("cafe")));
+    }
+
+    #[test]
+    fn unknown_builtin_interpreter_is_an_error() {
+        let spec = SigningSpec {
+            interpreter: InterpreterSource::Builtin("no-such-format".into()),
+            ..spec()
+        };
+        let mut hal = MockHal::new([]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert!(err.to_string().contains("no-such-format"));
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn view_only_shows_pubkey_and_refuses_to_sign() {
+        let mut se = MockSe::unlocked();
+        se.lock();
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        // Pass the interlock, Select at the PIN prompt, dismiss the key
+        // screen, acknowledge LOCKED.
+        let mut hal = MockHal::new([
+            ButtonEvent::Reject,
+            ButtonEvent::Select,
+            ButtonEvent::Confirm,
+            ButtonEvent::Confirm,
+        ]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+
+        // The loop ends when the mock runs out of insertions.
+        let result = run(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err());
+        let pubkey = hex::encode(se.public_key(0).unwrap());
+        assert!(hal.screens.iter().flatten().any(|l| l.value == pubkey));
+        assert!(hal.messages.contains(&"LOCKED".to_string()));
+        assert_eq!(se.verify_calls, 0);
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn payload_size_is_checked() {
+        let spec = SigningSpec {
+            payload_size: Some(2),
+            ..spec()
+        };
+
+        let mut hal = MockHal::new([]);
+        let mut usb = MockUsb::signing(&spec, b"tx!", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+        assert_eq!(err.to_string(), "PAYLOAD SIZE MISMATCH");
+        assert_eq!(se.sign_calls, 0);
+
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+        assert!(signed);
+    }
+
+    #[test]
+    fn merkle_root_is_shown_and_signed() {
+        let spec = SigningSpec {
+            signable: Signable::MerkleRoot {
+                hash: HashAlgorithm::Sha256,
+            },
+            ..spec()
+        };
+        let payload = [&2u32.to_le_bytes()[..], b"ab", &1u32.to_le_bytes(), b"c"].concat();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, &payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let root = signer_core::crypto::merkle_root(HashAlgorithm::Sha256, &[b"ab", b"c"]);
+        // echo-hex names itself above the root.
+        assert_eq!(hal.screens[0][1].key.as_deref(), Some("Merkle root"));
+        assert_eq!(hal.screens[0][1].value, hex::encode(root));
+    }
+
+    #[test]
+    fn derivation_path_is_shown_and_signed_with() {
+        use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+        use signer_core::display::HARDENED;
+
+        let account = vec![
+            44 | HARDENED,
+            1815 | HARDENED,
+            HARDENED,
+            HARDENED,
+            5 | HARDENED,
+        ];
+        let spec = SigningSpec {
+            derivation_path: Some(account.clone()),
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let last = hal.screens[0].last().unwrap();
+        assert_eq!(last.key.as_deref(), Some("Key path"));
+        assert_eq!(
+            last.value,
+            "m/44'/1815'/0'/0'/5' (account 0, role 0', index 5')"
+        );
+        let public = se.derive_public_key(0, &account).unwrap();
+        assert_ne!(public, se.public_key(0).unwrap());
+        let key = VerifyingKey::from_bytes(public.as_slice().try_into().unwrap()).unwrap();
+        let sig = Signature::from_slice(usb.output.as_deref().unwrap()).unwrap();
+        key.verify(b"tx", &sig).unwrap();
+    }
+
+    #[test]
+    fn out_of_bounds_range_is_refused_before_review() {
+        let spec = SigningSpec {
+            signable: Signable::Range {
+                offset: usize::MAX,
+                length: 2,
+            },
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let result = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default());
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("outside the 2-byte payload"));
+        assert!(hal.screens.is_empty());
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn led_follows_the_signing_cycle() {
+        let cycle = |event| {
+            let mut hal = MockHal::new([event]);
+            let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+            run_with(
+                &mut hal,
+                &mut usb,
+                &mut MockSe::unlocked(),
+                &FlowConfig::default(),
+            )
+            .unwrap();
+            hal.leds
+        };
+        assert_eq!(
+            cycle(ButtonEvent::Confirm),
+            [LedColor::Amber, LedColor::Green]
+        );
+        assert_eq!(cycle(ButtonEvent::Reject), [LedColor::Amber, LedColor::Red]);
+    }
+
+    #[test]
+    fn detached_signature_is_written_under_its_name() {
+        let detached = |name: &str| SigningSpec {
+            output: OutputSpec::DetachedFile { name: name.into() },
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&detached("tx.sig"), b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        let mut settings = MockSettings::default();
+        let config = FlowConfig::default();
+
+        let signed = run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &config,
+            &mut Session::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert_eq!(usb.output, None);
+        assert_eq!(usb.files["tx.sig"], se.sign(0, b"tx").unwrap());
+
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&detached("../x"), b"tx", echo_hex_wasm());
+        let result = run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &config,
+            &mut Session::default(),
+        );
+        assert!(result.unwrap_err().to_string().contains("\"../x\""));
+        assert!(hal.screens.is_empty());
+        assert!(!usb.files.contains_key("../x"));
+    }
+
+    #[test]
+    fn multi_signs_each_range_in_order() {
+        let spec = SigningSpec {
+            signable: Signable::Multi(vec![
+                Signable::Range {
+                    offset: 0,
+                    length: 2,
+                },
+                Signable::Range {
+                    offset: 2,
+                    length: 3,
+                },
+            ]),
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"abcde", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert_eq!(se.sign_calls, 2);
+        // echo-hex names itself above the count.
+        assert_eq!(hal.screens[0][1].key.as_deref(), Some("Signatures"));
+        assert_eq!(hal.screens[0][1].value, "2");
+        let expected = [se.sign(0, b"ab").unwrap(), se.sign(0, b"cde").unwrap()];
+        let mut output = &usb.output.unwrap()[..];
+        for sig in expected {
+            let (len, rest) = output.split_at(4);
+            assert_eq!(u32::from_le_bytes(len.try_into().unwrap()), 64);
+            assert_eq!(&rest[..64], sig);
+            output = &rest[64..];
+        }
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn psbt_insert_adds_a_partial_signature() {
+        // Version 2 PSBT with one input, no outputs and empty maps.
+        let payload = b"psbt\xff\x01\x04\x01\x01\x01\x05\x01\x00\x00\x00";
+        let spec = SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Whole,
+            },
+            algorithm: SignAlgorithm::Secp256k1Ecdsa,
+            output: OutputSpec::PsbtInsert { input_index: 0 },
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let output = psbt::Psbt::parse(&usb.output.unwrap()).unwrap();
+        let (key, value) = &output.inputs[0][0];
+        let pubkey = se.public_key_for(SignAlgorithm::Secp256k1Ecdsa, 0).unwrap();
+        assert_eq!(pubkey.len(), 33);
+        assert_eq!(key[1..], pubkey[..]);
+        // DER `30 len 02 rlen r 02 slen s`, then SIGHASH_ALL.
+        let (der, sighash) = value.split_at(value.len() - 1);
+        assert_eq!(sighash, [0x01]);
+        let mut compact = Vec::new();
+        let mut rest = &der[2..];
+        for _ in 0..2 {
+            let (int, tail) = rest[2..].split_at(rest[1] as usize);
+            let int = &int[int.len().saturating_sub(32)..];
+            compact.extend(std::iter::repeat_n(0, 32 - int.len()));
+            compact.extend_from_slice(int);
+            rest = tail;
+        }
+        let digest = hash_bytes(HashAlgorithm::Sha256, payload);
+        assert!(verify(SignAlgorithm::Secp256k1Ecdsa, &pubkey, &digest, &compact).unwrap());
+    }
+
+    #[test]
+    fn payload_rejected_by_validate_is_not_reviewed() {
+        let payload = vec![0u8; (1 << 20) + 1];
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), &payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(!signed);
+        assert_eq!(
+            hal.messages.last().map(String::as_str),
+            Some("PAYLOAD REJECTED BY INTERPRETER")
+        );
+        assert!(hal.screens.is_empty(), "review must not be shown");
+        assert_eq!(se.sign_calls, 0);
+        assert!(usb.output.is_none());
+    }
+
+    /// A spec whose payload is streamed through the hasher.
+    fn streamed_spec() -> SigningSpec {
+        SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Blake2b256,
+                source: SignableSource::Whole,
+            },
+            ..spec()
+        }
+    }
+
+    #[test]
+    fn streamed_payload_is_validated_too() {
+        // echo-hex refuses payloads over 1 MiB.
+        let payload = vec![0u8; (1 << 20) + 1];
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&streamed_spec(), &payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert!(usb.streamed.get());
+        assert_eq!(err.to_string(), "PAYLOAD REJECTED BY INTERPRETER");
+        assert!(hal.screens.is_empty(), "review must not be shown");
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn chunked_interpreter_with_validate_is_refused_for_a_streamed_payload() {
+        let wasm = wat::parse_str(
+            r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 16) "\02\00\00\00{}")
+            (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+            (func (export "validate") (param i32 i32) (result i32) (i32.const 0))
+            (func (export "interpret_chunk") (param i32 i32) (result i32) (i32.const 0))
+            (func (export "interpret_finish") (result i32) (i32.const 16)))
+            "#,
+        )
+        .unwrap();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&streamed_spec(), b"tx", wasm);
+        let mut se = MockSe::unlocked();
+
+        let err = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap_err();
+
+        assert!(err.to_string().contains("exports validate"));
+        assert!(hal.screens.is_empty());
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn precompiled_interpreter_is_used_only_when_allowed() {
+        let sandbox = Sandbox::new().unwrap();
+        // Renders a fixed review, unlike the stick's echo-hex.
+        let cwasm = sandbox
+            .precompile(&wat::parse_str(NO_ASSEMBLE_WAT).unwrap())
+            .unwrap();
+        let wasm = echo_hex_wasm();
+        let spec_cbor = spec().to_cbor().unwrap();
+        let manifest = Manifest::new("0.1.0", b"tx", Some(&wasm), &spec_cbor);
+        // Whether the review came from the stick's echo-hex, or the error.
+        let run = |allow_precompiled, cwasm: &[u8], manifest: Option<&Manifest>| {
+            let mut hal = MockHal::new([ButtonEvent::Confirm]);
+            let mut usb = MockUsb::signing(&spec(), b"tx", wasm.clone());
+            usb.files
+                .insert(PRECOMPILED_INTERPRETER.into(), cwasm.to_vec());
+            if let Some(manifest) = manifest {
+                usb.files.insert(MANIFEST_FILE.into(), manifest.to_cbor());
+            }
+            let config = FlowConfig {
+                allow_precompiled,
+                ..FlowConfig::default()
+            };
+            run_with(&mut hal, &mut usb, &mut MockSe::unlocked(), &config)
+                .map(|signed| {
+                    assert!(signed);
+                    hal.screens[0]
+                        .iter()
+                        .any(|l| l.key.as_deref() == Some("hex"))
+                })
+                .map_err(|e| e.to_string())
+        };
+
+        let recorded = manifest.clone().with_precompiled(&cwasm);
+        assert_eq!(
+            run(false, &cwasm, None),
+            Ok(true),
+            "artifact loaded without the override"
+        );
+        assert_eq!(
+            run(true, &cwasm, Some(&recorded)),
+            Ok(false),
+            "artifact ignored with the override"
+        );
+        // Native code the manifest does not vouch for is refused.
+        assert_eq!(
+            run(true, &cwasm, None),
+            Err("interpreter.cwasm needs manifest.cbor".into())
+        );
+        assert_eq!(
+            run(true, &cwasm, Some(&manifest)),
+            Err("interpreter.cwasm does not match manifest.cbor".into())
+        );
+        // A recorded artifact that does not load is not silently replaced.
+        let garbage = manifest.with_precompiled(b"not an artifact");
+        assert!(run(true, b"not an artifact", Some(&garbage))
+            .unwrap_err()
+            .starts_with("interpreter.cwasm does not load"));
+    }
+
+    #[test]
+    fn done_screen_shows_output_as_qr() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert_eq!(
+            hal.messages.last().map(String::as_str),
+            Some("DONE \u{2014} REMOVE USB")
+        );
+        let output = usb.output.unwrap();
+        assert_eq!(hal.qr_codes, [output_to_qr(&output).unwrap()]);
+    }
+
+    #[test]
+    fn large_output_cycles_qr_frames() {
+        let spec = SigningSpec {
+            output: OutputSpec::AppendToPayload,
+            ..spec()
+        };
+        let payload = vec![0x5a; 1900];
+        let mut hal = MockHal::new([
+            ButtonEvent::Confirm,
+            ButtonEvent::Down,
+            ButtonEvent::Down,
+            ButtonEvent::Up,
+            ButtonEvent::Confirm,
+        ]);
+        let mut usb = MockUsb::signing(&spec, &payload, echo_hex_wasm());
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        let frames = output_to_qr_frames(&usb.output.unwrap(), QR_FRAME_CHUNK).unwrap();
+        assert_eq!(frames.len(), 4);
+        let shown = [0, 1, 2, 1].map(|i| frames[i].clone());
+        assert_eq!(hal.qr_codes, shown);
+        assert!(hal.messages.last().unwrap().contains("QR 2/4"));
+    }
+
+    #[test]
+    fn output_over_the_frame_cap_is_not_shown_as_qr() {
+        let spec = SigningSpec {
+            output: OutputSpec::AppendToPayload,
+            ..spec()
+        };
+        let payload = vec![0x5a; MAX_QR_FRAMES * QR_FRAME_CHUNK];
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, &payload, echo_hex_wasm());
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert!(usb.output.unwrap().starts_with(&payload));
+        assert!(hal.qr_codes.is_empty());
+        assert_eq!(
+            hal.messages.last().map(String::as_str),
+            Some("DONE \u{2014} REMOVE USB \u{2014} TOO LARGE FOR QR")
+        );
+    }
+
+    #[test]
+    fn interpreter_metadata_heads_review() {
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+
+        let signed = run_with(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert_eq!(hal.screens[0][0].key.as_deref(), Some("Interpreter"));
+        assert_eq!(hal.screens[0][0].value, "echo-hex 0.1.0");
+    }
+
+    #[test]
+    fn interpreter_signs_and_finalizes_in_one_instance() {
+        // `interpret` remembers where the payload was copied, `signable`
+        // returns its first byte and `finalize` returns payload || signature,
+        // none of them being given the payload again.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (global $heap (mut i32) (i32.const 1024))
+              (global $payload (mut i32) (i32.const 0))
+              (global $payload_len (mut i32) (i32.const 0))
+              (data (i32.const 16) "\0c\00\00\00{\"memo\":\"a\"}")
+              (func (export "alloc") (param $len i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $heap))
+                (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+                (local.get $ptr))
+              (func (export "interpret") (param $ptr i32) (param $len i32) (result i32)
+                (global.set $payload (local.get $ptr))
+                (global.set $payload_len (local.get $len))
+                (i32.const 16))
+              (func (export "signable") (result i32)
+                (i32.store (i32.const 512) (i32.const 1))
+                (i32.store8 (i32.const 516) (i32.load8_u (global.get $payload)))
+                (i32.const 512))
+              (func (export "finalize") (param $sig i32) (param $sig_len i32) (result i32)
+                (i32.store (i32.const 4096) (i32.add (global.get $payload_len) (local.get $sig_len)))
+                (memory.copy (i32.const 4100) (global.get $payload) (global.get $payload_len))
+                (memory.copy
+                  (i32.add (i32.const 4100) (global.get $payload_len))
+                  (local.get $sig)
+                  (local.get $sig_len))
+                (i32.const 4096)))
+            "#,
+        )
+        .unwrap();
+        let spec = SigningSpec {
+            signable: Signable::Interpreter,
+            output: OutputSpec::WasmAssemble,
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", wasm);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert_eq!(hal.screens[0][0].key.as_deref(), Some("Signable"));
+        assert_eq!(hal.screens[0][0].value, hex::encode(b"t"));
+        let sig = se.sign(0, b"t").unwrap();
+        assert_eq!(usb.output, Some([&b"tx"[..], &sig].concat()));
+    }
+
+    #[test]
+    fn chunked_interpreter_reports_progress() {
+        let wasm = wat::parse_str(
+            r#"
+        (module
+            (memory (export "memory") 2)
+            (global $seen (mut i32) (i32.const 0))
+            (data (i32.const 16) "\10\00\00\00{\"chunked\":true}")
+            (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+            (func (export "interpret_chunk") (param i32 i32) (result i32)
+              (global.set $seen (i32.add (global.get $seen) (local.get 1)))
+              (i32.const 0))
+            (func (export "interpret_finish") (result i32)
+              (i32.const 16)))
+            "#,
+        )
+        .unwrap();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), &vec![0u8; 4 * INTERPRET_CHUNK], wasm);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let progress: Vec<_> = hal
+            .messages
+            .iter()
+            .filter(|m| m.starts_with("INTERPRETING"))
+            .collect();
+        assert_eq!(
+            progress,
+            [
+                "INTERPRETING 25%",
+                "INTERPRETING 50%",
+                "INTERPRETING 75%",
+                "INTERPRETING 100%"
+            ]
+        );
+    }
+
+    #[test]
+    fn interpreter_schema_formats_review_lines() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 16) "\0f\00\00\00{\"fee\":1500000}")
+              (data (i32.const 64) "\57\00\00\00{\"fields\":{\"fee\":{\"label\":\"Fee\",\"format\":{\"type\":\"amount\",\"decimals\":6,\"unit\":\"ADA\"}}}}")
+              (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "interpret") (param i32 i32) (result i32) (i32.const 16))
+              (func (export "schema") (result i32) (i32.const 64)))
+            "#,
+        )
+        .unwrap();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec(), b"tx", wasm);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert_eq!(hal.screens[0][0].key.as_deref(), Some("Fee"));
+        assert_eq!(hal.screens[0][0].value, "1.500000 ADA");
+    }
+
+    #[test]
+    fn device_backup_moves_keys_to_new_element() {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+
+        // Old device: a stick without sign.cbor opens the tools menu.
+        let mut old = MockSe::unlocked();
+        let mut usb = MockUsb {
+            insertions: 1,
+            ..MockUsb::default()
+        };
+        // Choose the export, then enter PIN 0000.
+        let mut hal = MockHal::new([ButtonEvent::Confirm; 5]);
+        let result = run_loop(
+            &mut hal,
+            &mut usb,
+            &mut old,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+        assert!(result.is_err());
+        assert!(hal.messages.contains(&"BACKUP SAVED TO USB".to_string()));
+        let blob = usb.files[DEVICE_BACKUP].clone();
+
+        // New device: setup with the same PIN finds the backup on the private stick.
+        let mut new = MockSe::default();
+        let mut usb = MockUsb::default();
+        usb.files.insert(DEVICE_BACKUP.into(), blob);
+        let mut events = vec![ButtonEvent::Reject];
+        events.extend([ButtonEvent::Confirm; 14]);
+        let mut hal = MockHal::new(events);
+        let result = run(
+            &mut hal,
+            &mut usb,
+            &mut new,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+        assert!(result.is_err());
+        assert!(hal
+            .messages
+            .contains(&"RESTORING DEVICE BACKUP...".to_string()));
+        assert_eq!(usb.files["pubkey.bin"], old.public_key(0).unwrap());
+        assert_eq!(new.sign(0, b"hash").unwrap(), old.sign(0, b"hash").unwrap());
+    }
+
+    #[test]
+    fn idle_screen_counts_signatures_across_reloads() {
+        use crate::keystore::{OsEntropy, SimSecureElement};
+
+        let path = std::env::temp_dir().join(format!(
+            "signer-sim-{}-sign-counter.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let load = || {
+            let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+            se.verify_pin(b"0000").unwrap();
+            se
+        };
+        // One signing cycle on a freshly loaded keystore; the idle messages.
+        let cycle = |se: &mut SimSecureElement| {
+            let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+            // Confirm the review, then acknowledge DONE.
+            let mut hal = MockHal::new([ButtonEvent::Confirm; 2]);
+            let result = run_loop(
+                &mut hal,
+                &mut usb,
+                se,
+                &mut MockSettings::default(),
+                &FlowConfig::default(),
+            );
+            assert!(result.is_err());
+            assert!(usb.output.is_some());
+            hal.messages
+                .into_iter()
+                .filter(|m| m.starts_with("INSERT USB"))
+                .collect::<Vec<_>>()
+        };
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"0000").unwrap();
+        se.verify_pin(b"0000").unwrap();
+        se.generate_key(0, b"").unwrap();
+        drop(se);
+
+        assert_eq!(
+            cycle(&mut load()),
+            ["INSERT USB - SIGNED: 0", "INSERT USB - SIGNED: 1"]
+        );
+        assert_eq!(
+            cycle(&mut load()),
+            ["INSERT USB - SIGNED: 1", "INSERT USB - SIGNED: 2"]
+        );
+        assert_eq!(load().sign_count(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn public_key_is_exported_again_from_tools() {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        let mut se = MockSe::unlocked();
+        let mut usb = MockUsb {
+            insertions: 1,
+            ..MockUsb::default()
+        };
+        // Move to the second tool, choose it, then enter PIN 0000.
+        let mut events = vec![ButtonEvent::Down];
+        events.extend([ButtonEvent::Confirm; 5]);
+        let mut hal = MockHal::new(events);
+
+        let result = run_loop(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err());
+        assert!(hal.messages.contains(&"PUBKEY SAVED TO USB".to_string()));
+        assert_eq!(usb.files["pubkey.bin"], se.public_key(0).unwrap());
+        assert!(!usb.files.contains_key(DEVICE_BACKUP));
+    }
+
+    #[test]
+    fn attestation_lists_slots_and_verifies_against_slot_zero() {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        let mut se = MockSe::unlocked();
+        se.import_key(5, &[9u8; 32], b"").unwrap();
+        let mut usb = MockUsb {
+            insertions: 1,
+            ..MockUsb::default()
+        };
+        // Move to the third tool, choose it, then enter PIN 0000.
+        let mut events = vec![ButtonEvent::Down, ButtonEvent::Down];
+        events.extend([ButtonEvent::Confirm; 5]);
+        let mut hal = MockHal::new(events);
+
+        let result = run_loop(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+
+        assert!(result.is_err());
+        assert!(hal
+            .messages
+            .contains(&"ATTESTATION SAVED TO USB".to_string()));
+        let attestation = verify_attestation(&usb.files[ATTESTATION]).unwrap();
+        assert_eq!(attestation.firmware, FIRMWARE_VERSION);
+        assert_eq!(
+            attestation.slots.keys().copied().collect::<Vec<_>>(),
+            [0, 5]
+        );
+        assert_eq!(attestation.slots[&0], se.public_key(0).unwrap());
+        assert_eq!(attestation.slots[&5], se.public_key(5).unwrap());
+    }
+
+    #[test]
+    fn empty_slot_is_provisioned_on_request() {
+        let spec = SigningSpec {
+            key_slot: 2,
+            ..spec()
+        };
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        // Review, accept the offer, then PIN 0000.
+        let mut hal = MockHal::new([ButtonEvent::Confirm; 6]);
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        assert!(hal
+            .messages
+            .contains(&"NO KEY IN SLOT 2 - CONFIRM TO CREATE".to_string()));
+        let pubkey = se.public_key(2).unwrap();
+        assert_eq!(usb.files[&slot_pubkey_file(2)], pubkey);
+        assert_eq!(usb.output, Some(se.sign(2, b"tx").unwrap()));
+    }
+
+    #[test]
+    fn declining_slot_provisioning_rejects() {
+        let spec = SigningSpec {
+            key_slot: 2,
+            ..spec()
+        };
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        let mut hal = MockHal::new([ButtonEvent::Confirm, ButtonEvent::Reject]);
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(!signed);
+        assert!(se.public_key(2).is_err());
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    /// Run first-time setup with `seed.bin` on the private stick.
+    fn recover(seed: &[u8], events: Vec<ButtonEvent>) -> (MockHal, MockSe, Result<(), HalError>) {
+        recover_from("seed.bin", seed, events)
+    }
+
+    /// Run first-time setup with `file` holding `contents` on the private stick.
+    fn recover_from(
+        file: &str,
+        contents: &[u8],
+        events: Vec<ButtonEvent>,
+    ) -> (MockHal, MockSe, Result<(), HalError>) {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        let mut usb = MockUsb::default();
+        usb.files.insert(file.into(), contents.to_vec());
+        let mut hal = MockHal::new([ButtonEvent::Reject].into_iter().chain(events));
+        let mut se = MockSe::default();
+        let result = run(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        );
+        (hal, se, result)
+    }
+
+    #[test]
+    fn weak_seed_is_imported_after_confirmation() {
+        // SETUP, two PINs, private stick, the warning, no passphrase, then the
+        // remaining prompts.
+        let mut events = vec![ButtonEvent::Confirm; 11];
+        events.push(ButtonEvent::Reject);
+        events.extend([ButtonEvent::Confirm; 4]);
+        let (hal, se, _) = recover(&[0xFF; 32], events);
+        assert!(hal
+            .messages
+            .contains(&"WEAK SEED \u{2014} CONFIRM TO PROCEED".to_string()));
+        assert_eq!(se.export_seed(0).unwrap(), [0xFF; 32]);
+    }
+
+    const MNEMONIC: &str = "legal winner thank year wave sausage worth useful legal winner \
+        thank yellow";
+
+    #[test]
+    fn mnemonic_is_recovered() {
+        // SETUP, two PINs, private stick, no passphrase, then the remaining
+        // prompts.
+        let mut events = vec![ButtonEvent::Confirm; 10];
+        events.push(ButtonEvent::Reject);
+        events.extend([ButtonEvent::Confirm; 4]);
+        let (hal, se, _) = recover_from(MNEMONIC_FILE, MNEMONIC.as_bytes(), events);
+        assert!(hal
+            .messages
+            .contains(&"RECOVERING FROM MNEMONIC...".to_string()));
+        // The keys a wallet derives from the BIP39 seed, master and children.
+        let seed = signer_core::mnemonic::mnemonic_to_seed(MNEMONIC, b"").unwrap();
+        let wallet_key = |path: &[u32]| {
+            let key = signer_core::slip10::derive(&seed, path).unwrap();
+            ed25519_dalek::SigningKey::from_bytes(&key)
+                .verifying_key()
+                .to_bytes()
+                .to_vec()
+        };
+        use signer_core::display::HARDENED;
+        let path = [44 | HARDENED, 501 | HARDENED, HARDENED, HARDENED];
+        assert_eq!(se.public_key(0).unwrap(), wallet_key(&[]));
+        assert_eq!(se.derive_public_key(0, &path).unwrap(), wallet_key(&path));
+    }
+
+    #[test]
+    fn generated_seed_is_written_as_words_that_recover_it() {
+        let mut settings = MockSettings::default();
+        settings
+            .store(BUTTON_MAP_KEY, &ButtonMap::default().to_bytes())
+            .unwrap();
+        let mut usb = MockUsb::default();
+        // SETUP, two PINs, private stick, no passphrase, then the remaining
+        // prompts.
+        let mut events = vec![ButtonEvent::Reject];
+        events.extend([ButtonEvent::Confirm; 10]);
+        events.push(ButtonEvent::Reject);
+        events.extend([ButtonEvent::Confirm; 5]);
+        let mut se = MockSe::default();
+        run(
+            &mut MockHal::new(events),
+            &mut usb,
+            &mut se,
+            &mut settings,
+            &FlowConfig::default(),
+        )
+        .unwrap_err();
+
+        let words = String::from_utf8(usb.files[SEED_WORDS_FILE].clone()).unwrap();
+        assert_eq!(words.split_whitespace().count(), 24);
+        assert_eq!(mnemonic_to_entropy(&words).unwrap(), usb.files["seed.bin"]);
+
+        // The mock's constant seed also gets the weak-seed warning.
+        let mut events = vec![ButtonEvent::Confirm; 11];
+        events.push(ButtonEvent::Reject);
+        events.extend([ButtonEvent::Confirm; 4]);
+        let (_, recovered, _) = recover_from(SEED_WORDS_FILE, words.as_bytes(), events);
+        assert_eq!(recovered.public_key(0).unwrap(), se.public_key(0).unwrap());
+    }
+
+    #[test]
+    fn mnemonic_with_bad_checksum_is_refused() {
+        let bad = MNEMONIC.replace("yellow", "year");
+        let (hal, se, result) = recover_from(
+            MNEMONIC_FILE,
+            bad.as_bytes(),
+            vec![ButtonEvent::Confirm; 11],
+        );
+        assert!(result.is_err());
+        assert!(hal.messages.contains(&"INVALID MNEMONIC".to_string()));
+        assert!(se.public_key(0).is_err());
+    }
+
+    #[test]
+    fn all_zero_seed_is_not_imported() {
+        let (hal, se, result) = recover(&[0; 32], vec![ButtonEvent::Confirm; 11]);
+        assert!(result.is_err());
+        assert!(hal.messages.contains(&"ALL-ZERO SEED REFUSED".to_string()));
+        assert!(se.public_key(0).is_err());
+    }
+
+    #[test]
+    fn not_before_waits_for_sign_count() {
+        let spec = SigningSpec {
+            not_before: Some(3),
+            ..spec()
+        };
+        let mut se = MockSe::unlocked();
+        se.sign_calls = 2;
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let err = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut se,
+            &FlowConfig::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "TOO EARLY - SIGNS FROM COUNT 3, DEVICE AT 2"
+        );
+        assert_eq!(se.sign_calls, 2);
+
+        se.sign_calls = 3;
+        let signed = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut se,
+            &FlowConfig::default(),
+        )
+        .unwrap();
+        assert!(signed);
+        assert_eq!(se.sign_count(), 4);
+    }
+
+    #[test]
+    fn repeated_nonce_is_refused() {
+        let spec = SigningSpec {
+            nonce: Some([7; 16]),
+            ..spec()
+        };
+        let mut se = MockSe::unlocked();
+        let mut settings = MockSettings::default();
+        let mut sign = |payload: &[u8]| {
+            run_once(
+                &mut MockHal::new([ButtonEvent::Confirm]),
+                &mut MockUsb::signing(&spec, payload, echo_hex_wasm()),
+                &mut se,
+                &mut settings,
+                &FlowConfig::default(),
+                &mut Session::default(),
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(sign(b"tx"), Ok(true));
+        assert_eq!(sign(b"tx"), Err("SPEC ALREADY USED".into()));
+        assert_eq!(sign(b"other tx"), Err("SPEC ALREADY USED".into()));
+        assert_eq!(se.sign_calls, 1);
+    }
+
+    #[test]
+    fn stick_with_a_nonce_is_rewritten_after_a_failed_write() {
+        let spec = SigningSpec {
+            nonce: Some([7; 16]),
+            ..spec()
+        };
+        let mut se = MockSe::unlocked();
+        let mut settings = MockSettings::default();
+        let mut session = Session::default();
+        let mut sign = |usb: &mut MockUsb| {
+            run_once(
+                &mut MockHal::new([ButtonEvent::Confirm]),
+                usb,
+                &mut se,
+                &mut settings,
+                &FlowConfig::default(),
+                &mut session,
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        usb.write_budget = Some(10);
+        assert!(sign(&mut usb).is_err(), "the write fails");
+        assert_eq!(usb.output.as_deref(), Some(&[][..]));
+
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        assert_eq!(sign(&mut usb), Ok(true));
+        assert!(usb.output.is_some());
+        // The same spec over another payload is still refused.
+        let mut usb = MockUsb::signing(&spec, b"other tx", echo_hex_wasm());
+        assert_eq!(sign(&mut usb), Err("SPEC ALREADY USED".into()));
+        assert_eq!(se.sign_calls, 1);
+    }
+
+    #[test]
+    fn seen_nonces_are_capped() {
+        let mut settings = MockSettings::default();
+        for i in 0..=MAX_SEEN_NONCES as u16 {
+            let mut nonce = [0; 16];
+            nonce[..2].copy_from_slice(&i.to_le_bytes());
+            record_nonce(&mut settings, &nonce).unwrap();
+        }
+        assert_eq!(settings.values[SEEN_NONCES_KEY].len(), MAX_SEEN_NONCES * 16);
+        assert!(!nonce_seen(&settings, &[0; 16]).unwrap());
+        let mut last = [0; 16];
+        last[..2].copy_from_slice(&(MAX_SEEN_NONCES as u16).to_le_bytes());
+        assert!(nonce_seen(&settings, &last).unwrap());
+    }
+
+    #[test]
+    fn expired_spec_is_refused_when_the_time_is_known() {
+        let spec = SigningSpec {
+            not_after: Some(1_000),
+            ..spec()
+        };
+        let mut se = MockSe::unlocked();
+        let mut sign = |now: Option<u64>| {
+            let mut hal = MockHal::new([ButtonEvent::Confirm]);
+            hal.now = now;
+            run_with(
+                &mut hal,
+                &mut MockUsb::signing(&spec, b"tx", echo_hex_wasm()),
+                &mut se,
+                &FlowConfig::default(),
+            )
+            .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(sign(Some(1_001)), Err("SPEC EXPIRED".into()));
+        assert_eq!(sign(Some(1_000)), Ok(true));
+        // Without a clock the expiry is skipped.
+        assert_eq!(sign(None), Ok(true));
+    }
+
+    #[test]
+    fn ed25519ph_signs_the_sha512_of_the_message() {
+        let spec = SigningSpec {
+            algorithm: SignAlgorithm::Ed25519ph,
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let sig = usb.output.unwrap();
+        let pubkey = se.public_key_for(SignAlgorithm::Ed25519ph, 0).unwrap();
+        assert!(verify(SignAlgorithm::Ed25519ph, &pubkey, b"tx", &sig).unwrap());
+        // Not a plain Ed25519 signature of the same message.
+        assert!(!verify(SignAlgorithm::Ed25519, &pubkey, b"tx", &sig).unwrap());
+    }
+
+    #[test]
+    fn recoverable_signature_recovers_the_slot_key() {
+        let spec = SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Whole,
+            },
+            algorithm: SignAlgorithm::Secp256k1EcdsaRecoverable,
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let sig = usb.output.unwrap();
+        assert_eq!(sig.len(), 65);
+        let digest = hash_bytes(HashAlgorithm::Sha256, b"tx");
+        let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+            &digest,
+            &k256::ecdsa::Signature::from_slice(&sig[..64]).unwrap(),
+            k256::ecdsa::RecoveryId::from_byte(sig[64]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            recovered.to_encoded_point(true).as_bytes(),
+            se.public_key_for(SignAlgorithm::Secp256k1Ecdsa, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn algorithm_the_secure_element_lacks_is_refused_before_review() {
+        let spec = SigningSpec {
+            algorithm: SignAlgorithm::Secp256k1Schnorr,
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut se = MockSe::unlocked();
+
+        let result = run_with(
+            &mut hal,
+            &mut MockUsb::signing(&spec, b"tx", echo_hex_wasm()),
+            &mut se,
+            &FlowConfig::default(),
+        );
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            Err("SECP256K1SCHNORR NOT SUPPORTED BY SECURE ELEMENT".into())
+        );
+        assert!(hal.screens.is_empty(), "review must not be shown");
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    /// Interpreter reporting a transaction with three outputs.
+    const THREE_OUTPUTS_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "\13\00\00\00{\"outputs\":[1,2,3]}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "interpret") (param i32 i32) (result i32) (i32.const 16)))
+    "#;
+
+    fn run_capped(events: Vec<ButtonEvent>) -> (MockHal, bool) {
+        let config = FlowConfig {
+            max_review_items: 2,
+            ..FlowConfig::default()
+        };
+        let wasm = wat::parse_str(THREE_OUTPUTS_WAT).unwrap();
+        let mut hal = MockHal::new(events);
+        let mut usb = MockUsb::signing(&spec(), b"tx", wasm);
+        let signed = run_with(&mut hal, &mut usb, &mut MockSe::unlocked(), &config).unwrap();
+        (hal, signed)
+    }
+
+    #[test]
+    fn too_many_outputs_needs_override() {
+        let gate = "TRANSACTION HAS 3 OUTPUTS \u{2014} TOO MANY TO REVIEW SAFELY";
+
+        // Confirm does not pass the gate; Select does, then the review is confirmed.
+        let (hal, signed) = run_capped(vec![
+            ButtonEvent::Confirm,
+            ButtonEvent::Select,
+            ButtonEvent::Confirm,
+        ]);
+        assert!(signed);
+        assert!(hal.messages.contains(&gate.to_string()));
+        assert_eq!(hal.screens.len(), 1);
+
+        let (hal, signed) = run_capped(vec![ButtonEvent::Reject]);
+        assert!(!signed);
+        assert!(hal.screens.is_empty());
+    }
+
+    #[test]
+    fn passphrase_changes_recovered_key() {
+        use ButtonEvent::{Confirm, Down, Reject, Select, Up};
+        let seed = hash_bytes(HashAlgorithm::Blake2b256, b"recovery seed");
+        let recover_with = |passphrase: &[ButtonEvent]| {
+            // SETUP, two PINs, private stick.
+            let mut events = vec![ButtonEvent::Confirm; 10];
+            events.extend_from_slice(passphrase);
+            events.extend([ButtonEvent::Confirm; 4]);
+            let (_, se, _) = recover(&seed, events);
+            se.public_key(0).unwrap()
+        };
+
+        let none = recover_with(&[Reject]);
+        // "a", entered twice.
+        let a = recover_with(&[Confirm, Confirm, Select, Confirm, Select]);
+        // "b", entered twice.
+        let b = recover_with(&[Confirm, Up, Confirm, Select, Up, Confirm, Select]);
+        let again = recover_with(&[Confirm, Confirm, Select, Confirm, Select]);
+        // "~", wrapping round from "a", entered twice.
+        let tilde = recover_with(&[Confirm, Down, Confirm, Select, Down, Confirm, Select]);
+
+        assert_ne!(none, a);
+        assert_ne!(a, b);
+        assert_eq!(a, again);
+        let key = signer_core::crypto::derive_seed(&seed.try_into().unwrap(), b"~");
+        let expected = ed25519_dalek::SigningKey::from_bytes(&key).verifying_key();
+        assert_eq!(tilde, expected.to_bytes());
+    }
+
+    #[test]
+    fn short_signature_is_not_written() {
+        let spec = SigningSpec {
+            output: OutputSpec::AppendToPayload,
+            ..spec()
+        };
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+        se.truncate_signatures = Some(63);
+
+        let err = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut se,
+            &FlowConfig::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "UNEXPECTED SIGNATURE LENGTH");
+        assert_eq!(usb.output, None);
+    }
+
+    #[test]
+    fn hashed_signature_only_payload_is_streamed() {
+        let signable = Signable::HashThenSign {
+            hash: HashAlgorithm::Blake2b256,
+            source: SignableSource::Range {
+                offset: 10,
+                length: 500,
+            },
+        };
+        let spec = SigningSpec {
+            signable: signable.clone(),
+            ..spec()
+        };
+        let payload: Vec<u8> = (0..1000u32).map(|i| (i * 13) as u8).collect();
+        let mut usb = MockUsb::signing(&spec, &payload, echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(
+            &mut MockHal::new([ButtonEvent::Confirm]),
+            &mut usb,
+            &mut se,
+            &FlowConfig::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        assert!(usb.streamed.get());
+        let message = extract_signable(&payload, &signable).unwrap();
+        assert_eq!(
+            usb.output,
+            Some(se.sign(spec.key_slot, &message[0]).unwrap())
+        );
+    }
+
+    #[test]
+    fn overflowing_streamed_range_is_refused_before_hashing() {
+        let spec = SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Blake2b256,
+                source: SignableSource::Range {
+                    offset: usize::MAX,
+                    length: 1,
+                },
+            },
+            ..spec()
+        };
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut usb = MockUsb::signing(&spec, b"tx", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let result = run_with(&mut hal, &mut usb, &mut se, &FlowConfig::default());
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("outside the 2-byte payload"));
+        assert!(hal.screens.is_empty());
+        assert_eq!(se.sign_calls, 0);
+    }
+
+    #[test]
+    fn streamed_payload_is_checked_against_the_manifest() {
+        let spec = SigningSpec {
+            signable: Signable::HashThenSign {
+                hash: HashAlgorithm::Blake2b256,
+                source: SignableSource::Whole,
+            },
+            ..spec()
+        };
+        let spec_cbor = spec.to_cbor().unwrap();
+        let wasm = echo_hex_wasm();
+        let manifest = Manifest::new("0.1.0", b"tx", Some(&wasm), &spec_cbor);
+        let sign = |payload: &[u8]| {
+            let mut hal = MockHal::new([ButtonEvent::Confirm]);
+            let mut usb = MockUsb::signing(&spec, payload, wasm.clone());
+            usb.files.insert(MANIFEST_FILE.into(), manifest.to_cbor());
+            let result = run_with(
+                &mut hal,
+                &mut usb,
+                &mut MockSe::unlocked(),
+                &FlowConfig::default(),
+            )
+            .map_err(|e| e.to_string());
+            assert!(usb.streamed.get());
+            (result, hal.screens.len())
+        };
+
+        assert_eq!(sign(b"tx").0, Ok(true));
+        let (result, screens) = sign(b"tX");
+        assert_eq!(
+            result,
+            Err("payload.bin does not match manifest.cbor".into())
+        );
+        assert_eq!(screens, 0, "review must not be shown");
+    }
+
+    #[test]
+    fn request_is_signed_over_serial() {
+        use crate::mock::MockLink;
+        use signer_hal::serial::{read_record, send_request, Record, SerialTransport};
+        use signer_hal::UsbContents;
+
+        let contents = UsbContents {
+            payload: b"tx".to_vec(),
+            interpreter_wasm: Some(echo_hex_wasm()),
+            signing_spec_cbor: spec().to_cbor().unwrap(),
+        };
+        let mut link = MockLink::default();
+        send_request(&mut link.inbound, &contents, &[]).unwrap();
+        let mut serial = SerialTransport::new(link);
+        serial.receive().unwrap();
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_with(&mut hal, &mut serial, &mut se, &FlowConfig::default()).unwrap();
+
+        assert!(signed);
+        let reply = serial.into_inner().outbound;
+        let mut reply = &reply[..];
+        let mut output = Vec::new();
+        while !reply.is_empty() {
+            match read_record(&mut reply).unwrap() {
+                Record::Output(data) => output.extend(data),
+                Record::OutputAt { offset, data } => {
+                    assert_eq!(offset as usize, output.len());
+                    output.extend(data);
+                }
+                Record::File { .. } => {}
+                Record::End => panic!("device sent an end record"),
+            }
+        }
+        assert_eq!(output, se.sign(0, b"tx").unwrap());
     }
 }
@@ -1,16 +1,69 @@
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use argon2::Argon2;
 use ed25519_dalek::{Signer, SigningKey};
+use rand::rngs::OsRng;
 use rand::RngCore;
 use sha2::{Digest, Sha256};
-use signer_hal::HalError;
+use signer_core::crypto::{derive_seed, seed_quality, SeedQuality, SoftwareSigner};
+use signer_core::slip10::{self, ExtendedKey};
+use signer_core::spec::SignAlgorithm;
+use signer_hal::{Entropy, HalError};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use subtle::ConstantTimeEq;
+
+/// Leading bytes of a device backup blob; also authenticated as associated data.
+const BACKUP_MAGIC: &[u8] = b"AGSB1";
+
+const BACKUP_SALT_LEN: usize = 16;
+const BACKUP_NONCE_LEN: usize = 12;
+
+const PIN_SALT_LEN: usize = 16;
+
+/// Wrong PINs in a row before the keystore locks, as the SE050 policy
+/// would be configured.
+const DEFAULT_MAX_PIN_ATTEMPTS: u32 = 10;
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_PIN_ATTEMPTS
+}
 
 /// JSON-serializable keystore format.
+///
+/// Device backups carry this same document, encrypted.
 #[derive(serde::Serialize, serde::Deserialize)]
 struct KeystoreFile {
     pin_hash: Option<String>,
+    /// Argon2id salt of `pin_hash`. Keystores written before it existed
+    /// hold a SHA-256 PIN hash, upgraded on the next correct PIN.
+    #[serde(default)]
+    pin_salt: Option<String>,
     keys: HashMap<String, String>,
+    /// Public key of each slot when it was written, to detect seed corruption.
+    #[serde(default)]
+    pubkeys: HashMap<String, String>,
+    /// SLIP-0010 chain code of each slot imported as a master key.
+    #[serde(default)]
+    chain_codes: HashMap<String, String>,
+    #[serde(default)]
+    sign_count: u64,
+    /// Wrong PINs since the last correct one.
+    #[serde(default)]
+    failed_attempts: u32,
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+}
+
+/// Check that the element signs with `algorithm`: Ed25519, Ed25519ph or
+/// secp256k1 ECDSA, all from the slot seed. Recoverable ECDSA is signed as
+/// plain ECDSA, its recovery id found by the caller.
+pub fn check_algorithm(algorithm: SignAlgorithm) -> Result<(), HalError> {
+    match algorithm {
+        SignAlgorithm::Ed25519 | SignAlgorithm::Ed25519ph | SignAlgorithm::Secp256k1Ecdsa => Ok(()),
+        other => Err(HalError::Unsupported(format!("{other:?}"))),
+    }
 }
 
 /// Simulated secure element backed by a JSON keystore on disk.
@@ -19,14 +72,44 @@ struct KeystoreFile {
 pub struct SimSecureElement {
     path: PathBuf,
     pin_hash: Option<Vec<u8>>,
+    /// `None` with a `pin_hash` means a legacy SHA-256 hash.
+    pin_salt: Option<Vec<u8>>,
+    /// Signing seeds, with any passphrase already folded in.
     keys: HashMap<u8, [u8; 32]>,
+    /// Chain codes of slots imported as SLIP-0010 master keys; their
+    /// derived keys are children of the key and this chain code.
+    chain_codes: HashMap<u8, [u8; 32]>,
+    /// Seeds generated this session, before passphrase derivation, kept in
+    /// memory only for `export_seed`.
+    generated: HashMap<u8, [u8; 32]>,
+    sign_count: u64,
+    failed_attempts: u32,
+    max_attempts: u32,
     pin_verified: bool,
+    /// Source of generated seeds, PIN salts and backup nonces.
+    entropy: Box<dyn Entropy>,
+    /// Public keys recorded in the loaded keystore, checked against `keys`.
+    recorded_pubkeys: HashMap<u8, Vec<u8>>,
+}
+
+/// The host OS RNG, standing in for the device's hardware RNG.
+pub struct OsEntropy;
+
+impl Entropy for OsEntropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), HalError> {
+        OsRng
+            .try_fill_bytes(dest)
+            .map_err(|e| HalError::Entropy(e.to_string()))
+    }
 }
 
 impl SimSecureElement {
-    /// Load an existing keystore or create an empty one if the file doesn't exist.
-    pub fn from_file_or_new(path: &Path) -> Self {
-        if path.exists() {
+    /// Load an existing keystore or create an empty one if the file doesn't
+    /// exist. Generated seeds are drawn from `entropy`.
+    ///
+    /// Fails if a slot's seed no longer derives its recorded public key.
+    pub fn from_file_or_new(path: &Path, entropy: Box<dyn Entropy>) -> Result<Self, HalError> {
+        let mut se = if path.exists() {
             match Self::from_file(path) {
                 Ok(se) => se,
                 Err(e) => {
@@ -36,7 +119,24 @@ impl SimSecureElement {
             }
         } else {
             Self::create_empty(path)
+        };
+        se.check_integrity()?;
+        se.entropy = entropy;
+        Ok(se)
+    }
+
+    /// Check that every seed still derives the public key recorded with it.
+    fn check_integrity(&self) -> Result<(), HalError> {
+        for (slot, recorded) in &self.recorded_pubkeys {
+            let derived = self
+                .keys
+                .get(slot)
+                .map(|seed| SigningKey::from_bytes(seed).verifying_key().to_bytes());
+            if derived.as_ref().map(|key| &key[..]) != Some(&recorded[..]) {
+                return Err(HalError::Storage(format!("KEY SLOT {slot} CORRUPT")));
+            }
         }
+        Ok(())
     }
 
     /// Create a new empty (unprovisioned) keystore.
@@ -44,8 +144,16 @@ impl SimSecureElement {
         Self {
             path: path.to_path_buf(),
             pin_hash: None,
+            pin_salt: None,
             keys: HashMap::new(),
+            chain_codes: HashMap::new(),
+            generated: HashMap::new(),
+            sign_count: 0,
+            failed_attempts: 0,
+            max_attempts: DEFAULT_MAX_PIN_ATTEMPTS,
             pin_verified: false,
+            entropy: Box::new(OsEntropy),
+            recorded_pubkeys: HashMap::new(),
         }
     }
 
@@ -55,11 +163,18 @@ impl SimSecureElement {
             .map_err(|e| format!("failed to read keystore {}: {e}", path.display()))?;
         let kf: KeystoreFile = serde_json::from_str(&data)
             .map_err(|e| format!("failed to parse keystore JSON: {e}"))?;
+        Self::from_keystore_file(path, kf)
+    }
 
+    fn from_keystore_file(path: &Path, kf: KeystoreFile) -> Result<Self, String> {
         let pin_hash = kf
             .pin_hash
             .map(|h| hex::decode(&h).map_err(|e| format!("invalid pin_hash hex: {e}")))
             .transpose()?;
+        let pin_salt = kf
+            .pin_salt
+            .map(|s| hex::decode(&s).map_err(|e| format!("invalid pin_salt hex: {e}")))
+            .transpose()?;
 
         let mut keys = HashMap::new();
         for (slot_str, hex_str) in kf.keys {
@@ -74,37 +189,185 @@ impl SimSecureElement {
             keys.insert(slot, seed);
         }
 
+        let mut chain_codes = HashMap::new();
+        for (slot_str, hex_str) in kf.chain_codes {
+            let slot: u8 = slot_str
+                .parse()
+                .map_err(|e| format!("invalid slot number {slot_str}: {e}"))?;
+            let bytes = hex::decode(&hex_str)
+                .map_err(|e| format!("invalid chain code hex for slot {slot}: {e}"))?;
+            let chain_code: [u8; 32] = bytes
+                .try_into()
+                .map_err(|_| format!("slot {slot}: chain code must be 32 bytes"))?;
+            chain_codes.insert(slot, chain_code);
+        }
+
+        let mut recorded_pubkeys = HashMap::new();
+        for (slot_str, hex_str) in kf.pubkeys {
+            let slot: u8 = slot_str
+                .parse()
+                .map_err(|e| format!("invalid slot number {slot_str}: {e}"))?;
+            let pubkey = hex::decode(&hex_str)
+                .map_err(|e| format!("invalid pubkey hex for slot {slot}: {e}"))?;
+            recorded_pubkeys.insert(slot, pubkey);
+        }
+
         Ok(Self {
             path: path.to_path_buf(),
             pin_hash,
+            pin_salt,
             keys,
+            chain_codes,
+            generated: HashMap::new(),
+            sign_count: kf.sign_count,
+            failed_attempts: kf.failed_attempts,
+            max_attempts: kf.max_attempts,
             pin_verified: false,
+            entropy: Box::new(OsEntropy),
+            recorded_pubkeys,
         })
     }
 
-    /// Persist current state to disk.
-    fn save(&self) -> Result<(), HalError> {
-        let kf = KeystoreFile {
+    fn to_keystore_file(&self) -> KeystoreFile {
+        KeystoreFile {
             pin_hash: self.pin_hash.as_ref().map(hex::encode),
+            pin_salt: self.pin_salt.as_ref().map(hex::encode),
             keys: self
                 .keys
                 .iter()
                 .map(|(slot, seed)| (slot.to_string(), hex::encode(seed)))
                 .collect(),
-        };
-        let json = serde_json::to_string_pretty(&kf)
+            pubkeys: self
+                .keys
+                .iter()
+                .map(|(slot, seed)| {
+                    let pubkey = SigningKey::from_bytes(seed).verifying_key().to_bytes();
+                    (slot.to_string(), hex::encode(pubkey))
+                })
+                .collect(),
+            chain_codes: self
+                .chain_codes
+                .iter()
+                .map(|(slot, chain_code)| (slot.to_string(), hex::encode(chain_code)))
+                .collect(),
+            sign_count: self.sign_count,
+            failed_attempts: self.failed_attempts,
+            max_attempts: self.max_attempts,
+        }
+    }
+
+    /// Persist current state to disk.
+    fn save(&self) -> Result<(), HalError> {
+        let json = serde_json::to_string_pretty(&self.to_keystore_file())
             .map_err(|e| HalError::Storage(format!("failed to serialize keystore: {e}")))?;
         fs::write(&self.path, json)
             .map_err(|e| HalError::Storage(format!("failed to write keystore: {e}")))?;
         Ok(())
     }
 
+    /// Store an Argon2id hash of `pin` under a fresh salt.
+    fn store_pin(&mut self, pin: &[u8]) -> Result<(), HalError> {
+        let mut salt = vec![0u8; PIN_SALT_LEN];
+        self.entropy.fill_bytes(&mut salt)?;
+        self.pin_hash = Some(hash_pin(pin, &salt)?.to_vec());
+        self.pin_salt = Some(salt);
+        self.save()
+    }
+
+    /// Whether `pin` matches the stored hash, compared in constant time.
+    fn pin_matches(&self, pin: &[u8]) -> Result<bool, HalError> {
+        let stored = self
+            .pin_hash
+            .as_ref()
+            .ok_or_else(|| HalError::Storage("no PIN set".into()))?;
+        let hash: [u8; 32] = match &self.pin_salt {
+            Some(salt) => hash_pin(pin, salt)?,
+            None => Sha256::digest(pin).into(),
+        };
+        Ok(hash[..].ct_eq(stored).into())
+    }
+
     fn require_pin(&self) -> Result<(), HalError> {
         if !self.pin_verified {
             return Err(HalError::Storage("PIN not verified".into()));
         }
         Ok(())
     }
+
+    /// The SLIP-0010 child of a slot's key at `path`: below the slot's
+    /// master key when it was imported as one, else below the master key of
+    /// the slot seed.
+    fn derived_key(&self, slot: u8, path: &[u32]) -> Result<[u8; 32], HalError> {
+        let seed = self
+            .keys
+            .get(&slot)
+            .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
+        let derived = match self.chain_codes.get(&slot) {
+            Some(chain_code) => slip10::derive_from(
+                &ExtendedKey {
+                    key: *seed,
+                    chain_code: *chain_code,
+                },
+                path,
+            ),
+            None => slip10::derive(seed, path),
+        };
+        derived.map_err(|e| HalError::Storage(e.to_string()))
+    }
+}
+
+/// Argon2id hash of the PIN, at the crate's default cost (19 MiB, two
+/// passes), about 50 ms on a desktop.
+fn hash_pin(pin: &[u8], salt: &[u8]) -> Result<[u8; 32], HalError> {
+    let mut hash = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(pin, salt, &mut hash)
+        .map_err(|e| HalError::Storage(format!("PIN hashing failed: {e}")))?;
+    Ok(hash)
+}
+
+/// AES-256-GCM cipher keyed by Argon2id over the PIN.
+fn backup_cipher(pin: &[u8], salt: &[u8]) -> Result<Aes256Gcm, HalError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(pin, salt, &mut key)
+        .map_err(|e| HalError::Storage(format!("backup key derivation failed: {e}")))?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+impl signer_hal::PublicKeys for SimSecureElement {
+    fn is_provisioned(&self) -> bool {
+        self.pin_hash.is_some()
+    }
+
+    fn public_key(&self, slot: u8) -> Result<Vec<u8>, HalError> {
+        let seed = self
+            .keys
+            .get(&slot)
+            .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
+        let signing_key = SigningKey::from_bytes(seed);
+        Ok(signing_key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn derive_public_key(&self, slot: u8, path: &[u32]) -> Result<Vec<u8>, HalError> {
+        let signing_key = SigningKey::from_bytes(&self.derived_key(slot, path)?);
+        Ok(signing_key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn sign_count(&self) -> u64 {
+        self.sign_count
+    }
+
+    fn public_key_for(&self, algorithm: SignAlgorithm, slot: u8) -> Result<Vec<u8>, HalError> {
+        check_algorithm(algorithm)?;
+        let seed = self
+            .keys
+            .get(&slot)
+            .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
+        SoftwareSigner::from_seed(*seed)
+            .public_key(algorithm)
+            .map_err(|e| HalError::Storage(e.to_string()))
+    }
 }
 
 impl signer_hal::SecureElement for SimSecureElement {
@@ -112,36 +375,46 @@ impl signer_hal::SecureElement for SimSecureElement {
         if self.pin_hash.is_some() {
             return Err(HalError::Storage("PIN already set".into()));
         }
-        let hash = Sha256::digest(pin).to_vec();
-        self.pin_hash = Some(hash);
-        self.save()
+        self.store_pin(pin)
     }
 
+    /// Counts wrong PINs across reboots; after `max_attempts` in a row
+    /// every PIN, right or wrong, is refused.
     fn verify_pin(&mut self, pin: &[u8]) -> Result<(), HalError> {
-        let stored = self
-            .pin_hash
-            .as_ref()
-            .ok_or_else(|| HalError::Storage("no PIN set".into()))?;
-        let hash = Sha256::digest(pin).to_vec();
-        if hash != *stored {
+        if self.failed_attempts >= self.max_attempts {
             self.pin_verified = false;
-            return Err(HalError::Storage("wrong PIN".into()));
+            return Err(HalError::PinLocked);
+        }
+        if !self.pin_matches(pin)? {
+            self.pin_verified = false;
+            self.failed_attempts += 1;
+            self.save()?;
+            return Err(match self.max_attempts - self.failed_attempts {
+                0 => HalError::PinLocked,
+                remaining => HalError::WrongPin { remaining },
+            });
         }
         self.pin_verified = true;
+        if self.pin_salt.is_none() {
+            self.store_pin(pin)?;
+        }
+        if self.failed_attempts > 0 {
+            self.failed_attempts = 0;
+            self.save()?;
+        }
         Ok(())
     }
 
-    fn is_provisioned(&self) -> bool {
-        self.pin_hash.is_some()
-    }
-
-    fn generate_key(&mut self, slot: u8) -> Result<Vec<u8>, HalError> {
+    fn generate_key(&mut self, slot: u8, passphrase: &[u8]) -> Result<Vec<u8>, HalError> {
         self.require_pin()?;
         let mut seed = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut seed);
-        self.keys.insert(slot, seed);
+        self.entropy.fill_bytes(&mut seed)?;
+        let key_seed = derive_seed(&seed, passphrase);
+        self.generated.insert(slot, seed);
+        self.keys.insert(slot, key_seed);
+        self.chain_codes.remove(&slot);
         self.save()?;
-        let signing_key = SigningKey::from_bytes(&seed);
+        let signing_key = SigningKey::from_bytes(&key_seed);
         Ok(signing_key.verifying_key().to_bytes().to_vec())
     }
 
@@ -151,36 +424,433 @@ impl signer_hal::SecureElement for SimSecureElement {
             .keys
             .get(&slot)
             .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
-        let signing_key = SigningKey::from_bytes(seed);
-        let signature = signing_key.sign(hash);
+        let signature = SigningKey::from_bytes(seed).sign(hash);
+        self.sign_count += 1;
+        self.save()?;
         Ok(signature.to_bytes().to_vec())
     }
 
-    fn public_key(&self, slot: u8) -> Result<Vec<u8>, HalError> {
+    fn sign_with(
+        &mut self,
+        algorithm: SignAlgorithm,
+        slot: u8,
+        message: &[u8],
+    ) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        check_algorithm(algorithm)?;
         let seed = self
             .keys
             .get(&slot)
             .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
-        let signing_key = SigningKey::from_bytes(seed);
-        Ok(signing_key.verifying_key().to_bytes().to_vec())
+        let signature = SoftwareSigner::from_seed(*seed)
+            .sign(algorithm, message)
+            .map_err(|e| HalError::Storage(e.to_string()))?;
+        self.sign_count += 1;
+        self.save()?;
+        Ok(signature)
+    }
+
+    fn sign_derived(&mut self, slot: u8, path: &[u32], hash: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        let signature = SigningKey::from_bytes(&self.derived_key(slot, path)?).sign(hash);
+        self.sign_count += 1;
+        self.save()?;
+        Ok(signature.to_bytes().to_vec())
     }
 
-    fn import_key(&mut self, slot: u8, seed: &[u8]) -> Result<Vec<u8>, HalError> {
+    fn import_key(
+        &mut self,
+        slot: u8,
+        seed: &[u8],
+        passphrase: &[u8],
+    ) -> Result<Vec<u8>, HalError> {
         self.require_pin()?;
         let seed_arr: [u8; 32] = seed
             .try_into()
             .map_err(|_| HalError::Storage("seed must be 32 bytes".into()))?;
-        self.keys.insert(slot, seed_arr);
+        if seed_quality(seed) == SeedQuality::Refused {
+            return Err(HalError::Storage("refusing all-zero seed".into()));
+        }
+        let key_seed = derive_seed(&seed_arr, passphrase);
+        self.keys.insert(slot, key_seed);
+        self.chain_codes.remove(&slot);
+        self.save()?;
+        let signing_key = SigningKey::from_bytes(&key_seed);
+        Ok(signing_key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn import_master_key(&mut self, slot: u8, master: &ExtendedKey) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        self.keys.insert(slot, master.key);
+        self.chain_codes.insert(slot, master.chain_code);
         self.save()?;
-        let signing_key = SigningKey::from_bytes(&seed_arr);
+        let signing_key = SigningKey::from_bytes(&master.key);
         Ok(signing_key.verifying_key().to_bytes().to_vec())
     }
 
+    /// A seed generated with a passphrase is only exportable until reload;
+    /// the keystore keeps just the derived seed.
     fn export_seed(&self, slot: u8) -> Result<Vec<u8>, HalError> {
         let seed = self
-            .keys
+            .generated
             .get(&slot)
+            .or_else(|| self.keys.get(&slot))
             .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))?;
         Ok(seed.to_vec())
     }
+
+    fn export_backup(&mut self, pin: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        if !self.pin_matches(pin)? {
+            return Err(HalError::Storage("wrong PIN".into()));
+        }
+        let plaintext = serde_json::to_vec(&self.to_keystore_file())
+            .map_err(|e| HalError::Storage(format!("failed to serialize keystore: {e}")))?;
+
+        let mut salt = [0u8; BACKUP_SALT_LEN];
+        let mut nonce = [0u8; BACKUP_NONCE_LEN];
+        self.entropy.fill_bytes(&mut salt)?;
+        self.entropy.fill_bytes(&mut nonce)?;
+        let ciphertext = backup_cipher(pin, &salt)?
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: BACKUP_MAGIC,
+                },
+            )
+            .map_err(|_| HalError::Storage("backup encryption failed".into()))?;
+
+        let mut blob = BACKUP_MAGIC.to_vec();
+        blob.extend_from_slice(&salt);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+        Ok(blob)
+    }
+
+    fn restore_backup(&mut self, pin: &[u8], blob: &[u8]) -> Result<(), HalError> {
+        if !self.keys.is_empty() {
+            return Err(HalError::Storage(
+                "refusing to restore over existing keys".into(),
+            ));
+        }
+        let rest = blob
+            .strip_prefix(BACKUP_MAGIC)
+            .filter(|rest| rest.len() >= BACKUP_SALT_LEN + BACKUP_NONCE_LEN)
+            .ok_or_else(|| HalError::Storage("not a device backup".into()))?;
+        let (salt, rest) = rest.split_at(BACKUP_SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(BACKUP_NONCE_LEN);
+        let plaintext = backup_cipher(pin, salt)?
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: BACKUP_MAGIC,
+                },
+            )
+            .map_err(|_| HalError::Storage("backup decryption failed".into()))?;
+
+        let kf: KeystoreFile = serde_json::from_slice(&plaintext)
+            .map_err(|e| HalError::Storage(format!("invalid backup contents: {e}")))?;
+        let restored = Self::from_keystore_file(&self.path, kf).map_err(HalError::Storage)?;
+        restored.check_integrity()?;
+        self.pin_hash = restored.pin_hash;
+        self.pin_salt = restored.pin_salt;
+        self.keys = restored.keys;
+        self.chain_codes = restored.chain_codes;
+        self.sign_count = restored.sign_count;
+        self.failed_attempts = 0;
+        self.max_attempts = restored.max_attempts;
+        self.pin_verified = true;
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use signer_hal::{PublicKeys, SecureElement};
+
+    fn scratch(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("signer-sim-{}-{name}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn backup_restores_identical_signing_state() {
+        let (old_path, new_path) = (scratch("backup-old"), scratch("backup-new"));
+        let mut old = SimSecureElement::from_file_or_new(&old_path, Box::new(OsEntropy)).unwrap();
+        old.set_pin(b"1234").unwrap();
+        old.verify_pin(b"1234").unwrap();
+        old.generate_key(0, b"").unwrap();
+        old.generate_key(3, b"extra").unwrap();
+        let master = ExtendedKey::master(b"bip39 seed");
+        old.import_master_key(5, &master).unwrap();
+
+        assert!(old.export_backup(b"9999").is_err());
+        let blob = old.export_backup(b"1234").unwrap();
+
+        let mut new = SimSecureElement::from_file_or_new(&new_path, Box::new(OsEntropy)).unwrap();
+        assert!(new.restore_backup(b"9999", &blob).is_err());
+        new.restore_backup(b"1234", &blob).unwrap();
+
+        assert!(new.is_provisioned());
+        for slot in [0, 3] {
+            assert_eq!(
+                new.sign(slot, b"hash").unwrap(),
+                old.sign(slot, b"hash").unwrap()
+            );
+        }
+        // Restored state is persisted and unlocks with the original PIN.
+        let mut reloaded =
+            SimSecureElement::from_file_or_new(&new_path, Box::new(OsEntropy)).unwrap();
+        reloaded.verify_pin(b"1234").unwrap();
+        assert_eq!(reloaded.public_key(3).unwrap(), old.public_key(3).unwrap());
+        // A master key's children follow its chain code after restore and reload.
+        let path = [0x8000_002c, 0x8000_0001];
+        let child = slip10::derive_from(&master, &path).unwrap();
+        let expected = SigningKey::from_bytes(&child).verifying_key().to_bytes();
+        assert_eq!(reloaded.derive_public_key(5, &path).unwrap(), expected);
+
+        assert!(new.restore_backup(b"1234", &blob).is_err());
+        let _ = fs::remove_file(old_path);
+        let _ = fs::remove_file(new_path);
+    }
+
+    /// Entropy repeating one byte.
+    struct FixedEntropy(u8);
+
+    impl Entropy for FixedEntropy {
+        fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), HalError> {
+            dest.fill(self.0);
+            Ok(())
+        }
+    }
+
+    /// Entropy from a seeded PRNG, reproducible across runs.
+    struct SeededEntropy(StdRng);
+
+    impl Entropy for SeededEntropy {
+        fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), HalError> {
+            self.0.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn seeded_setup_is_reproducible() {
+        let setup = |name: &str| {
+            let path = scratch(name);
+            let entropy = SeededEntropy(StdRng::seed_from_u64(7));
+            let mut se = SimSecureElement::from_file_or_new(&path, Box::new(entropy)).unwrap();
+            se.set_pin(b"1234").unwrap();
+            se.verify_pin(b"1234").unwrap();
+            let pubkey = se.generate_key(0, b"").unwrap();
+            let backup = se.export_backup(b"1234").unwrap();
+            let _ = fs::remove_file(path);
+            (pubkey, backup)
+        };
+        let (pubkey, backup) = setup("seeded-a");
+        assert_eq!(setup("seeded-b"), (pubkey.clone(), backup));
+
+        let path = scratch("seeded-other");
+        let entropy = SeededEntropy(StdRng::seed_from_u64(8));
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(entropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.verify_pin(b"1234").unwrap();
+        assert_ne!(se.generate_key(0, b"").unwrap(), pubkey);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn generated_seed_comes_from_entropy_source() {
+        let path = scratch("entropy");
+        let mut se =
+            SimSecureElement::from_file_or_new(&path, Box::new(FixedEntropy(0x42))).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.verify_pin(b"1234").unwrap();
+
+        let pubkey = se.generate_key(0, b"").unwrap();
+        let expected = SigningKey::from_bytes(&[0x42; 32]).verifying_key();
+        assert_eq!(pubkey, expected.to_bytes());
+        assert_eq!(se.export_seed(0).unwrap(), [0x42; 32]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn corrupted_seed_is_detected_on_load() {
+        let path = scratch("corrupt");
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.verify_pin(b"1234").unwrap();
+        se.generate_key(2, b"").unwrap();
+        assert!(SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).is_ok());
+
+        let mut kf: KeystoreFile = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let mut seed = hex::decode(&kf.keys["2"]).unwrap();
+        seed[7] ^= 0x01;
+        kf.keys.insert("2".into(), hex::encode(seed));
+        fs::write(&path, serde_json::to_vec(&kf).unwrap()).unwrap();
+
+        let err = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "storage error: KEY SLOT 2 CORRUPT");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn pin_is_hashed_with_a_salt() {
+        let (a, b) = (scratch("pin-a"), scratch("pin-b"));
+        let mut se = SimSecureElement::from_file_or_new(&a, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        let mut other = SimSecureElement::from_file_or_new(&b, Box::new(OsEntropy)).unwrap();
+        other.set_pin(b"1234").unwrap();
+
+        let mut se = SimSecureElement::from_file_or_new(&a, Box::new(OsEntropy)).unwrap();
+        assert!(se.verify_pin(b"4321").is_err());
+        se.verify_pin(b"1234").unwrap();
+
+        let stored = |path: &Path| -> KeystoreFile {
+            serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+        };
+        let (a_file, b_file) = (stored(&a), stored(&b));
+        assert_ne!(a_file.pin_salt, b_file.pin_salt);
+        assert_ne!(a_file.pin_hash, b_file.pin_hash);
+        assert_ne!(a_file.pin_hash, Some(hex::encode(Sha256::digest(b"1234"))));
+        let _ = fs::remove_file(a);
+        let _ = fs::remove_file(b);
+    }
+
+    #[test]
+    fn pin_comparison_tells_match_from_mismatch() {
+        let path = scratch("pin-compare");
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"123456").unwrap();
+        assert!(se.pin_matches(b"123456").unwrap());
+        for wrong in [&b"123457"[..], b"12345", b"1234567", b""] {
+            assert!(!se.pin_matches(wrong).unwrap(), "{wrong:?}");
+        }
+
+        // A legacy SHA-256 hash, and a stored hash of the wrong length.
+        se.pin_salt = None;
+        se.pin_hash = Some(Sha256::digest(b"123456").to_vec());
+        assert!(se.pin_matches(b"123456").unwrap());
+        assert!(!se.pin_matches(b"654321").unwrap());
+        se.pin_hash = Some(vec![0; 16]);
+        assert!(!se.pin_matches(b"123456").unwrap());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn wrong_pins_count_down_to_lockout() {
+        let path = scratch("lockout");
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.max_attempts = 3;
+
+        assert!(matches!(
+            se.verify_pin(b"0000"),
+            Err(HalError::WrongPin { remaining: 2 })
+        ));
+        assert_eq!(se.failed_attempts, 1);
+        se.verify_pin(b"1234").unwrap();
+        assert_eq!(se.failed_attempts, 0);
+
+        assert!(matches!(
+            se.verify_pin(b"0000"),
+            Err(HalError::WrongPin { remaining: 2 })
+        ));
+        // The count survives a reboot.
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        assert_eq!((se.failed_attempts, se.max_attempts), (1, 3));
+        assert!(matches!(
+            se.verify_pin(b"0000"),
+            Err(HalError::WrongPin { remaining: 1 })
+        ));
+        assert!(matches!(se.verify_pin(b"0000"), Err(HalError::PinLocked)));
+        assert!(matches!(se.verify_pin(b"1234"), Err(HalError::PinLocked)));
+        assert!(se.sign(0, b"hash").is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn legacy_pin_hash_is_upgraded() {
+        let path = scratch("pin-legacy");
+        let legacy = KeystoreFile {
+            pin_hash: Some(hex::encode(Sha256::digest(b"1234"))),
+            pin_salt: None,
+            keys: HashMap::new(),
+            pubkeys: HashMap::new(),
+            chain_codes: HashMap::new(),
+            sign_count: 0,
+            failed_attempts: 0,
+            max_attempts: DEFAULT_MAX_PIN_ATTEMPTS,
+        };
+        fs::write(&path, serde_json::to_vec(&legacy).unwrap()).unwrap();
+
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        assert!(se.verify_pin(b"0000").is_err());
+        se.verify_pin(b"1234").unwrap();
+        let upgraded: KeystoreFile = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert!(upgraded.pin_salt.is_some());
+
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.verify_pin(b"1234").unwrap();
+        assert!(se.verify_pin(b"0000").is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn passphrase_gives_different_key_from_same_seed() {
+        let path = scratch("passphrase");
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.verify_pin(b"1234").unwrap();
+        let seed = [0x5A, 0x17, 0xC3, 0x08].repeat(8);
+
+        let plain = se.import_key(0, &seed, b"").unwrap();
+        let first = se.import_key(1, &seed, b"first").unwrap();
+        let second = se.import_key(2, &seed, b"second").unwrap();
+        assert_ne!(plain, first);
+        assert_ne!(first, second);
+        assert_eq!(se.import_key(3, &seed, b"first").unwrap(), first);
+
+        let stored = fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains(&hex::encode("first")));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn derived_key_signs_and_matches_slip10() {
+        use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+        use signer_core::display::HARDENED;
+
+        let path = scratch("derive");
+        let mut se = SimSecureElement::from_file_or_new(&path, Box::new(OsEntropy)).unwrap();
+        se.set_pin(b"1234").unwrap();
+        se.verify_pin(b"1234").unwrap();
+        se.import_key(0, &[0x42; 32], b"").unwrap();
+        let account = [44 | HARDENED, 1815 | HARDENED, HARDENED];
+
+        let expected = slip10::derive(&se.keys[&0], &account).unwrap();
+        let public = se.derive_public_key(0, &account).unwrap();
+        assert_eq!(
+            public,
+            SigningKey::from_bytes(&expected).verifying_key().to_bytes()
+        );
+        assert_ne!(public, se.public_key(0).unwrap());
+
+        let signature = se.sign_derived(0, &account, b"hash").unwrap();
+        let key = VerifyingKey::from_bytes(public.as_slice().try_into().unwrap()).unwrap();
+        key.verify(b"hash", &Signature::from_slice(&signature).unwrap())
+            .unwrap();
+        assert_eq!(se.sign_count(), 1);
+        assert!(se.sign_derived(0, &[44 | HARDENED, 0], b"hash").is_err());
+        let _ = fs::remove_file(path);
+    }
 }
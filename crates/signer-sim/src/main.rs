@@ -1,26 +1,97 @@
 mod buttons;
+mod control;
 mod display;
 mod flow;
 mod keystore;
+#[cfg(test)]
+mod mock;
+mod qr_source;
+mod replay;
+mod settings;
+mod tui;
 mod usb;
 
 use clap::Parser;
 use display::SimDisplay;
 use signer_core::display::DisplayLine;
-use signer_hal::{ButtonEvent, HalError};
-use std::path::PathBuf;
+use signer_hal::{
+    ButtonEvent, Buttons, Clock, Display, HalError, SecureElement, Settings, StatusLed, UsbMount,
+};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use usb::SimUsb;
 
 #[derive(Parser)]
 #[command(name = "signer-sim", about = "Air-gapped signer desktop simulator")]
 struct Cli {
     /// Directory simulating USB stick contents
-    #[arg(long)]
-    usb_dir: PathBuf,
+    #[arg(long, required_unless_present = "qr_dir")]
+    usb_dir: Option<PathBuf>,
+
+    /// Read requests from scanned QR frames instead of a stick: a directory
+    /// of `<file>.qr` files, one frame per line
+    #[arg(long, value_name = "DIR", conflicts_with = "usb_dir")]
+    qr_dir: Option<PathBuf>,
 
     /// Path to keystore JSON file (created automatically on first run)
     #[arg(long, default_value = "keys.json")]
     keystore: PathBuf,
+
+    /// Path to device settings JSON file (created automatically on first run)
+    #[arg(long, default_value = "settings.json")]
+    settings: PathBuf,
+
+    /// Wrap around when scrolling past the first or last review line
+    #[arg(long)]
+    scroll_wrap: bool,
+
+    /// Simulates the hardware override switch that permits blind signing
+    /// of sticks without an interpreter
+    #[arg(long)]
+    allow_blind_sign: bool,
+
+    /// Simulates the hardware override switch that permits loading a
+    /// stick's precompiled interpreter.cwasm instead of compiling the wasm;
+    /// the artifact must be recorded in the stick's manifest.cbor
+    #[arg(long)]
+    allow_precompiled: bool,
+
+    /// Longest list of outputs reviewed without an explicit override
+    #[arg(long, default_value_t = flow::FlowConfig::default().max_review_items)]
+    max_review_items: usize,
+
+    /// Show long review values as their first and last N characters
+    #[arg(long, value_name = "N")]
+    abbreviate: Option<usize>,
+
+    /// Skip the "PRESS REJECT" button check on boots with a stored mapping
+    #[arg(long)]
+    no_button_interlock: bool,
+
+    /// Run in the terminal instead of opening a window (for headless machines)
+    #[arg(long)]
+    tui: bool,
+
+    /// Drive the simulator over a loopback control socket instead of a
+    /// window (testing only; see docs/dev/getting-started.md)
+    #[arg(long, value_name = "ADDR")]
+    control_socket: Option<SocketAddr>,
+
+    /// Record button presses and the starting stick contents to this file
+    /// on exit, for replay in tests
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Directory the requests come from, stick or scans.
+    fn source_dir(&self) -> &Path {
+        self.qr_dir
+            .as_deref()
+            .or(self.usb_dir.as_deref())
+            .expect("clap requires --usb-dir or --qr-dir")
+    }
 }
 
 /// Wraps SimDisplay to also implement the Buttons trait,
@@ -41,30 +112,113 @@ impl signer_hal::Display for SimHal {
     fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
         signer_hal::Display::show_lines(&mut self.display, lines, scroll_offset)
     }
+
+    fn show_qr(&mut self, caption: &str, modules: &[Vec<bool>]) -> Result<(), HalError> {
+        signer_hal::Display::show_qr(&mut self.display, caption, modules)
+    }
 }
 
+/// The host's clock.
+impl signer_hal::Clock for SimHal {
+    fn now(&self) -> Option<u64> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Some(since_epoch.as_secs())
+    }
+}
+
+/// The simulator window has no LED.
+impl signer_hal::StatusLed for SimHal {}
+
 impl signer_hal::Buttons for SimHal {
     fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
         buttons::wait_event(self.display.window_mut())
     }
 }
 
+/// Run the flow on `hal`, recording the session if `--record` was given.
+fn run_flow<H: Display + Buttons + Clock + StatusLed>(
+    hal: H,
+    cli: &Cli,
+    usb: &mut dyn UsbMount,
+    se: &mut dyn SecureElement,
+    settings: &mut dyn Settings,
+    config: &flow::FlowConfig,
+) -> Result<(), HalError> {
+    let mut hal = hal;
+    let Some(path) = &cli.record else {
+        return flow::run(&mut hal, usb, se, settings, config);
+    };
+    let files = replay::snapshot_dir(cli.source_dir()).map_err(|e| HalError::Usb(e.to_string()))?;
+    let mut recorder = replay::SessionRecorder::new(hal, &files);
+    let result = flow::run(&mut recorder, usb, se, settings, config);
+    // Save even when the flow failed; that is usually the session to reproduce.
+    if let Err(e) = recorder.recording().save(path) {
+        eprintln!("failed to save recording: {e}");
+    }
+    result
+}
+
 fn main() {
     let cli = Cli::parse();
 
-    let mut se = keystore::SimSecureElement::from_file_or_new(&cli.keystore);
+    let mut se =
+        keystore::SimSecureElement::from_file_or_new(&cli.keystore, Box::new(keystore::OsEntropy))
+            .unwrap_or_else(|e| {
+                eprintln!("keystore error: {e}");
+                std::process::exit(1);
+            });
 
-    let sim_display = SimDisplay::new().unwrap_or_else(|e| {
-        eprintln!("display error: {e}");
+    let mut settings = settings::SimSettings::from_file_or_new(&cli.settings).unwrap_or_else(|e| {
+        eprintln!("settings error: {e}");
         std::process::exit(1);
     });
 
-    let mut hal = SimHal {
-        display: sim_display,
+    let mut usb: Box<dyn UsbMount> = match &cli.qr_dir {
+        Some(dir) => Box::new(qr_source::QrSource::new(dir.clone())),
+        None => Box::new(SimUsb::new(cli.source_dir().to_path_buf())),
+    };
+
+    let config = flow::FlowConfig {
+        scroll: if cli.scroll_wrap {
+            flow::ScrollMode::Wrap
+        } else {
+            flow::ScrollMode::Clamp
+        },
+        allow_blind_sign: cli.allow_blind_sign,
+        allow_precompiled: cli.allow_precompiled,
+        abbreviate: cli.abbreviate.map(|n| (n, n)),
+        max_review_items: cli.max_review_items,
+        button_interlock: !cli.no_button_interlock,
+        ..flow::FlowConfig::default()
+    };
+
+    let result = if let Some(addr) = cli.control_socket {
+        let hal = control::ControlHal::bind(addr).unwrap_or_else(|e| {
+            eprintln!("control socket error: {e}");
+            std::process::exit(1);
+        });
+        if let Ok(addr) = hal.local_addr() {
+            eprintln!("control socket listening on {addr}");
+        }
+        run_flow(hal, &cli, usb.as_mut(), &mut se, &mut settings, &config)
+    } else if cli.tui {
+        let hal = tui::TuiHal::stdout().unwrap_or_else(|e| {
+            eprintln!("terminal error: {e}");
+            std::process::exit(1);
+        });
+        run_flow(hal, &cli, usb.as_mut(), &mut se, &mut settings, &config)
+    } else {
+        let sim_display = SimDisplay::new().unwrap_or_else(|e| {
+            eprintln!("display error: {e}");
+            std::process::exit(1);
+        });
+        let hal = SimHal {
+            display: sim_display,
+        };
+        run_flow(hal, &cli, usb.as_mut(), &mut se, &mut settings, &config)
     };
-    let mut usb = SimUsb::new(cli.usb_dir);
 
-    if let Err(e) = flow::run(&mut hal, &mut usb, &mut se) {
+    if let Err(e) = result {
         eprintln!("flow error: {e}");
         std::process::exit(1);
     }
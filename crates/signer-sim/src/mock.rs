@@ -0,0 +1,516 @@
+//! In-memory HAL doubles for driving `flow` in tests.
+
+use crate::flow::{run_once, FlowConfig, Session};
+use crate::keystore::check_algorithm;
+use ed25519_dalek::{Signer, SigningKey};
+use signer_core::crypto::{derive_seed, SoftwareSigner};
+use signer_core::display::DisplayLine;
+use signer_core::slip10::{self, ExtendedKey};
+use signer_core::spec::{
+    InterpreterSource, OutputSpec, SignAlgorithm, Signable, SigningSpec, SPEC_VERSION,
+};
+use signer_core::stick::MANIFEST_FILE;
+use signer_hal::{
+    ButtonEvent, HalError, LedColor, PayloadSource, PayloadStream, PublicKeys, SecureElement,
+    Settings, UsbContents, UsbMount,
+};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+
+/// Path of the echo-hex interpreter built by `just build-wasm`.
+pub fn echo_hex_wasm() -> Vec<u8> {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/echo_hex.wasm"
+    );
+    std::fs::read(path).expect("echo_hex.wasm not found — run `just build-wasm` first")
+}
+
+/// Whole-payload Ed25519 spec on slot 0 with signature-only output.
+pub fn spec() -> SigningSpec {
+    SigningSpec {
+        version: SPEC_VERSION,
+        label: "Test".into(),
+        signable: Signable::Whole,
+        algorithm: SignAlgorithm::Ed25519,
+        key_slot: 0,
+        output: OutputSpec::SignatureOnly,
+        require_pin_on_sign: false,
+        verify_assembly: false,
+        format: None,
+        interpreter: InterpreterSource::Usb,
+        payload_size: None,
+        not_before: None,
+        derivation_path: None,
+        nonce: None,
+        not_after: None,
+    }
+}
+
+/// Run one signing cycle on `usb` with the scripted button `events`, an
+/// unlocked secure element and default settings, returning whether it
+/// signed and the stick afterwards.
+pub fn run_stick(
+    events: impl IntoIterator<Item = ButtonEvent>,
+    mut usb: MockUsb,
+    config: &FlowConfig,
+) -> (bool, MockUsb) {
+    let signed = run_with(
+        &mut MockHal::new(events),
+        &mut usb,
+        &mut MockSe::unlocked(),
+        config,
+    )
+    .unwrap();
+    (signed, usb)
+}
+
+/// Run one signing cycle with default settings and a fresh session, for
+/// tests that look at the display or secure element afterwards.
+pub fn run_with(
+    hal: &mut MockHal,
+    source: &mut dyn PayloadSource,
+    se: &mut dyn SecureElement,
+    config: &FlowConfig,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    run_once(
+        hal,
+        source,
+        se,
+        &mut MockSettings::default(),
+        config,
+        &mut Session::default(),
+    )
+}
+
+/// Scripted buttons and a recording display.
+pub struct MockHal {
+    events: VecDeque<ButtonEvent>,
+    pub messages: Vec<String>,
+    pub screens: Vec<Vec<DisplayLine>>,
+    /// QR codes shown, in order.
+    pub qr_codes: Vec<Vec<Vec<bool>>>,
+    /// What the clock reads; `None` (the default) is a board without one.
+    pub now: Option<u64>,
+    /// Status LED colors set, in order.
+    pub leds: Vec<LedColor>,
+}
+
+impl MockHal {
+    pub fn new(events: impl IntoIterator<Item = ButtonEvent>) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+            messages: Vec::new(),
+            screens: Vec::new(),
+            qr_codes: Vec::new(),
+            now: None,
+            leds: Vec::new(),
+        }
+    }
+}
+
+impl signer_hal::Display for MockHal {
+    fn clear(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+
+    fn show_message(&mut self, text: &str) -> Result<(), HalError> {
+        self.messages.push(text.to_string());
+        Ok(())
+    }
+
+    fn show_lines(&mut self, lines: &[DisplayLine], _scroll_offset: usize) -> Result<(), HalError> {
+        self.screens.push(lines.to_vec());
+        Ok(())
+    }
+
+    fn show_qr(&mut self, caption: &str, modules: &[Vec<bool>]) -> Result<(), HalError> {
+        self.messages.push(caption.to_string());
+        self.qr_codes.push(modules.to_vec());
+        Ok(())
+    }
+}
+
+impl signer_hal::Clock for MockHal {
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+}
+
+impl signer_hal::StatusLed for MockHal {
+    fn set(&mut self, color: LedColor) {
+        self.leds.push(color);
+    }
+}
+
+impl signer_hal::Buttons for MockHal {
+    fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
+        self.events
+            .pop_front()
+            .ok_or_else(|| HalError::Button("no more scripted events".into()))
+    }
+}
+
+/// USB stick held in memory.
+#[derive(Default)]
+pub struct MockUsb {
+    pub files: HashMap<String, Vec<u8>>,
+    pub output: Option<Vec<u8>>,
+    /// How many more times `wait_insert` succeeds; afterwards it errors,
+    /// which ends `run_loop`.
+    pub insertions: usize,
+    /// When set, `write_output_at` fails once this many more bytes have been written.
+    pub write_budget: Option<usize>,
+    /// Total bytes written through `write_output_at`.
+    pub written: usize,
+    /// Set once the payload has been opened for streaming.
+    pub streamed: Cell<bool>,
+}
+
+impl MockUsb {
+    /// A stick carrying the three signing files.
+    pub fn signing(spec: &SigningSpec, payload: &[u8], interpreter: Vec<u8>) -> Self {
+        let mut usb = Self {
+            insertions: 1,
+            ..Self::default()
+        };
+        usb.files.insert("payload.bin".into(), payload.to_vec());
+        usb.files.insert("interpreter.wasm".into(), interpreter);
+        usb.files
+            .insert("sign.cbor".into(), spec.to_cbor().expect("spec encodes"));
+        usb
+    }
+
+    fn file(&self, name: &str) -> Result<Vec<u8>, HalError> {
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| HalError::Usb(format!("{name} missing")))
+    }
+}
+
+impl PayloadSource for MockUsb {
+    fn read_contents(&self) -> Result<UsbContents, HalError> {
+        let contents = UsbContents {
+            payload: self.file("payload.bin")?,
+            interpreter_wasm: self.files.get("interpreter.wasm").cloned(),
+            signing_spec_cbor: self.file("sign.cbor")?,
+        };
+        crate::usb::check_manifest(self.files.get(MANIFEST_FILE).map(Vec::as_slice), &contents)?;
+        Ok(contents)
+    }
+
+    fn open_payload(&self) -> Result<PayloadStream<'_>, HalError> {
+        self.streamed.set(true);
+        let payload = self.file("payload.bin")?;
+        Ok(PayloadStream {
+            len: payload.len() as u64,
+            reader: Box::new(std::io::Cursor::new(payload)),
+        })
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> Result<(), HalError> {
+        self.output = Some(data.to_vec());
+        Ok(())
+    }
+
+    fn write_output_at(&mut self, offset: u64, data: &[u8]) -> Result<(), HalError> {
+        if let Some(budget) = &mut self.write_budget {
+            if data.len() > *budget {
+                return Err(HalError::Usb("stick removed during write".into()));
+            }
+            *budget -= data.len();
+        }
+        let output = self.output.get_or_insert_with(Vec::new);
+        let offset = offset as usize;
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(data);
+        self.written += data.len();
+        Ok(())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, HalError> {
+        Ok(self.files.get(name).cloned())
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), HalError> {
+        self.files.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn unmount(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+}
+
+impl UsbMount for MockUsb {
+    fn wait_insert(&mut self) -> Result<(), HalError> {
+        if self.insertions == 0 {
+            return Err(HalError::Usb("no more insertions".into()));
+        }
+        self.insertions -= 1;
+        Ok(())
+    }
+
+    fn mount_readonly(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+}
+
+/// Serial link looped back in memory: the device reads what the host
+/// queued in `inbound`, and what it writes collects in `outbound`.
+#[derive(Default)]
+pub struct MockLink {
+    pub inbound: VecDeque<u8>,
+    pub outbound: Vec<u8>,
+}
+
+impl Read for MockLink {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inbound.read(buf)
+    }
+}
+
+impl Write for MockLink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.outbound.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory secure element with Ed25519 keys and a call log.
+#[derive(Default)]
+pub struct MockSe {
+    pin: Option<Vec<u8>>,
+    pin_verified: bool,
+    keys: HashMap<u8, [u8; 32]>,
+    /// Chain codes of slots imported as SLIP-0010 master keys.
+    chain_codes: HashMap<u8, [u8; 32]>,
+    /// Seeds as generated, before passphrase derivation.
+    generated: HashMap<u8, [u8; 32]>,
+    pub verify_calls: usize,
+    pub sign_calls: usize,
+    /// Cut signatures down to this many bytes, like a faulty element.
+    pub truncate_signatures: Option<usize>,
+}
+
+impl MockSe {
+    /// Provisioned with PIN `0000` and a fixed key in slot 0, already unlocked.
+    pub fn unlocked() -> Self {
+        let mut se = Self {
+            pin: Some(b"0000".to_vec()),
+            pin_verified: true,
+            ..Self::default()
+        };
+        se.keys.insert(0, [7u8; 32]);
+        se
+    }
+
+    /// Forget the PIN verification, as after a reboot.
+    pub fn lock(&mut self) {
+        self.pin_verified = false;
+    }
+
+    fn seed(&self, slot: u8) -> Result<&[u8; 32], HalError> {
+        self.keys
+            .get(&slot)
+            .ok_or_else(|| HalError::Storage(format!("no key in slot {slot}")))
+    }
+
+    fn derived(&self, slot: u8, path: &[u32]) -> Result<[u8; 32], HalError> {
+        let seed = self.seed(slot)?;
+        let derived = match self.chain_codes.get(&slot) {
+            Some(chain_code) => slip10::derive_from(
+                &ExtendedKey {
+                    key: *seed,
+                    chain_code: *chain_code,
+                },
+                path,
+            ),
+            None => slip10::derive(seed, path),
+        };
+        derived.map_err(|e| HalError::Storage(e.to_string()))
+    }
+
+    fn require_pin(&self) -> Result<(), HalError> {
+        if !self.pin_verified {
+            return Err(HalError::Storage("PIN not verified".into()));
+        }
+        Ok(())
+    }
+}
+
+impl PublicKeys for MockSe {
+    fn is_provisioned(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    fn public_key(&self, slot: u8) -> Result<Vec<u8>, HalError> {
+        let key = SigningKey::from_bytes(self.seed(slot)?);
+        Ok(key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn derive_public_key(&self, slot: u8, path: &[u32]) -> Result<Vec<u8>, HalError> {
+        let key = SigningKey::from_bytes(&self.derived(slot, path)?);
+        Ok(key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn sign_count(&self) -> u64 {
+        self.sign_calls as u64
+    }
+
+    fn public_key_for(&self, algorithm: SignAlgorithm, slot: u8) -> Result<Vec<u8>, HalError> {
+        check_algorithm(algorithm)?;
+        SoftwareSigner::from_seed(*self.seed(slot)?)
+            .public_key(algorithm)
+            .map_err(|e| HalError::Storage(e.to_string()))
+    }
+}
+
+impl SecureElement for MockSe {
+    fn set_pin(&mut self, pin: &[u8]) -> Result<(), HalError> {
+        self.pin = Some(pin.to_vec());
+        Ok(())
+    }
+
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<(), HalError> {
+        self.verify_calls += 1;
+        self.pin_verified = self.pin.as_deref() == Some(pin);
+        if !self.pin_verified {
+            return Err(HalError::Storage("wrong PIN".into()));
+        }
+        Ok(())
+    }
+
+    fn generate_key(&mut self, slot: u8, passphrase: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        let seed = [slot.wrapping_add(1); 32];
+        self.generated.insert(slot, seed);
+        self.keys.insert(slot, derive_seed(&seed, passphrase));
+        self.chain_codes.remove(&slot);
+        self.public_key(slot)
+    }
+
+    fn sign(&mut self, slot: u8, hash: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        self.sign_calls += 1;
+        let key = SigningKey::from_bytes(self.seed(slot)?);
+        let mut sig = key.sign(hash).to_bytes().to_vec();
+        if let Some(len) = self.truncate_signatures {
+            sig.truncate(len);
+        }
+        Ok(sig)
+    }
+
+    fn sign_with(
+        &mut self,
+        algorithm: SignAlgorithm,
+        slot: u8,
+        message: &[u8],
+    ) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        check_algorithm(algorithm)?;
+        self.sign_calls += 1;
+        let mut sig = SoftwareSigner::from_seed(*self.seed(slot)?)
+            .sign(algorithm, message)
+            .map_err(|e| HalError::Storage(e.to_string()))?;
+        if let Some(len) = self.truncate_signatures {
+            sig.truncate(len);
+        }
+        Ok(sig)
+    }
+
+    fn sign_derived(&mut self, slot: u8, path: &[u32], hash: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        self.sign_calls += 1;
+        let key = SigningKey::from_bytes(&self.derived(slot, path)?);
+        Ok(key.sign(hash).to_bytes().to_vec())
+    }
+
+    fn import_key(
+        &mut self,
+        slot: u8,
+        seed: &[u8],
+        passphrase: &[u8],
+    ) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        let seed: [u8; 32] = seed
+            .try_into()
+            .map_err(|_| HalError::Storage("seed must be 32 bytes".into()))?;
+        self.keys.insert(slot, derive_seed(&seed, passphrase));
+        self.chain_codes.remove(&slot);
+        self.public_key(slot)
+    }
+
+    fn import_master_key(&mut self, slot: u8, master: &ExtendedKey) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        self.keys.insert(slot, master.key);
+        self.chain_codes.insert(slot, master.chain_code);
+        self.public_key(slot)
+    }
+
+    fn export_seed(&self, slot: u8) -> Result<Vec<u8>, HalError> {
+        match self.generated.get(&slot) {
+            Some(seed) => Ok(seed.to_vec()),
+            None => Ok(self.seed(slot)?.to_vec()),
+        }
+    }
+
+    /// Unencrypted: the PIN followed by `slot || seed` records, without
+    /// chain codes.
+    fn export_backup(&mut self, pin: &[u8]) -> Result<Vec<u8>, HalError> {
+        self.require_pin()?;
+        if self.pin.as_deref() != Some(pin) {
+            return Err(HalError::Storage("wrong PIN".into()));
+        }
+        let mut blob = pin.to_vec();
+        for (slot, seed) in &self.keys {
+            blob.push(*slot);
+            blob.extend_from_slice(seed);
+        }
+        Ok(blob)
+    }
+
+    fn restore_backup(&mut self, pin: &[u8], blob: &[u8]) -> Result<(), HalError> {
+        if !self.keys.is_empty() {
+            return Err(HalError::Storage(
+                "refusing to restore over existing keys".into(),
+            ));
+        }
+        let records = blob
+            .strip_prefix(pin)
+            .ok_or_else(|| HalError::Storage("backup decryption failed".into()))?;
+        for record in records.chunks(33) {
+            let seed = record[1..]
+                .try_into()
+                .map_err(|_| HalError::Storage("truncated backup".into()))?;
+            self.keys.insert(record[0], seed);
+        }
+        self.pin = Some(pin.to_vec());
+        self.pin_verified = true;
+        Ok(())
+    }
+}
+
+/// Settings held in memory.
+#[derive(Default)]
+pub struct MockSettings {
+    pub values: HashMap<String, Vec<u8>>,
+}
+
+impl Settings for MockSettings {
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, HalError> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<(), HalError> {
+        self.values.insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Requests scanned from QR codes instead of read from a USB stick.
+//!
+//! A camera sees each stick file as a run of frames in the format of
+//! [`signer_core::qr::frame_texts`]. The simulator stands in for the camera
+//! with a directory holding one `<file>.qr` per stick file (`payload.bin.qr`,
+//! `sign.cbor.qr`, optionally `interpreter.wasm.qr` and `manifest.cbor.qr`),
+//! one scanned frame per line, in any order.
+//!
+//! There is no stick to write back to: the signed output leaves on the
+//! DONE screen's QR code. Files the flow writes (output, logs, progress)
+//! land in the same directory as plain files, so they can be inspected.
+
+use crate::usb::check_manifest;
+use signer_core::qr::reassemble_frames;
+use signer_core::stick::MANIFEST_FILE;
+use signer_hal::{HalError, PayloadSource, UsbContents, UsbMount};
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Directory of scanned QR frames.
+pub struct QrSource {
+    dir: PathBuf,
+}
+
+impl QrSource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn scan_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.qr"))
+    }
+
+    /// The reassembled file `name`, or `None` if it was not scanned.
+    fn scanned(&self, name: &str) -> Result<Option<Vec<u8>>, HalError> {
+        let path = self.scan_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let frames = fs::read_to_string(&path).map_err(|e| HalError::Usb(e.to_string()))?;
+        reassemble_frames(frames.lines().map(str::trim).filter(|l| !l.is_empty()))
+            .map(Some)
+            .map_err(|e| HalError::Usb(format!("{name}: {e}")))
+    }
+
+    fn required(&self, name: &str) -> Result<Vec<u8>, HalError> {
+        self.scanned(name)?
+            .ok_or_else(|| HalError::Usb(format!("{name} not scanned")))
+    }
+}
+
+impl PayloadSource for QrSource {
+    fn read_contents(&self) -> Result<UsbContents, HalError> {
+        let contents = UsbContents {
+            payload: self.required("payload.bin")?,
+            interpreter_wasm: self.scanned("interpreter.wasm")?,
+            signing_spec_cbor: self.required("sign.cbor")?,
+        };
+        check_manifest(self.scanned(MANIFEST_FILE)?.as_deref(), &contents)?;
+        Ok(contents)
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> Result<(), HalError> {
+        self.write_file("signed.bin", data)
+    }
+
+    fn write_output_at(&mut self, offset: u64, data: &[u8]) -> Result<(), HalError> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.dir.join("signed.bin"))
+            .map_err(|e| HalError::Usb(e.to_string()))?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.write_all(data))
+            .map_err(|e| HalError::Usb(e.to_string()))
+    }
+
+    /// A scanned file, or else one the flow wrote earlier.
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, HalError> {
+        if let Some(bytes) = self.scanned(name)? {
+            return Ok(Some(bytes));
+        }
+        let path = self.dir.join(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read(&path)
+            .map(Some)
+            .map_err(|e| HalError::Usb(e.to_string()))
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), HalError> {
+        fs::write(self.dir.join(name), data).map_err(|e| HalError::Usb(e.to_string()))
+    }
+
+    fn unmount(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+}
+
+/// Scanning stands in for inserting a stick: the request is "inserted"
+/// once its payload and spec have been scanned.
+impl UsbMount for QrSource {
+    fn wait_insert(&mut self) -> Result<(), HalError> {
+        while !(self.scan_path("payload.bin").exists() && self.scan_path("sign.cbor").exists()) {
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn mount_readonly(&mut self) -> Result<(), HalError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::{run_once, FlowConfig, Session};
+    use crate::mock::{echo_hex_wasm, spec, MockHal, MockSe, MockSettings};
+    use signer_core::qr::frame_texts;
+    use signer_hal::{ButtonEvent, SecureElement};
+
+    fn scans(name: &str) -> QrSource {
+        let dir = std::env::temp_dir().join(format!("signer-sim-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        QrSource::new(dir)
+    }
+
+    /// Write `data` as `name`'s frames, in reverse order as a camera might
+    /// catch them.
+    fn scan(source: &QrSource, name: &str, data: &[u8], chunk: usize) {
+        let mut frames = frame_texts(data, chunk);
+        frames.reverse();
+        fs::write(source.scan_path(name), frames.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn multi_part_scans_reassemble() {
+        let source = scans("qr-reassemble");
+        let payload: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        scan(&source, "payload.bin", &payload, 120);
+        scan(&source, "sign.cbor", b"spec", 120);
+
+        let contents = source.read_contents().unwrap();
+        assert_eq!(contents.payload, payload);
+        assert_eq!(contents.signing_spec_cbor, b"spec");
+        assert_eq!(contents.interpreter_wasm, None);
+        assert_eq!(source.read_file("sign.cbor").unwrap().unwrap(), b"spec");
+        fs::remove_dir_all(&source.dir).unwrap();
+    }
+
+    #[test]
+    fn request_is_signed_from_scans() {
+        let mut source = scans("qr-flow");
+        scan(&source, "payload.bin", b"tx", 100);
+        scan(&source, "sign.cbor", &spec().to_cbor().unwrap(), 40);
+        scan(&source, "interpreter.wasm", &echo_hex_wasm(), 2000);
+        let mut hal = MockHal::new([ButtonEvent::Confirm]);
+        let mut se = MockSe::unlocked();
+
+        let signed = run_once(
+            &mut hal,
+            &mut source,
+            &mut se,
+            &mut MockSettings::default(),
+            &FlowConfig::default(),
+            &mut Session::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        let output = fs::read(source.dir.join("signed.bin")).unwrap();
+        assert_eq!(output, se.sign(0, b"tx").unwrap());
+        assert_eq!(hal.qr_codes.len(), 1);
+        fs::remove_dir_all(&source.dir).unwrap();
+    }
+
+    #[test]
+    fn missing_frame_is_refused() {
+        let source = scans("qr-missing");
+        let frames = frame_texts(&[7; 300], 100);
+        fs::write(
+            source.scan_path("payload.bin"),
+            [&frames[0], &frames[2]].map(String::as_str).join("\n"),
+        )
+        .unwrap();
+        scan(&source, "sign.cbor", b"spec", 100);
+
+        let err = source.read_contents().err().unwrap().to_string();
+        assert!(err.contains("part 2 of 3 is missing"), "{err}");
+        fs::remove_dir_all(&source.dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! Recording a session's button presses and stick contents so that it can be
+//! replayed against the flow with mocks, e.g. to reproduce a reported bug.
+//!
+//! `--record FILE` writes a [`SessionRecording`] as JSON when the simulator
+//! exits. Replay ignores the recorded timings; they are kept for reading.
+//! Secret files on the stick ([`SECRET_FILES`]) are left out, so a
+//! recording can be attached to a bug report.
+
+use crate::control::Button;
+use crate::flow::SECRET_FILES;
+use serde::{Deserialize, Serialize};
+use signer_core::display::DisplayLine;
+use signer_hal::{ButtonEvent, Buttons, Clock, Display, HalError, LedColor, StatusLed};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+use std::{fs, io};
+
+/// Everything needed to drive one session again.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecording {
+    /// Files on the stick when the session started, hex-encoded by name.
+    pub usb_files: BTreeMap<String, String>,
+    /// Button presses in order.
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub button: Button,
+    /// Milliseconds since the previous press, or since recording started.
+    pub after_ms: u64,
+}
+
+impl SessionRecording {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// The regular files in a simulated stick directory, but for
+/// [`SECRET_FILES`].
+pub fn snapshot_dir(dir: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && !SECRET_FILES.contains(&name.as_str()) {
+            files.insert(name, fs::read(entry.path())?);
+        }
+    }
+    Ok(files)
+}
+
+/// `Display`/`Buttons` wrapper recording every button press of `hal`.
+pub struct SessionRecorder<H> {
+    hal: H,
+    recording: SessionRecording,
+    last: Instant,
+}
+
+impl<H> SessionRecorder<H> {
+    /// Start recording, with `usb_files` as the stick's starting contents.
+    pub fn new(hal: H, usb_files: &BTreeMap<String, Vec<u8>>) -> Self {
+        Self {
+            hal,
+            recording: SessionRecording {
+                usb_files: usb_files
+                    .iter()
+                    .map(|(name, data)| (name.clone(), hex::encode(data)))
+                    .collect(),
+                events: Vec::new(),
+            },
+            last: Instant::now(),
+        }
+    }
+
+    pub fn recording(&self) -> &SessionRecording {
+        &self.recording
+    }
+}
+
+impl<H: Display> Display for SessionRecorder<H> {
+    fn clear(&mut self) -> Result<(), HalError> {
+        self.hal.clear()
+    }
+
+    fn show_message(&mut self, text: &str) -> Result<(), HalError> {
+        self.hal.show_message(text)
+    }
+
+    fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
+        self.hal.show_lines(lines, scroll_offset)
+    }
+
+    fn show_qr(&mut self, caption: &str, modules: &[Vec<bool>]) -> Result<(), HalError> {
+        self.hal.show_qr(caption, modules)
+    }
+}
+
+impl<H: Clock> Clock for SessionRecorder<H> {
+    fn now(&self) -> Option<u64> {
+        self.hal.now()
+    }
+}
+
+impl<H: StatusLed> StatusLed for SessionRecorder<H> {
+    fn set(&mut self, color: LedColor) {
+        self.hal.set(color)
+    }
+}
+
+impl<H: Buttons> Buttons for SessionRecorder<H> {
+    fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
+        let ev = self.hal.wait_event()?;
+        let now = Instant::now();
+        self.recording.events.push(RecordedEvent {
+            button: ev.into(),
+            after_ms: now.duration_since(self.last).as_millis() as u64,
+        });
+        self.last = now;
+        Ok(ev)
+    }
+}
+
+/// Mocks set up to replay a recording deterministically.
+#[cfg(test)]
+pub struct SessionPlayer {
+    recording: SessionRecording,
+}
+
+#[cfg(test)]
+impl SessionPlayer {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            recording: serde_json::from_slice(&fs::read(path)?)?,
+        })
+    }
+
+    /// Buttons that press the recorded events in order, without the delays.
+    pub fn hal(&self) -> crate::mock::MockHal {
+        crate::mock::MockHal::new(self.recording.events.iter().map(|e| e.button.into()))
+    }
+
+    /// A stick holding the recorded files, inserted once.
+    pub fn usb(&self) -> Result<crate::mock::MockUsb, hex::FromHexError> {
+        let mut usb = crate::mock::MockUsb {
+            insertions: 1,
+            ..Default::default()
+        };
+        for (name, data) in &self.recording.usb_files {
+            usb.files.insert(name.clone(), hex::decode(data)?);
+        }
+        Ok(usb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::{run_once, FlowConfig, Session};
+    use crate::mock::{echo_hex_wasm, spec, MockHal, MockSe, MockSettings, MockUsb};
+
+    #[test]
+    fn replayed_session_produces_same_output() {
+        let mut usb = MockUsb::signing(&spec(), b"tx", echo_hex_wasm());
+        let files = usb.files.clone().into_iter().collect();
+        // Scroll, open the full value and close it, then confirm.
+        let mut hal = SessionRecorder::new(
+            MockHal::new([
+                ButtonEvent::Down,
+                ButtonEvent::Select,
+                ButtonEvent::Reject,
+                ButtonEvent::Confirm,
+            ]),
+            &files,
+        );
+        let signed = run_once(
+            &mut hal,
+            &mut usb,
+            &mut MockSe::unlocked(),
+            &mut MockSettings::default(),
+            &FlowConfig::default(),
+            &mut Session::default(),
+        )
+        .unwrap();
+        assert!(signed);
+
+        let path =
+            std::env::temp_dir().join(format!("signer-sim-{}-replay.json", std::process::id()));
+        hal.recording().save(&path).unwrap();
+        let player = SessionPlayer::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(player.recording.events.len(), 4);
+
+        let mut replay_usb = player.usb().unwrap();
+        let replayed = run_once(
+            &mut player.hal(),
+            &mut replay_usb,
+            &mut MockSe::unlocked(),
+            &mut MockSettings::default(),
+            &FlowConfig::default(),
+            &mut Session::default(),
+        )
+        .unwrap();
+
+        assert!(replayed);
+        assert!(usb.output.is_some());
+        assert_eq!(replay_usb.output, usb.output);
+    }
+
+    #[test]
+    fn snapshot_leaves_out_secret_files() {
+        let dir = std::env::temp_dir().join(format!("signer-sim-{}-snapshot", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in SECRET_FILES.iter().chain(&["payload.bin"]) {
+            fs::write(dir.join(name), b"data").unwrap();
+        }
+
+        let files = snapshot_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["payload.bin"]);
+    }
+}
@@ -0,0 +1,45 @@
+use signer_hal::{HalError, Settings};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Device settings persisted as a JSON map of hex-encoded values.
+pub struct SimSettings {
+    path: PathBuf,
+    values: HashMap<String, String>,
+}
+
+impl SimSettings {
+    /// Load settings from `path`, starting empty if the file doesn't exist.
+    pub fn from_file_or_new(path: &Path) -> Result<Self, HalError> {
+        let values = if path.exists() {
+            let data = fs::read_to_string(path)
+                .map_err(|e| HalError::Storage(format!("failed to read settings: {e}")))?;
+            serde_json::from_str(&data)
+                .map_err(|e| HalError::Storage(format!("failed to parse settings JSON: {e}")))?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            values,
+        })
+    }
+}
+
+impl Settings for SimSettings {
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, HalError> {
+        self.values
+            .get(key)
+            .map(|v| hex::decode(v).map_err(|e| HalError::Storage(format!("setting {key}: {e}"))))
+            .transpose()
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<(), HalError> {
+        self.values.insert(key.to_string(), hex::encode(value));
+        let json = serde_json::to_string_pretty(&self.values)
+            .map_err(|e| HalError::Storage(format!("failed to serialize settings: {e}")))?;
+        fs::write(&self.path, json)
+            .map_err(|e| HalError::Storage(format!("failed to write settings: {e}")))
+    }
+}
@@ -0,0 +1,239 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, style, terminal};
+use signer_core::display::{layout, scroll_indicator, DisplayGeometry, DisplayLine};
+use signer_hal::{ButtonEvent, Buttons, Clock, Display, HalError, StatusLed};
+use std::io::{self, Stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of terminal events; scripted in tests, `crossterm::event::read` otherwise.
+type EventSource = Box<dyn FnMut() -> io::Result<Event>>;
+
+/// Terminal frontend implementing both `Display` and `Buttons`,
+/// for headless machines where a minifb window is unavailable.
+pub struct TuiHal<W: Write> {
+    out: W,
+    events: EventSource,
+    cols: usize,
+    rows: usize,
+    /// Whether the terminal was put in raw mode and must be restored on drop.
+    raw: bool,
+}
+
+impl TuiHal<Stdout> {
+    /// Take over the controlling terminal (raw mode, alternate screen).
+    pub fn stdout() -> Result<Self, HalError> {
+        let (cols, rows) = terminal::size().map_err(display_err)?;
+        terminal::enable_raw_mode().map_err(display_err)?;
+        let mut hal = Self {
+            out: io::stdout(),
+            events: Box::new(event::read),
+            cols: cols as usize,
+            rows: rows as usize,
+            raw: true,
+        };
+        queue!(hal.out, terminal::EnterAlternateScreen, cursor::Hide).map_err(display_err)?;
+        hal.out.flush().map_err(display_err)?;
+        Ok(hal)
+    }
+}
+
+impl<W: Write> TuiHal<W> {
+    /// Render into `out` and read events from `events` without touching the terminal.
+    #[cfg(test)]
+    pub fn scripted(out: W, events: impl IntoIterator<Item = Event>, rows: usize) -> Self {
+        let mut events = events.into_iter().collect::<Vec<_>>().into_iter();
+        Self {
+            out,
+            events: Box::new(move || {
+                events
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "script exhausted"))
+            }),
+            cols: 80,
+            rows,
+            raw: false,
+        }
+    }
+
+    fn draw(&mut self, rows: &[(usize, String)]) -> Result<(), HalError> {
+        queue!(self.out, terminal::Clear(terminal::ClearType::All)).map_err(display_err)?;
+        for (row, text) in rows {
+            queue!(self.out, cursor::MoveTo(0, *row as u16), style::Print(text))
+                .map_err(display_err)?;
+        }
+        self.out.flush().map_err(display_err)
+    }
+}
+
+impl<W: Write> Drop for TuiHal<W> {
+    fn drop(&mut self) {
+        if self.raw {
+            let _ = queue!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+            let _ = self.out.flush();
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+}
+
+impl<W: Write> Display for TuiHal<W> {
+    fn clear(&mut self) -> Result<(), HalError> {
+        self.draw(&[])
+    }
+
+    fn show_message(&mut self, text: &str) -> Result<(), HalError> {
+        self.draw(&[(self.rows / 2, text.to_string())])
+    }
+
+    fn show_lines(&mut self, lines: &[DisplayLine], scroll_offset: usize) -> Result<(), HalError> {
+        let content_start = 2;
+        let content_lines = self.rows.saturating_sub(content_start);
+        let indicator = scroll_indicator(scroll_offset, lines.len(), content_lines);
+
+        let mut rows = vec![
+            (
+                0,
+                format!(
+                    " [{}]  Enter=OK  Esc=Cancel  \u{2191}/\u{2193}=Scroll  Space=Full",
+                    indicator.label()
+                ),
+            ),
+            (1, "-".repeat(40)),
+        ];
+        let geometry = DisplayGeometry {
+            cols: self.cols,
+            rows: content_lines,
+        };
+        rows.extend(
+            layout(lines, geometry, scroll_offset)
+                .into_iter()
+                .enumerate()
+                .map(|(i, row)| (content_start + i, row)),
+        );
+        self.draw(&rows)
+    }
+}
+
+/// The host's clock.
+impl<W: Write> Clock for TuiHal<W> {
+    fn now(&self) -> Option<u64> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Some(since_epoch.as_secs())
+    }
+}
+
+/// A terminal has no LED.
+impl<W: Write> StatusLed for TuiHal<W> {}
+
+impl<W: Write> Buttons for TuiHal<W> {
+    fn wait_event(&mut self) -> Result<ButtonEvent, HalError> {
+        loop {
+            let Event::Key(key) = (self.events)().map_err(button_err)? else {
+                continue;
+            };
+            // Raw mode swallows SIGINT, so Ctrl-C has to be handled here.
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Err(HalError::Button("interrupted".into()));
+            }
+            if let Some(ev) = map_key(&key) {
+                return Ok(ev);
+            }
+        }
+    }
+}
+
+/// Map a terminal key press to a ButtonEvent, using the same keys as the window frontend.
+pub fn map_key(key: &KeyEvent) -> Option<ButtonEvent> {
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    match key.code {
+        KeyCode::Enter => Some(ButtonEvent::Confirm),
+        KeyCode::Esc => Some(ButtonEvent::Reject),
+        KeyCode::Up => Some(ButtonEvent::Up),
+        KeyCode::Down => Some(ButtonEvent::Down),
+        KeyCode::Char(' ') => Some(ButtonEvent::Select),
+        _ => None,
+    }
+}
+
+fn display_err(e: io::Error) -> HalError {
+    HalError::Display(e.to_string())
+}
+
+fn button_err(e: io::Error) -> HalError {
+    HalError::Button(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::{run_once, FlowConfig, Session};
+    use crate::mock::{echo_hex_wasm, spec, MockSe, MockSettings, MockUsb};
+    use signer_core::spec::SigningSpec;
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn keys_map_to_button_events() {
+        let mut hal = TuiHal::scripted(
+            Vec::new(),
+            [
+                key(KeyCode::Enter),
+                key(KeyCode::Char('x')),
+                Event::FocusGained,
+                key(KeyCode::Down),
+                key(KeyCode::Up),
+                key(KeyCode::Char(' ')),
+                key(KeyCode::Esc),
+            ],
+            24,
+        );
+        let events: Vec<_> = (0..5).map(|_| hal.wait_event().unwrap()).collect();
+        assert_eq!(
+            events,
+            [
+                ButtonEvent::Confirm,
+                ButtonEvent::Down,
+                ButtonEvent::Up,
+                ButtonEvent::Select,
+                ButtonEvent::Reject,
+            ]
+        );
+        assert!(hal.wait_event().is_err());
+    }
+
+    #[test]
+    fn ctrl_c_interrupts() {
+        let ctrl_c = Event::Key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        let mut hal = TuiHal::scripted(Vec::new(), [ctrl_c, key(KeyCode::Enter)], 24);
+        assert!(hal.wait_event().is_err());
+    }
+
+    #[test]
+    fn flow_runs_over_tui() {
+        let spec = SigningSpec {
+            label: "TUI test".into(),
+            ..spec()
+        };
+        let mut hal = TuiHal::scripted(Vec::new(), [key(KeyCode::Enter)], 24);
+        let mut usb = MockUsb::signing(&spec, b"hi", echo_hex_wasm());
+        let mut se = MockSe::unlocked();
+
+        let signed = run_once(
+            &mut hal,
+            &mut usb,
+            &mut se,
+            &mut MockSettings::default(),
+            &FlowConfig::default(),
+            &mut Session::default(),
+        )
+        .unwrap();
+
+        assert!(signed);
+        let screen = String::from_utf8_lossy(&hal.out).into_owned();
+        assert!(screen.contains("TUI test"));
+        assert!(screen.contains("Enter=OK"));
+    }
+}
@@ -1,15 +1,38 @@
-use signer_hal::{HalError, UsbContents, UsbMount};
+use signer_core::stick::{Manifest, MANIFEST_FILE};
+use signer_hal::{HalError, PayloadSource, PayloadStream, UsbContents, UsbMount};
 use std::fs;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 
 const POLL_INTERVAL: Duration = Duration::from_millis(500);
 
+/// Dropping this file in the directory simulates inserting a stick with
+/// nothing to sign, which opens the tools menu. It is consumed on insertion.
+const TOOLS_MARKER: &str = "tools";
+
+/// Check `contents` against the stick's `manifest.cbor`, if it has one.
+pub fn check_manifest(manifest: Option<&[u8]>, contents: &UsbContents) -> Result<(), HalError> {
+    let Some(manifest) = manifest else {
+        return Ok(());
+    };
+    Manifest::from_cbor(manifest)
+        .and_then(|m| {
+            m.verify(
+                &contents.payload,
+                contents.interpreter_wasm.as_deref(),
+                &contents.signing_spec_cbor,
+            )
+        })
+        .map_err(|e| HalError::Usb(e.to_string()))
+}
+
 /// Directory-based USB simulation.
 ///
-/// Watches a directory for `payload.bin`, `interpreter.wasm`, and `sign.cbor`.
-/// Writes output as `signed.bin`.
+/// Watches a directory for `payload.bin` and `sign.cbor`, plus an optional
+/// `interpreter.wasm` and `manifest.cbor`. Writes output as `signed.bin`. A `tools` file stands
+/// for a stick with nothing to sign.
 pub struct SimUsb {
     dir: PathBuf,
 }
@@ -36,35 +59,38 @@ impl SimUsb {
     }
 
     fn files_present(&self) -> bool {
-        self.payload_path().exists()
-            && self.interpreter_path().exists()
-            && self.spec_path().exists()
+        self.payload_path().exists() && self.spec_path().exists()
     }
 }
 
-impl UsbMount for SimUsb {
-    fn wait_insert(&mut self) -> Result<(), HalError> {
-        while !self.files_present() {
-            thread::sleep(POLL_INTERVAL);
-        }
-        Ok(())
-    }
-
-    fn mount_readonly(&mut self) -> Result<(), HalError> {
-        // no-op for directory simulation
-        Ok(())
-    }
-
+impl PayloadSource for SimUsb {
     fn read_contents(&self) -> Result<UsbContents, HalError> {
         let payload = fs::read(self.payload_path()).map_err(|e| HalError::Usb(e.to_string()))?;
-        let interpreter_wasm =
-            fs::read(self.interpreter_path()).map_err(|e| HalError::Usb(e.to_string()))?;
+        let interpreter_wasm = if self.interpreter_path().exists() {
+            Some(fs::read(self.interpreter_path()).map_err(|e| HalError::Usb(e.to_string()))?)
+        } else {
+            None
+        };
         let signing_spec_cbor =
             fs::read(self.spec_path()).map_err(|e| HalError::Usb(e.to_string()))?;
-        Ok(UsbContents {
+        let contents = UsbContents {
             payload,
             interpreter_wasm,
             signing_spec_cbor,
+        };
+        check_manifest(self.read_file(MANIFEST_FILE)?.as_deref(), &contents)?;
+        Ok(contents)
+    }
+
+    fn open_payload(&self) -> Result<PayloadStream<'_>, HalError> {
+        let file = fs::File::open(self.payload_path()).map_err(|e| HalError::Usb(e.to_string()))?;
+        let len = file
+            .metadata()
+            .map_err(|e| HalError::Usb(e.to_string()))?
+            .len();
+        Ok(PayloadStream {
+            len,
+            reader: Box::new(file),
         })
     }
 
@@ -72,6 +98,18 @@ impl UsbMount for SimUsb {
         fs::write(self.output_path(), data).map_err(|e| HalError::Usb(e.to_string()))
     }
 
+    fn write_output_at(&mut self, offset: u64, data: &[u8]) -> Result<(), HalError> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.output_path())
+            .map_err(|e| HalError::Usb(e.to_string()))?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.write_all(data))
+            .map_err(|e| HalError::Usb(e.to_string()))
+    }
+
     fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, HalError> {
         let path = self.dir.join(name);
         if !path.exists() {
@@ -91,3 +129,60 @@ impl UsbMount for SimUsb {
         Ok(())
     }
 }
+
+impl UsbMount for SimUsb {
+    fn wait_insert(&mut self) -> Result<(), HalError> {
+        loop {
+            if self.files_present() {
+                return Ok(());
+            }
+            let marker = self.dir.join(TOOLS_MARKER);
+            if marker.exists() {
+                return fs::remove_file(marker).map_err(|e| HalError::Usb(e.to_string()));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn mount_readonly(&mut self) -> Result<(), HalError> {
+        // no-op for directory simulation
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(name: &str) -> SimUsb {
+        let dir = std::env::temp_dir().join(format!("signer-sim-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("payload.bin"), b"tx").unwrap();
+        fs::write(dir.join("sign.cbor"), b"spec").unwrap();
+        let manifest = Manifest::new("0.1.0", b"tx", None, b"spec");
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_cbor()).unwrap();
+        SimUsb::new(dir)
+    }
+
+    #[test]
+    fn matching_manifest_is_accepted() {
+        let usb = stick("manifest-ok");
+        assert_eq!(usb.read_contents().unwrap().payload, b"tx");
+        fs::remove_dir_all(&usb.dir).unwrap();
+    }
+
+    #[test]
+    fn tampered_stick_is_refused() {
+        let usb = stick("manifest-tampered");
+        fs::write(usb.payload_path(), b"tX").unwrap();
+        let err = usb.read_contents().err().unwrap().to_string();
+        assert!(err.contains("payload.bin does not match"), "{err}");
+
+        fs::write(usb.payload_path(), b"tx").unwrap();
+        fs::write(usb.interpreter_path(), b"\0asm").unwrap();
+        let err = usb.read_contents().err().unwrap().to_string();
+        assert!(err.contains("interpreter.wasm does not match"), "{err}");
+        fs::remove_dir_all(&usb.dir).unwrap();
+    }
+}
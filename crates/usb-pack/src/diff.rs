@@ -0,0 +1,83 @@
+//! `usb-pack diff`: compare what the device would show for two packed sticks.
+
+use signer_core::builtin;
+use signer_core::display::format_line;
+use signer_core::review::review_lines;
+use signer_core::spec::{InterpreterSource, SigningSpec};
+use signer_core::wasm_sandbox::Sandbox;
+use std::fs;
+use std::path::Path;
+
+/// One line of a diff between two lists of review lines.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Review lines the device would show for the stick contents in `dir`.
+fn review(sandbox: &Sandbox, dir: &Path) -> Vec<String> {
+    let read = |name: &str| {
+        fs::read(dir.join(name)).unwrap_or_else(|e| panic!("failed to read {name}: {e}"))
+    };
+    let spec = SigningSpec::from_cbor(&read("sign.cbor")).expect("invalid sign.cbor");
+    let wasm = match &spec.interpreter {
+        InterpreterSource::Usb => read("interpreter.wasm"),
+        InterpreterSource::Builtin(name) => builtin::interpreter(name)
+            .unwrap_or_else(|| panic!("built-in interpreter '{name}' is not in this build"))
+            .to_vec(),
+    };
+    let module = sandbox
+        .load_module(&wasm)
+        .expect("failed to load interpreter");
+    let json = module
+        .interpret(&read("payload.bin"))
+        .expect("interpreter failed");
+    let review = review_lines(&module, &json).expect("invalid interpreter output");
+    review.lines.iter().map(format_line).collect()
+}
+
+/// Line-level diff (longest common subsequence) of `a` against `b`.
+pub fn diff<'a>(a: &'a [String], b: &'a [String]) -> Vec<Change<'a>> {
+    // lcs[i][j] = length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change::Removed(&a[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&b[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+pub fn run(a: &Path, b: &Path) {
+    let sandbox = Sandbox::new().expect("failed to create sandbox");
+    let (lines_a, lines_b) = (review(&sandbox, a), review(&sandbox, b));
+    let changes = diff(&lines_a, &lines_b);
+    if changes.is_empty() {
+        println!("no differences");
+    }
+    for change in changes {
+        match change {
+            Change::Removed(line) => println!("- {line}"),
+            Change::Added(line) => println!("+ {line}"),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! `usb-pack dump`: show what a `sign.cbor` asks the device to sign.
+
+use signer_core::builtin;
+use signer_core::display::{describe_path, json_to_lines_sorted, render_text};
+use signer_core::spec::{InterpreterSource, Signable, SignableSource, SigningSpec};
+use signer_core::wasm_sandbox::Sandbox;
+use std::fs;
+use std::path::Path;
+
+fn describe_source(source: &SignableSource) -> String {
+    match source {
+        SignableSource::Whole => "the whole payload".into(),
+        SignableSource::Range { offset, length } => describe_range(*offset, *length),
+    }
+}
+
+fn describe_range(offset: usize, length: usize) -> String {
+    format!(
+        "payload bytes {offset}..{} ({length} bytes)",
+        offset.saturating_add(length)
+    )
+}
+
+/// What part of the payload gets signed, in words.
+fn describe_signable(signable: &Signable) -> String {
+    match signable {
+        Signable::Whole => "the whole payload".into(),
+        Signable::Range { offset, length } => describe_range(*offset, *length),
+        Signable::HashThenSign { hash, source } => {
+            format!("{hash:?} hash of {}", describe_source(source))
+        }
+        Signable::KeyedHashThenSign { key, source } => format!(
+            "keyed Blake2b256 MAC ({}-byte key) of {}",
+            key.len(),
+            describe_source(source)
+        ),
+        Signable::MerkleRoot { hash } => format!("{hash:?} Merkle root of the payload leaves"),
+        Signable::Interpreter => "bytes chosen by the interpreter".into(),
+        Signable::Prefixed { prefix, inner } => {
+            format!(
+                "{} with prefix {}",
+                describe_signable(inner),
+                hex::encode(prefix)
+            )
+        }
+        Signable::Multi(items) => format!(
+            "{} signatures: {}",
+            items.len(),
+            items
+                .iter()
+                .map(describe_signable)
+                .collect::<Vec<_>>()
+                .join("; ")
+        ),
+    }
+}
+
+/// Human-readable summary of `spec`, one `name: value` field per line.
+fn summary(spec: &SigningSpec) -> String {
+    let mut fields = vec![
+        ("label", spec.label.clone()),
+        ("signable", describe_signable(&spec.signable)),
+        ("algorithm", format!("{:?}", spec.algorithm)),
+        ("key slot", spec.key_slot.to_string()),
+        ("output", format!("{:?}", spec.output)),
+        (
+            "interpreter",
+            match &spec.interpreter {
+                InterpreterSource::Usb => "interpreter.wasm on the stick".into(),
+                InterpreterSource::Builtin(name) => format!("built-in {name}"),
+            },
+        ),
+    ];
+    if let Some(format) = &spec.format {
+        fields.push(("format", format.clone()));
+    }
+    if let Some(size) = spec.payload_size {
+        fields.push(("payload size", format!("{size} bytes")));
+    }
+    if let Some(count) = spec.not_before {
+        fields.push(("not before", format!("signature count {count}")));
+    }
+    if let Some(path) = &spec.derivation_path {
+        fields.push(("derivation path", describe_path(path)));
+    }
+    if let Some(nonce) = &spec.nonce {
+        fields.push(("nonce", hex::encode(nonce)));
+    }
+    if let Some(secs) = spec.not_after {
+        fields.push(("not after", format!("{secs} (Unix seconds)")));
+    }
+    if spec.require_pin_on_sign {
+        fields.push(("pin on sign", "required".into()));
+    }
+    if spec.verify_assembly {
+        fields.push(("verify assembly", "yes".into()));
+    }
+    fields
+        .into_iter()
+        .map(|(name, value)| format!("{name}: {value}\n"))
+        .collect()
+}
+
+/// Print the spec at `spec_path` and, given a payload, the interpreter's
+/// rendering of it. A stick interpreter is `interpreter` or, by default,
+/// `interpreter.wasm` next to the spec.
+pub fn run(spec_path: &Path, payload: Option<&Path>, interpreter: Option<&Path>) {
+    let spec = SigningSpec::from_cbor(&fs::read(spec_path).expect("failed to read spec"))
+        .expect("invalid sign.cbor");
+    print!("{}", summary(&spec));
+
+    let Some(payload) = payload else {
+        return;
+    };
+    let payload = fs::read(payload).expect("failed to read payload");
+    let wasm = match &spec.interpreter {
+        InterpreterSource::Builtin(name) => builtin::interpreter(name)
+            .unwrap_or_else(|| panic!("built-in interpreter '{name}' is not in this build"))
+            .to_vec(),
+        InterpreterSource::Usb => {
+            let path = match interpreter {
+                Some(path) => path.to_path_buf(),
+                None => spec_path.with_file_name("interpreter.wasm"),
+            };
+            fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path:?}: {e}"))
+        }
+    };
+    let sandbox = Sandbox::new().expect("failed to create sandbox");
+    let module = sandbox
+        .load_module(&wasm)
+        .expect("failed to load interpreter");
+    let json = module.interpret(&payload).expect("interpreter failed");
+    let value: serde_json::Value =
+        serde_json::from_str(&json).expect("interpreter output is not JSON");
+    println!();
+    print!("{}", render_text(&json_to_lines_sorted(&value)));
+}
@@ -1,23 +1,69 @@
-use clap::Parser;
+mod diff;
+mod dump;
+
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use signer_core::crypto::hash_bytes;
+use signer_core::display::HARDENED;
 use signer_core::spec::{
-    HashAlgorithm, OutputSpec, SignAlgorithm, Signable, SignableSource, SigningSpec,
+    HashAlgorithm, InterpreterSource, OutputSpec, SignAlgorithm, Signable, SignableSource,
+    SigningSpec, SPEC_VERSION,
 };
+use signer_core::stick::{Manifest, MANIFEST_FILE};
+use signer_core::wasm_sandbox::Sandbox;
 use std::fs;
+use std::io::{self, Read};
 use std::path::PathBuf;
 
 /// Prepare USB stick contents for air-gapped signing.
 #[derive(Parser)]
-#[command(name = "usb-pack")]
+#[command(name = "usb-pack", args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Raw transaction payload file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    pack: Option<PackArgs>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Interpret the payloads of two packed directories and print the review
+    /// lines that differ
+    Diff { a: PathBuf, b: PathBuf },
+    /// Print what a signing spec asks the device to sign and, given a
+    /// payload, how its interpreter renders it
+    Dump {
+        /// sign.cbor to describe
+        #[arg(long)]
+        spec: PathBuf,
+        /// Payload to run through the interpreter
+        #[arg(long)]
+        payload: Option<PathBuf>,
+        /// Interpreter for a spec without a built-in one (default:
+        /// interpreter.wasm next to the spec)
+        #[arg(long, requires = "payload")]
+        interpreter: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct PackArgs {
+    /// Raw transaction payload file, or `-` to read it from stdin
     #[arg(long)]
     payload: PathBuf,
 
     /// WASM interpreter module
-    #[arg(long)]
-    interpreter: PathBuf,
+    #[arg(long, required_unless_present = "builtin")]
+    interpreter: Option<PathBuf>,
 
-    /// Output directory (will contain payload.bin, interpreter.wasm, sign.cbor)
+    /// Use the device's built-in interpreter with this name instead of
+    /// copying one to the stick
+    #[arg(long, conflicts_with = "interpreter")]
+    builtin: Option<String>,
+
+    /// Output directory (will contain payload.bin, sign.cbor and, unless
+    /// --builtin is given, interpreter.wasm)
     #[arg(long)]
     output: PathBuf,
 
@@ -33,71 +79,354 @@ struct Cli {
     #[arg(long)]
     key_slot: u8,
 
-    /// Signable mode: whole, hash-blake2b, hash-sha256
+    /// Signable mode: whole, range, hash-blake2b, hash-blake2b224,
+    /// hash-blake2b512, hash-blake3, hash-sha256, hash-sha256-range,
+    /// hash-sha256d, hash-keccak256, keyed-blake2b, merkle-sha256,
+    /// merkle-blake2b, interpreter
     #[arg(long, default_value = "whole")]
     signable: String,
 
-    /// Output mode: signature-only, append, wasm-assemble
+    /// First payload byte signed (or hashed) by --signable range and
+    /// hash-sha256-range
+    #[arg(
+        long,
+        requires = "length",
+        required_if_eq_any([("signable", "range"), ("signable", "hash-sha256-range")])
+    )]
+    offset: Option<usize>,
+
+    /// Number of payload bytes signed (or hashed) by --signable range and
+    /// hash-sha256-range
+    #[arg(
+        long,
+        requires = "offset",
+        required_if_eq_any([("signable", "range"), ("signable", "hash-sha256-range")])
+    )]
+    length: Option<usize>,
+
+    /// Hex-encoded Blake2b MAC key (1–64 bytes) for --signable keyed-blake2b
+    #[arg(long, required_if_eq("signable", "keyed-blake2b"))]
+    mac_key: Option<String>,
+
+    /// Hex-encoded domain-separation tag prepended to what --signable
+    /// selects (hashed along with it for the hashing modes)
+    #[arg(long, value_name = "HEX")]
+    prefix: Option<String>,
+
+    /// Output mode: signature-only, append, wasm-assemble, psbt-insert,
+    /// detached
     #[arg(long, default_value = "signature-only")]
     output_mode: String,
+
+    /// PSBT input the signature is added to, for --output-mode psbt-insert
+    #[arg(long, required_if_eq("output_mode", "psbt-insert"))]
+    psbt_input: Option<u32>,
+
+    /// File the device writes the signature to, for --output-mode detached
+    #[arg(long, required_if_eq("output_mode", "detached"))]
+    detached_name: Option<String>,
+
+    /// Require the PIN to be re-entered on the device right before signing
+    #[arg(long)]
+    require_pin_on_sign: bool,
+
+    /// Have the device re-interpret the assembled output and check it still
+    /// shows the reviewed transaction
+    #[arg(long)]
+    verify_assembly: bool,
+
+    /// Declared payload format (e.g. cardano-tx); the device warns when a key
+    /// slot is used for a different format than last time
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Record the payload size in the spec so the device refuses a
+    /// truncated or substituted payload
+    #[arg(long)]
+    record_payload_size: bool,
+
+    /// Refuse signing until the device's signature counter reaches N
+    #[arg(long, value_name = "N")]
+    not_before: Option<u64>,
+
+    /// Sign with the key derived from the slot's key at this SLIP-0010 path,
+    /// e.g. m/44'/1815'/0' (every component hardened)
+    #[arg(long, value_name = "PATH")]
+    derivation_path: Option<String>,
+
+    /// Tag the spec with a random nonce, so the device signs it only once
+    #[arg(long)]
+    single_use: bool,
+
+    /// Refuse signing after this time, in Unix seconds (checked only on a
+    /// device with a clock)
+    #[arg(long, value_name = "SECS")]
+    not_after: Option<u64>,
+
+    /// Also write interpreter.cwasm, the interpreter compiled to native code
+    /// for this machine's architecture, so a device allowing precompiled
+    /// interpreters skips compiling it
+    #[arg(long, conflicts_with = "builtin")]
+    precompile: bool,
+
+    /// Print a machine-readable JSON summary to stdout
+    #[arg(long)]
+    json: bool,
+}
+
+/// Summary printed to stdout with `--json`. Digests are hex BLAKE2b-256.
+#[derive(Serialize)]
+struct Summary {
+    output: PathBuf,
+    payload_path: PathBuf,
+    /// `None` when the spec uses a built-in interpreter.
+    interpreter_path: Option<PathBuf>,
+    spec_path: PathBuf,
+    payload_blake2b256: String,
+    interpreter_blake2b256: Option<String>,
+    spec: SigningSpec,
+}
+
+fn blake2b256_hex(data: &[u8]) -> String {
+    hex::encode(hash_bytes(HashAlgorithm::Blake2b256, data))
 }
 
 fn parse_algorithm(s: &str) -> SignAlgorithm {
     match s {
         "ed25519" => SignAlgorithm::Ed25519,
+        "ed25519ph" => SignAlgorithm::Ed25519ph,
         "secp256k1-ecdsa" => SignAlgorithm::Secp256k1Ecdsa,
         "secp256k1-schnorr" => SignAlgorithm::Secp256k1Schnorr,
+        "secp256k1-ecdsa-recoverable" => SignAlgorithm::Secp256k1EcdsaRecoverable,
         other => panic!("unknown algorithm: {other}"),
     }
 }
 
-fn parse_signable(s: &str) -> Signable {
+/// `range` is the `--offset`/`--length` pair, which clap requires for the
+/// ranged modes.
+fn parse_signable(s: &str, mac_key: Option<&str>, range: Option<(usize, usize)>) -> Signable {
+    let range = || range.expect("--offset and --length are required");
     match s {
         "whole" => Signable::Whole,
+        "range" => {
+            let (offset, length) = range();
+            Signable::Range { offset, length }
+        }
         "hash-blake2b" => Signable::HashThenSign {
             hash: HashAlgorithm::Blake2b256,
             source: SignableSource::Whole,
         },
+        "hash-blake2b224" => Signable::HashThenSign {
+            hash: HashAlgorithm::Blake2b224,
+            source: SignableSource::Whole,
+        },
+        "hash-blake2b512" => Signable::HashThenSign {
+            hash: HashAlgorithm::Blake2b512,
+            source: SignableSource::Whole,
+        },
         "hash-sha256" => Signable::HashThenSign {
             hash: HashAlgorithm::Sha256,
             source: SignableSource::Whole,
         },
+        "hash-sha256-range" => {
+            let (offset, length) = range();
+            Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Range { offset, length },
+            }
+        }
+        "hash-sha256d" => Signable::HashThenSign {
+            hash: HashAlgorithm::Sha256d,
+            source: SignableSource::Whole,
+        },
+        "hash-blake3" => Signable::HashThenSign {
+            hash: HashAlgorithm::Blake3,
+            source: SignableSource::Whole,
+        },
+        "hash-keccak256" => Signable::HashThenSign {
+            hash: HashAlgorithm::Keccak256,
+            source: SignableSource::Whole,
+        },
+        "keyed-blake2b" => Signable::KeyedHashThenSign {
+            key: hex::decode(mac_key.expect("--mac-key is required"))
+                .expect("invalid --mac-key hex"),
+            source: SignableSource::Whole,
+        },
+        "merkle-sha256" => Signable::MerkleRoot {
+            hash: HashAlgorithm::Sha256,
+        },
+        "merkle-blake2b" => Signable::MerkleRoot {
+            hash: HashAlgorithm::Blake2b256,
+        },
+        "interpreter" => Signable::Interpreter,
         other => panic!("unknown signable mode: {other}"),
     }
 }
 
-fn parse_output_mode(s: &str) -> OutputSpec {
+fn parse_derivation_path(s: &str) -> Vec<u32> {
+    let components = s
+        .strip_prefix("m/")
+        .unwrap_or_else(|| panic!("derivation path {s:?} does not start with m/"));
+    components
+        .split('/')
+        .map(|c| {
+            let index = c
+                .strip_suffix('\'')
+                .unwrap_or_else(|| panic!("derivation path component {c:?} is not hardened"));
+            match index.parse::<u32>() {
+                Ok(index) if index < HARDENED => index | HARDENED,
+                _ => panic!("invalid derivation path component {c:?}"),
+            }
+        })
+        .collect()
+}
+
+fn parse_output_mode(
+    s: &str,
+    psbt_input: Option<u32>,
+    detached_name: Option<String>,
+) -> OutputSpec {
     match s {
         "signature-only" => OutputSpec::SignatureOnly,
         "append" => OutputSpec::AppendToPayload,
         "wasm-assemble" => OutputSpec::WasmAssemble,
+        "psbt-insert" => OutputSpec::PsbtInsert {
+            input_index: psbt_input.expect("--psbt-input is required with psbt-insert"),
+        },
+        "detached" => OutputSpec::DetachedFile {
+            name: detached_name.expect("--detached-name is required with detached"),
+        },
         other => panic!("unknown output mode: {other}"),
     }
 }
 
 fn main() {
     let cli = Cli::parse();
+    match (cli.command, cli.pack) {
+        (Some(Command::Diff { a, b }), _) => diff::run(&a, &b),
+        (
+            Some(Command::Dump {
+                spec,
+                payload,
+                interpreter,
+            }),
+            _,
+        ) => dump::run(&spec, payload.as_deref(), interpreter.as_deref()),
+        (None, Some(pack_args)) => pack(pack_args),
+        (None, None) => unreachable!("clap requires pack arguments without a subcommand"),
+    }
+}
+
+fn pack(cli: PackArgs) {
+    // Read payload
+    let payload = if cli.payload.as_os_str() == "-" {
+        let mut payload = Vec::new();
+        io::stdin()
+            .read_to_end(&mut payload)
+            .expect("failed to read payload from stdin");
+        payload
+    } else {
+        fs::read(&cli.payload).expect("failed to read payload")
+    };
 
+    let mut signable = parse_signable(
+        &cli.signable,
+        cli.mac_key.as_deref(),
+        cli.offset.zip(cli.length),
+    );
+    if let Some(prefix) = &cli.prefix {
+        signable = Signable::Prefixed {
+            prefix: hex::decode(prefix).expect("invalid --prefix hex"),
+            inner: Box::new(signable),
+        };
+    }
     let spec = SigningSpec {
+        version: SPEC_VERSION,
         label: cli.label,
-        signable: parse_signable(&cli.signable),
+        signable,
         algorithm: parse_algorithm(&cli.algorithm),
         key_slot: cli.key_slot,
-        output: parse_output_mode(&cli.output_mode),
+        output: parse_output_mode(&cli.output_mode, cli.psbt_input, cli.detached_name),
+        require_pin_on_sign: cli.require_pin_on_sign,
+        verify_assembly: cli.verify_assembly,
+        format: cli.format,
+        interpreter: match &cli.builtin {
+            Some(name) => InterpreterSource::Builtin(name.clone()),
+            None => InterpreterSource::Usb,
+        },
+        payload_size: cli.record_payload_size.then_some(payload.len() as u64),
+        not_before: cli.not_before,
+        derivation_path: cli.derivation_path.as_deref().map(parse_derivation_path),
+        nonce: cli.single_use.then(rand::random),
+        not_after: cli.not_after,
     };
 
+    // Check the interpreter here rather than have the device refuse it.
+    let interpreter = cli.interpreter.as_ref().map(|path| {
+        let interpreter = fs::read(path).expect("failed to read interpreter");
+        let sandbox = Sandbox::new().expect("failed to create sandbox");
+        sandbox
+            .load_module(&interpreter)
+            .and_then(|module| module.validate_exports(spec.output == OutputSpec::WasmAssemble))
+            .unwrap_or_else(|e| panic!("invalid interpreter {path:?}: {e}"));
+        interpreter
+    });
+
     fs::create_dir_all(&cli.output).expect("failed to create output directory");
 
+    let payload_path = cli.output.join("payload.bin");
+    let interpreter_path = cli.output.join("interpreter.wasm");
+    let spec_path = cli.output.join("sign.cbor");
+
     // Copy payload
-    fs::copy(&cli.payload, cli.output.join("payload.bin")).expect("failed to copy payload");
+    fs::write(&payload_path, &payload).expect("failed to copy payload");
 
-    // Copy interpreter
-    fs::copy(&cli.interpreter, cli.output.join("interpreter.wasm"))
-        .expect("failed to copy interpreter");
+    // Copy interpreter, unless the device provides it
+    if let Some(interpreter) = &interpreter {
+        fs::write(&interpreter_path, interpreter).expect("failed to copy interpreter");
+    }
+    let precompiled = cli.precompile.then(|| {
+        let interpreter = interpreter
+            .as_deref()
+            .expect("--precompile needs --interpreter");
+        let cwasm = Sandbox::new()
+            .and_then(|sandbox| sandbox.precompile(interpreter))
+            .expect("failed to precompile interpreter");
+        fs::write(cli.output.join("interpreter.cwasm"), &cwasm)
+            .expect("failed to write interpreter.cwasm");
+        cwasm
+    });
 
     // Write signing spec
     let cbor = spec.to_cbor().expect("failed to serialize signing spec");
-    fs::write(cli.output.join("sign.cbor"), cbor).expect("failed to write sign.cbor");
+    fs::write(&spec_path, &cbor).expect("failed to write sign.cbor");
+
+    // Bind the files together so the device notices one being swapped
+    let mut manifest = Manifest::new(
+        env!("CARGO_PKG_VERSION"),
+        &payload,
+        interpreter.as_deref(),
+        &cbor,
+    );
+    if let Some(cwasm) = &precompiled {
+        manifest = manifest.with_precompiled(cwasm);
+    }
+    fs::write(cli.output.join(MANIFEST_FILE), manifest.to_cbor())
+        .expect("failed to write manifest.cbor");
 
     eprintln!("USB stick contents written to {:?}", cli.output);
+
+    if cli.json {
+        let summary = Summary {
+            output: cli.output,
+            payload_path,
+            interpreter_path: interpreter.is_some().then_some(interpreter_path),
+            spec_path,
+            payload_blake2b256: blake2b256_hex(&payload),
+            interpreter_blake2b256: interpreter.as_deref().map(blake2b256_hex),
+            spec,
+        };
+        let json = serde_json::to_string_pretty(&summary).expect("failed to serialize summary");
+        println!("{json}");
+    }
 }
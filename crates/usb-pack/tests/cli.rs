@@ -0,0 +1,487 @@
+use signer_core::display::HARDENED;
+use signer_core::spec::{
+    HashAlgorithm, InterpreterSource, OutputSpec, Signable, SignableSource, SigningSpec,
+};
+use signer_core::stick::{Manifest, MANIFEST_FILE};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Fresh scratch directory under the system temp dir.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("usb-pack-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn usb_pack(args: &[&str]) -> Output {
+    let out = Command::new(env!("CARGO_BIN_EXE_usb-pack"))
+        .args(args)
+        .output()
+        .expect("usb-pack runs");
+    assert!(
+        out.status.success(),
+        "usb-pack failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out
+}
+
+#[derive(serde::Deserialize)]
+struct Summary {
+    output: PathBuf,
+    payload_path: PathBuf,
+    interpreter_path: Option<PathBuf>,
+    spec_path: PathBuf,
+    payload_blake2b256: String,
+    interpreter_blake2b256: Option<String>,
+    spec: SigningSpec,
+}
+
+#[test]
+fn json_summary() {
+    let dir = scratch("json");
+    let payload = dir.join("tx.raw");
+    let interpreter = echo_hex_wasm();
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+
+    let out = usb_pack(&[
+        "--payload",
+        payload.to_str().unwrap(),
+        "--interpreter",
+        interpreter.to_str().unwrap(),
+        "--output",
+        usb.to_str().unwrap(),
+        "--label",
+        "Scripted",
+        "--key-slot",
+        "2",
+        "--json",
+    ]);
+
+    let summary: Summary = serde_json::from_slice(&out.stdout).expect("stdout is JSON");
+    assert_eq!(summary.output, usb);
+    assert_eq!(summary.payload_path, usb.join("payload.bin"));
+    assert_eq!(summary.interpreter_path, Some(usb.join("interpreter.wasm")));
+    assert_eq!(summary.spec_path, usb.join("sign.cbor"));
+    assert_eq!(summary.payload_blake2b256.len(), 64);
+    assert_ne!(
+        Some(summary.payload_blake2b256.clone()),
+        summary.interpreter_blake2b256
+    );
+    assert_eq!(summary.spec.label, "Scripted");
+    assert_eq!(summary.spec.key_slot, 2);
+    assert_eq!(summary.spec.output, OutputSpec::SignatureOnly);
+    let manifest = Manifest::from_cbor(&std::fs::read(usb.join(MANIFEST_FILE)).unwrap()).unwrap();
+    manifest
+        .verify(
+            b"payload",
+            Some(&std::fs::read(&interpreter).unwrap()),
+            &std::fs::read(&summary.spec_path).unwrap(),
+        )
+        .unwrap();
+    assert!(String::from_utf8_lossy(&out.stderr).contains("written to"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn builtin_interpreter_is_not_copied() {
+    let dir = scratch("builtin");
+    let payload = dir.join("tx.raw");
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+
+    let out = usb_pack(&[
+        "--payload",
+        payload.to_str().unwrap(),
+        "--builtin",
+        "echo-hex",
+        "--output",
+        usb.to_str().unwrap(),
+        "--key-slot",
+        "0",
+        "--json",
+    ]);
+
+    let summary: Summary = serde_json::from_slice(&out.stdout).expect("stdout is JSON");
+    assert_eq!(summary.interpreter_path, None);
+    assert_eq!(summary.interpreter_blake2b256, None);
+    assert_eq!(
+        summary.spec.interpreter,
+        InterpreterSource::Builtin("echo-hex".into())
+    );
+    assert!(!usb.join("interpreter.wasm").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn precompiled_interpreter_is_recorded_in_the_manifest() {
+    let dir = scratch("precompile");
+    let payload = dir.join("tx.raw");
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+
+    usb_pack(&[
+        "--payload",
+        payload.to_str().unwrap(),
+        "--interpreter",
+        echo_hex_wasm().to_str().unwrap(),
+        "--output",
+        usb.to_str().unwrap(),
+        "--key-slot",
+        "0",
+        "--precompile",
+    ]);
+
+    let manifest = Manifest::from_cbor(&std::fs::read(usb.join(MANIFEST_FILE)).unwrap()).unwrap();
+    manifest
+        .verify_precompiled(&std::fs::read(usb.join("interpreter.cwasm")).unwrap())
+        .unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn key_slot_is_written_to_spec() {
+    let dir = scratch("slot");
+    let payload = dir.join("tx.raw");
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+
+    usb_pack(&[
+        "--payload",
+        payload.to_str().unwrap(),
+        "--builtin",
+        "echo-hex",
+        "--output",
+        usb.to_str().unwrap(),
+        "--key-slot",
+        "3",
+    ]);
+
+    let spec = SigningSpec::from_cbor(&std::fs::read(usb.join("sign.cbor")).unwrap()).unwrap();
+    assert_eq!(spec.key_slot, 3);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn key_slot_out_of_range_is_refused() {
+    let dir = scratch("bad-slot");
+    let payload = dir.join("tx.raw");
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_usb-pack"))
+        .args([
+            "--payload",
+            payload.to_str().unwrap(),
+            "--builtin",
+            "echo-hex",
+            "--output",
+            usb.to_str().unwrap(),
+            "--key-slot",
+            "256",
+        ])
+        .output()
+        .expect("usb-pack runs");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--key-slot"));
+    assert!(!usb.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The spec `usb-pack` writes for a builtin-interpreter stick with `extra`
+/// arguments, or its stderr if it fails.
+fn packed_spec(name: &str, extra: &[&str]) -> Result<SigningSpec, String> {
+    let dir = scratch(name);
+    let payload = dir.join("tx.raw");
+    std::fs::write(&payload, b"payload").unwrap();
+    let usb = dir.join("usb");
+    let out = Command::new(env!("CARGO_BIN_EXE_usb-pack"))
+        .args([
+            "--payload",
+            payload.to_str().unwrap(),
+            "--builtin",
+            "echo-hex",
+            "--output",
+            usb.to_str().unwrap(),
+            "--key-slot",
+            "0",
+        ])
+        .args(extra)
+        .output()
+        .expect("usb-pack runs");
+    let spec = if out.status.success() {
+        Ok(SigningSpec::from_cbor(&std::fs::read(usb.join("sign.cbor")).unwrap()).unwrap())
+    } else {
+        Err(String::from_utf8_lossy(&out.stderr).into_owned())
+    };
+    std::fs::remove_dir_all(dir).unwrap();
+    spec
+}
+
+#[test]
+fn prefix_wraps_the_signable() {
+    let spec = packed_spec("prefix", &["--signable", "hash-sha256", "--prefix", "1900"]).unwrap();
+    assert_eq!(
+        spec.signable,
+        Signable::Prefixed {
+            prefix: vec![0x19, 0x00],
+            inner: Box::new(Signable::HashThenSign {
+                hash: HashAlgorithm::Sha256,
+                source: SignableSource::Whole,
+            }),
+        }
+    );
+}
+
+#[test]
+fn derivation_path_is_written_to_spec() {
+    let spec = packed_spec("path", &["--derivation-path", "m/44'/1815'/0'"]).unwrap();
+    assert_eq!(
+        spec.derivation_path,
+        Some(vec![44 | HARDENED, 1815 | HARDENED, HARDENED])
+    );
+    assert_eq!(packed_spec("no-path", &[]).unwrap().derivation_path, None);
+
+    let err = packed_spec("soft-path", &["--derivation-path", "m/44'/0"]).unwrap_err();
+    assert!(err.contains("not hardened"), "{err}");
+}
+
+#[test]
+fn psbt_insert_names_its_input() {
+    let spec = packed_spec(
+        "psbt",
+        &["--output-mode", "psbt-insert", "--psbt-input", "3"],
+    )
+    .unwrap();
+    assert_eq!(spec.output, OutputSpec::PsbtInsert { input_index: 3 });
+
+    let err = packed_spec("psbt-no-input", &["--output-mode", "psbt-insert"]).unwrap_err();
+    assert!(err.contains("required"), "{err}");
+}
+
+#[test]
+fn detached_output_names_its_file() {
+    let spec = packed_spec(
+        "detached",
+        &["--output-mode", "detached", "--detached-name", "tx.sig"],
+    )
+    .unwrap();
+    assert_eq!(
+        spec.output,
+        OutputSpec::DetachedFile {
+            name: "tx.sig".into()
+        }
+    );
+}
+
+#[test]
+fn ranged_signables() {
+    let range = ["--offset", "2", "--length", "5"];
+    let spec = packed_spec("range", &[&["--signable", "range"][..], &range].concat()).unwrap();
+    assert_eq!(
+        spec.signable,
+        Signable::Range {
+            offset: 2,
+            length: 5
+        }
+    );
+
+    let spec = packed_spec(
+        "hash-range",
+        &[&["--signable", "hash-sha256-range"][..], &range].concat(),
+    )
+    .unwrap();
+    assert_eq!(
+        spec.signable,
+        Signable::HashThenSign {
+            hash: HashAlgorithm::Sha256,
+            source: SignableSource::Range {
+                offset: 2,
+                length: 5
+            },
+        }
+    );
+}
+
+#[test]
+fn ranged_signable_needs_offset_and_length() {
+    for (name, args) in [
+        ("no-range", &["--signable", "range"][..]),
+        ("no-length", &["--signable", "range", "--offset", "2"]),
+        (
+            "no-offset",
+            &["--signable", "hash-sha256-range", "--length", "5"],
+        ),
+    ] {
+        let err = packed_spec(name, args).unwrap_err();
+        assert!(err.contains("required"), "{name}: {err}");
+    }
+}
+
+/// Run `usb-pack` over `interpreter` in `output_mode`, returning its stderr
+/// if it fails.
+fn pack_interpreter(name: &str, interpreter: &[u8], output_mode: &str) -> Result<(), String> {
+    let dir = scratch(name);
+    let payload = dir.join("tx.raw");
+    let wasm = dir.join("interp.wasm");
+    std::fs::write(&payload, b"payload").unwrap();
+    std::fs::write(&wasm, interpreter).unwrap();
+    let usb = dir.join("usb");
+    let out = Command::new(env!("CARGO_BIN_EXE_usb-pack"))
+        .args([
+            "--payload",
+            payload.to_str().unwrap(),
+            "--interpreter",
+            wasm.to_str().unwrap(),
+            "--output",
+            usb.to_str().unwrap(),
+            "--key-slot",
+            "0",
+            "--output-mode",
+            output_mode,
+        ])
+        .output()
+        .expect("usb-pack runs");
+    let packed = usb.join("interpreter.wasm").exists();
+    std::fs::remove_dir_all(dir).unwrap();
+    if out.status.success() {
+        assert!(packed);
+        Ok(())
+    } else {
+        assert!(!packed, "{name}: refused interpreter was copied");
+        Err(String::from_utf8_lossy(&out.stderr).into_owned())
+    }
+}
+
+#[test]
+fn interpreter_is_validated() {
+    let echo_hex = std::fs::read(echo_hex_wasm()).unwrap();
+    pack_interpreter("valid", &echo_hex, "signature-only").unwrap();
+
+    let err = pack_interpreter("not-wasm", b"\0asm", "signature-only").unwrap_err();
+    assert!(err.contains("invalid interpreter"), "{err}");
+
+    pack_interpreter("assemble", &echo_hex, "wasm-assemble").unwrap();
+
+    // base58 interprets but cannot assemble.
+    let base58 = std::fs::read(echo_hex_wasm().with_file_name("base58.wasm")).unwrap();
+    let err = pack_interpreter("no-assemble", &base58, "wasm-assemble").unwrap_err();
+    assert!(err.contains("missing exports: assemble"), "{err}");
+}
+
+fn echo_hex_wasm() -> PathBuf {
+    let path = PathBuf::from(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../target/wasm32-unknown-unknown/release/echo_hex.wasm"
+    ));
+    assert!(
+        path.exists(),
+        "echo_hex.wasm not found — run `just build-wasm` first"
+    );
+    path
+}
+
+/// Pack `payload` with the echo-hex interpreter into `dir/name`.
+fn pack(dir: &std::path::Path, name: &str, payload: &[u8]) -> PathBuf {
+    let payload_path = dir.join(format!("{name}.raw"));
+    std::fs::write(&payload_path, payload).unwrap();
+    let usb = dir.join(name);
+    usb_pack(&[
+        "--payload",
+        payload_path.to_str().unwrap(),
+        "--interpreter",
+        echo_hex_wasm().to_str().unwrap(),
+        "--output",
+        usb.to_str().unwrap(),
+        "--key-slot",
+        "0",
+    ]);
+    usb
+}
+
+#[test]
+fn diff_shows_changed_lines() {
+    let dir = scratch("diff");
+    let a = pack(&dir, "a", b"amount=100");
+    let b = pack(&dir, "b", b"amount=150");
+
+    let out = usb_pack(&["diff", a.to_str().unwrap(), b.to_str().unwrap()]);
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "-   hex: 616d6f756e743d313030\n+   hex: 616d6f756e743d313530\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn diff_of_identical_payloads_is_empty() {
+    let dir = scratch("diff-same");
+    let a = pack(&dir, "a", b"amount=100");
+    let b = pack(&dir, "b", b"amount=100");
+
+    let out = usb_pack(&["diff", a.to_str().unwrap(), b.to_str().unwrap()]);
+
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "no differences\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dump_describes_spec_and_payload() {
+    let dir = scratch("dump");
+    let usb = pack(&dir, "usb", b"hi");
+    let spec = usb.join("sign.cbor");
+
+    let out = usb_pack(&["dump", "--spec", spec.to_str().unwrap()]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("label: Transaction\n"), "{stdout}");
+    assert!(stdout.contains("algorithm: Ed25519\n"), "{stdout}");
+    assert!(stdout.contains("key slot: 0\n"), "{stdout}");
+    assert!(!stdout.contains("hex:"));
+
+    let payload = usb.join("payload.bin");
+    let out = usb_pack(&[
+        "dump",
+        "--spec",
+        spec.to_str().unwrap(),
+        "--payload",
+        payload.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("hex: 6869\n"), "{stdout}");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn payload_from_stdin_matches_file() {
+    let dir = scratch("stdin");
+    let payload = b"piped\0payload";
+    let from_file = pack(&dir, "file", payload);
+
+    let usb = dir.join("stdin");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_usb-pack"))
+        .args([
+            "--payload",
+            "-",
+            "--interpreter",
+            echo_hex_wasm().to_str().unwrap(),
+            "--output",
+            usb.to_str().unwrap(),
+            "--key-slot",
+            "0",
+        ])
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("usb-pack runs");
+    child.stdin.take().unwrap().write_all(payload).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let read = |dir: &PathBuf| std::fs::read(dir.join("payload.bin")).unwrap();
+    assert_eq!(read(&usb), payload);
+    assert_eq!(read(&usb), read(&from_file));
+    std::fs::remove_dir_all(dir).unwrap();
+}
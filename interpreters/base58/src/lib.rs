@@ -0,0 +1,174 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    /// Linker-provided symbol marking the start of the heap in WASM linear memory.
+    static __heap_base: u8;
+}
+
+static HEAP_PTR: AtomicUsize = AtomicUsize::new(0);
+static HEAP_BASE: AtomicUsize = AtomicUsize::new(0);
+
+fn heap_base() -> usize {
+    let base = HEAP_BASE.load(Ordering::Relaxed);
+    if base != 0 {
+        return base;
+    }
+    let base = unsafe { &__heap_base as *const u8 as usize };
+    HEAP_BASE.store(base, Ordering::Relaxed);
+    HEAP_PTR.store(base, Ordering::Relaxed);
+    base
+}
+
+#[no_mangle]
+pub extern "C" fn alloc(size: i32) -> i32 {
+    heap_base(); // ensure initialized
+    let size = size as usize;
+    let ptr = HEAP_PTR.fetch_add(size, Ordering::SeqCst);
+    // Check against WASM memory size (in pages of 64 KiB)
+    let mem_size = core::arch::wasm32::memory_size(0) * 65536;
+    if ptr + size > mem_size {
+        // Try to grow memory
+        let pages_needed = ((ptr + size - mem_size) + 65535) / 65536;
+        if core::arch::wasm32::memory_grow(0, pages_needed) == usize::MAX {
+            HEAP_PTR.store(ptr, Ordering::SeqCst); // rollback
+            return 0;
+        }
+    }
+    ptr as i32
+}
+
+/// Free `size` bytes at `ptr`. Only the most recent allocation is
+/// reclaimed, by moving the bump pointer back; anything else is a no-op.
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: i32, size: i32) {
+    let end = ptr as usize + size as usize;
+    let _ = HEAP_PTR.compare_exchange(end, ptr as usize, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+/// Bitcoin's base58 alphabet, as used by Solana and IPFS.
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Convert `bytes` (without leading zeros) to base58 digit values in
+/// `digits`, most significant first, and return how many trailing digits
+/// are used.
+fn to_base58_digits(bytes: &[u8], digits: &mut [u8]) -> usize {
+    digits.fill(0);
+    let mut used = 0;
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        let mut i = 0;
+        for digit in digits.iter_mut().rev() {
+            if carry == 0 && i >= used {
+                break;
+            }
+            carry += 256 * *digit as u32;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+            i += 1;
+        }
+        used = i;
+    }
+    used
+}
+
+/// interpret(ptr, len) -> ptr to length-prefixed JSON string.
+///
+/// Output JSON: `{"base58":"<base58-encoded payload>","length":<n>}`
+#[no_mangle]
+pub extern "C" fn interpret(ptr: i32, len: i32) -> i32 {
+    let payload = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+
+    // Each leading zero byte is a leading '1'; the rest is a big number.
+    let zeros = payload.iter().take_while(|&&b| b == 0).count();
+    let rest = &payload[zeros..];
+    // log(256) / log(58) < 1.38
+    let bound = rest.len() * 138 / 100 + 1;
+    let digits_ptr = alloc(bound as i32);
+    if digits_ptr == 0 {
+        return 0;
+    }
+    let digits = unsafe { core::slice::from_raw_parts_mut(digits_ptr as *mut u8, bound) };
+    let used = to_base58_digits(rest, digits);
+    let digits = &digits[bound - used..];
+
+    let prefix = b"{\"base58\":\"";
+    let middle = b"\",\"length\":";
+    let suffix = b"}";
+
+    let mut len_buf = [0u8; 20];
+    let len_str = fmt_usize(payload.len(), &mut len_buf);
+
+    let total_len =
+        prefix.len() + zeros + digits.len() + middle.len() + len_str.len() + suffix.len();
+
+    let out_ptr = alloc((4 + total_len) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + total_len) };
+
+    // Length prefix (LE u32)
+    out[0..4].copy_from_slice(&(total_len as u32).to_le_bytes());
+    let mut offset = 4;
+
+    out[offset..offset + prefix.len()].copy_from_slice(prefix);
+    offset += prefix.len();
+
+    out[offset..offset + zeros].fill(ALPHABET[0]);
+    offset += zeros;
+
+    for &d in digits {
+        out[offset] = ALPHABET[d as usize];
+        offset += 1;
+    }
+
+    out[offset..offset + middle.len()].copy_from_slice(middle);
+    offset += middle.len();
+
+    out[offset..offset + len_str.len()].copy_from_slice(len_str);
+    offset += len_str.len();
+
+    out[offset..offset + suffix.len()].copy_from_slice(suffix);
+
+    out_ptr
+}
+
+/// Largest payload base58 will render. Encoding is quadratic in the
+/// payload length, so larger ones would run out of fuel; base58 is for
+/// keys, addresses and short messages.
+const MAX_PAYLOAD: usize = 256;
+
+/// validate(ptr, len) -> 0 to accept, 1 for payloads over 256 bytes.
+#[no_mangle]
+pub extern "C" fn validate(_ptr: i32, len: i32) -> i32 {
+    (len as usize > MAX_PAYLOAD) as i32
+}
+
+/// metadata() -> ptr to length-prefixed JSON naming this interpreter.
+#[no_mangle]
+pub extern "C" fn metadata() -> i32 {
+    let json = br#"{"name":"base58","version":"0.1.0"}"#;
+    let out_ptr = alloc((4 + json.len()) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + json.len()) };
+    out[0..4].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    out[4..].copy_from_slice(json);
+    out_ptr
+}
+
+fn fmt_usize(mut n: usize, buf: &mut [u8; 20]) -> &[u8] {
+    if n == 0 {
+        buf[19] = b'0';
+        return &buf[19..];
+    }
+    let mut i = 20;
+    while n > 0 {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+    &buf[i..]
+}
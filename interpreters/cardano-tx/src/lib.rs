@@ -0,0 +1,570 @@
+//! Cardano transaction interpreter.
+//!
+//! Reads a transaction (`[body, witnesses, is_valid, auxiliary_data]`) or a
+//! bare transaction body, and shows its fee, TTL and outputs, with output
+//! addresses in bech32 and native assets by policy, name and quantity. Any
+//! other body field but the inputs is named in a warned `unshown` line.
+//! Uses `core` only: the output is written in two passes, one to measure it
+//! and one into a single bump allocation.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    /// Linker-provided symbol marking the start of the heap in WASM linear memory.
+    static __heap_base: u8;
+}
+
+static HEAP_PTR: AtomicUsize = AtomicUsize::new(0);
+static HEAP_BASE: AtomicUsize = AtomicUsize::new(0);
+
+fn heap_base() -> usize {
+    let base = HEAP_BASE.load(Ordering::Relaxed);
+    if base != 0 {
+        return base;
+    }
+    let base = unsafe { &__heap_base as *const u8 as usize };
+    HEAP_BASE.store(base, Ordering::Relaxed);
+    HEAP_PTR.store(base, Ordering::Relaxed);
+    base
+}
+
+#[no_mangle]
+pub extern "C" fn alloc(size: i32) -> i32 {
+    heap_base(); // ensure initialized
+    let size = size as usize;
+    let ptr = HEAP_PTR.fetch_add(size, Ordering::SeqCst);
+    // Check against WASM memory size (in pages of 64 KiB)
+    let mem_size = core::arch::wasm32::memory_size(0) * 65536;
+    if ptr + size > mem_size {
+        // Try to grow memory
+        let pages_needed = ((ptr + size - mem_size) + 65535) / 65536;
+        if core::arch::wasm32::memory_grow(0, pages_needed) == usize::MAX {
+            HEAP_PTR.store(ptr, Ordering::SeqCst); // rollback
+            return 0;
+        }
+    }
+    ptr as i32
+}
+
+/// Free `size` bytes at `ptr`. Only the most recent allocation is
+/// reclaimed, by moving the bump pointer back; anything else is a no-op.
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: i32, size: i32) {
+    let end = ptr as usize + size as usize;
+    let _ = HEAP_PTR.compare_exchange(end, ptr as usize, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+/// Nesting deeper than this is refused rather than recursed into.
+const MAX_DEPTH: usize = 32;
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+
+/// Minimal reader for the definite-length CBOR Cardano uses.
+#[derive(Clone, Copy)]
+struct Cbor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cbor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let slice = self.bytes.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    /// Major type and argument of the next item. Indefinite lengths are
+    /// not supported.
+    fn head(&mut self) -> Option<(u8, u64)> {
+        let initial = *self.take(1)?.first()?;
+        let major = initial >> 5;
+        let arg = match initial & 0x1f {
+            n @ 0..=23 => n as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().ok()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().ok()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            _ => return None,
+        };
+        Some((major, arg))
+    }
+
+    /// The next item's major type and argument, skipping any tags.
+    fn untagged_head(&mut self) -> Option<(u8, u64)> {
+        loop {
+            match self.head()? {
+                (MAJOR_TAG, _) => continue,
+                head => return Some(head),
+            }
+        }
+    }
+
+    fn expect(&mut self, major: u8) -> Option<u64> {
+        match self.untagged_head()? {
+            (m, arg) if m == major => Some(arg),
+            _ => None,
+        }
+    }
+
+    fn uint(&mut self) -> Option<u64> {
+        self.expect(MAJOR_UINT)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.expect(MAJOR_BYTES)?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    fn array(&mut self) -> Option<u64> {
+        self.expect(MAJOR_ARRAY)
+    }
+
+    fn map(&mut self) -> Option<u64> {
+        self.expect(MAJOR_MAP)
+    }
+
+    /// Skip one whole item.
+    fn skip(&mut self, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let (major, arg) = self.head()?;
+        match major {
+            MAJOR_BYTES | MAJOR_TEXT => {
+                self.take(usize::try_from(arg).ok()?)?;
+            }
+            MAJOR_ARRAY => {
+                for _ in 0..arg {
+                    self.skip(depth + 1)?;
+                }
+            }
+            MAJOR_MAP => {
+                for _ in 0..arg {
+                    self.skip(depth + 1)?;
+                    self.skip(depth + 1)?;
+                }
+            }
+            MAJOR_TAG => self.skip(depth + 1)?,
+            // Integers, simple values and floats carry no further items.
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+/// Names of the body fields by key, for those listed as not shown.
+const BODY_FIELDS: [(u64, &str); 17] = [
+    (4, "certificates"),
+    (5, "withdrawals"),
+    (6, "update"),
+    (7, "auxiliary_data_hash"),
+    (8, "validity_start"),
+    (9, "mint"),
+    (11, "script_data_hash"),
+    (13, "collateral"),
+    (14, "required_signers"),
+    (15, "network_id"),
+    (16, "collateral_return"),
+    (17, "total_collateral"),
+    (18, "reference_inputs"),
+    (19, "voting_procedures"),
+    (20, "proposal_procedures"),
+    (21, "current_treasury_value"),
+    (22, "donation"),
+];
+
+/// Where the fields shown for review sit in the payload.
+struct Body<'a> {
+    fee: u64,
+    ttl: Option<u64>,
+    outputs: Cbor<'a>,
+    /// Bit `k` set for each body key `k` other than inputs, outputs, fee
+    /// and TTL; keys of 63 and above all set bit 63.
+    unshown: u64,
+}
+
+/// Locate the fee, TTL and outputs of a transaction or transaction body.
+fn parse_body(payload: &[u8]) -> Option<Body<'_>> {
+    let mut cbor = Cbor::new(payload);
+    let mut peek = cbor;
+    if peek.untagged_head()?.0 == MAJOR_ARRAY {
+        cbor.array()?;
+    }
+    let entries = cbor.map()?;
+    let (mut fee, mut ttl, mut outputs, mut unshown) = (None, None, None, 0);
+    for _ in 0..entries {
+        match cbor.uint()? {
+            0 => cbor.skip(0)?,
+            1 => {
+                outputs = Some(cbor);
+                cbor.skip(0)?;
+            }
+            2 => fee = Some(cbor.uint()?),
+            3 => ttl = Some(cbor.uint()?),
+            key => {
+                unshown |= 1 << key.min(63);
+                cbor.skip(0)?;
+            }
+        }
+    }
+    Some(Body {
+        fee: fee?,
+        ttl,
+        outputs: outputs?,
+        unshown,
+    })
+}
+
+/// An output's address bytes, lovelace and native assets.
+fn parse_output<'a>(cbor: &mut Cbor<'a>) -> Option<(&'a [u8], u64, Option<Cbor<'a>>)> {
+    let (mut address, mut value) = (None, None);
+    match cbor.untagged_head()? {
+        // Legacy: [address, value, ?datum_hash]
+        (MAJOR_ARRAY, len) => {
+            if len < 2 {
+                return None;
+            }
+            address = Some(cbor.bytes()?);
+            value = Some(parse_value(cbor)?);
+            for _ in 2..len {
+                cbor.skip(0)?;
+            }
+        }
+        // Babbage: {0: address, 1: value, ?2: datum, ?3: script_ref}
+        (MAJOR_MAP, len) => {
+            for _ in 0..len {
+                match cbor.uint()? {
+                    0 => address = Some(cbor.bytes()?),
+                    1 => value = Some(parse_value(cbor)?),
+                    _ => cbor.skip(0)?,
+                }
+            }
+        }
+        _ => return None,
+    }
+    let (lovelace, assets) = value?;
+    Some((address?, lovelace, assets))
+}
+
+/// `coin` or `[coin, {policy: {name: quantity}}]`, as lovelace and the
+/// asset map if it holds any asset, checked here and walked again by
+/// [`push_assets`].
+fn parse_value<'a>(cbor: &mut Cbor<'a>) -> Option<(u64, Option<Cbor<'a>>)> {
+    let mut peek = *cbor;
+    if peek.untagged_head()?.0 == MAJOR_UINT {
+        return Some((cbor.uint()?, None));
+    }
+    if cbor.array()? != 2 {
+        return None;
+    }
+    let lovelace = cbor.uint()?;
+    let map = *cbor;
+    let mut assets = 0;
+    for _ in 0..cbor.map()? {
+        cbor.bytes()?;
+        let names = cbor.map()?;
+        for _ in 0..names {
+            cbor.bytes()?;
+            cbor.uint()?;
+        }
+        assets += names;
+    }
+    Some((lovelace, (assets > 0).then_some(map)))
+}
+
+/// Output buffer; with an empty `buf` it only measures.
+struct Out<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Out<'_> {
+    fn push(&mut self, bytes: &[u8]) {
+        if let Some(dst) = self.buf.get_mut(self.len..self.len + bytes.len()) {
+            dst.copy_from_slice(bytes);
+        }
+        self.len += bytes.len();
+    }
+
+    fn push_u64(&mut self, n: u64) {
+        let mut buf = [0u8; 20];
+        self.push(fmt_u64(n, &mut buf));
+    }
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn bech32_polymod(chk: u32, value: u8) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let top = chk >> 25;
+    let mut chk = ((chk & 0x1ffffff) << 5) ^ value as u32;
+    for (i, g) in GEN.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            chk ^= g;
+        }
+    }
+    chk
+}
+
+/// Write `data` as bech32 with human-readable part `hrp` (CIP-19 allows
+/// addresses beyond BIP-173's 90 characters).
+fn push_bech32(out: &mut Out<'_>, hrp: &[u8], data: &[u8]) {
+    let mut chk = 1;
+    for &c in hrp {
+        chk = bech32_polymod(chk, c >> 5);
+    }
+    chk = bech32_polymod(chk, 0);
+    for &c in hrp {
+        chk = bech32_polymod(chk, c & 0x1f);
+    }
+    out.push(hrp);
+    out.push(b"1");
+
+    let mut emit = |out: &mut Out<'_>, value: u8| {
+        chk = bech32_polymod(chk, value);
+        out.push(&[BECH32_CHARSET[value as usize]]);
+    };
+    let (mut acc, mut bits) = (0u32, 0);
+    for &byte in data {
+        acc = (acc << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            emit(out, ((acc >> bits) & 0x1f) as u8);
+        }
+    }
+    if bits > 0 {
+        emit(out, ((acc << (5 - bits)) & 0x1f) as u8);
+    }
+
+    for _ in 0..6 {
+        chk = bech32_polymod(chk, 0);
+    }
+    chk ^= 1;
+    for i in 0..6 {
+        let value = (chk >> (5 * (5 - i))) & 0x1f;
+        out.push(&[BECH32_CHARSET[value as usize]]);
+    }
+}
+
+fn nibble_to_hex(n: u8) -> u8 {
+    if n < 10 {
+        b'0' + n
+    } else {
+        b'a' + (n - 10)
+    }
+}
+
+/// Shelley addresses (header types 0-7) in bech32, `addr` on mainnet and
+/// `addr_test` elsewhere; anything else, such as Byron addresses, in hex.
+fn push_address(out: &mut Out<'_>, address: &[u8]) {
+    match address.first() {
+        Some(&header) if header >> 4 <= 7 => {
+            let hrp: &[u8] = if header & 0x0f == 1 {
+                b"addr"
+            } else {
+                b"addr_test"
+            };
+            push_bech32(out, hrp, address);
+        }
+        _ => push_hex(out, address),
+    }
+}
+
+fn push_hex(out: &mut Out<'_>, bytes: &[u8]) {
+    for &b in bytes {
+        out.push(&[nibble_to_hex(b >> 4), nibble_to_hex(b & 0x0f)]);
+    }
+}
+
+/// Write an asset map as `[{"policy":..,"name":..,"quantity":..}]`, with
+/// policy IDs and asset names in hex.
+fn push_assets(out: &mut Out<'_>, mut assets: Cbor<'_>) -> Option<()> {
+    out.push(b"[");
+    let mut first = true;
+    for _ in 0..assets.map()? {
+        let policy = assets.bytes()?;
+        for _ in 0..assets.map()? {
+            let name = assets.bytes()?;
+            let quantity = assets.uint()?;
+            if !first {
+                out.push(b",");
+            }
+            first = false;
+            out.push(b"{\"policy\":\"");
+            push_hex(out, policy);
+            out.push(b"\",\"name\":\"");
+            push_hex(out, name);
+            out.push(b"\",\"quantity\":");
+            out.push_u64(quantity);
+            out.push(b"}");
+        }
+    }
+    out.push(b"]");
+    Some(())
+}
+
+/// Write the body fields set in `unshown` as a JSON array of names.
+fn push_unshown(out: &mut Out<'_>, unshown: u64) {
+    out.push(b"[");
+    let mut first = true;
+    for key in (0..64).filter(|key| unshown & (1 << key) != 0) {
+        if !first {
+            out.push(b",");
+        }
+        first = false;
+        out.push(b"\"");
+        match BODY_FIELDS.iter().find(|(k, _)| *k == key) {
+            Some((_, name)) => out.push(name.as_bytes()),
+            None if key == 63 => out.push(b"key 63 or above"),
+            None => {
+                out.push(b"key ");
+                out.push_u64(key);
+            }
+        }
+        out.push(b"\"");
+    }
+    out.push(b"]");
+}
+
+/// Write the review JSON for `payload`, or `None` if it is not a
+/// transaction this interpreter understands.
+fn render(payload: &[u8], out: &mut Out<'_>) -> Option<()> {
+    let body = parse_body(payload)?;
+    out.push(b"{\"fee\":");
+    out.push_u64(body.fee);
+    if let Some(ttl) = body.ttl {
+        out.push(b",\"ttl\":");
+        out.push_u64(ttl);
+    }
+    out.push(b",\"outputs\":[");
+    let mut outputs = body.outputs;
+    for i in 0..outputs.array()? {
+        let (address, lovelace, assets) = parse_output(&mut outputs)?;
+        if i > 0 {
+            out.push(b",");
+        }
+        out.push(b"{\"address\":\"");
+        push_address(out, address);
+        out.push(b"\",\"amount\":");
+        out.push_u64(lovelace);
+        if let Some(assets) = assets {
+            out.push(b",\"assets\":");
+            push_assets(out, assets)?;
+        }
+        out.push(b"}");
+    }
+    out.push(b"]");
+    if body.unshown != 0 {
+        out.push(b",\"unshown\":{\"_warn\":true,\"value\":");
+        push_unshown(out, body.unshown);
+        out.push(b"}");
+    }
+    out.push(b"}");
+    Some(())
+}
+
+/// interpret(ptr, len) -> ptr to length-prefixed JSON string, or 0 if the
+/// payload is not a Cardano transaction.
+///
+/// Output JSON: `{"fee":<lovelace>,"ttl":<slot>,"outputs":[{"address":"<bech32>","amount":<lovelace>}]}`,
+/// where `ttl` is left out if the transaction has none, and an output
+/// carrying native assets also has
+/// `"assets":[{"policy":"<hex>","name":"<hex>","quantity":<n>}]`. A body
+/// with fields other than inputs, outputs, fee and TTL adds
+/// `"unshown":{"_warn":true,"value":["<field>",..]}`.
+#[no_mangle]
+pub extern "C" fn interpret(ptr: i32, len: i32) -> i32 {
+    let payload = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+
+    let mut measure = Out {
+        buf: &mut [],
+        len: 0,
+    };
+    if render(payload, &mut measure).is_none() {
+        return 0;
+    }
+    let total_len = measure.len;
+
+    let out_ptr = alloc((4 + total_len) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + total_len) };
+
+    // Length prefix (LE u32)
+    out[0..4].copy_from_slice(&(total_len as u32).to_le_bytes());
+    let mut json = Out {
+        buf: &mut out[4..],
+        len: 0,
+    };
+    render(payload, &mut json);
+
+    out_ptr
+}
+
+/// validate(ptr, len) -> 0 to accept, 1 if the payload is not a
+/// transaction this interpreter can show.
+#[no_mangle]
+pub extern "C" fn validate(ptr: i32, len: i32) -> i32 {
+    let payload = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+    let mut measure = Out {
+        buf: &mut [],
+        len: 0,
+    };
+    render(payload, &mut measure).is_none() as i32
+}
+
+/// schema() -> ptr to length-prefixed JSON display schema showing lovelace
+/// amounts as ADA.
+#[no_mangle]
+pub extern "C" fn schema() -> i32 {
+    let json = br#"{"fields":{"fee":{"label":"Fee","format":{"type":"amount","decimals":6,"unit":"ADA"}},"outputs.amount":{"label":"Amount","format":{"type":"amount","decimals":6,"unit":"ADA"}},"outputs.address":{"label":"To"},"outputs.assets.policy":{"label":"Policy"},"outputs.assets.name":{"label":"Asset"},"outputs.assets.quantity":{"label":"Quantity"},"unshown":{"label":"Not shown"}},"group_digits":true}"#;
+    let out_ptr = alloc((4 + json.len()) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + json.len()) };
+    out[0..4].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    out[4..].copy_from_slice(json);
+    out_ptr
+}
+
+/// metadata() -> ptr to length-prefixed JSON naming this interpreter.
+#[no_mangle]
+pub extern "C" fn metadata() -> i32 {
+    let json = br#"{"name":"cardano-tx","version":"0.1.0"}"#;
+    let out_ptr = alloc((4 + json.len()) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + json.len()) };
+    out[0..4].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    out[4..].copy_from_slice(json);
+    out_ptr
+}
+
+fn fmt_u64(mut n: u64, buf: &mut [u8; 20]) -> &[u8] {
+    if n == 0 {
+        buf[19] = b'0';
+        return &buf[19..];
+    }
+    let mut i = 20;
+    while n > 0 {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+    &buf[i..]
+}
@@ -37,6 +37,14 @@ pub extern "C" fn alloc(size: i32) -> i32 {
     ptr as i32
 }
 
+/// Free `size` bytes at `ptr`. Only the most recent allocation is
+/// reclaimed, by moving the bump pointer back; anything else is a no-op.
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: i32, size: i32) {
+    let end = ptr as usize + size as usize;
+    let _ = HEAP_PTR.compare_exchange(end, ptr as usize, Ordering::SeqCst, Ordering::SeqCst);
+}
+
 fn nibble_to_hex(n: u8) -> u8 {
     if n < 10 {
         b'0' + n
@@ -93,6 +101,49 @@ pub extern "C" fn interpret(ptr: i32, len: i32) -> i32 {
     out_ptr
 }
 
+/// assemble(payload_ptr, payload_len, sig_ptr, sig_len) -> ptr to
+/// length-prefixed bytes: the payload with the signature appended.
+#[no_mangle]
+pub extern "C" fn assemble(payload_ptr: i32, payload_len: i32, sig_ptr: i32, sig_len: i32) -> i32 {
+    let payload =
+        unsafe { core::slice::from_raw_parts(payload_ptr as *const u8, payload_len as usize) };
+    let sig = unsafe { core::slice::from_raw_parts(sig_ptr as *const u8, sig_len as usize) };
+
+    let total_len = payload.len() + sig.len();
+    let out_ptr = alloc((4 + total_len) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + total_len) };
+    out[0..4].copy_from_slice(&(total_len as u32).to_le_bytes());
+    out[4..4 + payload.len()].copy_from_slice(payload);
+    out[4 + payload.len()..].copy_from_slice(sig);
+    out_ptr
+}
+
+/// Largest payload echo-hex will render.
+const MAX_PAYLOAD: usize = 1024 * 1024;
+
+/// validate(ptr, len) -> 0 to accept, 1 for payloads over 1 MiB.
+#[no_mangle]
+pub extern "C" fn validate(_ptr: i32, len: i32) -> i32 {
+    (len as usize > MAX_PAYLOAD) as i32
+}
+
+/// metadata() -> ptr to length-prefixed JSON naming this interpreter.
+#[no_mangle]
+pub extern "C" fn metadata() -> i32 {
+    let json = br#"{"name":"echo-hex","version":"0.1.0"}"#;
+    let out_ptr = alloc((4 + json.len()) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + json.len()) };
+    out[0..4].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    out[4..].copy_from_slice(json);
+    out_ptr
+}
+
 fn fmt_usize(mut n: usize, buf: &mut [u8; 20]) -> &[u8] {
     if n == 0 {
         buf[19] = b'0';
@@ -0,0 +1,190 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    /// Linker-provided symbol marking the start of the heap in WASM linear memory.
+    static __heap_base: u8;
+}
+
+static HEAP_PTR: AtomicUsize = AtomicUsize::new(0);
+static HEAP_BASE: AtomicUsize = AtomicUsize::new(0);
+
+fn heap_base() -> usize {
+    let base = HEAP_BASE.load(Ordering::Relaxed);
+    if base != 0 {
+        return base;
+    }
+    let base = unsafe { &__heap_base as *const u8 as usize };
+    HEAP_BASE.store(base, Ordering::Relaxed);
+    HEAP_PTR.store(base, Ordering::Relaxed);
+    base
+}
+
+#[no_mangle]
+pub extern "C" fn alloc(size: i32) -> i32 {
+    heap_base(); // ensure initialized
+    let size = size as usize;
+    let ptr = HEAP_PTR.fetch_add(size, Ordering::SeqCst);
+    // Check against WASM memory size (in pages of 64 KiB)
+    let mem_size = core::arch::wasm32::memory_size(0) * 65536;
+    if ptr + size > mem_size {
+        // Try to grow memory
+        let pages_needed = ((ptr + size - mem_size) + 65535) / 65536;
+        if core::arch::wasm32::memory_grow(0, pages_needed) == usize::MAX {
+            HEAP_PTR.store(ptr, Ordering::SeqCst); // rollback
+            return 0;
+        }
+    }
+    ptr as i32
+}
+
+/// Free `size` bytes at `ptr`. Only the most recent allocation is
+/// reclaimed, by moving the bump pointer back; anything else is a no-op.
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: i32, size: i32) {
+    let end = ptr as usize + size as usize;
+    let _ = HEAP_PTR.compare_exchange(end, ptr as usize, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+fn nibble_to_hex(n: u8) -> u8 {
+    if n < 10 {
+        b'0' + n
+    } else {
+        b'a' + (n - 10)
+    }
+}
+
+/// The JSON string escape for `c`, or `None` if it can appear as-is.
+///
+/// Control characters (C0, DEL and C1) are written as `\u00XX`, so a
+/// message cannot smuggle raw line breaks or terminal codes into the output.
+fn escape(c: char) -> Option<[u8; 6]> {
+    match c {
+        '"' | '\\' => Some([b'\\', c as u8, 0, 0, 0, 0]),
+        c if c.is_control() => {
+            let b = c as u32 as u8;
+            Some([
+                b'\\',
+                b'u',
+                b'0',
+                b'0',
+                nibble_to_hex(b >> 4),
+                nibble_to_hex(b & 0x0f),
+            ])
+        }
+        _ => None,
+    }
+}
+
+/// Length of an escape returned by [`escape`].
+fn escape_len(escaped: &[u8; 6]) -> usize {
+    if escaped[1] == b'u' {
+        6
+    } else {
+        2
+    }
+}
+
+fn escaped_len(text: &str) -> usize {
+    text.chars()
+        .map(|c| match escape(c) {
+            Some(e) => escape_len(&e),
+            None => c.len_utf8(),
+        })
+        .sum()
+}
+
+/// Write `text` JSON-escaped at the start of `out`, returning the bytes used.
+fn write_escaped(text: &str, out: &mut [u8]) -> usize {
+    let mut offset = 0;
+    for c in text.chars() {
+        match escape(c) {
+            Some(e) => {
+                let len = escape_len(&e);
+                out[offset..offset + len].copy_from_slice(&e[..len]);
+                offset += len;
+            }
+            None => {
+                offset += c.encode_utf8(&mut out[offset..]).len();
+            }
+        }
+    }
+    offset
+}
+
+/// interpret(ptr, len) -> ptr to length-prefixed JSON string.
+///
+/// Output JSON: `{"text":"<payload as text>","valid_utf8":true}`, or
+/// `{"hex":"<hex-encoded payload>","valid_utf8":false}` when the payload
+/// is not UTF-8.
+#[no_mangle]
+pub extern "C" fn interpret(ptr: i32, len: i32) -> i32 {
+    let payload = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+
+    let text = core::str::from_utf8(payload).ok();
+    let (prefix, body_len, suffix): (&[u8], usize, &[u8]) = match text {
+        Some(text) => (
+            b"{\"text\":\"",
+            escaped_len(text),
+            b"\",\"valid_utf8\":true}",
+        ),
+        None => (
+            b"{\"hex\":\"",
+            payload.len() * 2,
+            b"\",\"valid_utf8\":false}",
+        ),
+    };
+
+    let total_len = prefix.len() + body_len + suffix.len();
+
+    let out_ptr = alloc((4 + total_len) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + total_len) };
+
+    // Length prefix (LE u32)
+    out[0..4].copy_from_slice(&(total_len as u32).to_le_bytes());
+    let mut offset = 4;
+
+    out[offset..offset + prefix.len()].copy_from_slice(prefix);
+    offset += prefix.len();
+
+    match text {
+        Some(text) => offset += write_escaped(text, &mut out[offset..]),
+        None => {
+            for &b in payload {
+                out[offset] = nibble_to_hex(b >> 4);
+                out[offset + 1] = nibble_to_hex(b & 0x0f);
+                offset += 2;
+            }
+        }
+    }
+
+    out[offset..offset + suffix.len()].copy_from_slice(suffix);
+
+    out_ptr
+}
+
+/// Largest payload utf8-text will render: messages, not documents.
+const MAX_PAYLOAD: usize = 64 * 1024;
+
+/// validate(ptr, len) -> 0 to accept, 1 for payloads over 64 KiB.
+#[no_mangle]
+pub extern "C" fn validate(_ptr: i32, len: i32) -> i32 {
+    (len as usize > MAX_PAYLOAD) as i32
+}
+
+/// metadata() -> ptr to length-prefixed JSON naming this interpreter.
+#[no_mangle]
+pub extern "C" fn metadata() -> i32 {
+    let json = br#"{"name":"utf8-text","version":"0.1.0"}"#;
+    let out_ptr = alloc((4 + json.len()) as i32);
+    if out_ptr == 0 {
+        return 0;
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(out_ptr as *mut u8, 4 + json.len()) };
+    out[0..4].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    out[4..].copy_from_slice(json);
+    out_ptr
+}